@@ -26,6 +26,14 @@ const GET_SHOP_ID_QUERY = `#graphql
   }
 `;
 
+const GET_SHOP_TIMEZONE_QUERY = `#graphql
+  query GetShopTimezone {
+    shop {
+      timezoneOffsetMinutes
+    }
+  }
+`;
+
 const GET_RULES_CONFIG_QUERY = `#graphql
   query GetRulesConfig {
     shop {
@@ -75,6 +83,18 @@ export async function getAppInstallationId(admin: AdminGraphQL): Promise<string
   return getShopId(admin);
 }
 
+/**
+ * Get the shop's current UTC offset in minutes
+ */
+export async function getShopTimezoneOffset(admin: AdminGraphQL): Promise<number | null> {
+  const response = await admin.graphql(GET_SHOP_TIMEZONE_QUERY);
+  const data: GraphQLResponse<{
+    shop: { timezoneOffsetMinutes: number };
+  }> = await response.json();
+
+  return data.data?.shop?.timezoneOffsetMinutes ?? null;
+}
+
 /**
  * Get the current rules configuration from shop metafield
  */
@@ -121,6 +141,14 @@ export async function saveRulesConfig(
   ownerId: string,
   config: RulesConfig
 ): Promise<{ success: boolean; error?: string }> {
+  // The function reads dates without an offset in the shop's timezone,
+  // and can't look it up at checkout. The offset is refreshed on every
+  // save; after a daylight saving change it's an hour off until then.
+  const timezoneOffsetMinutes = await getShopTimezoneOffset(admin);
+  if (timezoneOffsetMinutes !== null) {
+    config = { ...config, timezone_offset_minutes: timezoneOffsetMinutes };
+  }
+
   const response = await admin.graphql(SET_RULES_CONFIG_MUTATION, {
     variables: {
       metafields: [
@@ -27,6 +27,10 @@ export interface RulesConfig {
   version: string;
   total_complexity: number;
   rules: Rule[];
+  /** The shop's UTC offset, written on save; dates without an offset use it */
+  timezone_offset_minutes?: number;
+  /** How slash dates such as 01/02/2024 are read (default MONTH_FIRST) */
+  date_order?: "MONTH_FIRST" | "DAY_FIRST";
 }
 
 // ============================================================================
@@ -0,0 +1,73 @@
+//! Bump allocator for the wasm build.
+//!
+//! The function handles one checkout and exits, so freed memory is never
+//! worth reusing. Handing out memory from a pointer that only moves up
+//! replaces the default allocator's free lists (~10 KB of the function).
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::arch::wasm32::memory_grow;
+use std::cell::Cell;
+use std::ptr::null_mut;
+
+const PAGE_SIZE: usize = 65536;
+
+pub struct Bump {
+    next: Cell<usize>,
+    end: Cell<usize>,
+}
+
+// wasm32 without atomics has a single thread.
+unsafe impl Sync for Bump {}
+
+impl Bump {
+    pub const fn new() -> Self {
+        Bump { next: Cell::new(0), end: Cell::new(0) }
+    }
+}
+
+fn align_up(address: usize, align: usize) -> usize {
+    (address + align - 1) & !(align - 1)
+}
+
+unsafe impl GlobalAlloc for Bump {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut start = align_up(self.next.get(), layout.align());
+        if start.checked_add(layout.size()).is_none_or(|end| end > self.end.get()) {
+            // Enough fresh pages for the block at any alignment. Memory
+            // grown by someone else since the last time breaks the run, so
+            // start again at the new pages.
+            let pages = (layout.size() + layout.align()).div_ceil(PAGE_SIZE);
+            let first = memory_grow(0, pages);
+            if first == usize::MAX {
+                return null_mut();
+            }
+            if first * PAGE_SIZE != self.end.get() {
+                self.next.set(first * PAGE_SIZE);
+            }
+            self.end.set((first + pages) * PAGE_SIZE);
+            start = align_up(self.next.get(), layout.align());
+        }
+        self.next.set(start + layout.size());
+        start as *mut u8
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+
+    /// Grows or shrinks the newest block in place, which covers a `Vec` or
+    /// `String` being built up while parsing.
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let address = ptr as usize;
+        if address + layout.size() == self.next.get() && new_size <= self.end.get() - address {
+            self.next.set(address + new_size);
+            return ptr;
+        }
+        if new_size <= layout.size() {
+            return ptr;
+        }
+        let new_ptr = self.alloc(Layout::from_size_align_unchecked(new_size, layout.align()));
+        if !new_ptr.is_null() {
+            std::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size());
+        }
+        new_ptr
+    }
+}
@@ -1,9 +1,24 @@
 //! Rule evaluation engine for the Shopify Function.
 
 use crate::{
-    ComparisonOperator, Condition, ConditionGroup, Criterion, LogicalOperator, Rule, RulesConfig,
+    Aggregate, AggregateFunction, ComparisonOperator, Condition, ConditionGroup, Criterion, LogicalOperator, Rule,
+    RulesConfig,
 };
-use crate::patterns::check_preset;
+use crate::normalize::Normalization;
+use crate::patterns::{check_preset, preset};
+use logicflow_core::address_quality::{address_looks_invalid, AddressParts, AddressSignal};
+use logicflow_core::attributes::is_checkout_attribute;
+use logicflow_core::dates::{days_span, parse_date, parse_weekday, DateOrder, DateTime, ParsedDate};
+use logicflow_core::emails::{email_domain, is_disposable_email, normalize_domain, parent_domains};
+use logicflow_core::keywords::KeywordSet;
+use logicflow_core::military::is_military_address;
+use logicflow_core::phones::{is_valid_phone, normalize_phone, phone_forms, phone_key};
+use logicflow_core::postcodes::{
+    canonical_postcode, in_postcode_range, is_valid_postcode, postcode_prefix, postcode_range,
+};
+use logicflow_core::profanity::{contains_profanity, profanity_list, PROFANITY_LISTS};
+use logicflow_core::regions::zip_matches_province;
+use logicflow_core::sets::ValueSet;
 
 // ============================================================================
 // Cart Input (simplified for vertical slice)
@@ -16,20 +31,33 @@ pub struct CartInput {
     pub quantity: u32,
     pub total_weight: f64,
     pub customer_tags: Vec<String>,
+    /// The buyer's email, once checkout has it (guests included).
+    pub customer_email: Option<String>,
+    /// The buyer's phone, as entered.
+    pub customer_phone: Option<String>,
+    /// Cart attributes the input query asks for, as key and value.
+    pub attributes: Vec<(String, String)>,
     pub shipping_address: Address,
     pub line_items: Vec<LineItem>,
+    /// The shop's local date. `now` is only known to the day.
+    pub today: Option<ParsedDate>,
 }
 
+/// Address fields are `None` when checkout didn't provide them, which is
+/// distinct from an empty string.
 #[derive(Debug, Clone, Default)]
 pub struct Address {
-    pub address1: String,
-    pub address2: String,
-    pub city: String,
-    pub province_code: String,
-    pub country_code: String,
-    pub zip: String,
+    pub address1: Option<String>,
+    pub address2: Option<String>,
+    pub city: Option<String>,
+    pub province_code: Option<String>,
+    pub country_code: Option<String>,
+    pub zip: Option<String>,
+    pub phone: Option<String>,
 }
 
+/// A cart line. Fields the line doesn't have (e.g. the SKU of a custom
+/// item) are empty strings, as in the POC.
 #[derive(Debug, Clone, Default)]
 pub struct LineItem {
     pub product_id: String,
@@ -37,9 +65,18 @@ pub struct LineItem {
     pub sku: String,
     pub vendor: String,
     pub quantity: u32,
+    /// Unit price.
     pub price: f64,
 }
 
+/// How dates are read: the shop's offset (seconds east of UTC) for dates
+/// written without one, and the order of slash dates.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DateSettings {
+    pub offset: i32,
+    pub order: DateOrder,
+}
+
 // ============================================================================
 // Evaluation Result
 // ============================================================================
@@ -50,7 +87,6 @@ pub struct EvaluationResult {
 }
 
 pub struct ValidationError {
-    pub rule_id: String,
     pub message: String,
 }
 
@@ -61,6 +97,182 @@ pub struct ValidationError {
 const MAX_RULES: usize = 100;
 const MAX_REGEX_RULES: usize = 30;
 
+#[cfg(feature = "regex")]
+type CompiledPattern = std::rc::Rc<crate::regex::Regex>;
+#[cfg(not(feature = "regex"))]
+type CompiledPattern = std::convert::Infallible;
+
+/// Named patterns from `regex_patterns`, each compiled once. A handful at
+/// most, so a list is smaller in the binary than a map.
+type NamedPatterns<'a> = Vec<(&'a str, Result<CompiledPattern, String>)>;
+
+/// Compile every custom `REGEX_MATCH` pattern once, when the config loads.
+///
+/// Rules are disabled if their conditions couldn't be read (e.g. an
+/// unknown operator), read a field that isn't in the function input, or
+/// for a pattern that can't be compiled (bad syntax, unsupported
+/// constructs, over the size limits, or no regex support in this build),
+/// an unknown pattern name, an unknown profanity language, a `BEFORE` or
+/// `AFTER` value that isn't a date, or an unknown weekday.
+/// Returns one message per disabled rule.
+pub fn prepare_rules(config: &mut RulesConfig) -> Vec<String> {
+    let named: NamedPatterns = config
+        .regex_patterns
+        .iter()
+        .map(|(name, pattern)| {
+            let compiled = pattern
+                .as_str()
+                .ok_or_else(|| "regex pattern must be a string".to_string())
+                .and_then(compile_pattern);
+            (name.as_str(), compiled)
+        })
+        .collect();
+
+    let dates = DateSettings { offset: config.timezone_offset_minutes * 60, order: config.date_order };
+
+    let mut warnings = Vec::new();
+    for rule in &mut config.rules {
+        let prepared = match rule.read_error.take() {
+            Some(e) => Err(e),
+            None => prepare_group(&mut rule.conditions, &named, dates),
+        };
+        if let Err(e) = prepared {
+            rule.enabled = false;
+            warnings.push(format!("rule {}: {}", rule.id, e));
+        }
+    }
+    warnings
+}
+
+fn prepare_group(group: &mut ConditionGroup, named: &NamedPatterns, dates: DateSettings) -> Result<(), String> {
+    for criterion in &mut group.criteria {
+        match criterion {
+            Criterion::Condition(c) => prepare_condition(c, named, dates)?,
+            Criterion::Group(g) => prepare_group(g, named, dates)?,
+        }
+    }
+    Ok(())
+}
+
+fn prepare_condition(condition: &mut Condition, named: &NamedPatterns, dates: DateSettings) -> Result<(), String> {
+    if let Some(filter) = condition.aggregate.as_mut().and_then(|a| a.filter.as_mut()) {
+        prepare_group(filter, named, dates)?;
+    }
+    condition.dates = dates;
+    // Fields the input query can't ask for: collection membership needs
+    // collection ids, attributes need a literal key, and customers have no
+    // creation date
+    let unavailable = match condition.field.strip_prefix("cart.attributes.") {
+        Some(key) => !is_checkout_attribute(key),
+        None => matches!(condition.field.as_str(), "line_item.collections" | "customer.created_at"),
+    };
+    if unavailable {
+        return Err(format!("{} isn't available at checkout", condition.field));
+    }
+    if let Some(option) = condition.normalize.unsupported {
+        return Err(format!("normalize.{} isn't supported at checkout", option));
+    }
+
+    match condition.operator {
+        ComparisonOperator::RegexMatch => {}
+        ComparisonOperator::ContainsProfanity | ComparisonOperator::NotContainsProfanity => {
+            return match word_lists(&condition.value) {
+                Some(_) => Ok(()),
+                None => Err("unknown profanity language".to_string()),
+            };
+        }
+        ComparisonOperator::AddressLooksInvalid => {
+            return match address_signals(&condition.value) {
+                Some(_) => Ok(()),
+                None => Err("unknown address signal".to_string()),
+            };
+        }
+        ComparisonOperator::In
+        | ComparisonOperator::NotIn
+        | ComparisonOperator::EmailDomainIn
+        | ComparisonOperator::NotEmailDomainIn => {
+            // A value that's neither a list nor a set is unknown at runtime,
+            // like other mistyped values; a set that doesn't decode is a
+            // config error
+            let norm = &condition.normalize;
+            let normalize: &dyn Fn(&str) -> String = match condition.operator {
+                _ if is_phone_field(&condition.field) => &phone_key,
+                ComparisonOperator::In | ComparisonOperator::NotIn => &|s| norm.apply(s).into_owned(),
+                _ => &normalize_domain,
+            };
+            condition.set = ValueSet::from_value(&condition.value, normalize);
+            return match (&condition.set, &condition.value) {
+                (None, serde_json::Value::Object(_)) => Err("malformed encoded set".to_string()),
+                _ => Ok(()),
+            };
+        }
+        ComparisonOperator::Before | ComparisonOperator::After => {
+            return match date_operand(condition) {
+                Some(_) => Ok(()),
+                None => Err(format!("{} isn't a date", condition.value)),
+            };
+        }
+        ComparisonOperator::DayOfWeekIn => {
+            return match weekdays(&condition.value) {
+                Some(_) => Ok(()),
+                None => Err("unknown weekday".to_string()),
+            };
+        }
+        ComparisonOperator::ContainsAnyOf | ComparisonOperator::ContainsNoneOf => {
+            // A value that isn't a list leaves the set empty, so the
+            // condition is unknown rather than the rule disabled
+            condition.keywords = condition.value.as_array().map(|items| {
+                let norm = &condition.normalize;
+                let keywords: Vec<String> =
+                    items.iter().filter_map(|v| v.as_str()).map(|s| norm.apply(s).into_owned()).collect();
+                KeywordSet::new(keywords.iter().map(String::as_str))
+            });
+            return Ok(());
+        }
+        _ => return Ok(()),
+    }
+
+    let pattern = condition
+        .value
+        .as_str()
+        .ok_or_else(|| "regex pattern must be a string".to_string())?;
+
+    let compiled = if condition.is_preset {
+        match named.iter().find(|(name, _)| *name == pattern) {
+            Some((_, result)) => result.clone().map_err(|e| format!("pattern {}: {}", pattern, e))?,
+            None => {
+                return match preset(pattern) {
+                    Some(_) => Ok(()),
+                    None => Err(format!("unknown preset pattern {}", pattern)),
+                }
+            }
+        }
+    } else {
+        compile_pattern(pattern)?
+    };
+
+    #[cfg(feature = "regex")]
+    {
+        condition.compiled = Some(compiled);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "regex"))]
+    match compiled {}
+}
+
+fn compile_pattern(pattern: &str) -> Result<CompiledPattern, String> {
+    #[cfg(feature = "regex")]
+    {
+        crate::regex::Regex::new(pattern)
+            .map(std::rc::Rc::new)
+            .map_err(|e| format!("{}: {}", pattern, e))
+    }
+
+    #[cfg(not(feature = "regex"))]
+    Err(format!("{}: custom regex patterns are not supported in this build", pattern))
+}
+
 pub fn evaluate_rules(config: &RulesConfig, cart: &CartInput) -> EvaluationResult {
     let mut errors = Vec::new();
     let mut rules_evaluated = 0;
@@ -84,7 +296,6 @@ pub fn evaluate_rules(config: &RulesConfig, cart: &CartInput) -> EvaluationResul
 
         if evaluate_rule(rule, cart) {
             errors.push(ValidationError {
-                rule_id: rule.id.clone(),
                 message: rule.error_message.clone(),
             });
         }
@@ -100,32 +311,59 @@ pub fn evaluate_rules(config: &RulesConfig, cart: &CartInput) -> EvaluationResul
 
 fn rule_uses_regex(group: &ConditionGroup) -> bool {
     group.criteria.iter().any(|criterion| match criterion {
-        Criterion::Condition(c) => c.operator == ComparisonOperator::RegexMatch,
+        Criterion::Condition(c) => {
+            c.operator == ComparisonOperator::RegexMatch
+                || c.aggregate.as_ref().and_then(|a| a.filter.as_ref()).is_some_and(rule_uses_regex)
+        }
         Criterion::Group(g) => rule_uses_regex(g),
     })
 }
 
+// Missing or mistyped data evaluates to "unknown" (`None`), following
+// Kleene logic: unknown survives NOT, loses to false under AND and to true
+// under OR. A rule only fires when its conditions are definitely true.
+//
+// `line` is the line item an aggregate filter is being evaluated against;
+// conditions then read `line_item.*` fields from it and nothing else.
+
 fn evaluate_rule(rule: &Rule, cart: &CartInput) -> bool {
-    evaluate_group(&rule.conditions, cart)
+    evaluate_group(&rule.conditions, cart, None) == Some(true)
 }
 
-fn evaluate_group(group: &ConditionGroup, cart: &CartInput) -> bool {
+fn evaluate_group(group: &ConditionGroup, cart: &CartInput, line: Option<&LineItem>) -> Option<bool> {
     match group.operator {
-        LogicalOperator::And => group
-            .criteria
-            .iter()
-            .all(|c| evaluate_criterion(c, cart)),
-        LogicalOperator::Or => group
-            .criteria
-            .iter()
-            .any(|c| evaluate_criterion(c, cart)),
+        LogicalOperator::And => evaluate_all(&group.criteria, cart, line),
+        LogicalOperator::Or => {
+            let mut result = Some(false);
+            for c in &group.criteria {
+                match evaluate_criterion(c, cart, line) {
+                    Some(true) => return Some(true),
+                    None => result = None,
+                    Some(false) => {}
+                }
+            }
+            result
+        }
+        LogicalOperator::Not => evaluate_all(&group.criteria, cart, line).map(|b| !b),
     }
 }
 
-fn evaluate_criterion(criterion: &Criterion, cart: &CartInput) -> bool {
+fn evaluate_all(criteria: &[Criterion], cart: &CartInput, line: Option<&LineItem>) -> Option<bool> {
+    let mut result = Some(true);
+    for c in criteria {
+        match evaluate_criterion(c, cart, line) {
+            Some(false) => return Some(false),
+            None => result = None,
+            Some(true) => {}
+        }
+    }
+    result
+}
+
+fn evaluate_criterion(criterion: &Criterion, cart: &CartInput, line: Option<&LineItem>) -> Option<bool> {
     match criterion {
-        Criterion::Condition(c) => evaluate_condition(c, cart),
-        Criterion::Group(g) => evaluate_group(g, cart),
+        Criterion::Condition(c) => evaluate_condition(c, cart, line),
+        Criterion::Group(g) => evaluate_group(g, cart, line),
     }
 }
 
@@ -137,6 +375,8 @@ enum FieldValue {
     String(String),
     Number(f64),
     StringArray(Vec<String>),
+    /// `now`: some time on the day starting at this local midnight.
+    Today(DateTime),
 }
 
 fn get_field_value(field: &str, cart: &CartInput) -> Option<FieldValue> {
@@ -146,144 +386,645 @@ fn get_field_value(field: &str, cart: &CartInput) -> Option<FieldValue> {
         "cart.quantity" => Some(FieldValue::Number(cart.quantity as f64)),
         "cart.total_weight" => Some(FieldValue::Number(cart.total_weight)),
         "customer.tags" => Some(FieldValue::StringArray(cart.customer_tags.clone())),
-        "shipping_address.address1" => Some(FieldValue::String(cart.shipping_address.address1.clone())),
-        "shipping_address.address2" => Some(FieldValue::String(cart.shipping_address.address2.clone())),
-        "shipping_address.city" => Some(FieldValue::String(cart.shipping_address.city.clone())),
-        "shipping_address.province_code" => Some(FieldValue::String(cart.shipping_address.province_code.clone())),
-        "shipping_address.country_code" => Some(FieldValue::String(cart.shipping_address.country_code.clone())),
-        "shipping_address.zip" => Some(FieldValue::String(cart.shipping_address.zip.clone())),
+        "customer.email" => cart.customer_email.clone().map(FieldValue::String),
+        "shipping_address.address1" => cart.shipping_address.address1.clone().map(FieldValue::String),
+        "shipping_address.address2" => cart.shipping_address.address2.clone().map(FieldValue::String),
+        "shipping_address.city" => cart.shipping_address.city.clone().map(FieldValue::String),
+        "shipping_address.province_code" => cart.shipping_address.province_code.clone().map(FieldValue::String),
+        "shipping_address.country_code" => cart.shipping_address.country_code.clone().map(FieldValue::String),
+        "shipping_address.zip" => cart.shipping_address.zip.clone().map(FieldValue::String),
+        "shipping_address.phone" => cart.shipping_address.phone.as_deref().map(|p| phone_field(cart, p)),
+        "customer.phone" => cart.customer_phone.as_deref().map(|p| phone_field(cart, p)),
+        _ => {
+            let key = field.strip_prefix("cart.attributes.")?;
+            cart.attributes.iter().find(|(k, _)| k == key).map(|(_, v)| FieldValue::String(v.clone()))
+        }
+    }
+}
+
+/// A `line_item.*` field. Collections aren't in the function input, so
+/// `line_item.collections` is missing.
+fn get_line_field(field: &str, line: &LineItem) -> Option<FieldValue> {
+    match field {
+        "line_item.product_id" => Some(FieldValue::String(line.product_id.clone())),
+        "line_item.variant_id" => Some(FieldValue::String(line.variant_id.clone())),
+        "line_item.sku" => Some(FieldValue::String(line.sku.clone())),
+        "line_item.vendor" => Some(FieldValue::String(line.vendor.clone())),
+        "line_item.quantity" => Some(FieldValue::Number(line.quantity as f64)),
+        "line_item.price" => Some(FieldValue::Number(line.price)),
+        "line_item.line_total" => Some(FieldValue::Number(line.price * line.quantity as f64)),
         _ => None,
     }
 }
 
+/// Phone fields compare by number, however they're written.
+fn is_phone_field(field: &str) -> bool {
+    matches!(field, "shipping_address.phone" | "customer.phone")
+}
+
+/// A phone field's value: E.164 using the shipping address's country if
+/// the number can be normalized, otherwise as entered.
+fn phone_field(cart: &CartInput, phone: &str) -> FieldValue {
+    let country = cart.shipping_address.country_code.as_deref().unwrap_or("");
+    FieldValue::String(normalize_phone(country, phone).unwrap_or_else(|| phone.to_string()))
+}
+
 // ============================================================================
 // Condition Evaluation
 // ============================================================================
 
-fn evaluate_condition(condition: &Condition, cart: &CartInput) -> bool {
-    let field_value = match get_field_value(&condition.field, cart) {
-        Some(v) => v,
-        None => return false,
+fn evaluate_condition(condition: &Condition, cart: &CartInput, line: Option<&LineItem>) -> Option<bool> {
+    let field_value = match (&condition.aggregate, line) {
+        // Inside a line filter there are no lines to aggregate
+        (Some(aggregate), Some(_)) => evaluate_aggregate(aggregate, &condition.field, &[]),
+        (Some(aggregate), None) => evaluate_aggregate(aggregate, &condition.field, &cart.line_items),
+        (None, Some(line)) => get_line_field(&condition.field, line),
+        (None, None) if condition.field == "now" => {
+            cart.today.map(|date| FieldValue::Today(date.resolve(condition.dates.offset)))
+        }
+        (None, None) => get_field_value(&condition.field, cart),
     };
 
-    compare(&field_value, &condition.operator, &condition.value, condition.is_preset)
+    // Presence checks are the only operators with a definite answer for
+    // missing data.
+    match condition.operator {
+        ComparisonOperator::Exists => return Some(field_value.is_some()),
+        ComparisonOperator::NotExists => return Some(field_value.is_none()),
+        ComparisonOperator::IsEmpty => {
+            return Some(field_value.as_ref().is_none_or(|v| is_empty(v, &condition.normalize)))
+        }
+        ComparisonOperator::IsNotEmpty => {
+            return Some(field_value.as_ref().is_some_and(|v| !is_empty(v, &condition.normalize)))
+        }
+        ComparisonOperator::IsValidPostcode => return check_postcode(field_value.as_ref(), condition, cart),
+        ComparisonOperator::IsInvalidPostcode => {
+            return check_postcode(field_value.as_ref(), condition, cart).map(|b| !b)
+        }
+        ComparisonOperator::IsValidPhone => return check_phone(field_value.as_ref(), condition, cart),
+        ComparisonOperator::IsInvalidPhone => return check_phone(field_value.as_ref(), condition, cart).map(|b| !b),
+        ComparisonOperator::InZipRanges => return check_postcode_ranges(field_value.as_ref(), condition, cart),
+        ComparisonOperator::ZipMatchesProvince => return check_region(field_value.as_ref(), condition, cart),
+        ComparisonOperator::NotZipMatchesProvince => {
+            return check_region(field_value.as_ref(), condition, cart).map(|b| !b)
+        }
+        ComparisonOperator::AddressLooksInvalid => return check_address(condition, cart),
+        // Need today's date when the value is "now"
+        ComparisonOperator::Before => return compare_dates(&field_value?, condition, cart, true),
+        ComparisonOperator::After => return compare_dates(&field_value?, condition, cart, false),
+        ComparisonOperator::WithinDays => return compare_within_days(&field_value?, condition, cart),
+        ComparisonOperator::IsMilitaryAddress => return address_parts(condition, cart).map(|p| is_military_address(&p)),
+        ComparisonOperator::IsNotMilitaryAddress => {
+            return address_parts(condition, cart).map(|p| !is_military_address(&p))
+        }
+        _ => {}
+    }
+
+    compare(&field_value?, condition)
 }
 
-fn compare(
-    field_value: &FieldValue,
-    operator: &ComparisonOperator,
-    condition_value: &serde_json::Value,
-    is_preset: bool,
-) -> bool {
-    match operator {
-        ComparisonOperator::Equals => compare_equals(field_value, condition_value),
-        ComparisonOperator::NotEquals => !compare_equals(field_value, condition_value),
+/// Compute an aggregate of `field` over the lines matching the filter.
+///
+/// `None` for `MIN`/`MAX` over no lines, or when a line has no numeric
+/// value for `SUM`/`MIN`/`MAX`. Lines whose filter is unknown don't
+/// contribute.
+fn evaluate_aggregate(aggregate: &Aggregate, field: &str, lines: &[LineItem]) -> Option<FieldValue> {
+    // Filter conditions see the line, not the cart (so no address either)
+    let no_cart = CartInput::default();
+    let matching = lines.iter().filter(|line| {
+        aggregate
+            .filter
+            .as_ref()
+            .is_none_or(|filter| evaluate_group(filter, &no_cart, Some(line)) == Some(true))
+    });
+
+    let result = match aggregate.function {
+        AggregateFunction::Count => matching.count() as f64,
+        AggregateFunction::CountDistinct => {
+            // Sorted and deduplicated like an `IN` set
+            let (mut texts, mut numbers) = (Vec::new(), Vec::new());
+            for line in matching {
+                match get_line_field(field, line) {
+                    Some(FieldValue::String(s)) => texts.push(s.to_lowercase()),
+                    Some(FieldValue::Number(n)) => numbers.push(n),
+                    _ => {}
+                }
+            }
+            ValueSet::new(texts, numbers).len() as f64
+        }
+        AggregateFunction::Sum | AggregateFunction::Min | AggregateFunction::Max => {
+            let mut values = Vec::new();
+            for line in matching {
+                match get_line_field(field, line) {
+                    Some(FieldValue::Number(n)) => values.push(n),
+                    _ => return None,
+                }
+            }
+            match aggregate.function {
+                AggregateFunction::Sum => values.iter().sum(),
+                AggregateFunction::Min => values.into_iter().reduce(f64::min)?,
+                _ => values.into_iter().reduce(f64::max)?,
+            }
+        }
+    };
+    Some(FieldValue::Number(result))
+}
+
+/// Check a postcode against its country's formats. The country is the
+/// condition value if given, otherwise the shipping address's.
+fn check_postcode(field_value: Option<&FieldValue>, condition: &Condition, cart: &CartInput) -> Option<bool> {
+    let Some(FieldValue::String(postcode)) = field_value else {
+        return None;
+    };
+    let country = match condition.value.as_str() {
+        Some(code) => code,
+        None if condition.field.starts_with("shipping_address.") => {
+            cart.shipping_address.country_code.as_deref()?
+        }
+        None => return None,
+    };
+    is_valid_postcode(country, postcode)
+}
+
+/// Check a phone number's length for its country. The country is the
+/// condition value if given, otherwise the shipping address's; numbers
+/// already in E.164 don't need one.
+fn check_phone(field_value: Option<&FieldValue>, condition: &Condition, cart: &CartInput) -> Option<bool> {
+    let Some(FieldValue::String(phone)) = field_value else {
+        return None;
+    };
+    let country = match condition.value.as_str() {
+        Some(code) => code,
+        None if condition.field.starts_with("shipping_address.") || is_phone_field(&condition.field) => {
+            cart.shipping_address.country_code.as_deref().unwrap_or("")
+        }
+        None => "",
+    };
+    is_valid_phone(country, phone)
+}
+
+/// Check a postcode against `IN_ZIP_RANGES`. The shipping address's
+/// country picks numeric or lexicographic comparison. Every range must be
+/// well formed.
+fn check_postcode_ranges(field_value: Option<&FieldValue>, condition: &Condition, cart: &CartInput) -> Option<bool> {
+    let Some(FieldValue::String(postcode)) = field_value else {
+        return None;
+    };
+    let country = match condition.field.starts_with("shipping_address.") {
+        true => cart.shipping_address.country_code.as_deref(),
+        false => None,
+    };
+    let ranges = condition.value.as_array()?.iter().map(postcode_range).collect::<Option<Vec<_>>>()?;
+    let mut result = Some(false);
+    for (low, high) in &ranges {
+        match in_postcode_range(country, postcode, low, high) {
+            Some(true) => return Some(true),
+            Some(false) => {}
+            None => result = None,
+        }
+    }
+    result
+}
+
+/// Check that a shipping address postcode belongs to the address's
+/// province.
+fn check_region(field_value: Option<&FieldValue>, condition: &Condition, cart: &CartInput) -> Option<bool> {
+    let Some(FieldValue::String(postcode)) = field_value else {
+        return None;
+    };
+    if !condition.field.starts_with("shipping_address.") {
+        return None;
+    }
+    let address = &cart.shipping_address;
+    zip_matches_province(address.country_code.as_deref()?, postcode, address.province_code.as_deref()?)
+}
+
+/// Run the selected address quality signals over the shipping address.
+fn check_address(condition: &Condition, cart: &CartInput) -> Option<bool> {
+    let signals = address_signals(&condition.value)?;
+    Some(address_looks_invalid(&signals, &address_parts(condition, cart)?))
+}
+
+/// The shipping address, for operators that read all of it. `None` on
+/// other fields, or when checkout gave no address.
+fn address_parts<'a>(condition: &Condition, cart: &'a CartInput) -> Option<AddressParts<'a>> {
+    if !condition.field.starts_with("shipping_address.") {
+        return None;
+    }
+    let address = &cart.shipping_address;
+    Some(AddressParts {
+        address1: address.address1.as_deref()?,
+        address2: address.address2.as_deref().unwrap_or_default(),
+        city: address.city.as_deref().unwrap_or_default(),
+        province_code: address.province_code.as_deref().unwrap_or_default(),
+        country_code: address.country_code.as_deref().unwrap_or_default(),
+    })
+}
+
+/// True for empty strings (after normalization, so `trim` makes
+/// whitespace-only values empty) and empty lists.
+fn is_empty(field_value: &FieldValue, norm: &Normalization) -> bool {
+    match field_value {
+        FieldValue::String(s) => norm.apply(s).is_empty(),
+        FieldValue::StringArray(arr) => arr.is_empty(),
+        FieldValue::Number(_) | FieldValue::Today(_) => false,
+    }
+}
+
+/// Returns `None` when the field and value can't be compared.
+fn compare(field_value: &FieldValue, condition: &Condition) -> Option<bool> {
+    let condition_value = &condition.value;
+    let norm = &condition.normalize;
+
+    match condition.operator {
+        ComparisonOperator::Equals | ComparisonOperator::In if is_phone_field(&condition.field) => {
+            compare_phone(field_value, condition)
+        }
+        ComparisonOperator::NotEquals | ComparisonOperator::NotIn if is_phone_field(&condition.field) => {
+            compare_phone(field_value, condition).map(|b| !b)
+        }
+        ComparisonOperator::Equals => compare_equals(field_value, condition_value, norm),
+        ComparisonOperator::NotEquals => compare_equals(field_value, condition_value, norm).map(|b| !b),
         ComparisonOperator::GreaterThan => compare_numeric(field_value, condition_value, |a, b| a > b),
         ComparisonOperator::GreaterThanOrEqual => compare_numeric(field_value, condition_value, |a, b| a >= b),
         ComparisonOperator::LessThan => compare_numeric(field_value, condition_value, |a, b| a < b),
         ComparisonOperator::LessThanOrEqual => compare_numeric(field_value, condition_value, |a, b| a <= b),
-        ComparisonOperator::Contains => compare_contains(field_value, condition_value),
-        ComparisonOperator::NotContains => !compare_contains(field_value, condition_value),
-        ComparisonOperator::StartsWith => compare_starts_with(field_value, condition_value),
-        ComparisonOperator::EndsWith => compare_ends_with(field_value, condition_value),
-        ComparisonOperator::RegexMatch => compare_regex(field_value, condition_value, is_preset),
-        ComparisonOperator::In => compare_in(field_value, condition_value),
-        ComparisonOperator::NotIn => !compare_in(field_value, condition_value),
+        ComparisonOperator::Contains => compare_contains(field_value, condition_value, norm),
+        ComparisonOperator::NotContains => compare_contains(field_value, condition_value, norm).map(|b| !b),
+        ComparisonOperator::StartsWith => compare_text(field_value, condition_value, norm, |s, cv| s.starts_with(cv)),
+        ComparisonOperator::EndsWith => compare_text(field_value, condition_value, norm, |s, cv| s.ends_with(cv)),
+        ComparisonOperator::RegexMatch => compare_regex(field_value, condition),
+        ComparisonOperator::In => compare_in(field_value, condition),
+        ComparisonOperator::NotIn => compare_in(field_value, condition).map(|b| !b),
+        ComparisonOperator::IsDisposableEmail => match field_value {
+            FieldValue::String(email) => is_disposable_email(email),
+            _ => None,
+        },
+        ComparisonOperator::EmailDomainIn => compare_email_domain(field_value, condition),
+        ComparisonOperator::NotEmailDomainIn => compare_email_domain(field_value, condition).map(|b| !b),
         ComparisonOperator::IsPoBox => compare_po_box(field_value),
-        ComparisonOperator::IsNotPoBox => !compare_po_box(field_value),
+        ComparisonOperator::IsNotPoBox => compare_po_box(field_value).map(|b| !b),
+        ComparisonOperator::Exists => Some(true),
+        ComparisonOperator::NotExists => Some(false),
+        ComparisonOperator::IsEmpty => Some(is_empty(field_value, norm)),
+        ComparisonOperator::IsNotEmpty => Some(!is_empty(field_value, norm)),
+        // Need the country, so also handled in evaluate_condition
+        ComparisonOperator::IsValidPostcode
+        | ComparisonOperator::IsInvalidPostcode
+        | ComparisonOperator::IsValidPhone
+        | ComparisonOperator::IsInvalidPhone
+        | ComparisonOperator::InZipRanges
+        | ComparisonOperator::ZipMatchesProvince
+        | ComparisonOperator::NotZipMatchesProvince
+        | ComparisonOperator::AddressLooksInvalid
+        | ComparisonOperator::IsMilitaryAddress
+        | ComparisonOperator::IsNotMilitaryAddress
+        | ComparisonOperator::Before
+        | ComparisonOperator::After
+        | ComparisonOperator::WithinDays => None,
+        ComparisonOperator::ContainsProfanity => compare_profanity(field_value, condition_value),
+        ComparisonOperator::NotContainsProfanity => compare_profanity(field_value, condition_value).map(|b| !b),
+        ComparisonOperator::ContainsAnyOf => compare_keywords(field_value, condition),
+        ComparisonOperator::ContainsNoneOf => compare_keywords(field_value, condition).map(|b| !b),
+        ComparisonOperator::MatchesPrefixAny => compare_prefixes(field_value, condition_value),
+        ComparisonOperator::DayOfWeekIn => compare_day_of_week(field_value, condition),
+        ComparisonOperator::Between => compare_ranges(field_value, numeric_range(condition_value).map(|r| vec![r])),
+        // Every range must be well formed, as with `IN_ZIP_RANGES`
+        ComparisonOperator::InRanges => compare_ranges(
+            field_value,
+            condition_value.as_array().and_then(|items| items.iter().map(numeric_range).collect()),
+        ),
     }
 }
 
-fn compare_equals(field_value: &FieldValue, condition_value: &serde_json::Value) -> bool {
+fn compare_equals(
+    field_value: &FieldValue,
+    condition_value: &serde_json::Value,
+    norm: &Normalization,
+) -> Option<bool> {
     match (field_value, condition_value) {
-        (FieldValue::String(s), serde_json::Value::String(cv)) => {
-            s.to_lowercase() == cv.to_lowercase()
-        }
+        (FieldValue::String(s), serde_json::Value::String(cv)) => Some(norm.apply(s) == norm.apply(cv)),
         (FieldValue::Number(n), serde_json::Value::Number(cv)) => {
-            cv.as_f64().map_or(false, |cv| (*n - cv).abs() < f64::EPSILON)
+            cv.as_f64().map(|cv| (*n - cv).abs() < f64::EPSILON)
         }
-        _ => false,
+        _ => None,
     }
 }
 
-fn compare_numeric<F>(field_value: &FieldValue, condition_value: &serde_json::Value, cmp: F) -> bool
+/// `EQUALS` / `IN` on a phone field: true if any way of writing the number
+/// is the value (or in the set).
+fn compare_phone(field_value: &FieldValue, condition: &Condition) -> Option<bool> {
+    let FieldValue::String(number) = field_value else {
+        return None;
+    };
+    let forms = phone_forms(number);
+    match condition.operator {
+        ComparisonOperator::In | ComparisonOperator::NotIn => {
+            let set = condition.set.as_ref()?;
+            Some(forms.iter().any(|form| set.contains_text(form)))
+        }
+        _ => Some(forms.contains(&phone_key(condition.value.as_str()?))),
+    }
+}
+
+fn compare_numeric<F>(field_value: &FieldValue, condition_value: &serde_json::Value, cmp: F) -> Option<bool>
 where
     F: Fn(f64, f64) -> bool,
 {
     match field_value {
-        FieldValue::Number(n) => condition_value.as_f64().map_or(false, |cv| cmp(*n, cv)),
-        _ => false,
+        FieldValue::Number(n) => condition_value.as_f64().map(|cv| cmp(*n, cv)),
+        _ => None,
     }
 }
 
-fn compare_contains(field_value: &FieldValue, condition_value: &serde_json::Value) -> bool {
+/// True if a number lies within any of the ranges.
+fn compare_ranges(field_value: &FieldValue, ranges: Option<Vec<NumericRange>>) -> Option<bool> {
+    match field_value {
+        FieldValue::Number(n) => Some(ranges?.iter().any(|range| range.contains(*n))),
+        _ => None,
+    }
+}
+
+/// A range for `BETWEEN` / `IN_RANGES`. Either bound may be omitted for an
+/// open-ended range; bounds are inclusive unless stated.
+struct NumericRange {
+    min: Option<f64>,
+    max: Option<f64>,
+    min_inclusive: bool,
+    max_inclusive: bool,
+}
+
+impl NumericRange {
+    fn contains(&self, n: f64) -> bool {
+        let above_min = self.min.is_none_or(|min| if self.min_inclusive { n >= min } else { n > min });
+        let below_max = self.max.is_none_or(|max| if self.max_inclusive { n <= max } else { n < max });
+        above_min && below_max
+    }
+}
+
+/// A `[min, max]` pair (inclusive), or an object with `min`/`max` and
+/// optional `min_inclusive`/`max_inclusive`. `None` if malformed.
+fn numeric_range(value: &serde_json::Value) -> Option<NumericRange> {
+    match value {
+        serde_json::Value::Array(pair) if pair.len() == 2 => Some(NumericRange {
+            min: Some(pair[0].as_f64()?),
+            max: Some(pair[1].as_f64()?),
+            min_inclusive: true,
+            max_inclusive: true,
+        }),
+        serde_json::Value::Object(range) => {
+            // A null bound is omitted; a null flag is malformed
+            let bound = |key: &str| match range.get(key) {
+                None | Some(serde_json::Value::Null) => Some(None),
+                Some(v) => v.as_f64().map(Some),
+            };
+            let flag = |key: &str| range.get(key).map_or(Some(true), serde_json::Value::as_bool);
+            let range = NumericRange {
+                min: bound("min")?,
+                max: bound("max")?,
+                min_inclusive: flag("min_inclusive")?,
+                max_inclusive: flag("max_inclusive")?,
+            };
+            (range.min.is_some() || range.max.is_some()).then_some(range)
+        }
+        _ => None,
+    }
+}
+
+fn compare_contains(
+    field_value: &FieldValue,
+    condition_value: &serde_json::Value,
+    norm: &Normalization,
+) -> Option<bool> {
     match (field_value, condition_value) {
         (FieldValue::String(s), serde_json::Value::String(cv)) => {
-            s.to_lowercase().contains(&cv.to_lowercase())
+            Some(norm.apply(s).contains(norm.apply(cv).as_ref()))
         }
         (FieldValue::StringArray(arr), serde_json::Value::String(cv)) => {
-            arr.iter().any(|s| s.to_lowercase() == cv.to_lowercase())
+            let cv = norm.apply(cv);
+            Some(arr.iter().any(|s| norm.apply(s) == cv))
         }
-        _ => false,
+        _ => None,
     }
 }
 
-fn compare_starts_with(field_value: &FieldValue, condition_value: &serde_json::Value) -> bool {
-    match (field_value, condition_value) {
-        (FieldValue::String(s), serde_json::Value::String(cv)) => {
-            s.to_lowercase().starts_with(&cv.to_lowercase())
-        }
-        _ => false,
+/// `CONTAINS` against a keyword list: a substring of text, or a member of a
+/// list field.
+fn compare_keywords(field_value: &FieldValue, condition: &Condition) -> Option<bool> {
+    let keywords = condition.keywords.as_ref()?;
+    let norm = &condition.normalize;
+    match field_value {
+        FieldValue::String(s) => Some(keywords.is_match(&norm.apply(s))),
+        FieldValue::StringArray(arr) => Some(arr.iter().any(|s| keywords.contains(&norm.apply(s)))),
+        _ => None,
     }
 }
 
-fn compare_ends_with(field_value: &FieldValue, condition_value: &serde_json::Value) -> bool {
+/// Compare a string field with a string value, both normalized.
+fn compare_text(
+    field_value: &FieldValue,
+    condition_value: &serde_json::Value,
+    norm: &Normalization,
+    cmp: fn(&str, &str) -> bool,
+) -> Option<bool> {
     match (field_value, condition_value) {
-        (FieldValue::String(s), serde_json::Value::String(cv)) => {
-            s.to_lowercase().ends_with(&cv.to_lowercase())
-        }
-        _ => false,
+        (FieldValue::String(s), serde_json::Value::String(cv)) => Some(cmp(&norm.apply(s), &norm.apply(cv))),
+        _ => None,
     }
 }
 
-fn compare_regex(field_value: &FieldValue, condition_value: &serde_json::Value, is_preset: bool) -> bool {
-    let pattern_str = match condition_value.as_str() {
-        Some(s) => s,
-        None => return false,
-    };
+fn compare_regex(field_value: &FieldValue, condition: &Condition) -> Option<bool> {
+    let pattern_str = condition.value.as_str()?;
 
+    // The pattern decides case sensitivity; other normalization applies
     let field_str = match field_value {
-        FieldValue::String(s) => s,
-        _ => return false,
+        FieldValue::String(s) => condition.normalize.apply_keep_case(s),
+        _ => return None,
     };
+    let field_str = field_str.as_ref();
 
-    if is_preset {
+    // Custom and named patterns are compiled by prepare_rules; rules whose
+    // pattern didn't compile are disabled before evaluation
+    #[cfg(feature = "regex")]
+    if let Some(re) = &condition.compiled {
+        return Some(re.is_match(field_str));
+    }
+
+    if condition.is_preset {
         return check_preset(pattern_str, field_str);
     }
 
-    // For non-preset patterns in vertical slice, just do contains check
-    field_str.to_lowercase().contains(&pattern_str.to_lowercase())
+    None
 }
 
-fn compare_in(field_value: &FieldValue, condition_value: &serde_json::Value) -> bool {
-    match condition_value {
-        serde_json::Value::Array(arr) => match field_value {
-            FieldValue::String(s) => arr.iter().any(|v| {
-                v.as_str().map_or(false, |vs| vs.to_lowercase() == s.to_lowercase())
-            }),
-            FieldValue::Number(n) => arr.iter().any(|v| {
-                v.as_f64().map_or(false, |vn| (*n - vn).abs() < f64::EPSILON)
-            }),
-            _ => false,
-        },
-        _ => false,
+fn compare_prefixes(field_value: &FieldValue, condition_value: &serde_json::Value) -> Option<bool> {
+    let prefixes = condition_value
+        .as_array()?
+        .iter()
+        .map(|v| v.as_str().and_then(postcode_prefix))
+        .collect::<Option<Vec<_>>>()?;
+    let matches = |s: &str| {
+        let s = canonical_postcode(s);
+        prefixes.iter().any(|prefix| s.starts_with(prefix.as_str()))
+    };
+    match field_value {
+        FieldValue::String(s) => Some(matches(s)),
+        FieldValue::StringArray(arr) => Some(arr.iter().any(|s| matches(s))),
+        _ => None,
     }
 }
 
-fn compare_po_box(field_value: &FieldValue) -> bool {
+fn compare_profanity(field_value: &FieldValue, condition_value: &serde_json::Value) -> Option<bool> {
+    let lists = word_lists(condition_value)?;
     match field_value {
-        FieldValue::String(s) => crate::patterns::is_po_box(s),
-        _ => false,
+        FieldValue::String(s) => Some(contains_profanity(s, &lists)),
+        FieldValue::StringArray(arr) => Some(arr.iter().any(|s| contains_profanity(s, &lists))),
+        _ => None,
+    }
+}
+
+/// Word lists for a language code, a list of codes, or every language when
+/// the value is omitted. `None` if any code is unknown.
+fn word_lists(value: &serde_json::Value) -> Option<Vec<&'static [&'static str]>> {
+    match value {
+        serde_json::Value::Null => Some(PROFANITY_LISTS.iter().map(|(_, list)| *list).collect()),
+        serde_json::Value::String(language) => profanity_list(language).map(|list| vec![list]),
+        serde_json::Value::Array(items) => items.iter().map(|v| v.as_str().and_then(profanity_list)).collect(),
+        _ => None,
+    }
+}
+
+fn address_signals(value: &serde_json::Value) -> Option<Vec<AddressSignal>> {
+    match value {
+        serde_json::Value::Null => Some(AddressSignal::ALL.to_vec()),
+        serde_json::Value::String(name) => AddressSignal::from_name(name).map(|s| vec![s]),
+        serde_json::Value::Array(items) => items.iter().map(|v| v.as_str().and_then(AddressSignal::from_name)).collect(),
+        _ => None,
+    }
+}
+
+/// True if the email's domain, or one it's a subdomain of, is in the set.
+fn compare_email_domain(field_value: &FieldValue, condition: &Condition) -> Option<bool> {
+    let (FieldValue::String(email), Some(set)) = (field_value, &condition.set) else {
+        return None;
+    };
+    let domain = email_domain(email)?;
+    let listed = parent_domains(&domain).any(|d| set.contains_text(d));
+    Some(listed)
+}
+
+fn compare_in(field_value: &FieldValue, condition: &Condition) -> Option<bool> {
+    let set = condition.set.as_ref()?;
+    match field_value {
+        FieldValue::String(s) => Some(set.contains_text(&condition.normalize.apply(s))),
+        FieldValue::Number(n) => Some(set.contains_number(*n)),
+        _ => None,
+    }
+}
+
+fn compare_po_box(field_value: &FieldValue) -> Option<bool> {
+    match field_value {
+        FieldValue::String(s) => Some(crate::patterns::is_po_box(s)),
+        _ => None,
+    }
+}
+
+
+// Dates. `now` is only known to the day, so it stands for the span of
+// moments from local midnight to the end of the day, and a comparison is
+// unknown unless the whole span agrees. Dates written without an offset
+// are read in the shop's timezone.
+
+/// A date value for `BEFORE`/`AFTER`: "now" or a date string.
+enum DateOperand {
+    Now,
+    At(DateTime),
+}
+
+fn date_operand(condition: &Condition) -> Option<DateOperand> {
+    let value = condition.value.as_str()?;
+    if value.trim().eq_ignore_ascii_case("now") {
+        return Some(DateOperand::Now);
+    }
+    let dates = condition.dates;
+    parse_date(value, dates.offset, dates.order).map(DateOperand::At)
+}
+
+/// The first and last moment, in nanoseconds since the epoch, a date
+/// field could be.
+fn date_span(field_value: &FieldValue, dates: DateSettings) -> Option<(i128, i128)> {
+    match field_value {
+        FieldValue::Today(midnight) => Some(day_span(midnight)),
+        FieldValue::String(s) => parse_date(s, dates.offset, dates.order).map(|date| (date.instant(), date.instant())),
+        _ => None,
+    }
+}
+
+fn day_span(midnight: &DateTime) -> (i128, i128) {
+    (midnight.instant(), midnight.instant() + days_span(1.0) - 1)
+}
+
+fn today_span(condition: &Condition, cart: &CartInput) -> Option<(i128, i128)> {
+    cart.today.map(|date| day_span(&date.resolve(condition.dates.offset)))
+}
+
+/// `BEFORE` (or `AFTER`, with `before` false).
+fn compare_dates(field_value: &FieldValue, condition: &Condition, cart: &CartInput, before: bool) -> Option<bool> {
+    let (low, high) = date_span(field_value, condition.dates)?;
+    let (other_low, other_high) = match date_operand(condition)? {
+        // Now isn't before or after itself
+        DateOperand::Now if matches!(field_value, FieldValue::Today(_)) => return Some(false),
+        DateOperand::Now => today_span(condition, cart)?,
+        DateOperand::At(date) => (date.instant(), date.instant()),
+    };
+    let (low, high, other_low, other_high) = match before {
+        true => (low, high, other_low, other_high),
+        false => (other_low, other_high, low, high),
+    };
+    if high < other_low {
+        Some(true)
+    } else if low >= other_high {
+        Some(false)
+    } else {
+        None
     }
 }
 
+/// True if the date is between now and now + `value` days. The date is
+/// within the span for every moment of today, for none, or it's unknown.
+fn compare_within_days(field_value: &FieldValue, condition: &Condition, cart: &CartInput) -> Option<bool> {
+    let span = days_span(condition.value.as_f64()?);
+    if let FieldValue::Today(_) = field_value {
+        return Some(true);
+    }
+    let (date, _) = date_span(field_value, condition.dates)?;
+    let (today_low, today_high) = today_span(condition, cart)?;
+    // The moments `now` could be for the date to be within the span
+    let (low, high) = match span >= 0 {
+        true => (date - span, date),
+        false => (date, date - span),
+    };
+    if low <= today_low && today_high <= high {
+        Some(true)
+    } else if today_high < low || high < today_low {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// True if the date, in its own offset, falls on one of the listed days.
+/// Today's weekday is the shop's.
+fn compare_day_of_week(field_value: &FieldValue, condition: &Condition) -> Option<bool> {
+    let days = weekdays(&condition.value)?;
+    let weekday = match field_value {
+        FieldValue::Today(midnight) => midnight.weekday(),
+        FieldValue::String(s) => parse_date(s, condition.dates.offset, condition.dates.order)?.weekday(),
+        _ => return None,
+    };
+    Some(days.contains(&weekday))
+}
+
+/// Every name in a list of weekdays, or `None` if any is unknown.
+fn weekdays(value: &serde_json::Value) -> Option<Vec<logicflow_core::dates::Weekday>> {
+    value.as_array()?.iter().map(|v| v.as_str().and_then(parse_weekday)).collect()
+}
@@ -3,72 +3,123 @@
 //! A simplified vertical slice that validates checkout based on rules
 //! stored in an app metafield.
 
-use serde::{Deserialize, Serialize};
-
+use logicflow_core::dates::{parse_date_parts, DateOrder};
+use logicflow_core::keywords::KeywordSet;
+use logicflow_core::sets::ValueSet;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+#[cfg(target_arch = "wasm32")]
+mod bump;
 mod evaluator;
+mod normalize;
 mod patterns;
+#[cfg(feature = "regex")]
+mod regex;
+mod unicode;
+
+use evaluator::{evaluate_rules, prepare_rules, Address, CartInput, DateSettings, LineItem};
 
-use evaluator::{evaluate_rules, CartInput, Address};
+#[cfg(target_arch = "wasm32")]
+#[global_allocator]
+static ALLOCATOR: bump::Bump = bump::Bump::new();
 
 // ============================================================================
 // Rules Configuration (loaded from metafield)
 // ============================================================================
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct RulesConfig {
     pub version: String,
-    #[serde(default)]
     pub total_complexity: u32,
     pub rules: Vec<Rule>,
+    /// Named custom patterns, referenced with `is_preset: true`. Names here
+    /// shadow built-in presets. Kept as raw JSON so one malformed entry
+    /// only disables the rules that use it.
+    pub regex_patterns: Map<String, Value>,
+    /// The shop's UTC offset, written by the app when it saves the config.
+    /// Offset-less dates are read in it. Defaults to UTC.
+    pub timezone_offset_minutes: i32,
+    /// How all-numeric slash dates such as `01/02/2024` are read.
+    pub date_order: DateOrder,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct Rule {
     pub id: String,
     pub name: String,
-    #[serde(default)]
     pub complexity: u32,
-    #[serde(default = "default_true")]
     pub enabled: bool,
     pub error_message: String,
     pub conditions: ConditionGroup,
+    /// Why the conditions couldn't be read (e.g. an operator this build
+    /// doesn't know). `prepare_rules` disables the rule.
+    pub read_error: Option<String>,
 }
 
-fn default_true() -> bool {
-    true
-}
-
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct ConditionGroup {
     pub operator: LogicalOperator,
     pub criteria: Vec<Criterion>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-#[serde(untagged)]
+#[derive(Debug, Clone)]
 pub enum Criterion {
     Condition(Condition),
     Group(ConditionGroup),
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LogicalOperator {
     And,
     Or,
+    /// Negation of the AND of the group's criteria.
+    Not,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct Condition {
     pub field: String,
     pub operator: ComparisonOperator,
-    pub value: serde_json::Value,
-    #[serde(default)]
+    pub value: Value,
     pub is_preset: bool,
+    /// How strings are normalized before comparison.
+    pub normalize: normalize::Normalization,
+    /// If set, `field` names a `line_item.*` field that is aggregated
+    /// across the cart's lines before comparison.
+    pub aggregate: Option<Aggregate>,
+    /// Custom pattern, compiled once by `prepare_rules`. Shared between
+    /// conditions that use the same named pattern.
+    #[cfg(feature = "regex")]
+    pub compiled: Option<std::rc::Rc<regex::Regex>>,
+    /// Keyword list for `CONTAINS_ANY_OF`, built once by `prepare_rules`.
+    pub keywords: Option<KeywordSet>,
+    /// Sorted `IN` set, built once by `prepare_rules`.
+    pub set: Option<ValueSet>,
+    /// How dates are read, copied from the config by `prepare_rules`.
+    pub dates: DateSettings,
+}
+
+/// An aggregate over line items, e.g. "sum of `line_item.line_total`
+/// where `line_item.vendor` = Acme".
+#[derive(Debug, Clone)]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    /// Only lines matching this group (of `line_item.*` conditions)
+    /// contribute.
+    pub filter: Option<ConditionGroup>,
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFunction {
+    Sum,
+    Count,
+    CountDistinct,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ComparisonOperator {
     Equals,
     NotEquals,
@@ -85,82 +136,272 @@ pub enum ComparisonOperator {
     NotIn,
     IsPoBox,
     IsNotPoBox,
+    /// The shipping address is a US military (APO/FPO/DPO) address: by
+    /// city, AA/AE/AP state code, or unit/PSC/CMR box line.
+    IsMilitaryAddress,
+    IsNotMilitaryAddress,
+    /// Email address is at a disposable mail domain or a subdomain of one.
+    IsDisposableEmail,
+    /// Email address's domain, or a parent domain, is in `value` (a list
+    /// or an encoded set, as with `IN`).
+    EmailDomainIn,
+    NotEmailDomainIn,
+    Exists,
+    NotExists,
+    IsEmpty,
+    IsNotEmpty,
+    /// Postal code fits a format for the address's `country_code` (or the
+    /// country code given as `value`). Unknown for countries with no
+    /// format on file.
+    IsValidPostcode,
+    IsInvalidPostcode,
+    /// Phone number has a valid length for its country. National numbers
+    /// are read in the address's country, or in `value` if given.
+    IsValidPhone,
+    IsInvalidPhone,
+    /// Text contains a word from the profanity lists for the languages in
+    /// `value` (a code or a list of codes; omitted means all).
+    ContainsProfanity,
+    NotContainsProfanity,
+    /// Text contains any of the strings in `value` (a list), matched in a
+    /// single pass. On list fields, any member equals one of them.
+    ContainsAnyOf,
+    ContainsNoneOf,
+    /// Postal code lies within any of a list of `[low, high]` ranges:
+    /// numeric for all-digit countries such as the US, lexicographic
+    /// otherwise.
+    InZipRanges,
+    /// Text starts with any of a list of prefixes, ignoring case, spaces
+    /// and hyphens. A trailing `*` is allowed (`"902*"`).
+    MatchesPrefixAny,
+    /// Date is strictly before the value (a date string or "now").
+    Before,
+    /// Date is strictly after the value (a date string or "now").
+    After,
+    /// Date is within `value` days of now; negative looks backwards.
+    WithinDays,
+    /// Date falls on one of a list of weekdays (`["sat", "sunday"]`).
+    DayOfWeekIn,
+    /// Number lies within a range: `[5, 10]` (inclusive) or
+    /// `{"min": 5, "max": 10, "max_inclusive": false}`.
+    Between,
+    /// Number lies within any of a list of ranges (same shapes as
+    /// `BETWEEN`). A malformed range leaves the condition unknown.
+    InRanges,
+    /// The shipping address's zip belongs to its `province_code` (US ZIP3
+    /// to state, Canadian FSA to province). Unknown for other countries.
+    ZipMatchesProvince,
+    NotZipMatchesProvince,
+    /// The shipping address shows signs of junk input. `value` is a signal
+    /// name or a list of them; omitted means every signal.
+    AddressLooksInvalid,
 }
 
 // ============================================================================
-// Shopify Function Input/Output
+// Reading JSON
 // ============================================================================
+//
+// The config and the function input are read from a `serde_json::Value`
+// by hand. Derived `Deserialize` impls cost 1-2 KB of the function per
+// struct, and the function is close to Shopify's 256 KB limit.
+
+/// `key` of an object; `null` counts as absent.
+fn get<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    value.get(key).filter(|v| !v.is_null())
+}
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct Input {
-    cart: Cart,
-    shop: Option<Shop>,
+fn get_str<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    get(value, key).and_then(Value::as_str)
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct Cart {
-    cost: CartCost,
-    lines: Vec<CartLine>,
-    buyer_identity: Option<BuyerIdentity>,
-    delivery_groups: Vec<DeliveryGroup>,
+fn read_string(value: &Value, key: &str) -> Result<String, String> {
+    get_str(value, key)
+        .map(str::to_string)
+        .ok_or_else(|| format!("missing or invalid field `{}`", key))
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct CartCost {
-    total_amount: Money,
-    subtotal_amount: Money,
+/// An optional field: `default` when absent, an error when it has the
+/// wrong type.
+fn read_or<T>(value: &Value, key: &str, default: T, read: fn(&Value) -> Option<T>) -> Result<T, String> {
+    match get(value, key) {
+        None => Ok(default),
+        Some(v) => read(v).ok_or_else(|| format!("invalid field `{}`", key)),
+    }
 }
 
-#[derive(Deserialize)]
-struct Money {
-    amount: String,
+fn read_list<T>(value: &Value, key: &str, read: fn(&Value) -> Result<T, String>) -> Result<Vec<T>, String> {
+    get(value, key)
+        .and_then(Value::as_array)
+        .ok_or_else(|| format!("missing or invalid field `{}`", key))?
+        .iter()
+        .map(read)
+        .collect()
 }
 
-#[derive(Deserialize)]
-struct CartLine {
-    quantity: i32,
+fn read_u32(value: &Value) -> Option<u32> {
+    value.as_u64().and_then(|n| u32::try_from(n).ok())
 }
 
-#[derive(Deserialize)]
-struct BuyerIdentity {
-    customer: Option<Customer>,
+impl RulesConfig {
+    pub fn read(value: &Value) -> Result<Self, String> {
+        Ok(RulesConfig {
+            version: read_string(value, "version")?,
+            total_complexity: read_or(value, "total_complexity", 0, read_u32)?,
+            rules: read_list(value, "rules", Rule::read)?,
+            regex_patterns: read_or(value, "regex_patterns", Map::new(), |v| v.as_object().cloned())?,
+            timezone_offset_minutes: read_or(value, "timezone_offset_minutes", 0, |v| {
+                v.as_i64().filter(|m| m.abs() < 24 * 60).map(|m| m as i32)
+            })?,
+            date_order: read_or(value, "date_order", DateOrder::MonthFirst, |v| {
+                v.as_str().and_then(DateOrder::from_name)
+            })?,
+        })
+    }
 }
 
-#[derive(Deserialize)]
-struct Customer {
-    id: Option<String>,
+impl Rule {
+    /// Conditions that can't be read only cost the rule itself, so a
+    /// config from a newer app version still runs its other rules.
+    fn read(value: &Value) -> Result<Self, String> {
+        let conditions = get(value, "conditions")
+            .ok_or_else(|| "missing field `conditions`".to_string())
+            .and_then(ConditionGroup::read);
+        let (conditions, read_error) = match conditions {
+            Ok(conditions) => (conditions, None),
+            Err(e) => (ConditionGroup { operator: LogicalOperator::And, criteria: vec![] }, Some(e)),
+        };
+        Ok(Rule {
+            id: read_string(value, "id")?,
+            name: read_string(value, "name")?,
+            complexity: read_or(value, "complexity", 0, read_u32)?,
+            enabled: read_or(value, "enabled", true, Value::as_bool)?,
+            error_message: read_string(value, "error_message")?,
+            conditions,
+            read_error,
+        })
+    }
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct DeliveryGroup {
-    delivery_address: Option<DeliveryAddress>,
+impl ConditionGroup {
+    fn read(value: &Value) -> Result<Self, String> {
+        let operator = read_string(value, "operator")?;
+        Ok(ConditionGroup {
+            operator: LogicalOperator::from_name(&operator)
+                .ok_or_else(|| format!("unknown logical operator `{}`", operator))?,
+            criteria: read_list(value, "criteria", Criterion::read)?,
+        })
+    }
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct DeliveryAddress {
-    address1: Option<String>,
-    address2: Option<String>,
-    city: Option<String>,
-    province_code: Option<String>,
-    country_code: Option<String>,
-    zip: Option<String>,
+impl Criterion {
+    /// A group is the criterion with `criteria`.
+    fn read(value: &Value) -> Result<Self, String> {
+        if get(value, "criteria").is_some() {
+            return ConditionGroup::read(value).map(Criterion::Group);
+        }
+        let operator = read_string(value, "operator")?;
+        Ok(Criterion::Condition(Condition {
+            field: read_string(value, "field")?,
+            operator: ComparisonOperator::from_name(&operator)
+                .ok_or_else(|| format!("unknown operator `{}`", operator))?,
+            value: value.get("value").cloned().unwrap_or(Value::Null),
+            is_preset: read_or(value, "is_preset", false, Value::as_bool)?,
+            normalize: read_or(value, "normalize", Default::default(), normalize::Normalization::read)?,
+            aggregate: get(value, "aggregate").map(Aggregate::read).transpose()?,
+            #[cfg(feature = "regex")]
+            compiled: None,
+            keywords: None,
+            set: None,
+            dates: DateSettings::default(),
+        }))
+    }
 }
 
-#[derive(Deserialize)]
-struct Shop {
-    metafield: Option<Metafield>,
+impl Aggregate {
+    fn read(value: &Value) -> Result<Self, String> {
+        let function = read_string(value, "function")?;
+        Ok(Aggregate {
+            function: match function.as_str() {
+                "SUM" => AggregateFunction::Sum,
+                "COUNT" => AggregateFunction::Count,
+                "COUNT_DISTINCT" => AggregateFunction::CountDistinct,
+                "MIN" => AggregateFunction::Min,
+                "MAX" => AggregateFunction::Max,
+                _ => return Err(format!("unknown aggregate function `{}`", function)),
+            },
+            filter: get(value, "filter").map(ConditionGroup::read).transpose()?,
+        })
+    }
 }
 
-#[derive(Deserialize)]
-struct Metafield {
-    value: String,
+impl LogicalOperator {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "AND" => LogicalOperator::And,
+            "OR" => LogicalOperator::Or,
+            "NOT" => LogicalOperator::Not,
+            _ => return None,
+        })
+    }
 }
 
+impl ComparisonOperator {
+    /// The operator spelled `name` in the config.
+    fn from_name(name: &str) -> Option<Self> {
+        use ComparisonOperator::*;
+        Some(match name {
+            "EQUALS" => Equals,
+            "NOT_EQUALS" => NotEquals,
+            "GREATER_THAN" => GreaterThan,
+            "GREATER_THAN_OR_EQUAL" => GreaterThanOrEqual,
+            "LESS_THAN" => LessThan,
+            "LESS_THAN_OR_EQUAL" => LessThanOrEqual,
+            "CONTAINS" => Contains,
+            "NOT_CONTAINS" => NotContains,
+            "STARTS_WITH" => StartsWith,
+            "ENDS_WITH" => EndsWith,
+            "REGEX_MATCH" => RegexMatch,
+            "IN" => In,
+            "NOT_IN" => NotIn,
+            "IS_PO_BOX" => IsPoBox,
+            "IS_NOT_PO_BOX" => IsNotPoBox,
+            "IS_MILITARY_ADDRESS" => IsMilitaryAddress,
+            "IS_NOT_MILITARY_ADDRESS" => IsNotMilitaryAddress,
+            "IS_DISPOSABLE_EMAIL" => IsDisposableEmail,
+            "EMAIL_DOMAIN_IN" => EmailDomainIn,
+            "NOT_EMAIL_DOMAIN_IN" => NotEmailDomainIn,
+            "EXISTS" => Exists,
+            "NOT_EXISTS" => NotExists,
+            "IS_EMPTY" => IsEmpty,
+            "IS_NOT_EMPTY" => IsNotEmpty,
+            "IS_VALID_POSTCODE" => IsValidPostcode,
+            "IS_INVALID_POSTCODE" => IsInvalidPostcode,
+            "IS_VALID_PHONE" => IsValidPhone,
+            "IS_INVALID_PHONE" => IsInvalidPhone,
+            "CONTAINS_PROFANITY" => ContainsProfanity,
+            "NOT_CONTAINS_PROFANITY" => NotContainsProfanity,
+            "CONTAINS_ANY_OF" => ContainsAnyOf,
+            "CONTAINS_NONE_OF" => ContainsNoneOf,
+            "IN_ZIP_RANGES" => InZipRanges,
+            "MATCHES_PREFIX_ANY" => MatchesPrefixAny,
+            "BEFORE" => Before,
+            "AFTER" => After,
+            "WITHIN_DAYS" => WithinDays,
+            "DAY_OF_WEEK_IN" => DayOfWeekIn,
+            "BETWEEN" => Between,
+            "IN_RANGES" => InRanges,
+            "ZIP_MATCHES_PROVINCE" => ZipMatchesProvince,
+            "NOT_ZIP_MATCHES_PROVINCE" => NotZipMatchesProvince,
+            "ADDRESS_LOOKS_INVALID" => AddressLooksInvalid,
+            _ => return None,
+        })
+    }
+}
+
+// ============================================================================
+// Shopify Function Input/Output
+// ============================================================================
+
 #[derive(Serialize)]
 struct Output {
     errors: Vec<FunctionError>,
@@ -178,11 +419,14 @@ struct FunctionError {
 // ============================================================================
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Read input from stdin
-    let input: Input = serde_json::from_reader(std::io::stdin())?;
+    // Read input from stdin. Parsing from a string shares the deserializer
+    // with the config; `from_reader` would add a second copy (~7 KB).
+    let mut buffer = String::new();
+    std::io::Read::read_to_string(&mut std::io::stdin(), &mut buffer)?;
+    let input: Value = serde_json::from_str(&buffer)?;
     
     // Process the input
-    let output = process_input(input);
+    let output = process_input(&input);
     
     // Write output to stdout
     serde_json::to_writer(std::io::stdout(), &output)?;
@@ -190,11 +434,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn process_input(input: Input) -> Output {
+fn process_input(input: &Value) -> Output {
     // Parse rules configuration from metafield
-    let config = match input.shop.as_ref().and_then(|s| s.metafield.as_ref()) {
+    let mut config = match input.pointer("/shop/metafield/value").and_then(Value::as_str) {
         Some(metafield) => {
-            match serde_json::from_str::<RulesConfig>(&metafield.value) {
+            match serde_json::from_str(metafield).map_err(|e| e.to_string()).and_then(|v| RulesConfig::read(&v)) {
                 Ok(c) => c,
                 Err(e) => {
                     eprintln!("Gatekeep: Failed to parse config: {}", e);
@@ -208,6 +452,12 @@ fn process_input(input: Input) -> Output {
         }
     };
 
+    // Compile custom patterns up front; rules we can't run faithfully are
+    // disabled rather than mis-evaluated
+    for warning in prepare_rules(&mut config) {
+        eprintln!("Gatekeep: Disabled {}", warning);
+    }
+
     // Skip if no rules
     if config.rules.is_empty() {
         eprintln!("Gatekeep: No rules configured");
@@ -215,7 +465,7 @@ fn process_input(input: Input) -> Output {
     }
 
     // Build cart input from Shopify data
-    let cart_input = build_cart_input(&input);
+    let cart_input = build_cart_input(input);
 
     // Evaluate rules
     let result = evaluate_rules(&config, &cart_input);
@@ -239,50 +489,516 @@ fn process_input(input: Input) -> Output {
     Output { errors }
 }
 
+/// Parse a `Decimal` amount string, or 0 if it isn't a number. Uses
+/// serde_json's float parser, which is linked anyway; `str::parse` would
+/// add a second one (~4 KB).
+fn parse_amount(amount: &str) -> f64 {
+    serde_json::from_str(amount).unwrap_or(0.0)
+}
+
 /// Build a CartInput struct from the Shopify input data
-fn build_cart_input(input: &Input) -> CartInput {
-    let cart = &input.cart;
+fn build_cart_input(input: &Value) -> CartInput {
+    let cart = get(input, "cart").unwrap_or(&Value::Null);
+    let text = |path: &str| cart.pointer(path).and_then(Value::as_str);
 
     // Get total
-    let total = cart
-        .cost
-        .total_amount
-        .amount
-        .parse::<f64>()
-        .unwrap_or(0.0);
-
-    let subtotal = cart
-        .cost
-        .subtotal_amount
-        .amount
-        .parse::<f64>()
-        .unwrap_or(0.0);
+    let total = parse_amount(text("/cost/totalAmount/amount").unwrap_or(""));
+    let subtotal = parse_amount(text("/cost/subtotalAmount/amount").unwrap_or(""));
 
     // Calculate quantity
-    let quantity: u32 = cart.lines.iter().map(|l| l.quantity as u32).sum();
+    let lines = get(cart, "lines").and_then(Value::as_array).map_or(&[][..], Vec::as_slice);
+    let quantity: u32 = lines
+        .iter()
+        .filter_map(|l| get(l, "quantity").and_then(Value::as_u64))
+        .map(|q| q as u32)
+        .sum();
 
     // Get shipping address from first delivery group
     let address = cart
-        .delivery_groups
-        .first()
-        .and_then(|dg| dg.delivery_address.as_ref())
-        .map(|da| Address {
-            address1: da.address1.clone().unwrap_or_default(),
-            address2: da.address2.clone().unwrap_or_default(),
-            city: da.city.clone().unwrap_or_default(),
-            province_code: da.province_code.clone().unwrap_or_default(),
-            country_code: da.country_code.clone().unwrap_or_default(),
-            zip: da.zip.clone().unwrap_or_default(),
+        .pointer("/deliveryGroups/0/deliveryAddress")
+        .filter(|da| da.is_object())
+        .map(|da| {
+            let field = |key: &str| get_str(da, key).map(str::to_string);
+            Address {
+                address1: field("address1"),
+                address2: field("address2"),
+                city: field("city"),
+                province_code: field("provinceCode"),
+                country_code: field("countryCode"),
+                zip: field("zip"),
+                phone: field("phone"),
+            }
         })
         .unwrap_or_default();
 
+    // Line items, for aggregate conditions. Merchandise other than a
+    // product variant leaves the variant and product fields empty.
+    let line_items = lines
+        .iter()
+        .map(|line| {
+            let field = |path: &str| line.pointer(path).and_then(Value::as_str).unwrap_or("").to_string();
+            LineItem {
+                product_id: field("/merchandise/product/id"),
+                variant_id: field("/merchandise/id"),
+                sku: field("/merchandise/sku"),
+                vendor: field("/merchandise/product/vendor"),
+                quantity: get(line, "quantity").and_then(Value::as_u64).unwrap_or(0) as u32,
+                price: parse_amount(&field("/cost/amountPerQuantity/amount")),
+            }
+        })
+        .collect();
+
+    // Attributes the query asked for and the cart has, aliased
+    // `attribute1`, `attribute2`, ...
+    let attributes = cart
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(alias, _)| alias.starts_with("attribute"))
+        .filter_map(|(_, a)| Some((get_str(a, "key")?.to_string(), get_str(a, "value")?.to_string())))
+        .collect();
+
+    // The queried tags the customer has
+    let customer_tags = cart
+        .pointer("/buyerIdentity/customer/hasTags")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .filter(|t| get(t, "hasTag").and_then(Value::as_bool) == Some(true))
+        .filter_map(|t| get_str(t, "tag").map(str::to_string))
+        .collect();
+
     CartInput {
         total,
         subtotal,
         quantity,
         total_weight: 0.0,
-        customer_tags: vec![],
+        customer_tags,
+        customer_email: text("/buyerIdentity/email").map(str::to_string),
+        customer_phone: text("/buyerIdentity/phone").map(str::to_string),
+        attributes,
         shipping_address: address,
-        line_items: vec![],
+        line_items,
+        // The shop's date; functions don't see the time of day
+        today: input
+            .pointer("/shop/localTime/date")
+            .and_then(Value::as_str)
+            .and_then(|date| parse_date_parts(date, DateOrder::MonthFirst)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// The same cart as the POC sees it.
+    fn poc_cart(input: &Value) -> logicflow::CartInput {
+        let cart = build_cart_input(input);
+        let address = &cart.shipping_address;
+        let text = |field: &Option<String>| field.clone().unwrap_or_default();
+        logicflow::CartInput {
+            total: cart.total,
+            subtotal: cart.subtotal,
+            quantity: cart.quantity,
+            customer_tags: cart.customer_tags.clone(),
+            customer_email: cart.customer_email.clone(),
+            customer_phone: cart.customer_phone.clone(),
+            attributes: cart.attributes.iter().cloned().collect(),
+            shipping_address: address.address1.as_ref().map(|_| logicflow::models::Address {
+                address1: text(&address.address1),
+                address2: text(&address.address2),
+                city: text(&address.city),
+                province_code: text(&address.province_code),
+                country_code: text(&address.country_code),
+                zip: text(&address.zip),
+                phone: text(&address.phone),
+                ..Default::default()
+            }),
+            line_items: cart
+                .line_items
+                .iter()
+                .map(|line| logicflow::LineItem {
+                    product_id: line.product_id.clone(),
+                    variant_id: line.variant_id.clone(),
+                    sku: line.sku.clone(),
+                    vendor: line.vendor.clone(),
+                    quantity: line.quantity,
+                    price: line.price,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Run `rules` through the function and the POC against the same cart,
+    /// check they agree, and return the messages of the rules that fired.
+    fn fire(rules: Value, cart: Value) -> Vec<String> {
+        fire_with(json!({ "version": "1.0", "rules": rules }), cart, None)
+    }
+
+    /// `fire` with a whole config, on `today` (`YYYY-MM-DD`) in the shop.
+    /// The POC runs at noon, since the function only knows the day.
+    fn fire_with(config: Value, cart: Value, today: Option<&str>) -> Vec<String> {
+        let mut input = json!({
+            "cart": {
+                "cost": { "totalAmount": { "amount": "0.0" }, "subtotalAmount": { "amount": "0.0" } },
+                "lines": [],
+                "deliveryGroups": []
+            },
+            "shop": { "metafield": { "value": config.to_string() } }
+        });
+        input["cart"].as_object_mut().unwrap().extend(cart.as_object().unwrap().clone());
+        if let Some(date) = today {
+            input["shop"]["localTime"] = json!({ "date": date });
+        }
+
+        let poc_config: logicflow::RulesConfig = serde_json::from_value(config).unwrap();
+        let mut cart = poc_cart(&input);
+        let dates = logicflow::dates::DateSettings::from_config(&poc_config);
+        cart.now = today.zip(dates.offset).and_then(|(date, offset)| {
+            logicflow::dates::parse_date(&format!("{}T12:00:00", date), offset, dates.order)
+        });
+        let poc: Vec<String> = logicflow::evaluate_rules(&poc_config, &cart)
+            .errors
+            .into_iter()
+            .map(|e| e.message)
+            .collect();
+        let function: Vec<String> = process_input(&input).errors.into_iter().map(|e| e.localized_message).collect();
+        assert_eq!(function, poc, "function and POC disagree");
+        function
+    }
+
+    fn rule(id: &str, criteria: Value) -> Value {
+        json!({
+            "id": id,
+            "name": id,
+            "error_message": id,
+            "conditions": { "operator": "AND", "criteria": criteria }
+        })
+    }
+
+    fn line(sku: &str, vendor: &str, quantity: i32, price: &str) -> Value {
+        json!({
+            "quantity": quantity,
+            "merchandise": { "id": format!("gid://shopify/ProductVariant/{}", sku), "sku": sku, "product": { "id": "gid://shopify/Product/1", "vendor": vendor } },
+            "cost": { "amountPerQuantity": { "amount": price } }
+        })
+    }
+
+    #[test]
+    fn test_aggregates_match_poc() {
+        let cart = json!({
+            "lines": [
+                line("A-1", "Acme", 2, "50.0"),
+                line("A-1", "Acme", 1, "60.0"),
+                line("O-1", "Other", 10, "100.0"),
+                { "quantity": 1, "merchandise": {}, "cost": { "amountPerQuantity": { "amount": "5.0" } } }
+            ]
+        });
+        let acme = json!({
+            "operator": "AND",
+            "criteria": [{ "field": "line_item.vendor", "operator": "EQUALS", "value": "acme" }]
+        });
+        let rules = json!([
+            rule("acme_under_200", json!([{
+                "field": "line_item.line_total", "operator": "LESS_THAN", "value": 200,
+                "aggregate": { "function": "SUM", "filter": acme }
+            }])),
+            rule("distinct_skus", json!([{
+                "field": "line_item.sku", "operator": "EQUALS", "value": 3,
+                "aggregate": { "function": "COUNT_DISTINCT" }
+            }])),
+            rule("max_quantity", json!([{
+                "field": "line_item.quantity", "operator": "GREATER_THAN_OR_EQUAL", "value": 10,
+                "aggregate": { "function": "MAX" }
+            }])),
+            rule("cheapest", json!([{
+                "field": "line_item.price", "operator": "LESS_THAN", "value": 10,
+                "aggregate": { "function": "MIN" }
+            }])),
+            rule("count", json!([{
+                "field": "line_item.sku", "operator": "EQUALS", "value": 4,
+                "aggregate": { "function": "COUNT" }
+            }])),
+            rule("no_lines_match", json!([{
+                "field": "line_item.price", "operator": "LESS_THAN", "value": 1000,
+                "aggregate": { "function": "MIN", "filter": {
+                    "operator": "AND",
+                    "criteria": [{ "field": "line_item.vendor", "operator": "EQUALS", "value": "Nobody" }]
+                } }
+            }])),
+            // Only line item fields are visible inside a filter
+            rule("cart_field_in_filter", json!([{
+                "field": "line_item.sku", "operator": "GREATER_THAN", "value": 0,
+                "aggregate": { "function": "COUNT", "filter": {
+                    "operator": "AND",
+                    "criteria": [{ "field": "cart.total", "operator": "EXISTS", "value": null }]
+                } }
+            }])),
+        ]);
+
+        assert_eq!(fire(rules, cart), ["acme_under_200", "distinct_skus", "max_quantity", "cheapest", "count"]);
+    }
+
+    fn condition(field: &str, operator: &str, value: Value) -> Value {
+        json!([{ "field": field, "operator": operator, "value": value }])
+    }
+
+    #[test]
+    fn test_dates_match_poc() {
+        // A Saturday, in New York in summer
+        let config = |rules: Value, order: &str| {
+            json!({ "version": "1.0", "rules": rules, "timezone_offset_minutes": -240, "date_order": order })
+        };
+        let cart = json!({ "attribute1": { "key": "delivery_date", "value": "06/20/2024" } });
+        let delivery = "cart.attributes.delivery_date";
+        let rules = json!([
+            rule("weekend", condition("now", "DAY_OF_WEEK_IN", json!(["sat", "SUNDAY"]))),
+            rule("monday", condition("now", "DAY_OF_WEEK_IN", json!(["mon"]))),
+            rule("before_tomorrow", condition("now", "BEFORE", json!("2024-06-16"))),
+            rule("before_today", condition("now", "BEFORE", json!("2024-06-15"))),
+            rule("after_last_night", condition("now", "AFTER", json!("2024-06-15T01:00:00Z"))),
+            rule("now_before_now", condition("now", "BEFORE", json!("now"))),
+            rule("now_within_now", condition("now", "WITHIN_DAYS", json!(0))),
+            rule("delivery_after_now", condition(delivery, "AFTER", json!("now"))),
+            rule("delivery_before_now", condition(delivery, "BEFORE", json!("NOW"))),
+            rule("delivery_within_week", condition(delivery, "WITHIN_DAYS", json!(7))),
+            rule("delivery_within_5", condition(delivery, "WITHIN_DAYS", json!(5))),
+            rule("delivery_within_4", condition(delivery, "WITHIN_DAYS", json!(4))),
+            rule("delivery_last_week", condition(delivery, "WITHIN_DAYS", json!(-7))),
+            rule("delivery_thursday", condition(delivery, "DAY_OF_WEEK_IN", json!(["thu"]))),
+            rule("unknown_weekday", condition("now", "DAY_OF_WEEK_IN", json!(["sat", "someday"]))),
+            rule("not_a_date", condition("now", "BEFORE", json!("next tuesday"))),
+        ]);
+
+        assert_eq!(
+            fire_with(config(rules.clone(), "MONTH_FIRST"), cart.clone(), Some("2024-06-15")),
+            [
+                "weekend",
+                "before_tomorrow",
+                "after_last_night",
+                "now_within_now",
+                "delivery_after_now",
+                "delivery_within_week",
+                "delivery_within_5",
+                "delivery_thursday",
+            ]
+        );
+
+        // 01/02/2024 is 1 February, a Thursday, when days come first
+        let cart = json!({ "attribute2": { "key": "Delivery Date", "value": "01/02/2024" } });
+        let delivery = "cart.attributes.Delivery Date";
+        let rules = json!([rule("delivery_thursday", condition(delivery, "DAY_OF_WEEK_IN", json!(["thu"])))]);
+        assert_eq!(fire_with(config(rules.clone(), "DAY_FIRST"), cart.clone(), None), ["delivery_thursday"]);
+        assert!(fire_with(config(rules, "MONTH_FIRST"), cart, None).is_empty());
+    }
+
+    #[test]
+    fn test_queries_checkout_attributes() {
+        let query = include_str!("run.graphql");
+        let keys: Vec<&str> = query
+            .split("attribute(key: \"")
+            .skip(1)
+            .filter_map(|rest| rest.split('"').next())
+            .collect();
+        assert_eq!(keys, logicflow_core::attributes::CHECKOUT_ATTRIBUTES);
+    }
+
+    #[test]
+    fn test_unavailable_fields_disable_the_rule() {
+        let value = json!({
+            "version": "1.0",
+            "rules": [
+                rule("created", condition("customer.created_at", "BEFORE", json!("now"))),
+                rule("unqueried", condition("cart.attributes.gift_wrap", "EQUALS", json!("yes"))),
+                rule("collections", json!([{
+                    "field": "line_item.collections", "operator": "GREATER_THAN", "value": 0,
+                    "aggregate": { "function": "COUNT" }
+                }])),
+                rule("queried", condition("cart.attributes.gift_message", "EXISTS", Value::Null)),
+            ]
+        });
+        let mut config = RulesConfig::read(&value).unwrap();
+        let warnings = prepare_rules(&mut config);
+        assert_eq!(config.rules.iter().map(|r| r.enabled).collect::<Vec<_>>(), [false, false, false, true]);
+        assert!(warnings[0].contains("customer.created_at isn't available"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn test_time_of_day_is_unknown() {
+        // The function knows the date but not the time, so neither a
+        // comparison nor its negation can fire
+        let before_six = json!([{ "field": "now", "operator": "BEFORE", "value": "2024-06-15T18:00:00" }]);
+        let rules = json!([
+            rule("before_six", before_six.clone()),
+            rule("not_before_six", json!([{ "operator": "NOT", "criteria": before_six }])),
+        ]);
+        let config = json!({ "version": "1.0", "rules": rules, "timezone_offset_minutes": -240 });
+        let input = json!({
+            "cart": { "lines": [] },
+            "shop": { "localTime": { "date": "2024-06-15" }, "metafield": { "value": config.to_string() } }
+        });
+        assert!(process_input(&input).errors.is_empty());
+    }
+
+    #[test]
+    fn test_bad_dates_disable_the_rule() {
+        let value = json!({
+            "version": "1.0",
+            "rules": [
+                rule("unknown_weekday", condition("now", "DAY_OF_WEEK_IN", json!(["someday"]))),
+                rule("not_a_date", condition("now", "AFTER", json!("31/31/2024"))),
+                rule("fine", condition("now", "WITHIN_DAYS", json!(1))),
+            ]
+        });
+        let mut config = RulesConfig::read(&value).unwrap();
+        assert_eq!(prepare_rules(&mut config).len(), 2);
+        assert_eq!(config.rules.iter().map(|r| r.enabled).collect::<Vec<_>>(), [false, false, true]);
+
+        let bad_offset = json!({ "version": "1.0", "rules": [], "timezone_offset_minutes": 1440 });
+        assert!(RulesConfig::read(&bad_offset).is_err());
+    }
+
+    #[test]
+    fn test_customer_tags_match_poc() {
+        let cart = json!({
+            "buyerIdentity": { "customer": { "hasTags": [
+                { "tag": "wholesale", "hasTag": true },
+                { "tag": "vip", "hasTag": false },
+                { "tag": "staff", "hasTag": true }
+            ] } }
+        });
+        let tags = |operator: &str, value: Value| condition("customer.tags", operator, value);
+        let rules = json!([
+            rule("wholesale", tags("CONTAINS", json!("Wholesale"))),
+            rule("vip", tags("CONTAINS", json!("vip"))),
+            rule("any_of", tags("CONTAINS_ANY_OF", json!(["vip", "staff"]))),
+            rule("partial", tags("CONTAINS_ANY_OF", json!(["whole"]))),
+            rule("not_vip", tags("NOT_CONTAINS", json!("vip"))),
+        ]);
+
+        assert_eq!(fire(rules.clone(), cart), ["wholesale", "any_of", "not_vip"]);
+        // A guest checkout has no tags
+        assert_eq!(fire(rules, json!({})), ["not_vip"]);
+    }
+
+    #[test]
+    fn test_ranges_match_poc() {
+        let cart = json!({
+            "cost": { "totalAmount": { "amount": "10.0" }, "subtotalAmount": { "amount": "8.0" } },
+            "deliveryGroups": [{ "deliveryAddress": { "address1": "1 Main St", "zip": "90210" } }]
+        });
+        let total = |operator: &str, value: Value| condition("cart.total", operator, value);
+        let rules = json!([
+            rule("inclusive", total("BETWEEN", json!([5, 10]))),
+            rule("exclusive", total("BETWEEN", json!({ "min": 5, "max": 10, "max_inclusive": false }))),
+            rule("open_ended", total("BETWEEN", json!({ "min": 9.5 }))),
+            rule("null_bound", total("BETWEEN", json!({ "min": null, "max": 10 }))),
+            rule("no_bounds", total("BETWEEN", json!({}))),
+            rule("null_flag", total("BETWEEN", json!({ "min": 5, "min_inclusive": null }))),
+            rule("triple", total("BETWEEN", json!([1, 2, 3]))),
+            rule("text_bound", total("BETWEEN", json!(["5", 10]))),
+            rule("any_range", total("IN_RANGES", json!([[1, 3], { "min": 9, "max": 12 }]))),
+            rule("no_range", total("IN_RANGES", json!([[1, 3], [20, 30]]))),
+            rule("empty_list", total("IN_RANGES", json!([]))),
+            // One bad range spoils the list, matching or not
+            rule("bad_range", total("IN_RANGES", json!([[9, 12], [3]]))),
+            rule("not_bad_range", json!([{ "operator": "NOT", "criteria": total("IN_RANGES", json!([[1, 2], "x"])) }])),
+            rule("on_string", condition("shipping_address.zip", "BETWEEN", json!([0, 99999]))),
+        ]);
+
+        assert_eq!(fire(rules, cart), ["inclusive", "open_ended", "null_bound", "any_range"]);
+    }
+
+    #[test]
+    fn test_unknown_operator_disables_only_its_rule() {
+        let value = json!({
+            "version": "1.0",
+            "rules": [
+                rule("future", condition("cart.total", "FROM_THE_FUTURE", json!(1))),
+                rule("nested", json!([{ "operator": "XOR", "criteria": [] }])),
+                rule("fine", condition("cart.total", "GREATER_THAN", json!(-1))),
+            ]
+        });
+        let input = json!({
+            "cart": { "cost": { "totalAmount": { "amount": "10.0" } } },
+            "shop": { "metafield": { "value": value.to_string() } }
+        });
+        let fired: Vec<String> = process_input(&input).errors.into_iter().map(|e| e.localized_message).collect();
+        assert_eq!(fired, ["fine"]);
+
+        let mut config = RulesConfig::read(&value).unwrap();
+        let warnings = prepare_rules(&mut config);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("FROM_THE_FUTURE"), "{}", warnings[0]);
+        assert_eq!(config.rules.iter().map(|r| r.enabled).collect::<Vec<_>>(), [false, false, true]);
+    }
+
+    #[test]
+    fn test_normalization_matches_poc() {
+        let cart = json!({
+            "deliveryGroups": [{ "deliveryAddress": {
+                "address1": "  12  Main\tSt ", "address2": "   ", "city": "ISPARTA"
+            } }]
+        });
+        let normalized = |field: &str, operator: &str, value: Value, normalize: Value| {
+            json!([{ "field": field, "operator": operator, "value": value, "normalize": normalize }])
+        };
+        let tidy = json!({ "trim": true, "collapse_whitespace": true });
+        let address = "shipping_address.address1";
+        let address2 = "shipping_address.address2";
+        let city = "shipping_address.city";
+        let rules = json!([
+            // Case is ignored unless asked for, as the function always did
+            rule("default", condition(city, "EQUALS", json!("isparta"))),
+            rule("case_sensitive", normalized(city, "EQUALS", json!("isparta"), json!({ "case_sensitive": true }))),
+            rule("turkish", normalized(city, "EQUALS", json!("ısparta"), json!({ "locale": "tr-TR" }))),
+            rule("not_turkish", condition(city, "EQUALS", json!("ısparta"))),
+            rule("tidy_equals", normalized(address, "EQUALS", json!(" 12 MAIN ST"), tidy.clone())),
+            rule("untidy_equals", condition(address, "EQUALS", json!("12 main st"))),
+            rule("tidy_starts", normalized(address, "STARTS_WITH", json!("12 main"), tidy.clone())),
+            rule("tidy_in", normalized(address, "IN", json!(["12 main st"]), tidy.clone())),
+            rule("tidy_keywords", normalized(address, "CONTAINS_ANY_OF", json!(["main st"]), tidy.clone())),
+            rule("tidy_regex", normalized(address, "REGEX_MATCH", json!("^12 Main St$"), tidy.clone())),
+            rule("trimmed_empty", normalized(address2, "IS_EMPTY", Value::Null, json!({ "trim": true }))),
+            rule("untrimmed_empty", condition(address2, "IS_EMPTY", Value::Null)),
+        ]);
+
+        assert_eq!(
+            fire(rules, cart),
+            [
+                "default",
+                "turkish",
+                "tidy_equals",
+                "tidy_starts",
+                "tidy_in",
+                "tidy_keywords",
+                "tidy_regex",
+                "trimmed_empty",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_normalization_disables_the_rule() {
+        let normalized = |id: &str, normalize: Value| {
+            let field = "shipping_address.city";
+            rule(id, json!([{ "field": field, "operator": "EQUALS", "value": "x", "normalize": normalize }]))
+        };
+        let value = json!({
+            "version": "1.0",
+            "rules": [
+                normalized("nfkc", json!({ "nfkc": true })),
+                normalized("diacritics", json!({ "fold_diacritics": true, "trim": true })),
+                normalized("mistyped", json!({ "trim": "yes" })),
+                normalized("fine", json!({ "nfkc": false, "trim": true })),
+            ]
+        });
+        let mut config = RulesConfig::read(&value).unwrap();
+        let warnings = prepare_rules(&mut config);
+        assert_eq!(config.rules.iter().map(|r| r.enabled).collect::<Vec<_>>(), [false, false, false, true]);
+        assert!(warnings[0].contains("normalize.nfkc"), "{}", warnings[0]);
     }
 }
@@ -0,0 +1,127 @@
+//! Per-condition string normalization, mirroring `logicflow::normalize`.
+//!
+//! The default is case-insensitive matching with no other changes. NFKC
+//! and diacritic folding need Unicode tables the function has no room for,
+//! so `prepare_rules` disables rules that ask for them.
+
+use serde_json::Value;
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Normalization {
+    pub case_sensitive: bool,
+    pub trim: bool,
+    pub collapse_whitespace: bool,
+    /// Turkic case folding (locale "tr" or "az"): "I" folds to "ı" and
+    /// "İ" to "i".
+    pub turkic: bool,
+    /// The first requested option this build can't apply.
+    pub unsupported: Option<&'static str>,
+}
+
+impl Normalization {
+    /// Read a condition's `normalize` object. Missing options are off;
+    /// `None` if an option has the wrong type.
+    pub fn read(value: &Value) -> Option<Self> {
+        let options = value.as_object()?;
+        let flag = |key: &str| match options.get(key) {
+            None => Some(false),
+            Some(v) => v.as_bool(),
+        };
+        let locale = match options.get("locale") {
+            None | Some(Value::Null) => "",
+            Some(v) => v.as_str()?,
+        };
+        let language = locale.split(['-', '_']).next().unwrap_or("");
+        let (nfkc, fold_diacritics) = (flag("nfkc")?, flag("fold_diacritics")?);
+        Some(Normalization {
+            case_sensitive: flag("case_sensitive")?,
+            trim: flag("trim")?,
+            collapse_whitespace: flag("collapse_whitespace")?,
+            turkic: language.eq_ignore_ascii_case("tr") || language.eq_ignore_ascii_case("az"),
+            unsupported: match (nfkc, fold_diacritics) {
+                (true, _) => Some("nfkc"),
+                (_, true) => Some("fold_diacritics"),
+                _ => None,
+            },
+        })
+    }
+
+    /// Normalize `input`, borrowing when no step changes it.
+    pub fn apply<'a>(&self, input: &'a str) -> Cow<'a, str> {
+        self.apply_steps(input, !self.case_sensitive)
+    }
+
+    /// Normalize `input` but leave its case alone, for regex matching.
+    pub fn apply_keep_case<'a>(&self, input: &'a str) -> Cow<'a, str> {
+        self.apply_steps(input, false)
+    }
+
+    fn apply_steps<'a>(&self, input: &'a str, fold_case: bool) -> Cow<'a, str> {
+        let mut out = Cow::Borrowed(if self.trim { input.trim() } else { input });
+        if self.collapse_whitespace && out.contains(char::is_whitespace) {
+            out = Cow::Owned(collapse_whitespace(&out));
+        }
+        if fold_case {
+            out = Cow::Owned(self.fold_case(&out));
+        }
+        out
+    }
+
+    fn fold_case(&self, s: &str) -> String {
+        if self.turkic {
+            s.chars()
+                .map(|c| match c {
+                    'I' => 'ı',
+                    'İ' => 'i',
+                    _ => c,
+                })
+                .flat_map(char::to_lowercase)
+                .collect()
+        } else {
+            s.to_lowercase()
+        }
+    }
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_space = false;
+    for c in s.chars() {
+        if c.is_whitespace() {
+            if !in_space {
+                out.push(' ');
+            }
+            in_space = true;
+        } else {
+            out.push(c);
+            in_space = false;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_read_options() {
+        let n = Normalization::read(&json!({ "trim": true, "locale": "az_AZ", "extra": 1 })).unwrap();
+        assert!(n.trim && n.turkic && !n.case_sensitive);
+        assert_eq!(n.apply(" IRMAK "), "ırmak");
+
+        assert_eq!(Normalization::read(&json!({ "locale": null })), Some(Normalization::default()));
+        assert_eq!(Normalization::read(&json!({ "trim": 1 })), None);
+        assert_eq!(Normalization::read(&json!({ "locale": 1 })), None);
+        assert_eq!(Normalization::read(&json!({ "fold_diacritics": true })).unwrap().unsupported, Some("fold_diacritics"));
+    }
+
+    #[test]
+    fn test_case_sensitive_borrows_unchanged_input() {
+        let n = Normalization { case_sensitive: true, ..Default::default() };
+        assert!(matches!(n.apply("Hello"), Cow::Borrowed("Hello")));
+        assert_eq!(n.apply_keep_case(" A  B "), " A  B ");
+    }
+}
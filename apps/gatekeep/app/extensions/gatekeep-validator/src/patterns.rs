@@ -1,18 +1,337 @@
-//! Simple pattern matching (regex-free for smaller WASM size)
+//! Preset pattern matching (regex-free for smaller WASM size)
+//!
+//! Each matcher reproduces the POC regex of the same name in
+//! `logicflow::patterns` exactly, including its Unicode behaviour: `\d` is
+//! any decimal digit, `\s` any Unicode whitespace, `\b` uses Unicode word
+//! characters, and `(?i)` also folds `ſ` to `s` and the Kelvin sign to
+//! `k`. The vectors in `testdata/preset_patterns.json` are checked
+//! against both sides.
 
-/// Check if text contains a PO Box pattern
+use crate::unicode::{is_digit, is_word_char};
+
+/// Get a preset matcher by name.
+pub fn preset(name: &str) -> Option<fn(&str) -> bool> {
+    let matcher: fn(&str) -> bool = match name {
+        "po_box" => is_po_box,
+        "uk_postcode" => is_uk_postcode,
+        "us_zip" => is_us_zip,
+        "ca_postal" => is_ca_postal,
+        "email_basic" => is_email_basic,
+        "us_phone" => is_us_phone,
+        "profanity" => has_profanity,
+        "numeric_only" => is_numeric_only,
+        "suspicious_chars" => has_suspicious_chars,
+        _ => return None,
+    };
+    Some(matcher)
+}
+
+/// Check `text` against a preset. `None` if there is no such preset.
+pub fn check_preset(name: &str, text: &str) -> Option<bool> {
+    preset(name).map(|matcher| matcher(text))
+}
+
+// ============================================================================
+// Presets
+// ============================================================================
+
+/// `(?i)\b(p\.?\s*o\.?\s*box|post\s*office\s*box)\b`
 pub fn is_po_box(text: &str) -> bool {
-    let lower = text.to_lowercase();
-    lower.contains("po box") || 
-    lower.contains("p.o. box") || 
-    lower.contains("p o box") ||
-    lower.contains("post office box")
+    let chars: Vec<char> = text.chars().collect();
+    (0..chars.len()).any(|start| {
+        if !is_boundary(&chars, start) {
+            return false;
+        }
+        let end = match_short_po_box(&chars, start).or_else(|| match_post_office_box(&chars, start));
+        end.is_some_and(|end| is_boundary(&chars, end))
+    })
+}
+
+/// `p\.?\s*o\.?\s*box`
+fn match_short_po_box(chars: &[char], pos: usize) -> Option<usize> {
+    let pos = literal_ci(chars, pos, "p")?;
+    let pos = skip_whitespace(chars, optional(chars, pos, '.'));
+    let pos = literal_ci(chars, pos, "o")?;
+    let pos = skip_whitespace(chars, optional(chars, pos, '.'));
+    literal_ci(chars, pos, "box")
 }
 
-/// Get a preset pattern checker by name
-pub fn check_preset(name: &str, text: &str) -> bool {
-    match name {
-        "po_box" => is_po_box(text),
+/// `post\s*office\s*box`
+fn match_post_office_box(chars: &[char], pos: usize) -> Option<usize> {
+    let pos = skip_whitespace(chars, literal_ci(chars, pos, "post")?);
+    let pos = skip_whitespace(chars, literal_ci(chars, pos, "office")?);
+    literal_ci(chars, pos, "box")
+}
+
+/// `(?i)^[A-Z]{1,2}\d[A-Z\d]?\s*\d[A-Z]{2}$`
+pub fn is_uk_postcode(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+    // Outward code: 1-2 letters, a digit, then an optional letter or digit
+    let outward_ends = [1, 2].into_iter().flat_map(|letters| {
+        let pos = (chars.len() >= letters && chars[..letters].iter().all(|c| is_letter_ci(*c)))
+            .then_some(letters)
+            .filter(|&pos| chars.get(pos).is_some_and(|c| is_digit(*c)))
+            .map(|pos| pos + 1);
+        let extra = pos.filter(|&pos| chars.get(pos).is_some_and(|c| is_letter_ci(*c) || is_digit(*c)));
+        [pos, extra.map(|pos| pos + 1)]
+    });
+
+    // Inward code: optional whitespace, a digit, two letters
+    outward_ends.flatten().any(|pos| {
+        let rest = &chars[skip_whitespace(&chars, pos)..];
+        rest.len() == 3 && is_digit(rest[0]) && is_letter_ci(rest[1]) && is_letter_ci(rest[2])
+    })
+}
+
+/// `^\d{5}(-\d{4})?$`
+pub fn is_us_zip(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+    match chars.len() {
+        5 => chars.iter().all(|c| is_digit(*c)),
+        10 => {
+            chars[..5].iter().all(|c| is_digit(*c))
+                && chars[5] == '-'
+                && chars[6..].iter().all(|c| is_digit(*c))
+        }
         _ => false,
     }
 }
+
+/// `(?i)^[A-Z]\d[A-Z]\s*\d[A-Z]\d$`
+pub fn is_ca_postal(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() < 6 || !(is_letter_ci(chars[0]) && is_digit(chars[1]) && is_letter_ci(chars[2])) {
+        return false;
+    }
+    let rest = &chars[skip_whitespace(&chars, 3)..];
+    rest.len() == 3 && is_digit(rest[0]) && is_letter_ci(rest[1]) && is_digit(rest[2])
+}
+
+/// `^[^\s@]+@[^\s@]+\.[^\s@]+$`
+pub fn is_email_basic(text: &str) -> bool {
+    if text.chars().any(char::is_whitespace) {
+        return false;
+    }
+    // Neither side may contain '@', so there is exactly one
+    let Some((local, domain)) = text.split_once('@') else {
+        return false;
+    };
+    if local.is_empty() || domain.contains('@') {
+        return false;
+    }
+    // A '.' with at least one character on each side
+    let domain: Vec<char> = domain.chars().collect();
+    domain.len() >= 3 && domain[1..domain.len() - 1].contains(&'.')
+}
+
+/// `^(\+1[-.\s]?)?(\(?\d{3}\)?[-.\s]?)?\d{3}[-.\s]?\d{4}$`
+pub fn is_us_phone(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+
+    // Each optional part can end in a few places; try every combination
+    let mut country_ends = vec![0];
+    if chars.starts_with(&['+', '1']) {
+        country_ends.push(2);
+        if chars.get(2).is_some_and(|c| is_phone_separator(*c)) {
+            country_ends.push(3);
+        }
+    }
+
+    country_ends.into_iter().any(|pos| {
+        let mut area_ends = vec![pos];
+        let open = optional(&chars, pos, '(');
+        if let Some(after_digits) = digits(&chars, open, 3) {
+            let close = optional(&chars, after_digits, ')');
+            area_ends.extend([after_digits, close]);
+            if chars.get(close).is_some_and(|c| is_phone_separator(*c)) {
+                area_ends.push(close + 1);
+            }
+            if close != after_digits && chars.get(after_digits).is_some_and(|c| is_phone_separator(*c)) {
+                area_ends.push(after_digits + 1);
+            }
+        }
+
+        area_ends.into_iter().any(|pos| {
+            let Some(pos) = digits(&chars, pos, 3) else {
+                return false;
+            };
+            [pos, pos + 1].into_iter().any(|sep_end| {
+                (sep_end == pos || chars.get(pos).is_some_and(|c| is_phone_separator(*c)))
+                    && digits(&chars, sep_end, 4) == Some(chars.len())
+            })
+        })
+    })
+}
+
+/// `(?i)\b(badword1|badword2|offensive)\b`
+pub fn has_profanity(text: &str) -> bool {
+    const WORDS: &[&str] = &["badword1", "badword2", "offensive"];
+
+    let chars: Vec<char> = text.chars().collect();
+    (0..chars.len()).any(|start| {
+        is_boundary(&chars, start)
+            && WORDS
+                .iter()
+                .filter_map(|word| literal_ci(&chars, start, word))
+                .any(|end| is_boundary(&chars, end))
+    })
+}
+
+/// `^\d+$`
+pub fn is_numeric_only(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(is_digit)
+}
+
+/// `[<>{}|\\^~\[\]`]`
+pub fn has_suspicious_chars(text: &str) -> bool {
+    text.contains(['<', '>', '{', '}', '|', '\\', '^', '~', '[', ']', '`'])
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// `\b` at `pos`: a word character on exactly one side.
+fn is_boundary(chars: &[char], pos: usize) -> bool {
+    let before = pos > 0 && is_word_char(chars[pos - 1]);
+    let after = chars.get(pos).is_some_and(|c| is_word_char(*c));
+    before != after
+}
+
+/// `(?i)` equality against a lowercase ASCII pattern character.
+fn eq_ci(c: char, pattern: char) -> bool {
+    c.eq_ignore_ascii_case(&pattern) || (pattern == 's' && c == 'ſ') || (pattern == 'k' && c == '\u{212A}')
+}
+
+/// `(?i)[A-Z]`
+fn is_letter_ci(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == 'ſ' || c == '\u{212A}'
+}
+
+/// Match a lowercase ASCII literal case-insensitively at `pos`; returns the
+/// position after it.
+fn literal_ci(chars: &[char], pos: usize, literal: &str) -> Option<usize> {
+    let mut end = pos;
+    for pattern in literal.chars() {
+        if !chars.get(end).is_some_and(|c| eq_ci(*c, pattern)) {
+            return None;
+        }
+        end += 1;
+    }
+    Some(end)
+}
+
+/// `c?`
+fn optional(chars: &[char], pos: usize, c: char) -> usize {
+    if chars.get(pos) == Some(&c) {
+        pos + 1
+    } else {
+        pos
+    }
+}
+
+/// `\s*`
+fn skip_whitespace(chars: &[char], mut pos: usize) -> usize {
+    while chars.get(pos).is_some_and(|c| c.is_whitespace()) {
+        pos += 1;
+    }
+    pos
+}
+
+/// `\d{n}`
+fn digits(chars: &[char], pos: usize, n: usize) -> Option<usize> {
+    let end = pos + n;
+    (end <= chars.len() && chars[pos..end].iter().all(|c| is_digit(*c))).then_some(end)
+}
+
+/// `[-.\s]`
+fn is_phone_separator(c: char) -> bool {
+    c == '-' || c == '.' || c.is_whitespace()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHARED_VECTORS: &str = include_str!("../../../../testdata/preset_patterns.json");
+
+    /// Preset names, in the order the POC lists them.
+    const PRESET_NAMES: &[&str] = &[
+        "po_box",
+        "uk_postcode",
+        "us_zip",
+        "ca_postal",
+        "email_basic",
+        "us_phone",
+        "profanity",
+        "numeric_only",
+        "suspicious_chars",
+    ];
+
+    fn vectors() -> Vec<(String, String, bool)> {
+        let table: serde_json::Value = serde_json::from_str(SHARED_VECTORS).unwrap();
+        let mut out = Vec::new();
+        for (name, cases) in table.as_object().unwrap() {
+            for (key, expected) in [("match", true), ("no_match", false)] {
+                for input in cases[key].as_array().unwrap() {
+                    out.push((name.clone(), input.as_str().unwrap().to_string(), expected));
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_presets_match_poc() {
+        let mut poc: Vec<&str> = logicflow::patterns::list_preset_patterns();
+        let mut ours = PRESET_NAMES.to_vec();
+        poc.sort();
+        ours.sort();
+        assert_eq!(ours, poc);
+        assert!(check_preset("nonexistent", "x").is_none());
+    }
+
+    #[test]
+    fn test_shared_vectors() {
+        for (name, input, expected) in vectors() {
+            assert_eq!(check_preset(&name, &input), Some(expected), "{} on {:?}", name, input);
+        }
+    }
+
+    #[test]
+    fn test_parity_with_poc_regex() {
+        // The vectors, plus variants that stress anchors, boundaries and
+        // case folding
+        let affixes = ["", " ", "x", "_", "1", "-", "(", "é", "\n", "٣", "ſ"];
+        for name in PRESET_NAMES {
+            let re = logicflow::get_preset_pattern(name).unwrap();
+            let matcher = preset(name).unwrap();
+            for (_, input, _) in vectors() {
+                for base in [input.clone(), input.to_uppercase(), input.to_lowercase()] {
+                    for prefix in affixes {
+                        for suffix in affixes {
+                            let text = format!("{}{}{}", prefix, base, suffix);
+                            assert_eq!(matcher(&text), re.is_match(&text), "{} on {:?}", name, text);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_unicode_tables_match_regex_classes() {
+        let digit = regex::Regex::new(r"^\d$").unwrap();
+        let word = regex::Regex::new(r"^\w$").unwrap();
+        let space = regex::Regex::new(r"^\s$").unwrap();
+        let letter_ci = regex::Regex::new(r"(?i)^[A-Z]$").unwrap();
+        let mut buf = [0; 4];
+        for c in (0..=char::MAX as u32).filter_map(char::from_u32) {
+            let s = c.encode_utf8(&mut buf);
+            assert_eq!(is_digit(c), digit.is_match(s), "\\d on {:?}", c);
+            assert_eq!(is_word_char(c), word.is_match(s), "\\w on {:?}", c);
+            assert_eq!(c.is_whitespace(), space.is_match(s), "\\s on {:?}", c);
+            assert_eq!(is_letter_ci(c), letter_ci.is_match(s), "(?i)[A-Z] on {:?}", c);
+        }
+    }
+}
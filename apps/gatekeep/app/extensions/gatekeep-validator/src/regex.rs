@@ -0,0 +1,806 @@
+//! Bounded regular expression matcher (regex-free of external crates for
+//! smaller WASM size).
+//!
+//! `regex-lite` alone adds ~60 KB to the binary, which pushes the function
+//! past Shopify's 256 KB limit, so this is a small Pike VM instead. It runs
+//! in O(pattern × input) time with no backtracking.
+//!
+//! Supported syntax: literals, `.`, `[...]`/`[^...]` classes with ranges,
+//! `\d \w \s \D \W \S`, `\b \B`, `^ $`, `(...)`, `(?:...)`, `|`, and the
+//! greedy quantifiers `* + ? {n} {n,} {n,m}`, with counts up to
+//! [`MAX_REPETITION`]. As in the `regex` crate, `\d \w \s` and `\b` are
+//! Unicode-aware, using the tables in [`crate::unicode`]. A leading `(?i)`
+//! makes the whole pattern case-insensitive. Anything else
+//! (backreferences, lookaround, lazy quantifiers, Unicode classes, ...) is
+//! rejected by [`Regex::new`] so it can't be silently mis-evaluated.
+
+use crate::unicode;
+use std::fmt;
+
+/// Longest pattern accepted, in bytes.
+pub const MAX_PATTERN_LEN: usize = 256;
+/// Largest compiled program accepted, in instructions. Counted
+/// repetitions are expanded, so `a{1000}` is rejected here.
+pub const MAX_PROGRAM_LEN: usize = 2048;
+/// Largest count accepted in a counted repetition. A repeated empty group
+/// compiles to nothing, so the program size alone doesn't bound the work
+/// of compiling `(){4294967295}`.
+pub const MAX_REPETITION: u32 = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegexError {
+    TooLong,
+    TooLarge,
+    TooManyRepetitions,
+    Unsupported(&'static str),
+    Syntax(&'static str),
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegexError::TooLong => write!(f, "pattern longer than {} bytes", MAX_PATTERN_LEN),
+            RegexError::TooLarge => write!(f, "pattern compiles to more than {} instructions", MAX_PROGRAM_LEN),
+            RegexError::TooManyRepetitions => write!(f, "counted repetition over {}", MAX_REPETITION),
+            RegexError::Unsupported(what) => write!(f, "unsupported syntax: {}", what),
+            RegexError::Syntax(what) => write!(f, "invalid pattern: {}", what),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    program: Vec<Inst>,
+    classes: Vec<Class>,
+    case_insensitive: bool,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        if pattern.len() > MAX_PATTERN_LEN {
+            return Err(RegexError::TooLong);
+        }
+
+        let (case_insensitive, body) = match pattern.strip_prefix("(?i)") {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+
+        let mut parser = Parser {
+            chars: body.chars().collect(),
+            pos: 0,
+            case_insensitive,
+        };
+        let ast = parser.parse_alternation()?;
+        if parser.pos < parser.chars.len() {
+            return Err(RegexError::Syntax("unbalanced ')'"));
+        }
+
+        let mut compiler = Compiler {
+            program: Vec::new(),
+            classes: Vec::new(),
+        };
+        compiler.compile(&ast)?;
+        compiler.push(Inst::Match)?;
+
+        Ok(Regex {
+            program: compiler.program,
+            classes: compiler.classes,
+            case_insensitive,
+        })
+    }
+
+    /// True if the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        let n = self.program.len();
+        let mut current = Threads::new(n);
+        let mut next = Threads::new(n);
+        let mut stack = Vec::new();
+
+        for pos in 0..=chars.len() {
+            // Unanchored search: a new thread starts at every position.
+            self.add_thread(&mut current, &mut stack, 0, pos, &chars);
+
+            for i in 0..current.len() {
+                let pc = current.dense[i];
+                let c = match chars.get(pos) {
+                    Some(c) => *c,
+                    None => match self.program[pc] {
+                        Inst::Match => return true,
+                        _ => continue,
+                    },
+                };
+                let advances = match &self.program[pc] {
+                    Inst::Match => return true,
+                    Inst::Char(expected) => self.fold(c) == *expected,
+                    Inst::Any => c != '\n',
+                    Inst::Class(idx) => self.class_matches(*idx, c),
+                    _ => false,
+                };
+                if advances {
+                    self.add_thread(&mut next, &mut stack, pc + 1, pos + 1, &chars);
+                }
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+            if current.len() == 0 && pos >= chars.len() {
+                break;
+            }
+        }
+
+        false
+    }
+
+    /// Follow epsilon transitions from `pc`, adding every reachable
+    /// consuming instruction to `threads`. `stack` is scratch space, reused
+    /// across calls so the hot loop doesn't allocate.
+    fn add_thread(&self, threads: &mut Threads, stack: &mut Vec<usize>, pc: usize, pos: usize, chars: &[char]) {
+        stack.clear();
+        stack.push(pc);
+        while let Some(pc) = stack.pop() {
+            if !threads.insert(pc) {
+                continue;
+            }
+            match self.program[pc] {
+                Inst::Jmp(target) => stack.push(target),
+                Inst::Split(a, b) => {
+                    // Push b first so a is explored first
+                    stack.push(b);
+                    stack.push(a);
+                }
+                Inst::Assert(assertion) if assertion.holds(pos, chars) => stack.push(pc + 1),
+                _ => {}
+            }
+        }
+    }
+
+    fn fold(&self, c: char) -> char {
+        if self.case_insensitive {
+            simple_lowercase(c)
+        } else {
+            c
+        }
+    }
+
+    fn class_matches(&self, idx: usize, c: char) -> bool {
+        let class = &self.classes[idx];
+        let hit = class.contains(c)
+            || (self.case_insensitive
+                && (class.contains(simple_lowercase(c)) || class.contains(simple_uppercase(c))));
+        hit != class.negated
+    }
+}
+
+fn simple_lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Classes only fold ASCII under `(?i)`: Unicode uppercase tables would
+/// cost binary size for little benefit in address and tag matching.
+fn simple_uppercase(c: char) -> char {
+    c.to_ascii_uppercase()
+}
+
+// ============================================================================
+// Thread set
+// ============================================================================
+
+/// Sparse set of program counters, cleared in O(1).
+struct Threads {
+    dense: Vec<usize>,
+    sparse: Vec<usize>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Self {
+        Threads {
+            dense: Vec::with_capacity(size),
+            sparse: vec![0; size],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    fn insert(&mut self, pc: usize) -> bool {
+        let idx = self.sparse[pc];
+        if idx < self.dense.len() && self.dense[idx] == pc {
+            return false;
+        }
+        self.sparse[pc] = self.dense.len();
+        self.dense.push(pc);
+        true
+    }
+
+    fn clear(&mut self) {
+        self.dense.clear();
+    }
+}
+
+// ============================================================================
+// AST and parser
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Assertion {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+impl Assertion {
+    fn holds(self, pos: usize, chars: &[char]) -> bool {
+        match self {
+            Assertion::Start => pos == 0,
+            Assertion::End => pos == chars.len(),
+            Assertion::WordBoundary | Assertion::NotWordBoundary => {
+                let before = pos > 0 && unicode::is_word_char(chars[pos - 1]);
+                let after = pos < chars.len() && unicode::is_word_char(chars[pos]);
+                (before != after) == (self == Assertion::WordBoundary)
+            }
+        }
+    }
+}
+
+/// `\d`, `\w` or `\s`, matched by Unicode property rather than by range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn contains(self, c: char) -> bool {
+        match self {
+            Perl::Digit => unicode::is_digit(c),
+            Perl::Word => unicode::is_word_char(c),
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    ranges: Vec<(char, char)>,
+    perl: Vec<Perl>,
+    negated: bool,
+}
+
+impl Class {
+    fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) || self.perl.iter().any(|p| p.contains(c))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat { node: Box<Node>, min: u32, max: Option<u32> },
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    case_insensitive: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.parse_concat()?);
+        }
+        Ok(if branches.len() == 1 { branches.pop().unwrap() } else { Node::Alt(branches) })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut items = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            items.push(self.parse_quantifier(atom)?);
+        }
+        Ok(match items.len() {
+            0 => Node::Empty,
+            1 => items.pop().unwrap(),
+            _ => Node::Concat(items),
+        })
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, RegexError> {
+        match self.next() {
+            Some('(') => {
+                if self.peek() == Some('?') {
+                    self.pos += 1;
+                    match self.next() {
+                        Some(':') => {}
+                        Some('i') => return Err(RegexError::Unsupported("(?i) is only allowed at the start")),
+                        _ => return Err(RegexError::Unsupported("lookaround and named groups")),
+                    }
+                }
+                let inner = self.parse_alternation()?;
+                if self.next() != Some(')') {
+                    return Err(RegexError::Syntax("unclosed '('"));
+                }
+                Ok(inner)
+            }
+            Some('[') => self.parse_class(),
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Assert(Assertion::Start)),
+            Some('$') => Ok(Node::Assert(Assertion::End)),
+            Some('\\') => self.parse_escape(),
+            Some('*') | Some('+') | Some('?') => Err(RegexError::Syntax("quantifier without a target")),
+            Some('{') => Err(RegexError::Syntax("quantifier without a target")),
+            Some(c) => Ok(self.literal(c)),
+            None => Err(RegexError::Syntax("unexpected end of pattern")),
+        }
+    }
+
+    fn literal(&self, c: char) -> Node {
+        Node::Char(if self.case_insensitive { simple_lowercase(c) } else { c })
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, RegexError> {
+        let class = |perl, negated| {
+            Ok(Node::Class(Class {
+                ranges: Vec::new(),
+                perl: vec![perl],
+                negated,
+            }))
+        };
+        match self.next() {
+            Some('d') => class(Perl::Digit, false),
+            Some('D') => class(Perl::Digit, true),
+            Some('w') => class(Perl::Word, false),
+            Some('W') => class(Perl::Word, true),
+            Some('s') => class(Perl::Space, false),
+            Some('S') => class(Perl::Space, true),
+            Some('b') => Ok(Node::Assert(Assertion::WordBoundary)),
+            Some('B') => Ok(Node::Assert(Assertion::NotWordBoundary)),
+            Some(c) => Ok(self.literal(escaped_literal(c)?)),
+            None => Err(RegexError::Syntax("trailing backslash")),
+        }
+    }
+
+    fn parse_class(&mut self) -> Result<Node, RegexError> {
+        let mut ranges = Vec::new();
+        let mut perl = Vec::new();
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut first = true;
+        loop {
+            let c = match self.next() {
+                Some(']') if !first => break,
+                Some(c) => c,
+                None => return Err(RegexError::Syntax("unclosed '['")),
+            };
+            first = false;
+
+            let lo = match c {
+                '\\' => match self.next() {
+                    Some('d') => {
+                        perl.push(Perl::Digit);
+                        continue;
+                    }
+                    Some('w') => {
+                        perl.push(Perl::Word);
+                        continue;
+                    }
+                    Some('s') => {
+                        perl.push(Perl::Space);
+                        continue;
+                    }
+                    Some('D') | Some('W') | Some('S') => {
+                        return Err(RegexError::Unsupported("negated classes inside [...]"))
+                    }
+                    Some(e) => escaped_literal(e)?,
+                    None => return Err(RegexError::Syntax("trailing backslash")),
+                },
+                '[' if self.peek() == Some(':') => return Err(RegexError::Unsupported("POSIX classes")),
+                c => c,
+            };
+
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|c| *c != ']') {
+                self.pos += 1;
+                let hi = match self.next() {
+                    Some('\\') => escaped_literal(self.next().ok_or(RegexError::Syntax("trailing backslash"))?)?,
+                    Some(c) => c,
+                    None => return Err(RegexError::Syntax("unclosed '['")),
+                };
+                if hi < lo {
+                    return Err(RegexError::Syntax("invalid class range"));
+                }
+                ranges.push((lo, hi));
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+
+        Ok(Node::Class(Class { ranges, perl, negated }))
+    }
+
+    fn parse_quantifier(&mut self, atom: Node) -> Result<Node, RegexError> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.pos += 1;
+                return self.parse_counted(atom);
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        self.reject_lazy()?;
+        Ok(Node::Repeat { node: Box::new(atom), min, max })
+    }
+
+    fn parse_counted(&mut self, atom: Node) -> Result<Node, RegexError> {
+        let min = self.parse_number().ok_or(RegexError::Syntax("invalid counted repetition"))?;
+        let max = match self.next() {
+            Some('}') => Some(min),
+            Some(',') => {
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                    None
+                } else {
+                    let max = self.parse_number().ok_or(RegexError::Syntax("invalid counted repetition"))?;
+                    if self.next() != Some('}') || max < min {
+                        return Err(RegexError::Syntax("invalid counted repetition"));
+                    }
+                    Some(max)
+                }
+            }
+            _ => return Err(RegexError::Syntax("invalid counted repetition")),
+        };
+        if max.unwrap_or(min) > MAX_REPETITION {
+            return Err(RegexError::TooManyRepetitions);
+        }
+        self.reject_lazy()?;
+        Ok(Node::Repeat { node: Box::new(atom), min, max })
+    }
+
+    fn parse_number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return None;
+        }
+        self.chars[start..self.pos].iter().collect::<String>().parse().ok()
+    }
+
+    fn reject_lazy(&mut self) -> Result<(), RegexError> {
+        match self.peek() {
+            Some('?') => Err(RegexError::Unsupported("lazy quantifiers")),
+            Some('+') => Err(RegexError::Unsupported("possessive quantifiers")),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Resolve an escaped character to the literal it stands for.
+fn escaped_literal(c: char) -> Result<char, RegexError> {
+    match c {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        'r' => Ok('\r'),
+        '1'..='9' => Err(RegexError::Unsupported("backreferences")),
+        'p' | 'P' => Err(RegexError::Unsupported("Unicode classes")),
+        'A' | 'z' | 'Z' | 'x' | 'u' => Err(RegexError::Unsupported("this escape sequence")),
+        c if c.is_ascii_alphanumeric() => Err(RegexError::Syntax("unknown escape sequence")),
+        c => Ok(c),
+    }
+}
+
+// ============================================================================
+// Compiler
+// ============================================================================
+
+#[derive(Debug, Clone, Copy)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(usize),
+    Assert(Assertion),
+    Split(usize, usize),
+    Jmp(usize),
+    Match,
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+    classes: Vec<Class>,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<usize, RegexError> {
+        if self.program.len() >= MAX_PROGRAM_LEN {
+            return Err(RegexError::TooLarge);
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), RegexError> {
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => {
+                self.push(Inst::Char(*c))?;
+            }
+            Node::Any => {
+                self.push(Inst::Any)?;
+            }
+            Node::Class(class) => {
+                self.classes.push(class.clone());
+                self.push(Inst::Class(self.classes.len() - 1))?;
+            }
+            Node::Assert(assertion) => {
+                self.push(Inst::Assert(*assertion))?;
+            }
+            Node::Concat(items) => {
+                for item in items {
+                    self.compile(item)?;
+                }
+            }
+            Node::Alt(branches) => {
+                // split L1, next; L1: branch; jmp end; next: ...
+                let mut jumps = Vec::new();
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 < branches.len() {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.compile(branch)?;
+                        jumps.push(self.push(Inst::Jmp(0))?);
+                        let next = self.program.len();
+                        self.program[split] = Inst::Split(split + 1, next);
+                    } else {
+                        self.compile(branch)?;
+                    }
+                }
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jmp(end);
+                }
+            }
+            Node::Repeat { node, min, max } => {
+                for _ in 0..*min {
+                    // A body that compiles to nothing matches only the empty
+                    // string, so once is enough
+                    let start = self.program.len();
+                    self.compile(node)?;
+                    if self.program.len() == start {
+                        break;
+                    }
+                }
+                match max {
+                    None => {
+                        // loop: split body, end; body; jmp loop
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.compile(node)?;
+                        self.push(Inst::Jmp(split))?;
+                        let end = self.program.len();
+                        self.program[split] = Inst::Split(split + 1, end);
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(0, 0))?);
+                            self.compile(node)?;
+                        }
+                        let end = self.program.len();
+                        for split in splits {
+                            self.program[split] = Inst::Split(split + 1, end);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_anchored_digits() {
+        assert!(is_match(r"^\d{5}$", "90210"));
+        assert!(!is_match(r"^\d{5}$", "9021"));
+        assert!(!is_match(r"^\d{5}$", "902101"));
+        assert!(!is_match(r"^\d{5}$", "zip 90210"));
+        assert!(is_match(r"^\d{5}(-\d{4})?$", "90210-1234"));
+        assert!(!is_match(r"^\d{5}(-\d{4})?$", "90210-123"));
+    }
+
+    #[test]
+    fn test_unanchored_search() {
+        assert!(is_match("box", "PO box 12"));
+        assert!(!is_match("box", "PO Box 12"));
+        assert!(is_match("(?i)box", "PO BOX 12"));
+    }
+
+    #[test]
+    fn test_classes_and_alternation() {
+        assert!(is_match(r"(?i)^[A-Z]{1,2}\d[A-Z\d]?\s*\d[A-Z]{2}$", "sw1a 1aa"));
+        assert!(is_match(r"^(cat|dog)s?$", "dogs"));
+        assert!(!is_match(r"^(cat|dog)s?$", "cow"));
+        assert!(is_match(r"[^a-z]", "abc1"));
+        assert!(!is_match(r"^[^a-z]+$", "abc"));
+        assert!(!is_match(r"(?i)^[^a-z]+$", "ABC"));
+        assert!(is_match(r"^[\w.-]+$", "a.b-c_d"));
+        assert!(is_match(r"^a.c$", "abc"));
+        assert!(!is_match(r"^a.c$", "a\nc"));
+    }
+
+    #[test]
+    fn test_word_boundaries() {
+        let re = Regex::new(r"(?i)\b(p\.?\s*o\.?\s*box|post\s*office\s*box)\b").unwrap();
+        assert!(re.is_match("P.O. Box 456"));
+        assert!(re.is_match("123 Main St, PO Box 303"));
+        assert!(!re.is_match("Boxwood Lane"));
+        assert!(!re.is_match("Expo Boxes"));
+        assert!(is_match(r"\Bx\B", "axb"));
+    }
+
+    #[test]
+    fn test_perl_classes_are_unicode() {
+        assert!(is_match(r"^\d+$", "٣٤"));
+        assert!(is_match(r"^\w+$", "Straße_東京"));
+        assert!(is_match(r"^[\w\s]+$", "café\u{a0}crème"));
+        assert!(is_match(r"^\s$", "\u{2003}"));
+        assert!(!is_match(r"\D", "१२३"));
+        assert!(is_match(r"\bé\b", "à é ç"));
+        assert!(!is_match(r"\bcaf\b", "café"));
+    }
+
+    /// Deterministic xorshift, so failures reproduce without a `rand` dependency.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[self.below(items.len())]
+        }
+    }
+
+    fn random_pattern(rng: &mut Rng, depth: u32) -> String {
+        const ATOMS: &[&str] = &[
+            "a", "b", "Z", "1", "é", "ж", "Ж", "_", " ", "-", ".", r"\.", r"\d", r"\D", r"\w", r"\W", r"\s", r"\S",
+            "[a-c]", "[^a-c]", r"[\d_]", r"[^\w]", r"[\s.-]", "[A-Z0-9]", r"\b", r"\B", "^", "$",
+        ];
+        const QUANTIFIERS: &[&str] = &["", "", "", "*", "+", "?", "{2}", "{1,2}", "{0,}"];
+        let mut out = String::new();
+        for _ in 0..1 + rng.below(4) {
+            let atom = if depth > 0 && rng.below(5) == 0 {
+                let (left, right) = (random_pattern(rng, depth - 1), random_pattern(rng, depth - 1));
+                format!("({}|{})", left, right)
+            } else {
+                rng.pick(ATOMS).to_string()
+            };
+            out.push_str(&atom);
+            // The regex crate rejects repeating an anchor or boundary.
+            if !matches!(atom.as_str(), "^" | "$" | r"\b" | r"\B") {
+                out.push_str(rng.pick(QUANTIFIERS));
+            }
+        }
+        out
+    }
+
+    /// Compare against the `regex` crate the POC runs on. Texts avoid
+    /// characters whose case folding differs from `char::to_lowercase`
+    /// (Kelvin sign, long s, dotted capital I): classes only fold ASCII.
+    #[test]
+    fn test_matches_regex_crate() {
+        const TEXT: &[&str] = &[
+            "a", "b", "A", "Z", "z", "1", "0", "٣", "१", "é", "É", "ж", "Ж", "東", "_", "\u{203f}", "\u{301}", " ", "\t",
+            "\n", "\u{a0}", "\u{2003}", ".", "-", "!", "😀",
+        ];
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..400 {
+            let mut pattern = random_pattern(&mut rng, 2);
+            if rng.below(3) == 0 {
+                pattern.insert_str(0, "(?i)");
+            }
+            let ours = Regex::new(&pattern).unwrap();
+            let theirs = regex::Regex::new(&pattern).unwrap();
+            for _ in 0..50 {
+                let text: String = (0..rng.below(8)).map(|_| rng.pick(TEXT)).collect();
+                assert_eq!(ours.is_match(&text), theirs.is_match(&text), "{:?} on {:?}", pattern, text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_repetition_forms() {
+        assert!(is_match(r"^a{2,}$", "aaaa"));
+        assert!(!is_match(r"^a{2,}$", "a"));
+        assert!(is_match(r"^a{2,3}$", "aaa"));
+        assert!(!is_match(r"^a{2,3}$", "aaaa"));
+        assert!(is_match(r"^(ab)*$", ""));
+        assert!(is_match(r"^(ab)+$", "ababab"));
+        assert!(is_match(r"^$", ""));
+    }
+
+    #[test]
+    fn test_pathological_pattern_is_linear() {
+        let re = Regex::new(r"^(a+)+$").unwrap();
+        let text = format!("{}b", "a".repeat(5000));
+        assert!(!re.is_match(&text));
+    }
+
+    #[test]
+    fn test_rejects_unsupported_syntax() {
+        assert_eq!(Regex::new(r"(a)\1").unwrap_err(), RegexError::Unsupported("backreferences"));
+        assert!(matches!(Regex::new(r"a(?=b)"), Err(RegexError::Unsupported(_))));
+        assert!(matches!(Regex::new(r"a+?"), Err(RegexError::Unsupported(_))));
+        assert!(matches!(Regex::new(r"\p{L}"), Err(RegexError::Unsupported(_))));
+        assert!(matches!(Regex::new(r"[\D]"), Err(RegexError::Unsupported(_))));
+        assert!(matches!(Regex::new(r"a(?i)b"), Err(RegexError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_rejects_invalid_and_oversized_patterns() {
+        assert!(matches!(Regex::new("(abc"), Err(RegexError::Syntax(_))));
+        assert!(matches!(Regex::new("abc)"), Err(RegexError::Syntax(_))));
+        assert!(matches!(Regex::new("[abc"), Err(RegexError::Syntax(_))));
+        assert!(matches!(Regex::new("*a"), Err(RegexError::Syntax(_))));
+        assert!(matches!(Regex::new("a{3,2}"), Err(RegexError::Syntax(_))));
+        assert!(matches!(Regex::new("[z-a]"), Err(RegexError::Syntax(_))));
+        assert_eq!(Regex::new(&"a".repeat(300)).unwrap_err(), RegexError::TooLong);
+        assert_eq!(Regex::new("(abcdef){1000}").unwrap_err(), RegexError::TooLarge);
+        assert_eq!(Regex::new("a{1001}").unwrap_err(), RegexError::TooManyRepetitions);
+        assert_eq!(Regex::new("a{1,1001}").unwrap_err(), RegexError::TooManyRepetitions);
+    }
+
+    #[test]
+    fn test_empty_repetitions_compile_quickly() {
+        assert_eq!(Regex::new("(){4294967295}").unwrap_err(), RegexError::TooManyRepetitions);
+        assert_eq!(Regex::new("(?:){200000000}x").unwrap_err(), RegexError::TooManyRepetitions);
+
+        // Nesting would multiply the counts if each empty body were compiled
+        let start = std::time::Instant::now();
+        let nested = Regex::new("((((?:){1000}){1000}){1000}){1000}x").unwrap();
+        assert!(nested.is_match("x") && !nested.is_match("y"));
+        assert!(Regex::new("(()|){100}$").unwrap().is_match(""));
+        assert!(start.elapsed() < std::time::Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,255 @@
+//! Heuristics behind `ADDRESS_LOOKS_INVALID`: signs that an address was
+//! typed by a bot or a careless buyer ("asdf", "1", "test test", "xxxxxx").
+//!
+//! Each signal looks at `address1`, `address2` and `city` and can be
+//! selected on its own. Empty fields raise no signal; `IS_EMPTY` covers
+//! those. The checks are ASCII-oriented: non-ASCII characters count as
+//! letters, so addresses in other scripts don't trip them.
+
+use alloc::string::String;
+
+/// One address quality check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSignal {
+    /// No digit in either address line, in a country whose addresses have
+    /// house numbers.
+    MissingHouseNumber,
+    /// `address1` has no letters ("1", "12-14").
+    MissingStreetName,
+    /// A word that is a run along a keyboard row ("asdf", "lkjh"), or five
+    /// or more letters with no vowel ("sdfgh").
+    KeyboardMash,
+    /// The same character four or more times in a row ("xxxxxx"; digits
+    /// excepted), or an address line made of one word repeated ("test test").
+    RepeatedCharacters,
+    /// A field made only of placeholder words and numbers ("test", "n/a",
+    /// "123 unknown").
+    Placeholder,
+    /// Control characters, markup or code symbols (`<`, `{`, `|`, `$`, …),
+    /// arrows and other symbol blocks, emoji, or private-use characters.
+    SuspiciousCharacters,
+}
+
+impl AddressSignal {
+    /// Every signal, in the order names are listed in the docs.
+    pub const ALL: [AddressSignal; 6] = [
+        AddressSignal::MissingHouseNumber,
+        AddressSignal::MissingStreetName,
+        AddressSignal::KeyboardMash,
+        AddressSignal::RepeatedCharacters,
+        AddressSignal::Placeholder,
+        AddressSignal::SuspiciousCharacters,
+    ];
+
+    /// Look up a signal by its config name (e.g. `"keyboard_mash"`),
+    /// ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let signal = match name.trim().to_ascii_lowercase().as_str() {
+            "missing_house_number" => AddressSignal::MissingHouseNumber,
+            "missing_street_name" => AddressSignal::MissingStreetName,
+            "keyboard_mash" => AddressSignal::KeyboardMash,
+            "repeated_characters" => AddressSignal::RepeatedCharacters,
+            "placeholder" => AddressSignal::Placeholder,
+            "suspicious_characters" => AddressSignal::SuspiciousCharacters,
+            _ => return None,
+        };
+        Some(signal)
+    }
+}
+
+/// Countries whose street addresses are expected to carry a house number,
+/// sorted. Countries where named houses or rural addresses without numbers
+/// are common (e.g. GB, IE) are left out.
+pub const HOUSE_NUMBER_COUNTRIES: &[&str] = &[
+    "AR", "AT", "AU", "BE", "BR", "CA", "CH", "CL", "CO", "CZ", "DE", "DK", "ES", "FI", "FR", "HR", "HU", "IT",
+    "MX", "NL", "NO", "NZ", "PL", "PT", "SE", "SI", "SK", "US",
+];
+
+/// Words that stand in for an address rather than being one.
+pub const PLACEHOLDER_WORDS: &[&str] = &[
+    "abc", "address", "asdf", "blah", "dummy", "example", "fake", "na", "nil", "none", "null", "placeholder",
+    "qwerty", "sample", "test", "testing", "unknown", "x", "xx", "xxx", "xyz",
+];
+
+/// QWERTY letter rows, for keyboard mash detection.
+const KEYBOARD_ROWS: [&str; 3] = ["qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+/// The address fields the signals (and `military`) look at.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AddressParts<'a> {
+    pub address1: &'a str,
+    pub address2: &'a str,
+    pub city: &'a str,
+    pub province_code: &'a str,
+    pub country_code: &'a str,
+}
+
+impl AddressParts<'_> {
+    fn texts(&self) -> [&str; 3] {
+        [self.address1, self.address2, self.city]
+    }
+}
+
+/// True if any of `signals` fires for the address.
+pub fn address_looks_invalid(signals: &[AddressSignal], parts: &AddressParts) -> bool {
+    signals.iter().any(|signal| has_signal(*signal, parts))
+}
+
+/// True if `signal` fires for the address.
+pub fn has_signal(signal: AddressSignal, parts: &AddressParts) -> bool {
+    match signal {
+        AddressSignal::MissingHouseNumber => {
+            let country = parts.country_code.trim().to_ascii_uppercase();
+            !parts.address1.trim().is_empty()
+                && HOUSE_NUMBER_COUNTRIES.binary_search(&country.as_str()).is_ok()
+                && ![parts.address1, parts.address2].iter().any(|line| line.bytes().any(|b| b.is_ascii_digit()))
+        }
+        AddressSignal::MissingStreetName => {
+            !parts.address1.trim().is_empty() && !parts.address1.chars().any(|c| c.is_ascii_alphabetic() || !c.is_ascii())
+        }
+        AddressSignal::KeyboardMash => parts.texts().iter().any(|text| words(text).any(is_keyboard_mash)),
+        AddressSignal::RepeatedCharacters => {
+            parts.texts().iter().any(|text| has_character_run(text))
+                || [parts.address1, parts.address2].iter().any(|line| is_repeated_word(line))
+        }
+        AddressSignal::Placeholder => parts.texts().iter().any(|text| is_placeholder(text)),
+        AddressSignal::SuspiciousCharacters => parts.texts().iter().any(|text| text.chars().any(is_suspicious)),
+    }
+}
+
+/// Lowercased runs of ASCII letters and digits.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_ascii_lowercase())
+}
+
+fn is_keyboard_mash(word: String) -> bool {
+    let bytes = word.as_bytes();
+    if !bytes.iter().all(u8::is_ascii_lowercase) || bytes.len() < 4 {
+        return false;
+    }
+    let along_row = bytes.windows(2).all(|pair| {
+        KEYBOARD_ROWS.iter().any(|row| {
+            match (row.bytes().position(|b| b == pair[0]), row.bytes().position(|b| b == pair[1])) {
+                (Some(a), Some(b)) => a.abs_diff(b) == 1,
+                _ => false,
+            }
+        })
+    });
+    along_row || (bytes.len() >= 5 && !bytes.iter().any(|b| b"aeiouy".contains(b)))
+}
+
+fn has_character_run(text: &str) -> bool {
+    let mut previous = None;
+    let mut run = 0;
+    for c in text.chars().map(|c| c.to_ascii_lowercase()) {
+        run = if previous == Some(c) { run + 1 } else { 1 };
+        previous = Some(c);
+        if run >= 4 && !c.is_ascii_digit() && !c.is_whitespace() {
+            return true;
+        }
+    }
+    false
+}
+
+fn is_repeated_word(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    let Some(first) = words.next() else {
+        return false;
+    };
+    let mut count = 1;
+    for word in words {
+        if !word.eq_ignore_ascii_case(first) {
+            return false;
+        }
+        count += 1;
+    }
+    count >= 2
+}
+
+fn is_placeholder(text: &str) -> bool {
+    // "n/a" splits into "n" and "a", so also try the field run together
+    let compact: String = words(text).collect();
+    if PLACEHOLDER_WORDS.contains(&compact.as_str()) {
+        return true;
+    }
+    let mut any_placeholder = false;
+    for word in words(text) {
+        if PLACEHOLDER_WORDS.contains(&word.as_str()) {
+            any_placeholder = true;
+        } else if !word.bytes().all(|b| b.is_ascii_digit()) {
+            return false;
+        }
+    }
+    any_placeholder
+}
+
+fn is_suspicious(c: char) -> bool {
+    matches!(c,
+        '\0'..='\u{1f}' | '\u{7f}'..='\u{9f}'
+        | '<' | '>' | '{' | '}' | '[' | ']' | '|' | '\\' | '^' | '~' | '=' | '@' | '$' | '*' | '_' | '`'
+        // Arrows through miscellaneous symbols and arrows
+        | '\u{2190}'..='\u{2bff}'
+        // Private use, specials (including the replacement character)
+        | '\u{e000}'..='\u{f8ff}' | '\u{fff0}'..='\u{ffff}'
+        // Emoji and other supplementary symbols
+        | '\u{1f000}'..='\u{10ffff}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signals(address1: &str, address2: &str, city: &str, country_code: &str) -> Vec<AddressSignal> {
+        let parts = AddressParts { address1, address2, city, country_code, ..Default::default() };
+        AddressSignal::ALL.into_iter().filter(|s| has_signal(*s, &parts)).collect()
+    }
+
+    #[test]
+    fn test_real_addresses_pass() {
+        for (address1, address2, city, country) in [
+            ("350 Fifth Avenue", "Suite 5100", "New York", "US"),
+            ("1600 Amphitheatre Pkwy", "", "Mountain View", "US"),
+            ("Rose Cottage, Church Lane", "", "Little Snoring", "GB"),
+            ("Unter den Linden 77", "", "Berlin", "DE"),
+            ("Wertheimer Str. 12", "", "Wertheim", "DE"),
+            ("ul. Szczebrzeszyńska 3", "", "Szczecin", "PL"),
+            ("1-2-3 Ginza", "", "Chūō-ku", "JP"),
+            ("PO Box 1111", "", "Walla Walla", "US"),
+            ("Apt #4, 12 O'Connell St", "", "Dublin", "IE"),
+            ("Москва, ул. Тверская, д. 7", "", "Москва", "RU"),
+        ] {
+            assert_eq!(signals(address1, address2, city, country), vec![], "{:?}", address1);
+        }
+        assert_eq!(signals("", "", "", "US"), vec![]);
+    }
+
+    #[test]
+    fn test_junk_addresses() {
+        use AddressSignal::*;
+        assert_eq!(signals("asdf", "", "Boston", "US"), vec![MissingHouseNumber, KeyboardMash, Placeholder]);
+        assert_eq!(signals("1", "", "Boston", "US"), vec![MissingStreetName]);
+        assert_eq!(signals("test test", "", "Boston", "GB"), vec![RepeatedCharacters, Placeholder]);
+        assert_eq!(signals("xxxxxx", "", "xxxxxx", "GB"), vec![KeyboardMash, RepeatedCharacters]);
+        assert_eq!(signals("12 Main St", "", "n/a", "US"), vec![Placeholder]);
+        assert_eq!(signals("12 Main St", "", "sdfgh", "US"), vec![KeyboardMash]);
+        assert_eq!(signals("12 Main St", "", "lkjhg", "US"), vec![KeyboardMash]);
+        assert_eq!(signals("12 <script>", "", "Boston", "US"), vec![SuspiciousCharacters]);
+        assert_eq!(signals("12 Main St 🏠", "", "Boston", "US"), vec![SuspiciousCharacters]);
+        assert_eq!(signals("Main Street", "", "Boston", "us"), vec![MissingHouseNumber]);
+        assert_eq!(signals("Main Street", "Unit 4", "Boston", "US"), vec![]);
+    }
+
+    #[test]
+    fn test_signal_names() {
+        assert_eq!(AddressSignal::from_name(" Keyboard_Mash "), Some(AddressSignal::KeyboardMash));
+        assert_eq!(AddressSignal::from_name("gibberish"), None);
+        assert!(HOUSE_NUMBER_COUNTRIES.windows(2).all(|w| w[0] < w[1]));
+
+        let parts = AddressParts { address1: "asdf", city: "Boston", country_code: "US", ..Default::default() };
+        assert!(address_looks_invalid(&[AddressSignal::KeyboardMash], &parts));
+        assert!(!address_looks_invalid(&[AddressSignal::SuspiciousCharacters], &parts));
+        assert!(!address_looks_invalid(&[], &parts));
+    }
+}
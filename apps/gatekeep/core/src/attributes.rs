@@ -0,0 +1,41 @@
+//! Cart attributes the checkout function can read, for
+//! `cart.attributes.<key>` fields.
+//!
+//! A function's input query is static, so it can only ask for attributes
+//! by literal key (`attribute(key: "delivery_date")`), much as customer
+//! tags are limited to the ones `hasTags` lists. These are the keys
+//! `run.graphql` asks for: the usual spellings of delivery and pickup dates
+//! written by date pickers, and gift messages. Any other key is missing at
+//! checkout, so the function turns the rule off and lint reports it.
+
+/// Attribute keys in the checkout function's input query. Keys are matched
+/// exactly, as in the cart.
+pub const CHECKOUT_ATTRIBUTES: &[&str] = &[
+    "delivery_date",
+    "Delivery Date",
+    "Delivery-Date",
+    "delivery_time",
+    "Delivery Time",
+    "pickup_date",
+    "Pickup Date",
+    "gift_message",
+    "Gift Message",
+];
+
+/// True if the checkout function reads the attribute `key`.
+pub fn is_checkout_attribute(key: &str) -> bool {
+    CHECKOUT_ATTRIBUTES.contains(&key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkout_attributes() {
+        assert!(is_checkout_attribute("delivery_date"));
+        assert!(is_checkout_attribute("Delivery Date"));
+        assert!(!is_checkout_attribute("delivery date"));
+        assert!(!is_checkout_attribute("gift"));
+    }
+}
@@ -0,0 +1,602 @@
+//! Date parsing and comparison for date-valued fields.
+//!
+//! Storefronts hand us dates in many shapes: ISO-8601 timestamps from the
+//! Admin API, and whatever the theme's date picker writes into cart
+//! attributes ("06/15/2024", "15.06.2024", "June 15, 2024", ...). Dates
+//! written without an offset are placed in the shop's timezone when
+//! they're resolved.
+//!
+//! Accepted shapes, with one- or two-digit months, days, hours, minutes
+//! and seconds:
+//! - `2024-06-15T10:00:00Z`, `2024-06-15T10:00:00.5+02:00`,
+//!   `2024-06-15 10:00+0200`, and the same without an offset
+//! - `2024-06-15`, `2024/06/15`, `15.06.2024`, `15-06-2024`
+//! - `06/15/2024` or `15/06/2024`, read in the configured [`DateOrder`]
+//!   unless only the other order gives a real date
+//! - `June 15, 2024`, `15 June 2024`, `Sat Jun 15 2024`,
+//!   `Saturday, June 15, 2024` (full or three-letter names, any case; a
+//!   weekday must match the date)
+
+use core::cmp::Ordering;
+
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Longest span `within_days` looks across, in seconds (~31 billion
+/// years). Longer spans are clamped to it, so they're unbounded in
+/// practice instead of overflowing.
+const MAX_SPAN_SECONDS: f64 = 1e18;
+
+const MONTHS: [&str; 12] = [
+    "january", "february", "march", "april", "may", "june", "july", "august", "september", "october", "november",
+    "december",
+];
+
+const WEEKDAYS: [&str; 7] = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+
+/// A moment in time, with the UTC offset it was written in. Compares by
+/// the moment alone.
+#[derive(Debug, Clone, Copy)]
+pub struct DateTime {
+    /// Seconds since the Unix epoch.
+    seconds: i64,
+    nanos: u32,
+    /// Seconds east of UTC.
+    offset: i32,
+}
+
+impl DateTime {
+    /// `seconds` since the Unix epoch plus `nanos`, shown in `offset`
+    /// (seconds east of UTC).
+    pub fn new(seconds: i64, nanos: u32, offset: i32) -> Self {
+        DateTime { seconds, nanos, offset }
+    }
+
+    /// Seconds since the Unix epoch.
+    pub fn timestamp(&self) -> i64 {
+        self.seconds
+    }
+
+    pub fn nanos(&self) -> u32 {
+        self.nanos
+    }
+
+    /// Seconds east of UTC.
+    pub fn offset(&self) -> i32 {
+        self.offset
+    }
+
+    /// The same moment in another offset.
+    pub fn with_offset(&self, offset: i32) -> Self {
+        DateTime { offset, ..*self }
+    }
+
+    /// Nanoseconds since the Unix epoch.
+    pub fn instant(&self) -> i128 {
+        self.seconds as i128 * NANOS_PER_SECOND + self.nanos as i128
+    }
+
+    /// The day of the week in the date's own offset.
+    pub fn weekday(&self) -> Weekday {
+        let local = self.seconds.saturating_add(self.offset as i64);
+        Weekday::from_days(local.div_euclid(SECONDS_PER_DAY))
+    }
+}
+
+impl PartialEq for DateTime {
+    fn eq(&self, other: &Self) -> bool {
+        self.instant() == other.instant()
+    }
+}
+
+impl Eq for DateTime {}
+
+impl PartialOrd for DateTime {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DateTime {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.instant().cmp(&other.instant())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] =
+        [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun];
+
+    /// Monday is 0.
+    pub fn num_days_from_monday(self) -> u32 {
+        self as u32
+    }
+
+    /// The weekday `n` days after Monday, for `n` up to 6.
+    pub fn from_num_days_from_monday(n: u32) -> Option<Self> {
+        Self::ALL.get(n as usize).copied()
+    }
+
+    /// The weekday of a day counted from 1970-01-01, a Thursday.
+    fn from_days(days: i64) -> Self {
+        Self::ALL[(days + 3).rem_euclid(7) as usize]
+    }
+}
+
+/// How all-numeric slash dates such as `01/02/2024` are read. The other
+/// order is still tried when the preferred one isn't a real date
+/// (`13/02/2024` is always 13 February).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "SCREAMING_SNAKE_CASE"))]
+pub enum DateOrder {
+    /// `MM/DD/YYYY`, the Shopify storefront default.
+    #[default]
+    MonthFirst,
+    /// `DD/MM/YYYY`.
+    DayFirst,
+}
+
+impl DateOrder {
+    /// The order spelled `name` in the config.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "MONTH_FIRST" => Some(DateOrder::MonthFirst),
+            "DAY_FIRST" => Some(DateOrder::DayFirst),
+            _ => None,
+        }
+    }
+}
+
+/// A parsed date that may still need the shop's offset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParsedDate {
+    /// The input carried an explicit offset.
+    Fixed(DateTime),
+    /// No offset: `seconds` since the epoch as if written in UTC.
+    Local { seconds: i64, nanos: u32 },
+}
+
+impl ParsedDate {
+    /// Resolve to a moment, placing local dates in `offset` (seconds east
+    /// of UTC).
+    pub fn resolve(&self, offset: i32) -> DateTime {
+        match *self {
+            ParsedDate::Fixed(dt) => dt,
+            ParsedDate::Local { seconds, nanos } => DateTime::new(seconds - offset as i64, nanos, offset),
+        }
+    }
+}
+
+/// Parse a date or datetime string. Dates without an offset are placed in
+/// `offset` (seconds east of UTC); date-only strings resolve to midnight.
+pub fn parse_date(input: &str, offset: i32, order: DateOrder) -> Option<DateTime> {
+    parse_date_parts(input, order).map(|date| date.resolve(offset))
+}
+
+/// Parse a date or datetime string without resolving its offset, for
+/// condition values that are parsed before the shop's timezone is known.
+pub fn parse_date_parts(input: &str, order: DateOrder) -> Option<ParsedDate> {
+    let input = input.trim();
+    if input.is_empty() {
+        return None;
+    }
+    parse_iso(input)
+        .or_else(|| parse_numeric_date(input, order))
+        .or_else(|| parse_text_date(input))
+}
+
+/// Parse a weekday name ("sat", "Saturday", "SAT").
+pub fn parse_weekday(input: &str) -> Option<Weekday> {
+    let input = input.trim();
+    let index = WEEKDAYS
+        .iter()
+        .position(|name| input.eq_ignore_ascii_case(name) || input.eq_ignore_ascii_case(&name[..3]))?;
+    Weekday::from_num_days_from_monday(index as u32)
+}
+
+/// `days` as a span in nanoseconds, clamped to about ±31 billion years.
+/// NaN is an empty span.
+pub fn days_span(days: f64) -> i128 {
+    let seconds = (days * SECONDS_PER_DAY as f64).clamp(-MAX_SPAN_SECONDS, MAX_SPAN_SECONDS);
+    seconds as i128 * NANOS_PER_SECOND
+}
+
+/// True if `date` falls between `now` and `now + days`. Negative `days`
+/// look backwards, so `-30` means "within the last 30 days".
+pub fn within_days(date: &DateTime, now: &DateTime, days: f64) -> bool {
+    let (date, now, span) = (date.instant(), now.instant(), days_span(days));
+    if span >= 0 {
+        date >= now && date <= now + span
+    } else {
+        date <= now && date >= now + span
+    }
+}
+
+/// True if `date`, in its own offset, falls on one of `days`.
+pub fn day_of_week_in(date: &DateTime, days: &[Weekday]) -> bool {
+    days.contains(&date.weekday())
+}
+
+/// Days from 1970-01-01 to a calendar date, or `None` if it isn't one.
+fn days_from_civil(year: u32, month: u32, day: u32) -> Option<i64> {
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    let month_days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if day == 0 || day > month_days {
+        return None;
+    }
+
+    // Howard Hinnant's days_from_civil, with years starting in March
+    let year = year as i64 - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146_097 + day_of_era - 719_468)
+}
+
+fn local_midnight(days: i64) -> ParsedDate {
+    ParsedDate::Local { seconds: days * SECONDS_PER_DAY, nanos: 0 }
+}
+
+/// `YYYY-MM-DD`, optionally followed by a time and an offset.
+fn parse_iso(input: &str) -> Option<ParsedDate> {
+    let mut cursor = Cursor::new(input);
+    let year = cursor.number(4, 4)?;
+    cursor.expect(b'-')?;
+    let month = cursor.number(1, 2)?;
+    cursor.expect(b'-')?;
+    let day = cursor.number(1, 2)?;
+    let days = days_from_civil(year, month, day)?;
+    if cursor.is_done() {
+        return Some(local_midnight(days));
+    }
+
+    if !(cursor.eat(b'T') || cursor.eat(b't') || cursor.eat(b' ')) {
+        return None;
+    }
+    let hour = cursor.number(1, 2)?;
+    cursor.expect(b':')?;
+    let minute = cursor.number(1, 2)?;
+    let second = if cursor.eat(b':') { cursor.number(1, 2)? } else { 0 };
+    let nanos = if cursor.eat(b'.') { cursor.fraction()? } else { 0 };
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let seconds = days * SECONDS_PER_DAY + (hour * 3600 + minute * 60 + second) as i64;
+    if cursor.is_done() {
+        return Some(ParsedDate::Local { seconds, nanos });
+    }
+
+    let offset = cursor.offset()?;
+    if !cursor.is_done() {
+        return None;
+    }
+    Some(ParsedDate::Fixed(DateTime::new(seconds - offset as i64, nanos, offset)))
+}
+
+/// `YYYY/MM/DD`, `DD.MM.YYYY`, `DD-MM-YYYY`, and slash dates in either
+/// order.
+fn parse_numeric_date(input: &str, order: DateOrder) -> Option<ParsedDate> {
+    let mut cursor = Cursor::new(input);
+    let start = cursor.position;
+    let first = cursor.number(1, 4)?;
+    let first_digits = cursor.position - start;
+    let separator = cursor.next().filter(|c| matches!(c, b'/' | b'.' | b'-'))?;
+    let second = cursor.number(1, 2)?;
+    cursor.expect(separator)?;
+
+    let days = if first_digits == 4 {
+        // Year first: only `YYYY/MM/DD` (dashes are ISO, handled above)
+        if separator != b'/' {
+            return None;
+        }
+        days_from_civil(first, second, cursor.number(1, 2)?)
+    } else {
+        if first_digits > 2 {
+            return None;
+        }
+        let year = cursor.number(4, 4)?;
+        match (separator, order) {
+            (b'/', DateOrder::MonthFirst) => {
+                days_from_civil(year, first, second).or_else(|| days_from_civil(year, second, first))
+            }
+            (b'/', DateOrder::DayFirst) => {
+                days_from_civil(year, second, first).or_else(|| days_from_civil(year, first, second))
+            }
+            _ => days_from_civil(year, second, first),
+        }
+    }?;
+    cursor.is_done().then(|| local_midnight(days))
+}
+
+/// `June 15, 2024` or `15 June 2024`, optionally after a weekday.
+fn parse_text_date(input: &str) -> Option<ParsedDate> {
+    let mut words = input.split_whitespace().map(|word| word.strip_suffix(',').unwrap_or(word));
+    let mut word = words.next()?;
+    let weekday = parse_weekday(word);
+    if weekday.is_some() {
+        word = words.next()?;
+    }
+    let (month, day) = match month_number(word) {
+        Some(month) => (month, day_number(words.next()?)?),
+        None => (month_number(words.next()?)?, day_number(word)?),
+    };
+    let year = words.next().filter(|w| w.len() == 4)?;
+    let year = Cursor::new(year).number(4, 4)?;
+    if words.next().is_some() {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day)?;
+    if weekday.is_some_and(|w| w != Weekday::from_days(days)) {
+        return None;
+    }
+    Some(local_midnight(days))
+}
+
+/// 1 for "January" or "jan".
+fn month_number(word: &str) -> Option<u32> {
+    let index = MONTHS
+        .iter()
+        .position(|name| word.eq_ignore_ascii_case(name) || word.eq_ignore_ascii_case(&name[..3]))?;
+    Some(index as u32 + 1)
+}
+
+fn day_number(word: &str) -> Option<u32> {
+    let mut cursor = Cursor::new(word);
+    let day = cursor.number(1, 2)?;
+    cursor.is_done().then_some(day)
+}
+
+/// Reads a date string byte by byte.
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str) -> Self {
+        Cursor { bytes: input.as_bytes(), position: 0 }
+    }
+
+    fn is_done(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.position += 1;
+        Some(byte)
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let matched = self.peek() == Some(byte);
+        if matched {
+            self.position += 1;
+        }
+        matched
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.eat(byte).then_some(())
+    }
+
+    /// A number of `min` to `max` ASCII digits.
+    fn number(&mut self, min: usize, max: usize) -> Option<u32> {
+        let start = self.position;
+        let mut value = 0;
+        while self.position - start < max {
+            match self.peek() {
+                Some(digit @ b'0'..=b'9') => {
+                    value = value * 10 + (digit - b'0') as u32;
+                    self.position += 1;
+                }
+                _ => break,
+            }
+        }
+        (self.position - start >= min).then_some(value)
+    }
+
+    /// Fractional seconds after the `.`, as nanoseconds. Digits past the
+    /// ninth are dropped.
+    fn fraction(&mut self) -> Option<u32> {
+        let start = self.position;
+        let mut nanos = 0;
+        let mut scale = 100_000_000;
+        while let Some(digit @ b'0'..=b'9') = self.peek() {
+            nanos += (digit - b'0') as u32 * scale;
+            scale /= 10;
+            self.position += 1;
+        }
+        (self.position > start).then_some(nanos)
+    }
+
+    /// `Z`, or `+HH:MM` / `+HHMM` / `-HH:MM`, in seconds east of UTC.
+    fn offset(&mut self) -> Option<i32> {
+        if self.eat(b'Z') || self.eat(b'z') {
+            return Some(0);
+        }
+        let sign = match self.next()? {
+            b'+' => 1,
+            b'-' => -1,
+            _ => return None,
+        };
+        let hours = self.number(2, 2)?;
+        self.eat(b':');
+        let minutes = self.number(2, 2)?;
+        if hours > 23 || minutes > 59 {
+            return None;
+        }
+        Some(sign * (hours * 3600 + minutes * 60) as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Local midnight on a date, in UTC.
+    fn date(year: u32, month: u32, day: u32) -> DateTime {
+        local_midnight(days_from_civil(year, month, day).unwrap()).resolve(0)
+    }
+
+    #[test]
+    fn test_parse_iso_with_offset() {
+        let dt = parse_date("2024-06-15T10:00:00+02:00", 0, DateOrder::MonthFirst).unwrap();
+        assert_eq!((dt.timestamp(), dt.offset()), (1_718_438_400, 7200));
+
+        let dt = parse_date("2024-06-15T10:00:00.25Z", 3600, DateOrder::MonthFirst).unwrap();
+        assert_eq!((dt.timestamp(), dt.nanos(), dt.offset()), (1_718_445_600, 250_000_000, 0));
+
+        let dt = parse_date("2024-06-15 10:00-0530", 0, DateOrder::MonthFirst).unwrap();
+        assert_eq!((dt.timestamp(), dt.offset()), (1_718_465_400, -19_800));
+    }
+
+    #[test]
+    fn test_parse_naive_uses_offset() {
+        let est = -5 * 3600;
+        let dt = parse_date("2024-06-15", est, DateOrder::MonthFirst).unwrap();
+        assert_eq!((dt.timestamp(), dt.offset()), (date(2024, 6, 15).timestamp() + 5 * 3600, est));
+
+        let local = parse_date_parts("2024-06-15T08:30", DateOrder::MonthFirst).unwrap();
+        assert!(matches!(local, ParsedDate::Local { .. }));
+        assert_eq!(local.resolve(0).timestamp(), date(2024, 6, 15).timestamp() + 8 * 3600 + 30 * 60);
+    }
+
+    #[test]
+    fn test_parse_storefront_formats() {
+        let expected = date(2024, 6, 15);
+        for input in [
+            "2024-06-15",
+            "2024/06/15",
+            "06/15/2024",
+            "15/06/2024",
+            "15.06.2024",
+            "15-06-2024",
+            "6/15/2024",
+            "June 15, 2024",
+            "Jun 15, 2024",
+            "june 15 2024",
+            "15 June 2024",
+            "15 jun 2024",
+            "Sat Jun 15 2024",
+            "Saturday, June 15, 2024",
+            "  2024-06-15  ",
+        ] {
+            let dt = parse_date(input, 0, DateOrder::MonthFirst).unwrap_or_else(|| panic!("failed to parse {}", input));
+            assert_eq!(dt, expected, "{}", input);
+        }
+
+        for input in [
+            "",
+            "next tuesday",
+            "2024-13-45",
+            "2023-02-29",
+            "2024-06-15T24:00",
+            "2024-06-15T10:00:00+2",
+            "2024-06-15T10:00:00 UTC",
+            "Fri Jun 15 2024",
+            "June 31, 2024",
+            "15/06/24",
+            "123/06/2024",
+        ] {
+            assert!(parse_date(input, 0, DateOrder::MonthFirst).is_none(), "{}", input);
+        }
+        assert!(parse_date("2024-02-29", 0, DateOrder::MonthFirst).is_some());
+    }
+
+    #[test]
+    fn test_date_order() {
+        let month_first = parse_date("01/02/2024", 0, DateOrder::MonthFirst).unwrap();
+        let day_first = parse_date("01/02/2024", 0, DateOrder::DayFirst).unwrap();
+        assert_eq!(month_first, date(2024, 1, 2));
+        assert_eq!(day_first, date(2024, 2, 1));
+
+        // Only one reading is a date
+        assert_eq!(parse_date("13/02/2024", 0, DateOrder::MonthFirst), Some(date(2024, 2, 13)));
+        assert_eq!(parse_date("02/13/2024", 0, DateOrder::DayFirst), Some(date(2024, 2, 13)));
+
+        // Dots and dashes are always day first
+        assert_eq!(parse_date("01.02.2024", 0, DateOrder::MonthFirst), Some(date(2024, 2, 1)));
+
+        assert_eq!(DateOrder::from_name("DAY_FIRST"), Some(DateOrder::DayFirst));
+        assert_eq!(DateOrder::from_name("day_first"), None);
+    }
+
+    #[test]
+    fn test_days_from_civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), Some(0));
+        assert_eq!(days_from_civil(2000, 3, 1), Some(11_017));
+        assert_eq!(days_from_civil(1969, 12, 31), Some(-1));
+        assert_eq!(days_from_civil(0, 1, 1), Some(-719_528));
+        assert_eq!(days_from_civil(1900, 2, 29), None);
+        assert_eq!(days_from_civil(2024, 0, 1), None);
+    }
+
+    #[test]
+    fn test_within_days() {
+        let now = parse_date("2024-06-15T12:00:00Z", 0, DateOrder::MonthFirst).unwrap();
+        let tomorrow = parse_date("2024-06-16T12:00:00Z", 0, DateOrder::MonthFirst).unwrap();
+        let last_week = parse_date("2024-06-08T12:00:00Z", 0, DateOrder::MonthFirst).unwrap();
+
+        assert!(within_days(&tomorrow, &now, 2.0));
+        assert!(!within_days(&tomorrow, &now, -2.0));
+        assert!(within_days(&last_week, &now, -7.0));
+        assert!(!within_days(&last_week, &now, -6.0));
+    }
+
+    #[test]
+    fn test_within_days_huge_spans() {
+        let now = date(2024, 6, 15);
+        let far_future = date(9999, 12, 31);
+        let far_past = date(0, 1, 1);
+
+        assert!(within_days(&far_future, &now, f64::MAX));
+        assert!(within_days(&far_future, &now, f64::INFINITY));
+        assert!(within_days(&far_past, &now, f64::NEG_INFINITY));
+        assert!(within_days(&far_past, &now, -1e300));
+        assert!(!within_days(&far_past, &now, f64::INFINITY));
+        // NaN days is an empty span
+        assert!(within_days(&now, &now, f64::NAN));
+        assert!(!within_days(&far_future, &now, f64::NAN));
+    }
+
+    #[test]
+    fn test_weekdays() {
+        assert_eq!(date(1970, 1, 1).weekday(), Weekday::Thu);
+        assert_eq!(date(2024, 6, 15).weekday(), Weekday::Sat);
+        assert_eq!(date(1969, 12, 28).weekday(), Weekday::Sun);
+
+        // Saturday 23:30 in New York is already Sunday in UTC
+        let dt = parse_date("2024-06-15T23:30:00-04:00", 0, DateOrder::MonthFirst).unwrap();
+        assert!(day_of_week_in(&dt, &[Weekday::Sat]));
+        assert!(!day_of_week_in(&dt.with_offset(0), &[Weekday::Sat]));
+
+        assert_eq!(parse_weekday("sun"), Some(Weekday::Sun));
+        assert_eq!(parse_weekday(" SATURDAY "), Some(Weekday::Sat));
+        assert_eq!(parse_weekday("someday"), None);
+        assert_eq!(parse_weekday("sa"), None);
+    }
+}
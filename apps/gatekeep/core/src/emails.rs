@@ -0,0 +1,188 @@
+//! Email domain checks for `IS_DISPOSABLE_EMAIL` and `EMAIL_DOMAIN_IN`.
+//!
+//! A domain matches a list entry if it is the entry or a subdomain of it,
+//! so `mailinator.com` also covers `eu.mailinator.com`. Matching ignores
+//! case and a trailing dot.
+//!
+//! The disposable list is kept as one sorted, newline-separated string,
+//! which embeds at about the size of its text.
+
+use alloc::string::String;
+
+/// Well-known disposable and throwaway mail domains, sorted, one per line.
+pub const DISPOSABLE_DOMAINS: &str = "\
+0-mail.com
+0wnd.net
+0wnd.org
+10mail.org
+10minutemail.co.uk
+10minutemail.com
+10minutemail.net
+20minutemail.com
+33mail.com
+anonbox.net
+armyspy.com
+burnermail.io
+byom.de
+crazymailing.com
+cuvox.de
+dayrep.com
+discard.email
+discardmail.com
+discardmail.de
+disposableemailaddresses.com
+dispostable.com
+dropmail.me
+einrot.com
+emailfake.com
+emailondeck.com
+emlhub.com
+emlpro.com
+emltmp.com
+fake-box.com
+fakeinbox.com
+fakemail.net
+fakemailgenerator.com
+filzmail.com
+fleckens.hu
+getairmail.com
+getnada.com
+grr.la
+guerrillamail.biz
+guerrillamail.com
+guerrillamail.de
+guerrillamail.info
+guerrillamail.net
+guerrillamail.org
+guerrillamailblock.com
+gustr.com
+harakirimail.com
+inboxbear.com
+inboxkitten.com
+incognitomail.org
+jetable.org
+jourrapide.com
+luxusmail.org
+mail.tm
+mailcatch.com
+maildrop.cc
+mailexpire.com
+mailforspam.com
+mailinator.com
+mailinator.net
+mailinator2.com
+mailnesia.com
+mailnull.com
+mailpoof.com
+mailsac.com
+meltmail.com
+mintemail.com
+minuteinbox.com
+moakt.com
+mohmal.com
+mvrht.com
+mytemp.email
+mytrashmail.com
+nada.email
+owlymail.com
+pokemail.net
+rhyta.com
+sharklasers.com
+spam4.me
+spambox.us
+spamdecoy.net
+spamfree24.org
+spamgourmet.com
+superrito.com
+teleworm.us
+temp-mail.io
+temp-mail.org
+tempail.com
+tempemail.net
+tempinbox.com
+tempm.com
+tempmailaddress.com
+tempmailo.com
+temporary-mail.net
+tempr.email
+throwawaymail.com
+tmails.net
+tmpmail.net
+tmpmail.org
+trash-mail.com
+trashmail.com
+trashmail.de
+trashmail.me
+trashmail.net
+wegwerfmail.de
+wegwerfmail.net
+yopmail.com
+yopmail.fr
+yopmail.net
+";
+
+/// The domain of an email address, lowercased, without a trailing dot.
+/// `None` if the address has no `@`, or nothing before or after it.
+pub fn email_domain(email: &str) -> Option<String> {
+    let (local, domain) = email.trim().rsplit_once('@')?;
+    let domain = domain.strip_suffix('.').unwrap_or(domain);
+    (!local.is_empty() && !domain.is_empty()).then(|| domain.to_ascii_lowercase())
+}
+
+/// `domain` and each domain it's a subdomain of, most specific first:
+/// `a.b.com`, `b.com`, `com`.
+pub fn parent_domains(domain: &str) -> impl Iterator<Item = &str> {
+    core::iter::successors(Some(domain), |d| d.split_once('.').map(|(_, parent)| parent))
+}
+
+/// True if the address is at a disposable domain or a subdomain of one.
+/// `None` if it isn't an email address.
+pub fn is_disposable_email(email: &str) -> Option<bool> {
+    let domain = email_domain(email)?;
+    let disposable = parent_domains(&domain).any(|d| DISPOSABLE_DOMAINS.lines().any(|entry| entry == d));
+    Some(disposable)
+}
+
+/// Normalize an `EMAIL_DOMAIN_IN` entry: lowercase, and without a leading
+/// `@` or trailing dot, so `@Example.com` and `example.com.` both work.
+pub fn normalize_domain(entry: &str) -> String {
+    let entry = entry.trim();
+    let entry = entry.strip_prefix('@').unwrap_or(entry);
+    entry.strip_suffix('.').unwrap_or(entry).to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_is_sorted() {
+        let domains: Vec<&str> = DISPOSABLE_DOMAINS.lines().collect();
+        assert!(domains.windows(2).all(|w| w[0] < w[1]), "sorted and unique");
+        assert!(domains.iter().all(|d| *d == normalize_domain(d) && d.contains('.')));
+    }
+
+    #[test]
+    fn test_email_domain() {
+        assert_eq!(email_domain(" Jane.Doe@Example.COM ").as_deref(), Some("example.com"));
+        assert_eq!(email_domain("\"a@b\"@example.com.").as_deref(), Some("example.com"));
+        assert_eq!(email_domain("example.com"), None);
+        assert_eq!(email_domain("@example.com"), None);
+        assert_eq!(email_domain("jane@"), None);
+    }
+
+    #[test]
+    fn test_disposable() {
+        assert_eq!(is_disposable_email("bot@mailinator.com"), Some(true));
+        assert_eq!(is_disposable_email("bot@EU.Mailinator.com"), Some(true));
+        assert_eq!(is_disposable_email("bot@yopmail.fr"), Some(true));
+        assert_eq!(is_disposable_email("jane@gmail.com"), Some(false));
+        // Only whole labels count
+        assert_eq!(is_disposable_email("jane@notmailinator.com"), Some(false));
+        assert_eq!(is_disposable_email("jane@mailinator.com.au"), Some(false));
+        assert_eq!(is_disposable_email("not an email"), None);
+
+        assert_eq!(parent_domains("a.b.com").collect::<Vec<_>>(), vec!["a.b.com", "b.com", "com"]);
+        assert_eq!(normalize_domain(" @Example.COM. "), "example.com");
+    }
+}
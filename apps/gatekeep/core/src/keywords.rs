@@ -0,0 +1,191 @@
+//! Multi-keyword matching for `CONTAINS_ANY_OF` / `CONTAINS_NONE_OF`.
+//!
+//! The keyword list is compiled once into a small Aho-Corasick automaton
+//! over chars, so a field is scanned in a single pass however long the
+//! list is, instead of once per `CONTAINS` condition in an OR group. It's
+//! hand-written because the `aho-corasick` crate is too large for the
+//! checkout function's binary.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Keywords per extra complexity point on top of [`KEYWORD_LIST_POINTS`].
+pub const KEYWORDS_PER_POINT: usize = 50;
+
+/// Base complexity points for a keyword list condition, the same as a
+/// single `CONTAINS`.
+pub const KEYWORD_LIST_POINTS: u32 = 2;
+
+/// Complexity points for a keyword list of `count` keywords: the automaton
+/// is built on every checkout, so longer lists cost more.
+pub fn keyword_list_points(count: usize) -> u32 {
+    KEYWORD_LIST_POINTS + count.div_ceil(KEYWORDS_PER_POINT) as u32
+}
+
+/// A compiled keyword list.
+#[derive(Debug, Clone)]
+pub struct KeywordSet {
+    states: Vec<State>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    /// Trie edges, sorted by char.
+    next: Vec<(char, u32)>,
+    /// Longest proper suffix of this state that is also a trie prefix.
+    fail: u32,
+    /// A keyword ends here.
+    terminal: bool,
+    /// A keyword ends here or at a state on the fail chain.
+    matches: bool,
+}
+
+impl KeywordSet {
+    /// Compile `keywords`. They're matched as given, so callers normalize
+    /// them the same way as the text.
+    pub fn new<'a, I>(keywords: I) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut states = vec![State::default()];
+        for keyword in keywords {
+            let mut s = 0;
+            for c in keyword.chars() {
+                s = match states[s].next.binary_search_by_key(&c, |&(edge, _)| edge) {
+                    Ok(i) => states[s].next[i].1 as usize,
+                    Err(i) => {
+                        states.push(State::default());
+                        let id = states.len() - 1;
+                        states[s].next.insert(i, (c, id as u32));
+                        id
+                    }
+                };
+            }
+            states[s].terminal = true;
+            states[s].matches = true;
+        }
+
+        // Fail links, breadth first so shallower states are done first.
+        // States one step from the root fail to the root.
+        let mut queue: Vec<usize> = states[0].next.iter().map(|&(_, t)| t as usize).collect();
+        let mut head = 0;
+        while let Some(&s) = queue.get(head) {
+            head += 1;
+            for i in 0..states[s].next.len() {
+                let (c, t) = states[s].next[i];
+                let mut f = states[s].fail as usize;
+                let fail = loop {
+                    if let Some(n) = step(&states, f, c) {
+                        break n;
+                    }
+                    if f == 0 {
+                        break 0;
+                    }
+                    f = states[f].fail as usize;
+                };
+                states[t as usize].fail = fail as u32;
+                states[t as usize].matches |= states[fail].matches;
+                queue.push(t as usize);
+            }
+        }
+
+        Self { states }
+    }
+
+    /// True if any keyword occurs anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let mut s = 0;
+        if self.states[s].matches {
+            return true;
+        }
+        for c in text.chars() {
+            s = loop {
+                if let Some(n) = step(&self.states, s, c) {
+                    break n;
+                }
+                if s == 0 {
+                    break 0;
+                }
+                s = self.states[s].fail as usize;
+            };
+            if self.states[s].matches {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// True if `text` is exactly one of the keywords. Used for list fields
+    /// such as customer tags, which `CONTAINS` treats as membership.
+    pub fn contains(&self, text: &str) -> bool {
+        let mut s = 0;
+        for c in text.chars() {
+            match step(&self.states, s, c) {
+                Some(n) => s = n,
+                None => return false,
+            }
+        }
+        self.states[s].terminal
+    }
+}
+
+fn step(states: &[State], s: usize, c: char) -> Option<usize> {
+    let next = &states[s].next;
+    next.binary_search_by_key(&c, |&(edge, _)| edge).ok().map(|i| next[i].1 as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every string up to five chars over a small alphabet, compared with
+    /// `str::contains`.
+    #[test]
+    fn test_matches_like_contains() {
+        let lists: &[&[&str]] = &[
+            &["he", "she", "his", "hers"],
+            &["a", "ab", "bab", "bc", "bca", "c", "caa"],
+            &["aaa", "aab", "abaa"],
+            &["straße", "ſt", "é"],
+            &[],
+            &[""],
+        ];
+        let alphabet = ['a', 'b', 'c', 'h', 'e', 's'];
+        let mut texts = vec![String::new()];
+        let mut layer = texts.clone();
+        for _ in 0..5 {
+            layer = layer.iter().flat_map(|t| alphabet.iter().map(move |c| format!("{}{}", t, c))).collect();
+            texts.extend(layer.iter().cloned());
+        }
+        texts.extend(["hauptstraße", "ſtill", "café"].map(String::from));
+
+        for keywords in lists {
+            let set = KeywordSet::new(keywords.iter().copied());
+            for text in &texts {
+                let expected = keywords.iter().any(|k| text.contains(k));
+                assert_eq!(set.is_match(text), expected, "{:?} in {:?}", keywords, text);
+                assert_eq!(set.contains(text), keywords.contains(&text.as_str()), "{:?} == {:?}", keywords, text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_membership_and_edge_cases() {
+        let set = KeywordSet::new(["vip", "wholesale", "vip"]);
+        assert!(set.contains("vip"));
+        assert!(!set.contains("vip2"));
+        assert!(!set.contains("vi"));
+
+        assert!(!KeywordSet::new([]).is_match("anything"));
+        // An empty keyword occurs in every string, as with CONTAINS ""
+        assert!(KeywordSet::new([""]).is_match("anything"));
+    }
+
+    #[test]
+    fn test_complexity_points() {
+        assert_eq!(keyword_list_points(0), 2);
+        assert_eq!(keyword_list_points(1), 3);
+        assert_eq!(keyword_list_points(50), 3);
+        assert_eq!(keyword_list_points(200), 6);
+    }
+}
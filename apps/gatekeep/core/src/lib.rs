@@ -0,0 +1,21 @@
+//! LogicFlow Core - matching logic shared by the POC rule engine
+//! (`logicflow`) and the checkout function, so the two can't drift apart.
+//!
+//! `no_std` (with `alloc`): the checkout function is a size-limited wasm
+//! module, and nothing here needs the OS.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod address_quality;
+pub mod attributes;
+pub mod dates;
+pub mod emails;
+pub mod keywords;
+pub mod military;
+pub mod phones;
+pub mod postcodes;
+pub mod profanity;
+pub mod regions;
+pub mod sets;
@@ -0,0 +1,88 @@
+//! US military address detection for `IS_MILITARY_ADDRESS`.
+//!
+//! Military mail goes through APO (Army/Air Force), FPO (Fleet) or DPO
+//! (Diplomatic) post offices, written in the city field, with a pseudo
+//! state code (AA, AE, AP) in place of the state:
+//!
+//! ```text
+//! Unit 2050 Box 4190
+//! APO AP 96278
+//! ```
+//!
+//! Any one of the city, the state code or a unit/PSC/CMR box line is
+//! enough.
+
+use crate::address_quality::AddressParts;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Military post office "cities".
+pub const MILITARY_CITIES: &[&str] = &["APO", "DPO", "FPO"];
+
+/// US pseudo-state codes for military mail: Armed Forces Americas, Europe
+/// and Pacific.
+pub const MILITARY_STATES: &[&str] = &["AA", "AE", "AP"];
+
+/// Words that start a military box line, e.g. `PSC 802 Box 74`.
+pub const MILITARY_BOX_UNITS: &[&str] = &["CMR", "PSC", "UNIT"];
+
+/// True if the address is a US military address. The state code only
+/// counts for US addresses, since other countries reuse the codes (`AP` is
+/// a Brazilian state and an Indian one).
+pub fn is_military_address(parts: &AddressParts) -> bool {
+    let city: String = parts
+        .city
+        .chars()
+        .filter(char::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let state = parts.province_code.trim();
+    MILITARY_CITIES.contains(&city.as_str())
+        || (parts.country_code.trim().eq_ignore_ascii_case("US")
+            && MILITARY_STATES.iter().any(|s| s.eq_ignore_ascii_case(state)))
+        || [parts.address1, parts.address2].iter().any(|line| has_military_box(line))
+}
+
+/// True if `line` contains a box line: `UNIT`, `PSC` or `CMR` and a
+/// number, then `BOX` and a number (`Unit 2050 Box 4190`, `PSC 802, Box 74`).
+pub fn has_military_box(line: &str) -> bool {
+    let words: Vec<String> = line
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_ascii_uppercase())
+        .collect();
+    let number = |w: &str| w.bytes().all(|b| b.is_ascii_digit());
+    words
+        .windows(4)
+        .any(|w| MILITARY_BOX_UNITS.contains(&w[0].as_str()) && number(&w[1]) && w[2] == "BOX" && number(&w[3]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn military(address1: &str, city: &str, province_code: &str, country_code: &str) -> bool {
+        is_military_address(&AddressParts { address1, city, province_code, country_code, ..Default::default() })
+    }
+
+    #[test]
+    fn test_military_addresses() {
+        assert!(military("Unit 2050 Box 4190", "APO", "AP", "US"));
+        assert!(military("PSC 802, Box 74", "", "", "US"));
+        assert!(military("cmr 480 box 1234", "", "", ""));
+        assert!(military("USS Ronald Reagan (CVN 76)", "F.P.O.", "", "US"));
+        assert!(military("123 Embassy Row", "dpo", "", "US"));
+        assert!(military("123 Main St", "Anytown", "ae", "US"));
+    }
+
+    #[test]
+    fn test_civilian_addresses() {
+        assert!(!military("123 Main St", "Boston", "MA", "US"));
+        assert!(!military("Unit 4, 12 High St", "Leeds", "", "GB"));
+        assert!(!military("PO Box 12", "Apopka", "FL", "US"));
+        assert!(!military("Unit 4B Box 2", "Boston", "MA", "US"));
+        // Other countries' AP
+        assert!(!military("Rua 1, 100", "Macapá", "AP", "BR"));
+        assert!(!military("", "", "", ""));
+    }
+}
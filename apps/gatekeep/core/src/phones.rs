@@ -0,0 +1,256 @@
+//! Phone number normalization and length checks for `IS_VALID_PHONE` and
+//! phone field comparisons.
+//!
+//! Numbers are normalized to E.164 (`+` country calling code, then the
+//! national number): formatting is dropped, an international prefix
+//! (`00`, or `011` in North America) becomes `+`, and a national number
+//! loses its trunk prefix (`0` in most countries) and gains the calling
+//! code of the address's country. Validity is a length check of the
+//! national number against [`PHONE_PLANS`]; number ranges within a country
+//! are not checked.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Numbering plans, sorted by country: country code, calling code, trunk
+/// prefix (empty where numbers are dialled without one), and the shortest
+/// and longest national number.
+pub const PHONE_PLANS: &[(&str, &str, &str, u8, u8)] = &[
+    ("AE", "971", "0", 8, 9),
+    ("AR", "54", "0", 10, 11),
+    ("AT", "43", "0", 4, 13),
+    ("AU", "61", "0", 9, 9),
+    ("BE", "32", "0", 8, 9),
+    ("BR", "55", "0", 10, 11),
+    ("CA", "1", "1", 10, 10),
+    ("CH", "41", "0", 9, 9),
+    ("CL", "56", "", 9, 9),
+    ("CN", "86", "0", 7, 11),
+    ("CO", "57", "", 10, 10),
+    ("CZ", "420", "", 9, 9),
+    ("DE", "49", "0", 6, 13),
+    ("DK", "45", "", 8, 8),
+    ("EG", "20", "0", 9, 10),
+    ("ES", "34", "", 9, 9),
+    ("FI", "358", "0", 5, 12),
+    ("FR", "33", "0", 9, 9),
+    ("GB", "44", "0", 9, 10),
+    ("GR", "30", "", 10, 10),
+    ("HK", "852", "", 8, 8),
+    ("HU", "36", "06", 8, 9),
+    ("ID", "62", "0", 8, 12),
+    ("IE", "353", "0", 7, 9),
+    ("IL", "972", "0", 8, 9),
+    ("IN", "91", "0", 10, 10),
+    ("IS", "354", "", 7, 7),
+    ("IT", "39", "", 6, 11),
+    ("JP", "81", "0", 9, 10),
+    ("KE", "254", "0", 9, 9),
+    ("KR", "82", "0", 8, 10),
+    ("KZ", "7", "8", 10, 10),
+    ("LU", "352", "", 4, 11),
+    ("MX", "52", "", 10, 10),
+    ("MY", "60", "0", 8, 10),
+    ("NG", "234", "0", 8, 10),
+    ("NL", "31", "0", 9, 9),
+    ("NO", "47", "", 8, 8),
+    ("NZ", "64", "0", 8, 10),
+    ("PE", "51", "0", 8, 9),
+    ("PH", "63", "0", 8, 10),
+    ("PK", "92", "0", 9, 10),
+    ("PL", "48", "", 9, 9),
+    ("PR", "1", "1", 10, 10),
+    ("PT", "351", "", 9, 9),
+    ("RO", "40", "0", 9, 9),
+    ("RU", "7", "8", 10, 10),
+    ("SA", "966", "0", 9, 9),
+    ("SE", "46", "0", 7, 10),
+    ("SG", "65", "", 8, 8),
+    ("SK", "421", "0", 9, 9),
+    ("TH", "66", "0", 8, 9),
+    ("TR", "90", "0", 10, 10),
+    ("TW", "886", "0", 8, 9),
+    ("UA", "380", "0", 9, 9),
+    ("US", "1", "1", 10, 10),
+    ("VN", "84", "0", 9, 10),
+    ("ZA", "27", "0", 9, 9),
+];
+
+type PhonePlan = (&'static str, &'static str, &'static str, u8, u8);
+
+fn country_plan(country_code: &str) -> Option<&'static PhonePlan> {
+    let country = country_code.trim().to_ascii_uppercase();
+    PHONE_PLANS
+        .binary_search_by(|(code, ..)| (*code).cmp(country.as_str()))
+        .ok()
+        .map(|i| &PHONE_PLANS[i])
+}
+
+fn fits(plan: &PhonePlan, national: &str) -> bool {
+    (plan.3 as usize..=plan.4 as usize).contains(&national.len())
+}
+
+/// Split an E.164 number into its calling code and national number, using
+/// the longest calling code on file. `None` for unknown calling codes.
+fn split_e164(e164: &str) -> Option<(&'static str, &str)> {
+    let digits = e164.strip_prefix('+')?;
+    let code = PHONE_PLANS
+        .iter()
+        .map(|(_, code, ..)| *code)
+        .filter(|code| digits.starts_with(code))
+        .max_by_key(|code| code.len())?;
+    Some((code, &digits[code.len()..]))
+}
+
+/// The digits of a phone number, and whether it starts with `+`. Spaces,
+/// `-`, `.`, `/` and brackets are dropped; `None` for anything else
+/// (letters, extensions) or no digits at all.
+fn phone_digits(phone: &str) -> Option<(bool, String)> {
+    let phone = phone.trim();
+    let (plus, rest) = match phone.strip_prefix('+') {
+        Some(rest) => (true, rest),
+        None => (false, phone),
+    };
+    let mut digits = String::new();
+    for c in rest.chars() {
+        match c {
+            '0'..='9' => digits.push(c),
+            ' ' | '-' | '.' | '/' | '(' | ')' => {}
+            _ => return None,
+        }
+    }
+    (!digits.is_empty()).then_some((plus, digits))
+}
+
+/// Normalize a phone number to E.164, reading national numbers as being in
+/// `country_code`. `None` if it isn't a phone number, or it's national and
+/// the country isn't in [`PHONE_PLANS`]. The length isn't checked; see
+/// [`is_valid_phone`].
+pub fn normalize_phone(country_code: &str, phone: &str) -> Option<String> {
+    let (plus, digits) = phone_digits(phone)?;
+    if plus {
+        return Some(format!("+{}", digits));
+    }
+    let plan = country_plan(country_code);
+    let international_prefix = match plan {
+        Some((_, "1", ..)) => "011",
+        _ => "00",
+    };
+    if let Some(rest) = digits.strip_prefix(international_prefix) {
+        return Some(format!("+{}", rest));
+    }
+    let plan = plan?;
+    let mut national = match digits.strip_prefix(plan.2) {
+        Some(rest) if !plan.2.is_empty() => rest,
+        _ => digits.as_str(),
+    };
+    // The calling code written without its `+` ("44 7911 123456")
+    if !fits(plan, national) {
+        if let Some(rest) = digits.strip_prefix(plan.1).filter(|rest| fits(plan, rest)) {
+            national = rest;
+        }
+    }
+    Some(format!("+{}{}", plan.1, national))
+}
+
+/// True if the number's national part has a valid length for its calling
+/// code. National numbers are read as being in `country_code`. `None` when
+/// the calling code or country isn't in [`PHONE_PLANS`].
+pub fn is_valid_phone(country_code: &str, phone: &str) -> Option<bool> {
+    if phone_digits(phone).is_none() {
+        return Some(false);
+    }
+    let e164 = normalize_phone(country_code, phone)?;
+    let (code, national) = split_e164(&e164)?;
+    let valid = PHONE_PLANS.iter().any(|plan| plan.1 == code && fits(plan, national));
+    Some(valid)
+}
+
+/// A phone number written for comparison: its digits, with a leading `+`
+/// if it had one. Formatting is dropped, so `(555) 123-4567` and
+/// `555.123.4567` are the same key.
+pub fn phone_key(phone: &str) -> String {
+    let phone = phone.trim();
+    let plus = if phone.starts_with('+') { "+" } else { "" };
+    let digits: String = phone.chars().filter(char::is_ascii_digit).collect();
+    format!("{}{}", plus, digits)
+}
+
+/// The keys a number can be written as: E.164, the national number, and
+/// the national number with its trunk prefix (`+15551234567`,
+/// `5551234567`, `15551234567`). A number that isn't E.164 only has its
+/// own [`phone_key`].
+pub fn phone_forms(number: &str) -> Vec<String> {
+    let key = phone_key(number);
+    let Some((code, national)) = split_e164(&key) else {
+        return vec![key];
+    };
+    let mut forms = vec![key.clone(), national.to_string()];
+    if let Some((.., trunk, _, _)) = PHONE_PLANS.iter().find(|plan| plan.1 == code && !plan.2.is_empty()) {
+        forms.push(format!("{}{}", trunk, national));
+    }
+    forms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plans_are_sorted() {
+        assert!(PHONE_PLANS.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(PHONE_PLANS.iter().all(|plan| plan.3 <= plan.4 && plan.1.len() + plan.4 as usize <= 15));
+    }
+
+    #[test]
+    fn test_normalize_phone() {
+        for (country, phone, e164) in [
+            ("US", "+1 (555) 123-4567", "+15551234567"),
+            ("US", "5551234567", "+15551234567"),
+            ("us", "1-555-123-4567", "+15551234567"),
+            ("US", "011 44 20 7946 0000", "+442079460000"),
+            ("GB", "020 7946 0000", "+442079460000"),
+            ("GB", "0044 20 7946 0000", "+442079460000"),
+            ("GB", "44 7911 123456", "+447911123456"),
+            ("DE", "030/1234567", "+49301234567"),
+            ("IT", "06 1234 5678", "+390612345678"),
+            ("HU", "06 1 234 5678", "+3612345678"),
+            ("RU", "8 (495) 123-45-67", "+74951234567"),
+            ("", "+33 1 23 45 67 89", "+33123456789"),
+        ] {
+            assert_eq!(normalize_phone(country, phone).as_deref(), Some(e164), "{} {}", country, phone);
+        }
+        assert_eq!(normalize_phone("US", "555-1234 ext. 2"), None);
+        assert_eq!(normalize_phone("US", "n/a"), None);
+        assert_eq!(normalize_phone("", "5551234567"), None);
+        assert_eq!(normalize_phone("XX", "5551234567"), None);
+    }
+
+    #[test]
+    fn test_is_valid_phone() {
+        assert_eq!(is_valid_phone("US", "(555) 123-4567"), Some(true));
+        assert_eq!(is_valid_phone("US", "123-4567"), Some(false));
+        assert_eq!(is_valid_phone("GB", "07911 123456"), Some(true));
+        assert_eq!(is_valid_phone("GB", "07911 12345"), Some(true));
+        assert_eq!(is_valid_phone("GB", "07911 1234"), Some(false));
+        assert_eq!(is_valid_phone("US", "+44 7911 123456"), Some(true));
+        assert_eq!(is_valid_phone("US", "+1 555 123 45678"), Some(false));
+        assert_eq!(is_valid_phone("US", "call me"), Some(false));
+        assert_eq!(is_valid_phone("US", ""), Some(false));
+
+        // Calling code or country not on file
+        assert_eq!(is_valid_phone("US", "+999 123 456"), None);
+        assert_eq!(is_valid_phone("XX", "5551234567"), None);
+    }
+
+    #[test]
+    fn test_phone_forms() {
+        assert_eq!(phone_forms("+15551234567"), vec!["+15551234567", "5551234567", "15551234567"]);
+        assert_eq!(phone_forms("+442079460000"), vec!["+442079460000", "2079460000", "02079460000"]);
+        assert_eq!(phone_forms("+390612345678"), vec!["+390612345678", "0612345678"]);
+        assert_eq!(phone_forms("555-1234"), vec!["5551234"]);
+        assert_eq!(phone_key(" +1 (555) 123-4567 "), "+15551234567");
+    }
+}
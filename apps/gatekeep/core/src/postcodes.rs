@@ -0,0 +1,250 @@
+//! Per-country postal code formats for `IS_VALID_POSTCODE`, and the range
+//! and prefix matching behind `IN_ZIP_RANGES` / `MATCHES_PREFIX_ANY`.
+//!
+//! Each country has one or more masks: `#` is a digit, `@` a letter, `*` a
+//! letter or digit, and a space or `-` is an optional separator (either
+//! character, or none). Anything else is a literal. Matching trims the
+//! input and ignores case. The check is "looks valid", not "exists".
+
+use alloc::string::String;
+#[cfg(feature = "json")]
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// Postal code masks by ISO 3166-1 alpha-2 country code, sorted by code.
+pub const POSTCODE_FORMATS: &[(&str, &[&str])] = &[
+    ("AT", &["####"]),
+    ("AU", &["####"]),
+    ("BE", &["####"]),
+    ("BG", &["####"]),
+    ("BR", &["#####-###"]),
+    ("CA", &["@#@ #@#"]),
+    ("CH", &["####"]),
+    ("CN", &["######"]),
+    ("CZ", &["### ##"]),
+    ("DE", &["#####"]),
+    ("DK", &["####"]),
+    ("EE", &["#####"]),
+    ("ES", &["#####"]),
+    ("FI", &["#####"]),
+    ("FR", &["#####"]),
+    ("GB", &["@# #@@", "@## #@@", "@#@ #@@", "@@# #@@", "@@## #@@", "@@#@ #@@", "GIR 0AA"]),
+    ("GR", &["### ##"]),
+    ("HR", &["#####"]),
+    ("HU", &["####"]),
+    ("IE", &["@## ****", "D6W ****"]),
+    ("IL", &["#######"]),
+    ("IN", &["### ###"]),
+    ("IS", &["###"]),
+    ("IT", &["#####"]),
+    ("JP", &["###-####"]),
+    ("KR", &["#####"]),
+    ("LT", &["LT-#####", "#####"]),
+    ("LU", &["L-####", "####"]),
+    ("LV", &["LV-####"]),
+    ("MX", &["#####"]),
+    ("NL", &["#### @@"]),
+    ("NO", &["####"]),
+    ("NZ", &["####"]),
+    ("PL", &["##-###"]),
+    ("PT", &["####-###"]),
+    ("RO", &["######"]),
+    ("SE", &["### ##"]),
+    ("SG", &["######"]),
+    ("SI", &["####"]),
+    ("SK", &["### ##"]),
+    ("US", &["#####", "#####-####"]),
+    ("ZA", &["####"]),
+];
+
+/// Masks for a country code (case-insensitive), if it's in the table.
+pub fn postcode_formats(country_code: &str) -> Option<&'static [&'static str]> {
+    let code = country_code.trim().to_ascii_uppercase();
+    POSTCODE_FORMATS
+        .binary_search_by(|(c, _)| (*c).cmp(code.as_str()))
+        .ok()
+        .map(|i| POSTCODE_FORMATS[i].1)
+}
+
+/// True if `postcode` fits one of the country's formats; `None` if the
+/// country isn't in the table.
+pub fn is_valid_postcode(country_code: &str, postcode: &str) -> Option<bool> {
+    let formats = postcode_formats(country_code)?;
+    let postcode: Vec<char> = postcode.trim().chars().collect();
+    Some(formats.iter().any(|mask| matches_mask(mask, &postcode)))
+}
+
+/// Uppercase `postcode` and drop spaces and hyphens, so "sw1a-1aa" and
+/// "SW1A 1AA" compare equal.
+pub fn canonical_postcode(postcode: &str) -> String {
+    postcode
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-'))
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// A `MATCHES_PREFIX_ANY` entry in canonical form. A trailing `*` is
+/// allowed (`902*` is the same as `902`); `None` for a `*` anywhere else.
+pub fn postcode_prefix(pattern: &str) -> Option<String> {
+    let prefix = canonical_postcode(pattern.trim().strip_suffix('*').unwrap_or(pattern.trim()));
+    (!prefix.contains('*')).then_some(prefix)
+}
+
+/// An `IN_ZIP_RANGES` entry: a `[low, high]` pair of strings or whole
+/// numbers, in canonical form.
+#[cfg(feature = "json")]
+pub fn postcode_range(value: &serde_json::Value) -> Option<(String, String)> {
+    let bound = |v: &serde_json::Value| match v {
+        serde_json::Value::String(s) => Some(canonical_postcode(s)),
+        serde_json::Value::Number(n) => n.as_u64().map(|n| n.to_string()),
+        _ => None,
+    };
+    match value.as_array()?.as_slice() {
+        [low, high] => Some((bound(low)?, bound(high)?)),
+        _ => None,
+    }
+}
+
+/// Digits compared numerically for a country whose formats are all digits
+/// (e.g. 5 for the US, whose ZIP+4 extension is ignored), or `None` for
+/// countries compared lexicographically.
+fn numeric_digits(country_code: &str) -> Option<usize> {
+    let formats = postcode_formats(country_code)?;
+    if !formats.iter().all(|mask| mask.chars().all(|c| matches!(c, '#' | ' ' | '-'))) {
+        return None;
+    }
+    formats.iter().map(|mask| mask.matches('#').count()).min()
+}
+
+/// True if `postcode` lies within `[low, high]` (canonical bounds, as from
+/// [`postcode_range`]).
+///
+/// Countries whose formats are all digits compare their leading digits
+/// numerically, so `["501", "599"]` covers "00501". Other countries, and
+/// addresses with no known country, compare canonical strings, and the
+/// upper bound includes everything that starts with it, so `["BT1", "BT9"]`
+/// covers "BT9 1AA". `None` when a numeric country's postcode or bounds
+/// aren't numbers.
+pub fn in_postcode_range(country_code: Option<&str>, postcode: &str, low: &str, high: &str) -> Option<bool> {
+    let postcode = canonical_postcode(postcode);
+    match country_code.and_then(numeric_digits) {
+        Some(digits) => {
+            let number = |s: &str, min_len: usize| -> Option<u64> {
+                let s = s.get(..digits).unwrap_or(s);
+                if s.len() < min_len || !s.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                s.parse().ok()
+            };
+            let n = number(&postcode, digits)?;
+            Some(number(low, 1)? <= n && n <= number(high, 1)?)
+        }
+        None => Some(low <= postcode.as_str() && (postcode.as_str() <= high || postcode.starts_with(high))),
+    }
+}
+
+fn matches_mask(mask: &str, input: &[char]) -> bool {
+    let mut pos = 0;
+    for m in mask.chars() {
+        let c = input.get(pos).copied();
+        let ok = match m {
+            '#' => c.is_some_and(|c| c.is_ascii_digit()),
+            '@' => c.is_some_and(|c| c.is_ascii_alphabetic()),
+            '*' => c.is_some_and(|c| c.is_ascii_alphanumeric()),
+            ' ' | '-' => {
+                if c == Some(' ') || c == Some('-') {
+                    pos += 1;
+                }
+                continue;
+            }
+            _ => c.is_some_and(|c| c.eq_ignore_ascii_case(&m)),
+        };
+        if !ok {
+            return false;
+        }
+        pos += 1;
+    }
+    pos == input.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test vectors shared with the checkout function.
+    const SHARED_VECTORS: &str = include_str!("../../testdata/postcodes.json");
+
+    #[test]
+    fn test_table_is_sorted() {
+        assert!(POSTCODE_FORMATS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn test_shared_vectors() {
+        let vectors: serde_json::Value = serde_json::from_str(SHARED_VECTORS).unwrap();
+        let vectors = vectors.as_object().unwrap();
+
+        let countries: Vec<&str> = POSTCODE_FORMATS.iter().map(|(c, _)| *c).collect();
+        let mut tested: Vec<&str> = vectors.keys().map(String::as_str).collect();
+        tested.sort();
+        assert_eq!(tested, countries, "every country needs vectors");
+
+        for (country, cases) in vectors {
+            for (key, expected) in [("valid", true), ("invalid", false)] {
+                for input in cases[key].as_array().unwrap() {
+                    let input = input.as_str().unwrap();
+                    assert_eq!(is_valid_postcode(country, input), Some(expected), "{} {:?}", country, input);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_postcode_ranges() {
+        let range = |low, high| postcode_range(&serde_json::json!([low, high])).unwrap();
+        let in_range = |country, postcode, (low, high): (String, String)| {
+            in_postcode_range(country, postcode, &low, &high)
+        };
+
+        // Hawaii, numerically: ZIP+4 is ignored, bounds may drop leading zeros
+        let hawaii = range("96701", "96898");
+        assert_eq!(in_range(Some("US"), "96720", hawaii.clone()), Some(true));
+        assert_eq!(in_range(Some("us"), "96898-1234", hawaii.clone()), Some(true));
+        assert_eq!(in_range(Some("US"), "96899", hawaii.clone()), Some(false));
+        assert_eq!(in_range(Some("US"), "9672", hawaii.clone()), None);
+        assert_eq!(in_range(Some("US"), "00501", range("501", "599")), Some(true));
+        assert_eq!(in_range(Some("US"), "00501", postcode_range(&serde_json::json!([501, 599])).unwrap()), Some(true));
+
+        // Northern Ireland, lexicographically, with a prefix-inclusive upper bound
+        let belfast = range("BT1", "BT9");
+        for postcode in ["BT1 1AA", "bt9-1aa", "BT10 1AA"] {
+            assert_eq!(in_range(Some("GB"), postcode, belfast.clone()), Some(true), "{}", postcode);
+        }
+        assert_eq!(in_range(Some("GB"), "BS1 1AA", belfast.clone()), Some(false));
+        assert_eq!(in_range(None, "BT5 7AB", belfast), Some(true));
+
+        assert_eq!(postcode_range(&serde_json::json!(["1"])), None);
+        assert_eq!(postcode_range(&serde_json::json!([true, "2"])), None);
+    }
+
+    #[test]
+    fn test_postcode_prefixes() {
+        assert_eq!(postcode_prefix("902*").as_deref(), Some("902"));
+        assert_eq!(postcode_prefix(" bt ").as_deref(), Some("BT"));
+        assert_eq!(postcode_prefix("SW1A-1*").as_deref(), Some("SW1A1"));
+        assert_eq!(postcode_prefix("9*2"), None);
+        assert_eq!(postcode_prefix("**"), None);
+    }
+
+    #[test]
+    fn test_separators_and_case() {
+        assert_eq!(is_valid_postcode("gb", " sw1a1aa "), Some(true));
+        assert_eq!(is_valid_postcode("GB", "SW1A-1AA"), Some(true));
+        assert_eq!(is_valid_postcode("GB", "SW1A  1AA"), Some(false));
+        assert_eq!(is_valid_postcode("US", "90210 1234"), Some(true));
+        assert_eq!(is_valid_postcode("XX", "12345"), None);
+        assert_eq!(is_valid_postcode("US", ""), Some(false));
+    }
+}
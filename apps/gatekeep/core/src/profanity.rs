@@ -0,0 +1,278 @@
+//! Word-list profanity filter for `CONTAINS_PROFANITY`.
+//!
+//! Text is folded before matching: case, accents and look-alike letters
+//! (Cyrillic, Greek, fullwidth) map to plain letters. It is then split into
+//! words on whitespace and hard punctuation. Soft separators inside a word
+//! are dropped (`f.u.c.k`), and runs of single letters are joined
+//! (`f u c k`). List words are compared with leetspeak (`sh1t`, `a$$`,
+//! `f*ck`) and repeated letters (`fuuuck`) allowed, but a doubled letter in
+//! the list word must still be at least doubled, so `ass` doesn't match
+//! `as`.
+//!
+//! List entries match a whole word (`twat`), a word prefix (`wank*`) or
+//! anywhere in a word (`*fuck*`). Words on the allow list never match,
+//! which keeps prefix and infix entries off place names and foods
+//! (`shiitake`, `Slutsk`).
+
+use alloc::vec::Vec;
+
+/// Word lists by ISO 639-1 language code, sorted by code. Entries are
+/// lowercase and already folded (no accents except `ñ`).
+pub const PROFANITY_LISTS: &[(&str, &[&str])] = &[
+    ("de", &[
+        "arsch", "arschloch*", "fick", "ficken", "ficker", "fickt", "fotze*", "gefickt", "hure",
+        "huren*", "miststuck*", "scheiss*", "schlampe*", "schwuchtel*", "wichser*",
+    ]),
+    ("en", &[
+        "arse", "arsehole*", "ass", "asses", "asshole*", "bastard*", "bitch*", "bollock*", "clit",
+        "cock", "cocks", "cocksucker*", "cum", "cunt*", "dick", "dickhead*", "dildo*", "fag",
+        "faggot*", "fags", "*fuck*", "jizz*", "nigga*", "nigger*", "piss", "pissed", "pissing",
+        "prick", "pricks", "pussy", "*shit*", "slut*", "tits", "twat*", "wank*", "whore*",
+    ]),
+    ("es", &[
+        "cabron*", "carajo", "chingada*", "chingar*", "cojones", "coño", "culero*", "gilipolla*",
+        "hijoputa*", "joder", "jodido*", "maricon*", "mierda*", "pendejo*", "puta", "putas",
+        "puto", "putos", "verga",
+    ]),
+    ("fr", &[
+        "batard*", "connard*", "connasse*", "couille*", "encule*", "enfoire*", "merde*", "nique",
+        "niquer", "putain*", "pute", "putes", "salaud*", "salope*",
+    ]),
+    ("it", &[
+        "bastardo*", "cazzata", "cazzi", "cazzo", "coglione*", "fanculo", "merda*", "minchia",
+        "puttana*", "stronza", "stronzo*", "vaffanculo",
+    ]),
+    ("nl", &[
+        "flikker*", "geneukt", "godverdom*", "hoer", "hoeren", "klootzak*", "kut", "kutwijf*",
+        "lul", "neuk", "neuken", "trut",
+    ]),
+    ("pt", &[
+        "arrombado*", "buceta*", "caralho*", "cuzao", "foda", "fodase", "foder*", "fodido*",
+        "merda*", "porra", "puta", "puto", "viado*",
+    ]),
+];
+
+/// Words that never count as profanity, whatever the lists say.
+pub const PROFANITY_ALLOW_LIST: &[&str] = &["shiitake", "shitake", "shittah", "shittim", "slutsk"];
+
+/// Word list for a language code (case-insensitive), if there is one.
+pub fn profanity_list(language: &str) -> Option<&'static [&'static str]> {
+    let code = language.trim().to_ascii_lowercase();
+    PROFANITY_LISTS
+        .binary_search_by(|(c, _)| (*c).cmp(code.as_str()))
+        .ok()
+        .map(|i| PROFANITY_LISTS[i].1)
+}
+
+/// True if any word in `text` is on one of the given lists.
+pub fn contains_profanity(text: &str, lists: &[&[&str]]) -> bool {
+    words(text).iter().any(|word| {
+        !PROFANITY_ALLOW_LIST.iter().any(|allowed| entry_matches(allowed, word))
+            && lists.iter().flat_map(|list| list.iter()).any(|entry| entry_matches(entry, word))
+    })
+}
+
+/// Split text into folded words, joining runs of single characters.
+fn words(text: &str) -> Vec<Vec<char>> {
+    let mut raw = Vec::new();
+    let mut word = Vec::new();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_whitespace() || is_hard_separator(c) {
+            raw.push(trim_word(&word));
+            word.clear();
+        } else if c == 'ß' {
+            word.extend(['s', 's']);
+        } else {
+            word.push(fold(c));
+        }
+    }
+    raw.push(trim_word(&word));
+
+    let mut words = Vec::new();
+    let mut letters = Vec::new();
+    for word in raw.into_iter().filter(|w| !w.is_empty()) {
+        if word.len() == 1 {
+            letters.push(word[0]);
+        } else {
+            if !letters.is_empty() {
+                words.push(core::mem::take(&mut letters));
+            }
+            words.push(word);
+        }
+    }
+    if !letters.is_empty() {
+        words.push(letters);
+    }
+    words
+}
+
+/// Drop punctuation around a word and soft separators inside it.
+fn trim_word(word: &[char]) -> Vec<char> {
+    let start = word.iter().position(|c| is_letter(*c) || matches!(c, '@' | '$'));
+    let end = word.iter().rposition(|c| is_letter(*c) || matches!(c, '@' | '$'));
+    match (start, end) {
+        (Some(start), Some(end)) => word[start..=end]
+            .iter()
+            .copied()
+            .filter(|c| is_letter(*c) || is_leet(*c))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Letters and digits. Beyond ASCII, anything that isn't punctuation, a
+/// symbol, a combining mark or invisible counts, which avoids carrying
+/// Unicode tables into the checkout function.
+fn is_letter(c: char) -> bool {
+    if c.is_ascii() {
+        return c.is_ascii_alphanumeric();
+    }
+    !matches!(c,
+        '\u{80}'..='\u{BF}' | '×' | '÷' | '\u{300}'..='\u{36F}' | '\u{2000}'..='\u{2BFF}'
+        | '\u{3000}'..='\u{303F}' | '\u{FE00}'..='\u{FE0F}' | '\u{FEFF}' | '\u{1F000}'..='\u{1FAFF}'
+        | '\u{E0000}'..='\u{E007F}')
+}
+
+fn is_hard_separator(c: char) -> bool {
+    matches!(c, ',' | ';' | ':' | '?' | '(' | ')' | '[' | ']' | '{' | '}' | '"' | '/' | '\\' | '<' | '>' | '&' | '=')
+}
+
+fn is_leet(c: char) -> bool {
+    matches!(c, '@' | '$' | '!' | '|' | '+' | '*')
+}
+
+/// Map an accented or look-alike lowercase letter to the plain letter it
+/// stands for.
+fn fold(c: char) -> char {
+    match c {
+        'à'..='å' | 'ā' | 'ă' | 'ą' | 'а' | 'α' => 'a',
+        'в' | 'β' => 'b',
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' | 'с' | '¢' => 'c',
+        'ď' | 'đ' | 'ԁ' => 'd',
+        'è'..='ë' | 'ē' | 'ė' | 'ę' | 'ě' | 'е' | 'ё' | 'ε' | '€' => 'e',
+        'ğ' | 'ģ' => 'g',
+        'н' => 'h',
+        'ì'..='ï' | 'ī' | 'į' | 'ı' | 'і' | 'ї' | 'ι' => 'i',
+        'ј' => 'j',
+        'ķ' | 'к' | 'κ' => 'k',
+        'ĺ' | 'ļ' | 'ľ' | 'ł' => 'l',
+        'м' => 'm',
+        'ń' | 'ņ' | 'ň' | 'η' => 'n',
+        'ò'..='ö' | 'ø' | 'ō' | 'ő' | 'о' | 'ο' => 'o',
+        'р' | 'ρ' => 'p',
+        'ԛ' => 'q',
+        'ŕ' | 'ř' => 'r',
+        'ś' | 'ş' | 'š' | 'ș' | 'ѕ' => 's',
+        'ţ' | 'ť' | 'ț' | 'т' | 'τ' => 't',
+        'ù'..='ü' | 'ū' | 'ů' | 'ű' | 'ų' | 'υ' => 'u',
+        'ν' => 'v',
+        'ԝ' | 'ω' => 'w',
+        'х' | 'χ' => 'x',
+        'ý' | 'ÿ' | 'у' => 'y',
+        'ź' | 'ż' | 'ž' => 'z',
+        // Fullwidth ASCII
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).map_or(c, fold),
+        _ => c,
+    }
+}
+
+/// Whether a folded character can stand for a list letter.
+fn letter_matches(c: char, letter: char) -> bool {
+    c == letter
+        || match c {
+            '0' => letter == 'o',
+            '1' | '|' => letter == 'i' || letter == 'l',
+            '!' => letter == 'i',
+            '3' => letter == 'e',
+            '4' | '@' => letter == 'a',
+            '5' | '$' => letter == 's',
+            '6' | '9' => letter == 'g',
+            '7' | '+' => letter == 't',
+            '8' => letter == 'b',
+            // A masked vowel, as in "f*ck"
+            '*' => matches!(letter, 'a' | 'e' | 'i' | 'o' | 'u'),
+            _ => false,
+        }
+}
+
+fn entry_matches(entry: &str, word: &[char]) -> bool {
+    match entry.strip_prefix('*') {
+        Some(rest) => {
+            let letters = rest.strip_suffix('*').unwrap_or(rest);
+            (0..word.len()).any(|i| match_letters(&word[i..], letters, false))
+        }
+        None => match entry.strip_suffix('*') {
+            Some(letters) => match_letters(word, letters, false),
+            None => match_letters(word, entry, true),
+        },
+    }
+}
+
+/// Match `letters` at the start of `word`, each run of a letter taking at
+/// least as many characters as it has in `letters`. With `whole`, the
+/// match must use up the word.
+fn match_letters(word: &[char], letters: &str, whole: bool) -> bool {
+    let Some(letter) = letters.chars().next() else {
+        return !whole || word.is_empty();
+    };
+    let rest = letters.trim_start_matches(letter);
+    let min = (letters.len() - rest.len()) / letter.len_utf8();
+
+    let mut taken = 0;
+    while taken < word.len() && letter_matches(word[taken], letter) {
+        taken += 1;
+        if taken >= min && match_letters(&word[taken..], rest, whole) {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test vectors shared with the checkout function.
+    const SHARED_VECTORS: &str = include_str!("../../testdata/profanity.json");
+
+    fn check(text: &str, languages: &[&str]) -> bool {
+        let lists: Vec<&[&str]> = languages.iter().map(|l| profanity_list(l).unwrap()).collect();
+        contains_profanity(text, &lists)
+    }
+
+    #[test]
+    fn test_lists_are_sorted() {
+        assert!(PROFANITY_LISTS.windows(2).all(|w| w[0].0 < w[1].0));
+        for (language, list) in PROFANITY_LISTS {
+            let sorted = list.windows(2).all(|w| w[0].trim_matches('*') < w[1].trim_matches('*'));
+            assert!(sorted, "{} list isn't sorted", language);
+        }
+    }
+
+    #[test]
+    fn test_shared_vectors() {
+        let vectors: serde_json::Value = serde_json::from_str(SHARED_VECTORS).unwrap();
+        for case in vectors.as_array().unwrap() {
+            let text = case["text"].as_str().unwrap();
+            let languages: Vec<&str> = case["languages"].as_array().unwrap().iter().map(|l| l.as_str().unwrap()).collect();
+            let expected = case["expected"].as_bool().unwrap();
+            assert_eq!(check(text, &languages), expected, "{:?} in {:?}", text, languages);
+        }
+    }
+
+    #[test]
+    fn test_obfuscation() {
+        // The last two use Cyrillic "с" and "і"
+        for text in ["f.u.c.k", "f u c k off", "sh1t", "a$$", "FUUUCK", "f*ck", "ｆｕｃｋ", "fu\u{441}k", "sh\u{456}t"] {
+            assert!(check(text, &["en"]), "{:?}", text);
+        }
+        assert!(!check("fuck", &["de"]));
+    }
+
+    #[test]
+    fn test_scunthorpe() {
+        for text in ["Scunthorpe", "as", "class", "assassin", "cocktail", "shiitake risotto", "Dickens", "pass the ball"] {
+            assert!(!check(text, &["en"]), "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Postcode to state/province tables for `ZIP_MATCHES_PROVINCE`.
+//!
+//! US ZIP codes map to a state by their first three digits (ZIP3) and
+//! Canadian postal codes to a province by their forward sortation area
+//! (FSA), which for every province but the territories is just the first
+//! letter. Unassigned ZIP3s inside a state's block count as that state.
+
+use crate::postcodes::{canonical_postcode, is_valid_postcode};
+
+/// Inclusive ZIP3 ranges and the state codes they belong to, sorted.
+/// Includes territories and the military codes (AA, AE, AP).
+pub const US_ZIP3_STATES: &[(u16, u16, &[&str])] = &[
+    (5, 5, &["NY"]),
+    (6, 7, &["PR"]),
+    (8, 8, &["VI"]),
+    (9, 9, &["PR"]),
+    (10, 27, &["MA"]),
+    (28, 29, &["RI"]),
+    (30, 38, &["NH"]),
+    (39, 49, &["ME"]),
+    (50, 54, &["VT"]),
+    (55, 55, &["MA"]),
+    (56, 59, &["VT"]),
+    (60, 69, &["CT"]),
+    (70, 89, &["NJ"]),
+    (90, 99, &["AE"]),
+    (100, 149, &["NY"]),
+    (150, 196, &["PA"]),
+    (197, 199, &["DE"]),
+    (200, 200, &["DC"]),
+    (201, 201, &["VA"]),
+    (202, 205, &["DC"]),
+    (206, 219, &["MD"]),
+    (220, 246, &["VA"]),
+    (247, 268, &["WV"]),
+    (270, 289, &["NC"]),
+    (290, 299, &["SC"]),
+    (300, 319, &["GA"]),
+    (320, 339, &["FL"]),
+    (340, 340, &["AA"]),
+    (341, 349, &["FL"]),
+    (350, 369, &["AL"]),
+    (370, 385, &["TN"]),
+    (386, 397, &["MS"]),
+    (398, 399, &["GA"]),
+    (400, 427, &["KY"]),
+    (430, 459, &["OH"]),
+    (460, 479, &["IN"]),
+    (480, 499, &["MI"]),
+    (500, 528, &["IA"]),
+    (530, 549, &["WI"]),
+    (550, 567, &["MN"]),
+    (569, 569, &["DC"]),
+    (570, 577, &["SD"]),
+    (580, 588, &["ND"]),
+    (590, 599, &["MT"]),
+    (600, 629, &["IL"]),
+    (630, 658, &["MO"]),
+    (660, 679, &["KS"]),
+    (680, 693, &["NE"]),
+    (700, 714, &["LA"]),
+    (716, 729, &["AR"]),
+    (730, 732, &["OK"]),
+    (733, 733, &["TX"]),
+    (734, 749, &["OK"]),
+    (750, 799, &["TX"]),
+    (800, 816, &["CO"]),
+    (820, 831, &["WY"]),
+    (832, 838, &["ID"]),
+    (840, 847, &["UT"]),
+    (850, 865, &["AZ"]),
+    (870, 884, &["NM"]),
+    (885, 885, &["TX"]),
+    (889, 898, &["NV"]),
+    (900, 961, &["CA"]),
+    (962, 966, &["AP"]),
+    // 96799 is American Samoa
+    (967, 967, &["HI", "AS"]),
+    (968, 968, &["HI"]),
+    (969, 969, &["GU", "MP", "MH", "FM", "PW"]),
+    (970, 979, &["OR"]),
+    (980, 994, &["WA"]),
+    (995, 999, &["AK"]),
+];
+
+/// FSA prefixes and their provinces, sorted. The longest matching prefix
+/// wins, which splits X between the Northwest Territories and Nunavut.
+pub const CA_FSA_PROVINCES: &[(&str, &[&str])] = &[
+    ("A", &["NL"]),
+    ("B", &["NS"]),
+    ("C", &["PE"]),
+    ("E", &["NB"]),
+    ("G", &["QC"]),
+    ("H", &["QC"]),
+    ("J", &["QC"]),
+    ("K", &["ON"]),
+    ("L", &["ON"]),
+    ("M", &["ON"]),
+    ("N", &["ON"]),
+    ("P", &["ON"]),
+    ("R", &["MB"]),
+    ("S", &["SK"]),
+    ("T", &["AB"]),
+    ("V", &["BC"]),
+    ("X", &["NT", "NU"]),
+    ("X0A", &["NU"]),
+    ("X0B", &["NU"]),
+    ("X0C", &["NU"]),
+    ("X0E", &["NT"]),
+    ("X0G", &["NT"]),
+    ("X1A", &["NT"]),
+    ("Y", &["YT"]),
+];
+
+/// The states or provinces a postcode can belong to. `None` for countries
+/// other than the US and Canada, postcodes that don't fit the country's
+/// format, and unassigned ZIP3s.
+pub fn postcode_regions(country_code: &str, postcode: &str) -> Option<&'static [&'static str]> {
+    if is_valid_postcode(country_code, postcode) != Some(true) {
+        return None;
+    }
+    let postcode = canonical_postcode(postcode);
+    match country_code.trim().to_ascii_uppercase().as_str() {
+        "US" => {
+            let zip3: u16 = postcode.get(..3)?.parse().ok()?;
+            let i = US_ZIP3_STATES.partition_point(|(_, high, _)| *high < zip3);
+            US_ZIP3_STATES
+                .get(i)
+                .filter(|(low, _, _)| *low <= zip3)
+                .map(|(_, _, states)| *states)
+        }
+        "CA" => CA_FSA_PROVINCES
+            .iter()
+            .filter(|(prefix, _)| postcode.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, provinces)| *provinces),
+        _ => None,
+    }
+}
+
+/// True if `province_code` is a state or province the postcode belongs
+/// to. `None` when the province is empty or the postcode can't be placed
+/// (see [`postcode_regions`]).
+pub fn zip_matches_province(country_code: &str, postcode: &str, province_code: &str) -> Option<bool> {
+    let province_code = province_code.trim();
+    if province_code.is_empty() {
+        return None;
+    }
+    let regions = postcode_regions(country_code, postcode)?;
+    Some(regions.iter().any(|region| region.eq_ignore_ascii_case(province_code)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tables_are_sorted() {
+        assert!(US_ZIP3_STATES.iter().all(|(low, high, _)| low <= high));
+        assert!(US_ZIP3_STATES.windows(2).all(|w| w[0].1 < w[1].0));
+        assert!(CA_FSA_PROVINCES.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn test_us_zip3() {
+        for (zip, state) in [
+            ("10001", "NY"),
+            ("90210", "CA"),
+            ("96813", "HI"),
+            ("99501", "AK"),
+            ("73301", "TX"),
+            ("73101", "OK"),
+            ("20500", "DC"),
+            ("02134", "MA"),
+            ("00501", "NY"),
+            ("00901", "PR"),
+            ("09001", "AE"),
+            ("96799", "AS"),
+            ("88510-1234", "TX"),
+        ] {
+            assert_eq!(zip_matches_province("US", zip, state), Some(true), "{} {}", zip, state);
+        }
+        assert_eq!(zip_matches_province("US", "10001", "CA"), Some(false));
+        assert_eq!(zip_matches_province("us", "90210", " ca "), Some(true));
+
+        // Unassigned, malformed, or no province: can't tell
+        assert_eq!(zip_matches_province("US", "00123", "NY"), None);
+        assert_eq!(zip_matches_province("US", "1000", "NY"), None);
+        assert_eq!(zip_matches_province("US", "10001", ""), None);
+    }
+
+    #[test]
+    fn test_canadian_fsa() {
+        for (postcode, province) in [
+            ("K1A 0B1", "ON"),
+            ("H2X 1Y4", "QC"),
+            ("V6B 1A1", "BC"),
+            ("A1C 5M2", "NL"),
+            ("X0A 0H0", "NU"),
+            ("X1A 2L9", "NT"),
+            ("Y1A 1A1", "YT"),
+        ] {
+            assert_eq!(zip_matches_province("CA", postcode, province), Some(true), "{} {}", postcode, province);
+        }
+        assert_eq!(zip_matches_province("CA", "X0A 0H0", "NT"), Some(false));
+        assert_eq!(zip_matches_province("CA", "M5V 2T6", "QC"), Some(false));
+        assert_eq!(zip_matches_province("GB", "SW1A 1AA", "LND"), None);
+    }
+}
@@ -0,0 +1,301 @@
+//! Set-valued operands for `IN` / `NOT_IN`.
+//!
+//! Sets are sorted once when the rules are compiled, so a lookup is a
+//! binary search rather than a scan. Large sets can be written front-coded,
+//! which keeps a 5,000-entry zip blocklist well inside the 64 KB metafield:
+//!
+//! ```json
+//! { "operator": "IN", "value": { "set": "090210,41,42,14105" } }
+//! ```
+//!
+//! Entries are separated by `,`. Each starts with one base-36 digit giving
+//! how many leading characters it shares with the previous entry (at most
+//! 35), followed by the rest of the entry, with `,` and `\` escaped by `\`.
+//! Sorting the entries first makes the shared prefixes long. Entries that
+//! are JSON numbers also match numeric fields, so the same encoding serves
+//! numeric and string sets.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Longest shared prefix one digit can express.
+pub const MAX_SHARED_PREFIX: usize = 35;
+
+/// Entries per extra complexity point on top of [`SET_POINTS`].
+pub const ENTRIES_PER_POINT: usize = 1000;
+
+/// Base complexity points for a set condition, the same as an array
+/// include.
+pub const SET_POINTS: u32 = 2;
+
+/// Complexity points for a set of `count` entries: the checkout function
+/// decodes and sorts it on every run, so large sets cost more.
+pub fn set_points(count: usize) -> u32 {
+    SET_POINTS + (count / ENTRIES_PER_POINT) as u32
+}
+
+/// A sorted, deduplicated set. String and numeric members are kept apart.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValueSet {
+    texts: Vec<String>,
+    numbers: Vec<f64>,
+}
+
+impl ValueSet {
+    /// Sort and deduplicate the members.
+    pub fn new(mut texts: Vec<String>, mut numbers: Vec<f64>) -> Self {
+        heapsort(&mut texts, |a, b| a < b);
+        texts.dedup();
+        heapsort(&mut numbers, |a, b| a.total_cmp(b).is_lt());
+        numbers.dedup();
+        Self { texts, numbers }
+    }
+
+    /// Build the set for an `IN` value: a list, or `{"set": "<front-coded>"}`.
+    /// `normalize` is applied to string members (a trait object, so there's
+    /// one copy in the checkout function's binary). `None` if the value is
+    /// neither or the encoding is malformed.
+    #[cfg(feature = "json")]
+    pub fn from_value(value: &serde_json::Value, normalize: &dyn Fn(&str) -> String) -> Option<Self> {
+        match value {
+            serde_json::Value::Array(items) => Some(Self::new(
+                items.iter().filter_map(|v| v.as_str()).map(normalize).collect(),
+                items.iter().filter_map(|v| v.as_f64()).collect(),
+            )),
+            serde_json::Value::Object(map) => {
+                let entries = decode_set(map.get("set")?.as_str()?)?;
+                let numbers = entries.iter().filter_map(|e| serde_json::from_str(e).ok()).collect();
+                Some(Self::new(entries.iter().map(|e| normalize(e)).collect(), numbers))
+            }
+            _ => None,
+        }
+    }
+
+    /// String members, sorted.
+    pub fn texts(&self) -> &[String] {
+        &self.texts
+    }
+
+    /// Numeric members, sorted.
+    pub fn numbers(&self) -> &[f64] {
+        &self.numbers
+    }
+
+    /// Number of members.
+    pub fn len(&self) -> usize {
+        self.texts.len() + self.numbers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// True if `text` (already normalized) is a member.
+    pub fn contains_text(&self, text: &str) -> bool {
+        self.texts.binary_search_by(|t| t.as_str().cmp(text)).is_ok()
+    }
+
+    /// True if a member is within `f64::EPSILON` of `n`, as with `EQUALS`.
+    pub fn contains_number(&self, n: f64) -> bool {
+        let i = self.numbers.partition_point(|cv| *cv < n);
+        [i.checked_sub(1), Some(i)]
+            .into_iter()
+            .flatten()
+            .filter_map(|i| self.numbers.get(i))
+            .any(|cv| (n - cv).abs() < f64::EPSILON)
+    }
+}
+
+/// Sort in place. `slice::sort_unstable` adds ~9 KB to the checkout
+/// function's binary; this is a few hundred bytes and still O(n log n).
+fn heapsort<T>(v: &mut [T], less: impl Fn(&T, &T) -> bool) {
+    let sift_down = |v: &mut [T], mut node: usize| loop {
+        let mut child = 2 * node + 1;
+        if child >= v.len() {
+            break;
+        }
+        if child + 1 < v.len() && less(&v[child], &v[child + 1]) {
+            child += 1;
+        }
+        if !less(&v[node], &v[child]) {
+            break;
+        }
+        v.swap(node, child);
+        node = child;
+    };
+    for i in (0..v.len() / 2).rev() {
+        sift_down(v, i);
+    }
+    for end in (1..v.len()).rev() {
+        v.swap(0, end);
+        sift_down(&mut v[..end], 0);
+    }
+}
+
+/// Front-code `entries` for the config, sorted and without duplicates.
+pub fn encode_set<'a, I>(entries: I) -> String
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut entries: Vec<&str> = entries.into_iter().collect();
+    heapsort(&mut entries, |a, b| a < b);
+    entries.dedup();
+
+    let mut encoded = String::new();
+    let mut previous = "";
+    for (i, entry) in entries.into_iter().enumerate() {
+        if i > 0 {
+            encoded.push(',');
+        }
+        let shared = previous
+            .chars()
+            .zip(entry.chars())
+            .take_while(|(a, b)| a == b)
+            .count()
+            .min(MAX_SHARED_PREFIX);
+        encoded.push(char::from_digit(shared as u32, 36).unwrap_or('0'));
+        for c in entry.chars().skip(shared) {
+            if c == ',' || c == '\\' {
+                encoded.push('\\');
+            }
+            encoded.push(c);
+        }
+        previous = entry;
+    }
+    encoded
+}
+
+/// Decode a front-coded set, or `None` if it's malformed. The empty string
+/// is the empty set.
+pub fn decode_set(encoded: &str) -> Option<Vec<String>> {
+    let mut entries: Vec<String> = Vec::new();
+    if encoded.is_empty() {
+        return Some(entries);
+    }
+
+    for raw in split_entries(encoded) {
+        let mut chars = raw.chars();
+        let shared = chars.next()?.to_digit(36)? as usize;
+        let previous = entries.last().map_or("", String::as_str);
+        let mut entry = String::with_capacity(previous.len().max(raw.len()));
+        entry.push_str(char_prefix(previous, shared)?);
+        let rest = chars.as_str();
+        if rest.contains('\\') {
+            let mut chars = rest.chars();
+            while let Some(c) = chars.next() {
+                entry.push(if c == '\\' { chars.next()? } else { c });
+            }
+        } else {
+            entry.push_str(rest);
+        }
+        entries.push(entry);
+    }
+    Some(entries)
+}
+
+/// Split at unescaped commas. A trailing escape is left in the last piece
+/// for the decoder to reject.
+fn split_entries(encoded: &str) -> impl Iterator<Item = &str> {
+    let mut escaped = false;
+    encoded.split(move |c| {
+        let split = c == ',' && !escaped;
+        escaped = c == '\\' && !escaped;
+        split
+    })
+}
+
+/// The first `n` chars of `s`, or `None` if it's shorter.
+fn char_prefix(s: &str, n: usize) -> Option<&str> {
+    match s.char_indices().nth(n) {
+        Some((i, _)) => Some(&s[..i]),
+        None if s.chars().count() == n => Some(s),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_front_coding_round_trip() {
+        let zips = ["90212", "90210", "94105", "90211", "90210"];
+        let encoded = encode_set(zips);
+        assert_eq!(encoded, "090210,41,42,14105");
+        assert_eq!(decode_set(&encoded).unwrap(), vec!["90210", "90211", "90212", "94105"]);
+
+        let awkward = ["a,b", "a\\", "", "straße", "straßen", "\\,\\", &"x".repeat(50), &"x".repeat(60)];
+        let mut expected: Vec<String> = awkward.iter().map(|s| s.to_string()).collect();
+        expected.sort();
+        assert_eq!(decode_set(&encode_set(awkward)).unwrap(), expected);
+
+        assert_eq!(decode_set("").unwrap(), Vec::<String>::new());
+        assert_eq!(decode_set("0").unwrap(), vec![""]);
+    }
+
+    #[test]
+    fn test_malformed_encoding() {
+        // Bad prefix digit, prefix longer than the previous entry, dangling escape
+        for encoded in ["!a", "0ab,3c", "0ab\\", "0a,", ",0a"] {
+            assert_eq!(decode_set(encoded), None, "{:?}", encoded);
+        }
+    }
+
+    #[test]
+    fn test_heapsort() {
+        for len in 0..40 {
+            let mut v: Vec<u32> = (0..len).map(|i| (i * 7919 + 13) % 31).collect();
+            let mut expected = v.clone();
+            expected.sort();
+            heapsort(&mut v, |a, b| a < b);
+            assert_eq!(v, expected);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_membership() {
+        let lower = &str::to_lowercase;
+        let set = ValueSet::from_value(&serde_json::json!(["B", "a", 10, 2.5, "a"]), lower).unwrap();
+        assert_eq!(set.len(), 4);
+        assert!(set.contains_text("a") && set.contains_text("b"));
+        assert!(!set.contains_text("B") && !set.contains_text("10"));
+        assert!(set.contains_number(10.0) && set.contains_number(2.5));
+        assert!(!set.contains_number(3.0));
+
+        let encoded = serde_json::json!({ "set": encode_set(["SW1A 1AA", "10", "2.5", "007"]) });
+        let set = ValueSet::from_value(&encoded, lower).unwrap();
+        assert!(set.contains_text("sw1a 1aa") && set.contains_text("007"));
+        // "007" isn't a JSON number, so it only matches as text
+        assert!(set.contains_number(10.0) && set.contains_number(2.5) && !set.contains_number(7.0));
+
+        assert_eq!(ValueSet::from_value(&serde_json::json!({ "set": "!" }), lower), None);
+        assert_eq!(ValueSet::from_value(&serde_json::json!("a"), lower), None);
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_large_encoded_set() {
+        let zips: Vec<String> = (0..5000).map(|i| format!("{:05}", 10000 + i * 17)).collect();
+        let encoded = serde_json::json!({ "set": encode_set(zips.iter().map(String::as_str)) });
+        let set = ValueSet::from_value(&encoded, &str::to_lowercase).unwrap();
+        assert!(zips.iter().all(|zip| set.contains_text(zip)));
+        assert!(!set.contains_text("10001") && set.contains_number(10017.0));
+    }
+
+    #[test]
+    fn test_complexity_points() {
+        assert_eq!(set_points(3), 2);
+        assert_eq!(set_points(1000), 3);
+        assert_eq!(set_points(5000), 7);
+    }
+
+    #[test]
+    fn test_blocklist_fits_metafield() {
+        let zips: Vec<String> = (0..5000).map(|i| format!("{:05}", 10000 + i * 17)).collect();
+        let plain: usize = zips.iter().map(|zip| zip.len() + 3).sum();
+        let encoded = encode_set(zips.iter().map(String::as_str));
+        assert!(encoded.len() * 3 < plain * 2, "{} vs {}", encoded.len(), plain);
+        assert!(encoded.len() < 64 * 1024 / 2);
+    }
+}
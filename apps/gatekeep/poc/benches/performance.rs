@@ -7,11 +7,12 @@
 
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
 use logicflow::{
-    evaluate_rules, CartInput, ComparisonOperator, Condition, ConditionGroup,
-    LogicalOperator, Rule, RulesConfig,
+    evaluate_compiled, evaluate_rules, CartInput, CompiledRules, ComparisonOperator, Condition,
+    ConditionGroup, LogicalOperator, Rule, RulesConfig,
 };
 use logicflow::models::Criterion as RuleCriterion;
 use logicflow::patterns::{PO_BOX, UK_POSTCODE, US_ZIP};
+use logicflow::sets::encode_set;
 
 /// Generate a test configuration with the specified number of rules and regex patterns.
 fn generate_test_config(rule_count: usize, regex_count: usize) -> RulesConfig {
@@ -31,7 +32,7 @@ fn generate_test_config(rule_count: usize, regex_count: usize) -> RulesConfig {
                     field: "cart.total".to_string(),
                     operator: ComparisonOperator::GreaterThan,
                     value: serde_json::json!(999999.0), // Won't match
-                    is_preset: false,
+                    ..Default::default()
                 })],
             },
         });
@@ -52,6 +53,7 @@ fn generate_test_config(rule_count: usize, regex_count: usize) -> RulesConfig {
                     operator: ComparisonOperator::RegexMatch,
                     value: serde_json::json!("po_box"),
                     is_preset: true,
+                    ..Default::default()
                 })],
             },
         });
@@ -61,6 +63,9 @@ fn generate_test_config(rule_count: usize, regex_count: usize) -> RulesConfig {
         version: "1.0".to_string(),
         total_complexity: rule_count as u32,
         rules,
+        regex_patterns: Default::default(),
+        timezone_offset_minutes: None,
+        date_order: Default::default(),
     }
 }
 
@@ -72,7 +77,7 @@ fn generate_test_cart() -> CartInput {
         quantity: 3,
         total_weight: 2.5,
         customer_tags: vec!["returning".to_string(), "newsletter".to_string()],
-        shipping_address: logicflow::models::Address {
+        shipping_address: Some(logicflow::models::Address {
             address1: "123 Main Street".to_string(),
             address2: "Apt 4B".to_string(),
             city: "Los Angeles".to_string(),
@@ -81,8 +86,10 @@ fn generate_test_cart() -> CartInput {
             country: "United States".to_string(),
             country_code: "US".to_string(),
             zip: "90210".to_string(),
-        },
+            phone: "(555) 123-4567".to_string(),
+        }),
         line_items: vec![],
+        ..Default::default()
     }
 }
 
@@ -99,6 +106,16 @@ fn benchmark_rule_counts(c: &mut Criterion) {
                 b.iter(|| evaluate_rules(black_box(config), black_box(&cart)));
             },
         );
+
+        // Same rules, compiled once up front
+        let compiled = CompiledRules::compile(&config);
+        group.bench_with_input(
+            BenchmarkId::new("simple_rules_precompiled", rule_count),
+            &compiled,
+            |b, compiled| {
+                b.iter(|| evaluate_compiled(black_box(compiled), black_box(&cart)));
+            },
+        );
     }
 
     group.finish();
@@ -213,6 +230,103 @@ fn benchmark_regex_patterns(c: &mut Criterion) {
     group.finish();
 }
 
+/// 200 forbidden street names: an OR group of `CONTAINS` conditions versus
+/// one `CONTAINS_ANY_OF` list.
+fn benchmark_keyword_lists(c: &mut Criterion) {
+    let mut group = c.benchmark_group("keyword_lists");
+    let cart = generate_test_cart();
+    let keywords: Vec<String> = (0..200).map(|i| format!("{} warehouse road", i)).collect();
+
+    let config_with = |criteria: Vec<RuleCriterion>| RulesConfig {
+        version: "1.0".to_string(),
+        total_complexity: 1,
+        rules: vec![Rule {
+            id: "streets".to_string(),
+            name: "Blocked streets".to_string(),
+            complexity: 1,
+            enabled: true,
+            error_message: "We can't deliver to this address".to_string(),
+            conditions: ConditionGroup {
+                operator: LogicalOperator::Or,
+                criteria,
+            },
+        }],
+        regex_patterns: Default::default(),
+        timezone_offset_minutes: None,
+        date_order: Default::default(),
+    };
+    let condition = |operator, value| {
+        RuleCriterion::Condition(Condition {
+            field: "shipping_address.address1".to_string(),
+            operator,
+            value,
+            ..Default::default()
+        })
+    };
+
+    let or_group = CompiledRules::compile(&config_with(
+        keywords
+            .iter()
+            .map(|k| condition(ComparisonOperator::Contains, serde_json::json!(k)))
+            .collect(),
+    ));
+    let any_of = CompiledRules::compile(&config_with(vec![condition(
+        ComparisonOperator::ContainsAnyOf,
+        serde_json::json!(keywords),
+    )]));
+
+    group.bench_function("or_group_200_contains", |b| {
+        b.iter(|| evaluate_compiled(black_box(&or_group), black_box(&cart)));
+    });
+    group.bench_function("contains_any_of_200", |b| {
+        b.iter(|| evaluate_compiled(black_box(&any_of), black_box(&cart)));
+    });
+
+    group.finish();
+}
+
+/// A 5,000-zip blocklist: lookup in the compiled set, and compiling the
+/// front-coded set (which the checkout function does on every run).
+fn benchmark_blocklists(c: &mut Criterion) {
+    let mut group = c.benchmark_group("blocklists");
+    let cart = generate_test_cart();
+    let zips: Vec<String> = (0..5000).map(|i| format!("{:05}", 10000 + i * 17)).collect();
+
+    let config = RulesConfig {
+        version: "1.0".to_string(),
+        total_complexity: 1,
+        rules: vec![Rule {
+            id: "zips".to_string(),
+            name: "Blocked zips".to_string(),
+            complexity: 1,
+            enabled: true,
+            error_message: "We can't deliver to this postcode".to_string(),
+            conditions: ConditionGroup {
+                operator: LogicalOperator::And,
+                criteria: vec![RuleCriterion::Condition(Condition {
+                    field: "shipping_address.zip".to_string(),
+                    operator: ComparisonOperator::In,
+                    value: serde_json::json!({ "set": encode_set(zips.iter().map(String::as_str)) }),
+                    ..Default::default()
+                })],
+            },
+        }],
+        regex_patterns: Default::default(),
+        timezone_offset_minutes: None,
+        date_order: Default::default(),
+    };
+    let compiled = CompiledRules::compile(&config);
+
+    group.bench_function("in_set_5000", |b| {
+        b.iter(|| evaluate_compiled(black_box(&compiled), black_box(&cart)));
+    });
+    group.bench_function("compile_set_5000", |b| {
+        b.iter(|| CompiledRules::compile(black_box(&config)));
+    });
+
+    group.finish();
+}
+
 fn benchmark_full_pipeline(c: &mut Criterion) {
     let mut group = c.benchmark_group("full_pipeline");
 
@@ -259,6 +373,8 @@ criterion_group!(
     benchmark_with_regex,
     benchmark_json_parsing,
     benchmark_regex_patterns,
+    benchmark_keyword_lists,
+    benchmark_blocklists,
     benchmark_full_pipeline,
 );
 
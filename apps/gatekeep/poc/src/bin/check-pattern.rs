@@ -0,0 +1,31 @@
+//! Check custom regex patterns before saving them.
+//!
+//! Usage: `check-pattern <pattern>...`
+//!
+//! Prints one JSON report per pattern (see `logicflow::regex_safety`) and
+//! exits with status 1 if any pattern has errors.
+
+use logicflow::analyze_pattern;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let patterns: Vec<String> = std::env::args().skip(1).collect();
+    if patterns.is_empty() {
+        eprintln!("usage: check-pattern <pattern>...");
+        return ExitCode::from(2);
+    }
+
+    let mut ok = true;
+    for pattern in &patterns {
+        let report = analyze_pattern(pattern);
+        ok &= report.is_ok();
+        let output = serde_json::json!({ "pattern": pattern, "report": report });
+        println!("{}", output);
+    }
+
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,533 @@
+//! Compiled evaluation plan.
+//!
+//! `RulesConfig` is shaped for storage: field paths are strings, literals
+//! are raw JSON and regexes are source text. [`CompiledRules`] converts it
+//! once into the shape evaluation needs, so the hot path never splits a
+//! path, re-normalizes a literal or compiles a pattern.
+
+use crate::address_quality::AddressSignal;
+use crate::dates::{parse_date_parts, parse_weekday, DateSettings, ParsedDate, Weekday};
+use crate::emails::normalize_domain;
+use crate::keywords::KeywordSet;
+use crate::models::{
+    AddressField, AggregateFunction, ComparisonOperator, Condition, ConditionGroup, Criterion,
+    FieldPath, LogicalOperator, NumericRange, Rule, RulesConfig,
+};
+use crate::normalize::Normalization;
+use crate::optimizer::optimize;
+use crate::patterns::get_preset_pattern;
+use crate::phones::phone_key;
+use crate::postcodes::{postcode_prefix, postcode_range};
+use crate::profanity::{profanity_list, PROFANITY_LISTS};
+use crate::sets::ValueSet;
+use regex::Regex;
+use std::collections::HashMap;
+
+/// A rules config compiled for evaluation.
+#[derive(Debug, Clone)]
+pub struct CompiledRules {
+    pub(crate) rules: Vec<CompiledRule>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledRule {
+    pub(crate) id: String,
+    pub(crate) error_message: String,
+    pub(crate) enabled: bool,
+    /// Counts against the regex rule guardrail.
+    pub(crate) uses_regex: bool,
+    pub(crate) conditions: CompiledGroup,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledGroup {
+    pub(crate) operator: LogicalOperator,
+    pub(crate) criteria: Vec<CompiledCriterion>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum CompiledCriterion {
+    Condition(CompiledCondition),
+    Group(CompiledGroup),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledCondition {
+    pub(crate) field: FieldPath,
+    pub(crate) operator: ComparisonOperator,
+    pub(crate) operand: Operand,
+    pub(crate) aggregate: Option<CompiledAggregate>,
+    pub(crate) normalize: Normalization,
+    /// The shop's timezone and date order, for date operators.
+    pub(crate) dates: DateSettings,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledAggregate {
+    pub(crate) function: AggregateFunction,
+    pub(crate) filter: Option<CompiledGroup>,
+}
+
+/// A condition value converted to the type its operator works on. String
+/// literals are already normalized with the condition's options.
+#[derive(Debug, Clone)]
+pub(crate) enum Operand {
+    /// The value is ignored (presence operators) or doesn't fit the
+    /// operator (e.g. `GREATER_THAN "abc"`), in which case every
+    /// comparison is unknown.
+    None,
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    /// `IN` list or encoded set, sorted for binary search.
+    Set(ValueSet),
+    /// A phone number's comparison key (see `phones::phone_key`).
+    Phone(String),
+    /// `IN` on a phone field: a set of phone keys.
+    Phones(ValueSet),
+    Pattern(Regex),
+    Date(DateOperand),
+    Weekdays(Vec<Weekday>),
+    Range(NumericRange),
+    Ranges(Vec<NumericRange>),
+    Country(CountrySource),
+    /// Profanity word lists for the selected languages.
+    WordLists(Vec<&'static [&'static str]>),
+    Keywords(KeywordSet),
+    /// Canonical `[low, high]` postcode ranges, and where to find the
+    /// postcode's country (if anywhere).
+    PostcodeRanges { ranges: Vec<(String, String)>, country: Option<FieldPath> },
+    /// Canonical postcode prefixes.
+    Prefixes(Vec<String>),
+    /// Where to find the postcode's country and province.
+    Region { country: FieldPath, province: FieldPath },
+    /// Address quality signals to check.
+    AddressSignals(Vec<AddressSignal>),
+    /// The operator reads the whole shipping address.
+    ShippingAddress,
+}
+
+/// Where a postcode or phone check gets its country.
+#[derive(Debug, Clone)]
+pub(crate) enum CountrySource {
+    /// A country code given as the condition value.
+    Code(String),
+    /// The shipping address's `country_code`.
+    Field(FieldPath),
+}
+
+/// The config's named patterns (`regex_patterns`), compiled once. A name
+/// whose pattern doesn't compile keeps its error, so conditions that use it
+/// are unknown rather than matching the name as a regex, and `lint` can
+/// report it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct NamedPatterns(HashMap<String, Result<Regex, regex::Error>>);
+
+impl NamedPatterns {
+    pub(crate) fn compile(patterns: &HashMap<String, String>) -> Self {
+        Self(
+            patterns
+                .iter()
+                .map(|(name, pattern)| (name.clone(), Regex::new(pattern)))
+                .collect(),
+        )
+    }
+
+    /// Names whose pattern didn't compile, with the error.
+    pub(crate) fn errors(&self) -> impl Iterator<Item = (&str, &regex::Error)> {
+        self.0.iter().filter_map(|(name, result)| Some((name.as_str(), result.as_ref().err()?)))
+    }
+}
+
+/// Config-wide settings conditions are compiled with.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CompileContext {
+    pub(crate) patterns: NamedPatterns,
+    pub(crate) dates: DateSettings,
+}
+
+impl CompileContext {
+    pub(crate) fn new(config: &RulesConfig) -> Self {
+        Self {
+            patterns: NamedPatterns::compile(&config.regex_patterns),
+            dates: DateSettings::from_config(config),
+        }
+    }
+}
+
+/// A date literal. Offset-less dates are resolved against the shop's
+/// timezone at evaluation time.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum DateOperand {
+    Now,
+    Parsed(ParsedDate),
+}
+
+impl CompiledRules {
+    /// Compile every rule in `config`, optimizing its conditions (see
+    /// `optimizer`).
+    pub fn compile(config: &RulesConfig) -> Self {
+        let context = CompileContext::new(config);
+        Self {
+            rules: config
+                .rules
+                .iter()
+                .map(|rule| {
+                    let conditions = optimize(&rule.conditions, &config.regex_patterns);
+                    CompiledRule::compile(rule, &conditions, &context)
+                })
+                .collect(),
+        }
+    }
+
+    /// Number of rules in the plan, including disabled ones.
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+impl CompiledRule {
+    /// Compile a rule. `conditions` are the rule's own, or an optimized
+    /// equivalent.
+    pub(crate) fn compile(rule: &Rule, conditions: &ConditionGroup, context: &CompileContext) -> Self {
+        Self {
+            id: rule.id.clone(),
+            error_message: rule.error_message.clone(),
+            enabled: rule.enabled,
+            uses_regex: group_uses_regex(conditions),
+            conditions: CompiledGroup::compile(conditions, context),
+        }
+    }
+}
+
+impl CompiledGroup {
+    pub(crate) fn compile(group: &ConditionGroup, context: &CompileContext) -> Self {
+        Self {
+            operator: group.operator,
+            criteria: group
+                .criteria
+                .iter()
+                .map(|criterion| match criterion {
+                    Criterion::Condition(c) => CompiledCriterion::Condition(CompiledCondition::compile(c, context)),
+                    Criterion::Group(g) => CompiledCriterion::Group(CompiledGroup::compile(g, context)),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl CompiledCondition {
+    pub(crate) fn compile(condition: &Condition, context: &CompileContext) -> Self {
+        Self {
+            field: FieldPath::parse(&condition.field),
+            operator: condition.operator,
+            operand: compile_operand(condition, context),
+            aggregate: condition.aggregate.as_ref().map(|aggregate| CompiledAggregate {
+                function: aggregate.function,
+                filter: aggregate.filter.as_ref().map(|filter| CompiledGroup::compile(filter, context)),
+            }),
+            normalize: condition.normalize.clone(),
+            dates: context.dates,
+        }
+    }
+}
+
+/// Check if a condition group uses any regex operators.
+fn group_uses_regex(group: &ConditionGroup) -> bool {
+    group.criteria.iter().any(|criterion| match criterion {
+        Criterion::Condition(c) => {
+            c.operator == ComparisonOperator::RegexMatch
+                || c.aggregate
+                    .as_ref()
+                    .and_then(|a| a.filter.as_ref())
+                    .is_some_and(group_uses_regex)
+        }
+        Criterion::Group(g) => group_uses_regex(g),
+    })
+}
+
+/// Convert a condition's JSON value to the operand its operator expects.
+fn compile_operand(condition: &Condition, context: &CompileContext) -> Operand {
+    let value = &condition.value;
+    let norm = &condition.normalize;
+    let text = || value.as_str().map(|s| Operand::Text(norm.apply(s).into_owned()));
+    let phone_field = FieldPath::parse(&condition.field).is_phone();
+
+    let operand = match condition.operator {
+        // Phone numbers compare by number, however they're written
+        ComparisonOperator::Equals | ComparisonOperator::NotEquals if phone_field => {
+            value.as_str().map(|s| Operand::Phone(phone_key(s)))
+        }
+        ComparisonOperator::In | ComparisonOperator::NotIn if phone_field => {
+            ValueSet::from_value(value, &phone_key).map(Operand::Phones)
+        }
+        ComparisonOperator::Equals | ComparisonOperator::NotEquals => match value {
+            serde_json::Value::String(s) => Some(Operand::Text(norm.apply(s).into_owned())),
+            serde_json::Value::Number(n) => n.as_f64().map(Operand::Number),
+            serde_json::Value::Bool(b) => Some(Operand::Bool(*b)),
+            _ => None,
+        },
+        ComparisonOperator::GreaterThan
+        | ComparisonOperator::GreaterThanOrEqual
+        | ComparisonOperator::LessThan
+        | ComparisonOperator::LessThanOrEqual
+        | ComparisonOperator::WithinDays => value.as_f64().map(Operand::Number),
+        ComparisonOperator::Contains
+        | ComparisonOperator::NotContains
+        | ComparisonOperator::StartsWith
+        | ComparisonOperator::EndsWith => text(),
+        ComparisonOperator::RegexMatch => value
+            .as_str()
+            .and_then(|pattern| compile_pattern(pattern, condition.is_preset, &context.patterns))
+            .map(Operand::Pattern),
+        ComparisonOperator::In | ComparisonOperator::NotIn => {
+            ValueSet::from_value(value, &|s| norm.apply(s).into_owned()).map(Operand::Set)
+        }
+        ComparisonOperator::EmailDomainIn | ComparisonOperator::NotEmailDomainIn => {
+            ValueSet::from_value(value, &normalize_domain).map(Operand::Set)
+        }
+        ComparisonOperator::Before | ComparisonOperator::After => value.as_str().and_then(|s| {
+            if s.trim().eq_ignore_ascii_case("now") {
+                Some(Operand::Date(DateOperand::Now))
+            } else {
+                parse_date_parts(s, context.dates.order).map(|d| Operand::Date(DateOperand::Parsed(d)))
+            }
+        }),
+        // Every weekday name must be known
+        ComparisonOperator::DayOfWeekIn => value.as_array().and_then(|items| {
+            items
+                .iter()
+                .map(|v| v.as_str().and_then(parse_weekday))
+                .collect::<Option<Vec<_>>>()
+                .map(Operand::Weekdays)
+        }),
+        ComparisonOperator::Between => NumericRange::from_value(value).map(Operand::Range),
+        // Every range must be well formed, as with `IN_ZIP_RANGES`
+        ComparisonOperator::InRanges => value.as_array().and_then(|items| {
+            items.iter().map(NumericRange::from_value).collect::<Option<Vec<_>>>().map(Operand::Ranges)
+        }),
+        ComparisonOperator::IsValidPostcode
+        | ComparisonOperator::IsInvalidPostcode
+        | ComparisonOperator::IsValidPhone
+        | ComparisonOperator::IsInvalidPhone => {
+            match (value.as_str(), FieldPath::parse(&condition.field)) {
+                (Some(code), _) => Some(Operand::Country(CountrySource::Code(code.trim().to_ascii_uppercase()))),
+                (None, FieldPath::ShippingAddress(_) | FieldPath::CustomerPhone) => Some(Operand::Country(
+                    CountrySource::Field(FieldPath::ShippingAddress(AddressField::CountryCode)),
+                )),
+                (None, _) => None,
+            }
+        }
+        // Every range and prefix must be well formed
+        ComparisonOperator::InZipRanges => value.as_array().and_then(|items| {
+            let country = match FieldPath::parse(&condition.field) {
+                FieldPath::ShippingAddress(_) => Some(FieldPath::ShippingAddress(AddressField::CountryCode)),
+                _ => None,
+            };
+            let ranges = items.iter().map(postcode_range).collect::<Option<Vec<_>>>()?;
+            Some(Operand::PostcodeRanges { ranges, country })
+        }),
+        ComparisonOperator::ZipMatchesProvince | ComparisonOperator::NotZipMatchesProvince => {
+            match FieldPath::parse(&condition.field) {
+                FieldPath::ShippingAddress(_) => Some(Operand::Region {
+                    country: FieldPath::ShippingAddress(AddressField::CountryCode),
+                    province: FieldPath::ShippingAddress(AddressField::ProvinceCode),
+                }),
+                _ => None,
+            }
+        }
+        ComparisonOperator::IsMilitaryAddress | ComparisonOperator::IsNotMilitaryAddress => {
+            matches!(FieldPath::parse(&condition.field), FieldPath::ShippingAddress(_)).then_some(Operand::ShippingAddress)
+        }
+        ComparisonOperator::AddressLooksInvalid
+            if !matches!(FieldPath::parse(&condition.field), FieldPath::ShippingAddress(_)) =>
+        {
+            None
+        }
+        // Every signal name must be known
+        ComparisonOperator::AddressLooksInvalid => match value {
+            serde_json::Value::Null => Some(Operand::AddressSignals(AddressSignal::ALL.to_vec())),
+            serde_json::Value::String(name) => AddressSignal::from_name(name).map(|s| Operand::AddressSignals(vec![s])),
+            serde_json::Value::Array(items) => items
+                .iter()
+                .map(|v| v.as_str().and_then(AddressSignal::from_name))
+                .collect::<Option<Vec<_>>>()
+                .map(Operand::AddressSignals),
+            _ => None,
+        },
+        ComparisonOperator::MatchesPrefixAny => value.as_array().and_then(|items| {
+            items
+                .iter()
+                .map(|v| v.as_str().and_then(postcode_prefix))
+                .collect::<Option<Vec<_>>>()
+                .map(Operand::Prefixes)
+        }),
+        ComparisonOperator::ContainsAnyOf | ComparisonOperator::ContainsNoneOf => value.as_array().map(|items| {
+            let keywords: Vec<_> = items.iter().filter_map(|v| v.as_str()).map(|s| norm.apply(s)).collect();
+            Operand::Keywords(KeywordSet::new(keywords.iter().map(|k| k.as_ref())))
+        }),
+        ComparisonOperator::ContainsProfanity | ComparisonOperator::NotContainsProfanity => match value {
+            serde_json::Value::Null => Some(Operand::WordLists(PROFANITY_LISTS.iter().map(|(_, list)| *list).collect())),
+            serde_json::Value::String(language) => profanity_list(language).map(|list| Operand::WordLists(vec![list])),
+            // Every language must be known
+            serde_json::Value::Array(items) => items
+                .iter()
+                .map(|v| v.as_str().and_then(profanity_list))
+                .collect::<Option<Vec<_>>>()
+                .map(Operand::WordLists),
+            _ => None,
+        },
+        ComparisonOperator::Exists
+        | ComparisonOperator::NotExists
+        | ComparisonOperator::IsEmpty
+        | ComparisonOperator::IsNotEmpty
+        | ComparisonOperator::IsDisposableEmail => None,
+    };
+
+    operand.unwrap_or(Operand::None)
+}
+
+/// A preset name resolves to a named pattern, then a built-in one; an
+/// unknown name leaves the condition unknown instead of being matched as a
+/// regex. Anything else is compiled as a custom pattern, and one that
+/// doesn't compile leaves the condition unknown.
+fn compile_pattern(pattern: &str, is_preset: bool, patterns: &NamedPatterns) -> Option<Regex> {
+    if !is_preset {
+        return Regex::new(pattern).ok();
+    }
+    match patterns.0.get(pattern) {
+        Some(named) => named.as_ref().ok().cloned(),
+        None => get_preset_pattern(pattern).cloned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Aggregate;
+
+    fn condition(field: &str, operator: ComparisonOperator, value: serde_json::Value) -> Condition {
+        Condition {
+            field: field.to_string(),
+            operator,
+            value,
+            ..Default::default()
+        }
+    }
+
+    fn compile(condition: &Condition) -> CompiledCondition {
+        CompiledCondition::compile(condition, &CompileContext::default())
+    }
+
+    #[test]
+    fn test_literals_are_normalized_once() {
+        let compiled = compile(&condition(
+            "shipping_address.city",
+            ComparisonOperator::Equals,
+            serde_json::json!("MONTRÉAL"),
+        ));
+        assert_eq!(compiled.field, FieldPath::ShippingAddress(AddressField::City));
+        assert!(matches!(compiled.operand, Operand::Text(ref s) if s == "montréal"));
+
+        let list = compile(&condition(
+            "cart.total",
+            ComparisonOperator::In,
+            serde_json::json!(["A", 1, "b", 2.5]),
+        ));
+        match list.operand {
+            Operand::Set(set) => {
+                assert_eq!(set, ValueSet::new(vec!["a".into(), "b".into()], vec![1.0, 2.5]));
+            }
+            other => panic!("unexpected operand {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_patterns_compile_once() {
+        let mut preset = condition(
+            "shipping_address.address1",
+            ComparisonOperator::RegexMatch,
+            serde_json::json!("po_box"),
+        );
+        preset.is_preset = true;
+        assert!(matches!(
+            compile(&preset).operand,
+            Operand::Pattern(ref re) if re.is_match("P.O. Box 12")
+        ));
+
+        let custom = condition("shipping_address.zip", ComparisonOperator::RegexMatch, serde_json::json!(r"^9\d+$"));
+        assert!(matches!(compile(&custom).operand, Operand::Pattern(_)));
+
+        let invalid = condition("shipping_address.zip", ComparisonOperator::RegexMatch, serde_json::json!("(unclosed"));
+        assert!(matches!(compile(&invalid).operand, Operand::None));
+
+        // An unknown preset name isn't matched as a regex
+        let mut unknown = condition("shipping_address.zip", ComparisonOperator::RegexMatch, serde_json::json!("zip"));
+        unknown.is_preset = true;
+        assert!(matches!(compile(&unknown).operand, Operand::None));
+    }
+
+    #[test]
+    fn test_typed_operands() {
+        let range = compile(&condition("cart.total", ComparisonOperator::Between, serde_json::json!([5, 10])));
+        assert!(matches!(range.operand, Operand::Range(r) if r.contains(7.0)));
+
+        let ranges = compile(&condition("cart.total", ComparisonOperator::InRanges, serde_json::json!([[1, 2], [3]])));
+        assert!(matches!(ranges.operand, Operand::None));
+
+        let days = compile(&condition("now", ComparisonOperator::DayOfWeekIn, serde_json::json!(["sat", "SUNDAY"])));
+        assert!(matches!(days.operand, Operand::Weekdays(ref d) if *d == vec![Weekday::Sat, Weekday::Sun]));
+
+        // One unknown name makes the whole list unusable
+        let typo = compile(&condition(
+            "now",
+            ComparisonOperator::DayOfWeekIn,
+            serde_json::json!(["sat", "SUNDAY", "someday"]),
+        ));
+        assert!(matches!(typo.operand, Operand::None));
+
+        let now = compile(&condition("customer.created_at", ComparisonOperator::Before, serde_json::json!("now")));
+        assert!(matches!(now.operand, Operand::Date(DateOperand::Now)));
+
+        let mistyped = compile(&condition("cart.total", ComparisonOperator::GreaterThan, serde_json::json!("abc")));
+        assert!(matches!(mistyped.operand, Operand::None));
+    }
+
+    #[test]
+    fn test_regex_in_aggregate_filter_counts_as_regex_rule() {
+        let mut aggregated = condition("line_item.quantity", ComparisonOperator::GreaterThan, serde_json::json!(0));
+        aggregated.aggregate = Some(Aggregate {
+            function: AggregateFunction::Count,
+            filter: Some(ConditionGroup {
+                operator: LogicalOperator::And,
+                criteria: vec![Criterion::Condition(condition(
+                    "line_item.sku",
+                    ComparisonOperator::RegexMatch,
+                    serde_json::json!("^X-"),
+                ))],
+            }),
+        });
+        let rule = Rule {
+            id: "r".to_string(),
+            name: "r".to_string(),
+            complexity: 1,
+            enabled: true,
+            error_message: "Blocked".to_string(),
+            conditions: ConditionGroup {
+                operator: LogicalOperator::And,
+                criteria: vec![Criterion::Condition(aggregated)],
+            },
+        };
+
+        let compiled = CompiledRule::compile(&rule, &rule.conditions, &CompileContext::default());
+        assert!(compiled.uses_regex);
+        assert!(matches!(
+            compiled.conditions.criteria[0],
+            CompiledCriterion::Condition(CompiledCondition { aggregate: Some(CompiledAggregate { filter: Some(_), .. }), .. })
+        ));
+    }
+}
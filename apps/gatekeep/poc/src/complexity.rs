@@ -0,0 +1,355 @@
+//! Complexity points (TRD 3, PRD 5.3), computed from the condition tree.
+//!
+//! `Rule.complexity` and `RulesConfig.total_complexity` are written by the
+//! UI. [`complexity_report`] recomputes them from the rules, so the backend
+//! can check what it is about to save against the plan's budget:
+//!
+//! | Condition                                 | Points                                |
+//! | ----------------------------------------- | ------------------------------------- |
+//! | Numeric, date, equality or presence check | 1                                     |
+//! | Substring, prefix or list member search   | 2                                     |
+//! | `IN` set or keyword list                  | 2 + size (see `sets`, `keywords`)     |
+//! | Pre-built pattern or built-in check       | 3                                     |
+//! | Custom regex                              | 5 + program size (see `regex_safety`) |
+//! | Each nested group                         | +1                                    |
+//!
+//! Built-in checks (postcodes, phones, address quality, profanity, …) are
+//! table-driven, linear-time matchers, so they cost the same as a
+//! pre-built regex. An aggregate condition also pays 1 for the scan over
+//! line items, plus its filter's points.
+
+use crate::keywords::keyword_list_points;
+use crate::models::{ComparisonOperator, Condition, ConditionGroup, Criterion, Rule, RulesConfig};
+use crate::regex_safety::analyze_pattern;
+use crate::sets::{decode_set, set_points};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Points for a numeric, date, equality or presence comparison.
+pub const COMPARISON_POINTS: u32 = 1;
+/// Points for a substring, prefix or suffix search, or a list member scan.
+pub const SEARCH_POINTS: u32 = 2;
+/// Points for a pre-built regex pattern or built-in check.
+pub const PRESET_POINTS: u32 = 3;
+/// Points added for each group nested inside a rule's top-level group.
+pub const NESTED_GROUP_POINTS: u32 = 1;
+/// Points added for an aggregate's scan over line items.
+pub const AGGREGATE_POINTS: u32 = 1;
+
+/// A pricing plan and its complexity budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Plan {
+    Starter,
+    Growth,
+    Plus,
+}
+
+impl Plan {
+    /// Complexity points the plan allows across enabled rules.
+    pub fn budget(self) -> u32 {
+        match self {
+            Plan::Starter => 25,
+            Plan::Growth => 100,
+            Plan::Plus => 250,
+        }
+    }
+}
+
+/// Computed and stored complexity for a whole config.
+#[derive(Debug, Clone, Serialize)]
+pub struct ComplexityReport {
+    pub rules: Vec<RuleComplexity>,
+    /// Sum over every rule, which `total_complexity` should record.
+    pub total: u32,
+    /// Sum over enabled rules; this is what counts against the budget.
+    pub enabled_total: u32,
+    /// `total_complexity` as stored in the config.
+    pub stored_total: u32,
+    pub plan: Plan,
+    pub budget: u32,
+}
+
+impl ComplexityReport {
+    /// True if the stored total and every stored rule complexity match the
+    /// computed points.
+    pub fn stored_matches(&self) -> bool {
+        self.stored_total == self.total && self.rules.iter().all(RuleComplexity::stored_matches)
+    }
+
+    /// True if the enabled rules fit the plan's budget.
+    pub fn within_budget(&self) -> bool {
+        self.enabled_total <= self.budget
+    }
+}
+
+/// Computed and stored complexity for one rule.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleComplexity {
+    pub rule_id: String,
+    pub enabled: bool,
+    pub points: u32,
+    /// `complexity` as stored on the rule.
+    pub stored: u32,
+}
+
+impl RuleComplexity {
+    pub fn stored_matches(&self) -> bool {
+        self.points == self.stored
+    }
+}
+
+/// Compute every rule's points and compare them with the stored values and
+/// `plan`'s budget.
+pub fn complexity_report(config: &RulesConfig, plan: Plan) -> ComplexityReport {
+    let rules: Vec<RuleComplexity> = config
+        .rules
+        .iter()
+        .map(|rule| RuleComplexity {
+            rule_id: rule.id.clone(),
+            enabled: rule.enabled,
+            points: rule_points(rule, &config.regex_patterns),
+            stored: rule.complexity,
+        })
+        .collect();
+    ComplexityReport {
+        total: rules.iter().map(|r| r.points).sum(),
+        enabled_total: rules.iter().filter(|r| r.enabled).map(|r| r.points).sum(),
+        stored_total: config.total_complexity,
+        rules,
+        plan,
+        budget: plan.budget(),
+    }
+}
+
+/// Points for a rule. `patterns` are the config's named patterns, which
+/// cost as custom regexes.
+pub fn rule_points(rule: &Rule, patterns: &HashMap<String, String>) -> u32 {
+    group_points(&rule.conditions, patterns)
+}
+
+/// Points for a group's criteria; each nested group adds
+/// [`NESTED_GROUP_POINTS`].
+pub fn group_points(group: &ConditionGroup, patterns: &HashMap<String, String>) -> u32 {
+    group
+        .criteria
+        .iter()
+        .map(|criterion| match criterion {
+            Criterion::Condition(condition) => condition_points(condition, patterns),
+            Criterion::Group(nested) => NESTED_GROUP_POINTS + group_points(nested, patterns),
+        })
+        .sum()
+}
+
+/// Points for a single condition, including its aggregate filter.
+pub fn condition_points(condition: &Condition, patterns: &HashMap<String, String>) -> u32 {
+    let aggregate = condition.aggregate.as_ref().map_or(0, |aggregate| {
+        AGGREGATE_POINTS + aggregate.filter.as_ref().map_or(0, |filter| group_points(filter, patterns))
+    });
+    operator_points(condition, patterns) + aggregate
+}
+
+fn operator_points(condition: &Condition, patterns: &HashMap<String, String>) -> u32 {
+    match condition.operator {
+        ComparisonOperator::Equals
+        | ComparisonOperator::NotEquals
+        | ComparisonOperator::GreaterThan
+        | ComparisonOperator::GreaterThanOrEqual
+        | ComparisonOperator::LessThan
+        | ComparisonOperator::LessThanOrEqual
+        | ComparisonOperator::Before
+        | ComparisonOperator::After
+        | ComparisonOperator::WithinDays
+        | ComparisonOperator::DayOfWeekIn
+        | ComparisonOperator::Between
+        | ComparisonOperator::InRanges
+        | ComparisonOperator::Exists
+        | ComparisonOperator::NotExists
+        | ComparisonOperator::IsEmpty
+        | ComparisonOperator::IsNotEmpty => COMPARISON_POINTS,
+        ComparisonOperator::Contains
+        | ComparisonOperator::NotContains
+        | ComparisonOperator::StartsWith
+        | ComparisonOperator::EndsWith
+        | ComparisonOperator::InZipRanges
+        | ComparisonOperator::MatchesPrefixAny => SEARCH_POINTS,
+        ComparisonOperator::In
+        | ComparisonOperator::NotIn
+        | ComparisonOperator::EmailDomainIn
+        | ComparisonOperator::NotEmailDomainIn => set_points(set_len(&condition.value)),
+        ComparisonOperator::ContainsAnyOf | ComparisonOperator::ContainsNoneOf => {
+            keyword_list_points(condition.value.as_array().map_or(0, Vec::len))
+        }
+        ComparisonOperator::RegexMatch => match condition.value.as_str() {
+            // A named pattern is a custom regex
+            Some(name) if condition.is_preset => match patterns.get(name) {
+                Some(pattern) => analyze_pattern(pattern).complexity_points,
+                None => PRESET_POINTS,
+            },
+            Some(pattern) => analyze_pattern(pattern).complexity_points,
+            None => PRESET_POINTS,
+        },
+        ComparisonOperator::IsValidPostcode
+        | ComparisonOperator::IsInvalidPostcode
+        | ComparisonOperator::IsValidPhone
+        | ComparisonOperator::IsInvalidPhone
+        | ComparisonOperator::ContainsProfanity
+        | ComparisonOperator::NotContainsProfanity
+        | ComparisonOperator::ZipMatchesProvince
+        | ComparisonOperator::NotZipMatchesProvince
+        | ComparisonOperator::AddressLooksInvalid
+        | ComparisonOperator::IsMilitaryAddress
+        | ComparisonOperator::IsNotMilitaryAddress
+        | ComparisonOperator::IsDisposableEmail => PRESET_POINTS,
+    }
+}
+
+/// Entries in an `IN` value: a list, or an encoded set. Anything else
+/// counts as empty.
+fn set_len(value: &serde_json::Value) -> usize {
+    match value {
+        serde_json::Value::Array(items) => items.len(),
+        serde_json::Value::Object(map) => map
+            .get("set")
+            .and_then(|set| set.as_str())
+            .and_then(decode_set)
+            .map_or(0, |entries| entries.len()),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::LogicalOperator;
+    use crate::sets::encode_set;
+    use serde_json::json;
+
+    fn condition(field: &str, operator: ComparisonOperator, value: serde_json::Value) -> Criterion {
+        Criterion::Condition(Condition { field: field.to_string(), operator, value, ..Default::default() })
+    }
+
+    fn group(operator: LogicalOperator, criteria: Vec<Criterion>) -> ConditionGroup {
+        ConditionGroup { operator, criteria }
+    }
+
+    fn rule(id: &str, complexity: u32, conditions: ConditionGroup) -> Rule {
+        Rule {
+            id: id.to_string(),
+            name: id.to_string(),
+            complexity,
+            enabled: true,
+            error_message: String::new(),
+            conditions,
+        }
+    }
+
+    fn points(criterion: Criterion) -> u32 {
+        group_points(&group(LogicalOperator::And, vec![criterion]), &HashMap::new())
+    }
+
+    #[test]
+    fn test_point_table() {
+        use ComparisonOperator::*;
+        assert_eq!(points(condition("cart.total", GreaterThan, json!(100))), 1);
+        assert_eq!(points(condition("customer.tags", Equals, json!("VIP"))), 1);
+        assert_eq!(points(condition("shipping_address.city", Contains, json!("New"))), 2);
+        assert_eq!(points(condition("shipping_address.zip", IsInvalidPostcode, json!(null))), 3);
+
+        let mut preset = Condition {
+            field: "shipping_address.address1".to_string(),
+            operator: RegexMatch,
+            value: json!("po_box"),
+            is_preset: true,
+            ..Default::default()
+        };
+        assert_eq!(points(Criterion::Condition(preset.clone())), 3);
+        preset.is_preset = false;
+        preset.value = json!("^[0-9]+$");
+        assert_eq!(points(Criterion::Condition(preset)), 5);
+
+        // Sized lists
+        let zips: Vec<String> = (0..2500).map(|i| format!("{:05}", i)).collect();
+        assert_eq!(points(condition("shipping_address.zip", In, json!(zips))), 4);
+        let encoded = encode_set(zips.iter().map(String::as_str));
+        assert_eq!(points(condition("shipping_address.zip", NotIn, json!({ "set": encoded }))), 4);
+        assert_eq!(points(condition("cart.attributes.note", ContainsAnyOf, json!(["a", "b"]))), 3);
+    }
+
+    #[test]
+    fn test_nesting_and_named_patterns() {
+        use ComparisonOperator::*;
+        // total > 100 AND (country = US OR (country = CA AND city CONTAINS "x"))
+        let conditions = group(
+            LogicalOperator::And,
+            vec![
+                condition("cart.total", GreaterThan, json!(100)),
+                Criterion::Group(group(
+                    LogicalOperator::Or,
+                    vec![
+                        condition("shipping_address.country_code", Equals, json!("US")),
+                        Criterion::Group(group(
+                            LogicalOperator::And,
+                            vec![
+                                condition("shipping_address.country_code", Equals, json!("CA")),
+                                condition("shipping_address.city", Contains, json!("x")),
+                            ],
+                        )),
+                    ],
+                )),
+            ],
+        );
+        assert_eq!(group_points(&conditions, &HashMap::new()), 1 + (1 + 1 + (1 + 1 + 2)));
+
+        let named = Criterion::Condition(Condition {
+            field: "shipping_address.zip".to_string(),
+            operator: RegexMatch,
+            value: json!("zip_block"),
+            is_preset: true,
+            ..Default::default()
+        });
+        let patterns = HashMap::from([("zip_block".to_string(), "^9[0-6]".to_string())]);
+        assert_eq!(group_points(&group(LogicalOperator::And, vec![named]), &patterns), 5);
+    }
+
+    #[test]
+    fn test_report() {
+        use ComparisonOperator::*;
+        let mut config = RulesConfig {
+            version: "1.0".to_string(),
+            total_complexity: 3,
+            rules: vec![
+                rule("cheap", 1, group(LogicalOperator::And, vec![condition("cart.total", LessThan, json!(5))])),
+                rule(
+                    "search",
+                    2,
+                    group(LogicalOperator::And, vec![condition("shipping_address.city", StartsWith, json!("X"))]),
+                ),
+            ],
+            regex_patterns: HashMap::new(),
+            timezone_offset_minutes: None,
+            date_order: Default::default(),
+        };
+        let report = complexity_report(&config, Plan::Starter);
+        assert_eq!((report.total, report.enabled_total, report.budget), (3, 3, 25));
+        assert!(report.stored_matches());
+        assert!(report.within_budget());
+
+        // A stale stored value, and a disabled rule that doesn't count
+        config.rules[1].complexity = 1;
+        config.rules[0].enabled = false;
+        let report = complexity_report(&config, Plan::Starter);
+        assert!(!report.stored_matches());
+        assert_eq!(report.rules.iter().filter(|r| !r.stored_matches()).count(), 1);
+        assert_eq!(report.enabled_total, 2);
+
+        // Over budget
+        let heavy = condition("cart.attributes.note", NotContainsProfanity, json!(null));
+        config.rules = (0..9).map(|i| rule(&i.to_string(), 3, group(LogicalOperator::And, vec![heavy.clone()]))).collect();
+        let report = complexity_report(&config, Plan::Starter);
+        assert_eq!(report.enabled_total, 27);
+        assert!(!report.within_budget());
+        assert!(complexity_report(&config, Plan::Growth).within_budget());
+        assert_eq!(Plan::Plus.budget(), 250);
+    }
+}
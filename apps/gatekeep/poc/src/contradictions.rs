@@ -0,0 +1,558 @@
+//! Contradiction and tautology detection for condition groups.
+//!
+//! Conditions on the same field are reduced to constraints on its value:
+//! numeric comparisons and `BETWEEN` to an interval, `EQUALS` / `IN` to a
+//! list of allowed values, `NOT_EQUALS` / `NOT_IN` to excluded ones. A group
+//! is never true when the constraints it needs can't all hold (`total > 100
+//! AND total < 50`), and always true when every possible value satisfies
+//! it (`quantity >= 0`).
+//!
+//! Fields are reasoned about one at a time, and other operators and
+//! aggregates are opaque, so the analysis is incomplete but sound: whatever
+//! it reports is true of every cart. Only cart totals, quantity and weight
+//! are always present, so only they can make a condition always true; a
+//! missing field makes any comparison unknown.
+
+use crate::compiled::{CompileContext, CompiledCondition, Operand};
+use crate::models::{
+    ComparisonOperator, Condition, ConditionGroup, Criterion, FieldPath, LineItemField, LogicalOperator,
+};
+use crate::normalize::Normalization;
+
+/// Most partial results kept while checking whether an `OR` covers every
+/// value of a field. Past this the check gives up.
+const MAX_TERMS: usize = 64;
+
+/// Indexes into a group's criteria that can't all be true together, and
+/// without which the rest could be.
+pub type Conflict = Vec<usize>;
+
+/// A group that can never be true: the minimal subset of its criteria that
+/// conflict. `None` for groups that can be true, or that the analysis
+/// can't decide. Empty groups are `None`; the linter reports them itself.
+pub fn never_true(group: &ConditionGroup) -> Option<Conflict> {
+    if group.criteria.is_empty() {
+        return None;
+    }
+    let all = || (0..group.criteria.len()).collect();
+    match group.operator {
+        LogicalOperator::And => conflict(&group.criteria, false),
+        LogicalOperator::Or => group.criteria.iter().all(criterion_never_true).then(all),
+        LogicalOperator::Not => group.criteria.iter().all(criterion_always_true).then(all),
+    }
+}
+
+/// True if the group is true for every cart.
+pub fn always_true(group: &ConditionGroup) -> bool {
+    if group.criteria.is_empty() {
+        return false;
+    }
+    match group.operator {
+        LogicalOperator::And => group.criteria.iter().all(criterion_always_true),
+        LogicalOperator::Or => group.criteria.iter().any(criterion_always_true) || covers(&group.criteria),
+        LogicalOperator::Not => conflict(&group.criteria, true).is_some(),
+    }
+}
+
+/// True if the group is false for every cart. Unlike [`never_true`], this
+/// rules out unknown too, so it only follows from always-present fields.
+pub fn always_false(group: &ConditionGroup) -> bool {
+    if group.criteria.is_empty() {
+        return false;
+    }
+    match group.operator {
+        LogicalOperator::And => conflict(&group.criteria, true).is_some(),
+        LogicalOperator::Or => group.criteria.iter().all(criterion_always_false),
+        LogicalOperator::Not => group.criteria.iter().all(criterion_always_true),
+    }
+}
+
+pub fn criterion_never_true(criterion: &Criterion) -> bool {
+    match criterion {
+        Criterion::Condition(condition) => {
+            atom(condition).is_some_and(|atom| !domain(&atom.key.field).and(&atom.constraint).is_satisfiable())
+        }
+        Criterion::Group(group) => never_true(group).is_some(),
+    }
+}
+
+pub fn criterion_always_false(criterion: &Criterion) -> bool {
+    match criterion {
+        Criterion::Condition(condition) => atom(condition).is_some_and(|atom| {
+            always_present(&atom.key.field) && !domain(&atom.key.field).and(&atom.constraint).is_satisfiable()
+        }),
+        Criterion::Group(group) => always_false(group),
+    }
+}
+
+pub fn criterion_always_true(criterion: &Criterion) -> bool {
+    match criterion {
+        Criterion::Condition(condition) => atom(condition).is_some_and(|atom| {
+            always_present(&atom.key.field) && implies(&Constraint::default(), &atom.constraint, &atom.key.field)
+        }),
+        Criterion::Group(group) => always_true(group),
+    }
+}
+
+/// Conditions that add nothing to their group, each with a condition that
+/// makes it redundant: in an `AND` (or `NOT`) one that's implied by
+/// another (`total > 50` next to `total > 100`), in an `OR` one that
+/// implies another. Of two equivalent conditions, the later is redundant.
+pub fn redundant(group: &ConditionGroup) -> Vec<(usize, usize)> {
+    let atoms = atoms(&group.criteria);
+    let mut found = Vec::new();
+    for (i, a) in &atoms {
+        let because = atoms.iter().find(|(j, b)| {
+            if i == j || a.key != b.key {
+                return false;
+            }
+            let field = &a.key.field;
+            let (narrow, wide) = match group.operator {
+                LogicalOperator::Or => (a, b),
+                _ => (b, a),
+            };
+            let equivalent = implies(&wide.constraint, &narrow.constraint, field);
+            implies(&narrow.constraint, &wide.constraint, field) && (!equivalent || i > j)
+        });
+        if let Some((j, _)) = because {
+            found.push((*i, *j));
+        }
+    }
+    found
+}
+
+/// A literal from an `EQUALS` or `IN` value, already normalized.
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Number(f64),
+    Text(String),
+}
+
+/// The values of a field a condition (or conjunction) allows. Bounds are
+/// `(value, inclusive)` and only apply to numbers.
+#[derive(Debug, Clone, Default)]
+struct Constraint {
+    lo: Option<(f64, bool)>,
+    hi: Option<(f64, bool)>,
+    allowed: Option<Vec<Literal>>,
+    excluded: Vec<Literal>,
+}
+
+/// What a constraint is about. Text literals only compare under the same
+/// normalization.
+#[derive(Debug, Clone, PartialEq)]
+struct Key {
+    field: FieldPath,
+    normalize: Option<Normalization>,
+}
+
+#[derive(Debug, Clone)]
+struct Atom {
+    key: Key,
+    constraint: Constraint,
+}
+
+impl Constraint {
+    /// Values allowed by both.
+    fn and(&self, other: &Constraint) -> Constraint {
+        let tighter = |a: Option<(f64, bool)>, b: Option<(f64, bool)>, lower: bool| match (a, b) {
+            (Some(x), Some(y)) if x.0 == y.0 => Some((x.0, x.1 && y.1)),
+            (Some(x), Some(y)) => Some(if (x.0 > y.0) == lower { x } else { y }),
+            (x, None) | (None, x) => x,
+        };
+        let allowed = match (&self.allowed, &other.allowed) {
+            (Some(a), Some(b)) => Some(a.iter().filter(|v| b.contains(v)).cloned().collect()),
+            (Some(a), None) | (None, Some(a)) => Some(a.clone()),
+            (None, None) => None,
+        };
+        Constraint {
+            lo: tighter(self.lo, other.lo, true),
+            hi: tighter(self.hi, other.hi, false),
+            allowed,
+            excluded: self.excluded.iter().chain(&other.excluded).cloned().collect(),
+        }
+    }
+
+    /// Values allowed by neither, as alternatives.
+    fn negate(&self) -> Vec<Constraint> {
+        let mut alternatives = Vec::new();
+        if let Some((v, inclusive)) = self.lo {
+            alternatives.push(Constraint { hi: Some((v, !inclusive)), ..Default::default() });
+        }
+        if let Some((v, inclusive)) = self.hi {
+            alternatives.push(Constraint { lo: Some((v, !inclusive)), ..Default::default() });
+        }
+        if let Some(allowed) = &self.allowed {
+            alternatives.push(Constraint { excluded: allowed.clone(), ..Default::default() });
+        }
+        if !self.excluded.is_empty() {
+            alternatives.push(Constraint { allowed: Some(self.excluded.clone()), ..Default::default() });
+        }
+        alternatives
+    }
+
+    fn in_bounds(&self, n: f64) -> bool {
+        let above = self.lo.is_none_or(|(lo, inclusive)| if inclusive { n >= lo } else { n > lo });
+        let below = self.hi.is_none_or(|(hi, inclusive)| if inclusive { n <= hi } else { n < hi });
+        above && below
+    }
+
+    /// True if some value is allowed.
+    fn is_satisfiable(&self) -> bool {
+        if let (Some(lo), Some(hi)) = (self.lo, self.hi) {
+            if lo.0 > hi.0 || (lo.0 == hi.0 && !(lo.1 && hi.1)) {
+                return false;
+            }
+        }
+        let allowed = |literal: &Literal| {
+            !self.excluded.contains(literal)
+                && match literal {
+                    Literal::Number(n) => self.in_bounds(*n),
+                    Literal::Text(_) => true,
+                }
+        };
+        match (&self.allowed, self.lo, self.hi) {
+            (Some(values), ..) => values.iter().any(allowed),
+            // A single point
+            (None, Some(lo), Some(hi)) if lo.0 == hi.0 => allowed(&Literal::Number(lo.0)),
+            (None, ..) => true,
+        }
+    }
+}
+
+/// Numeric fields that can't be negative.
+fn is_numeric(field: &FieldPath) -> bool {
+    matches!(
+        field,
+        FieldPath::CartTotal
+            | FieldPath::CartSubtotal
+            | FieldPath::CartQuantity
+            | FieldPath::CartTotalWeight
+            | FieldPath::LineItem(LineItemField::Quantity | LineItemField::Price | LineItemField::LineTotal)
+    )
+}
+
+/// Fields every cart has. (Line item fields only exist inside aggregates.)
+fn always_present(field: &FieldPath) -> bool {
+    matches!(
+        field,
+        FieldPath::CartTotal | FieldPath::CartSubtotal | FieldPath::CartQuantity | FieldPath::CartTotalWeight
+    )
+}
+
+/// The values a field can take.
+fn domain(field: &FieldPath) -> Constraint {
+    Constraint {
+        lo: is_numeric(field).then_some((0.0, true)),
+        ..Default::default()
+    }
+}
+
+/// True if every value of the field allowed by `a` is allowed by `b`.
+fn implies(a: &Constraint, b: &Constraint, field: &FieldPath) -> bool {
+    let a = domain(field).and(a);
+    b.negate().iter().all(|not_b| !a.and(not_b).is_satisfiable())
+}
+
+/// The constraint a condition puts on its field, if it's one the analysis
+/// understands.
+fn atom(condition: &Condition) -> Option<Atom> {
+    if condition.aggregate.is_some() {
+        return None;
+    }
+    let field = FieldPath::parse(&condition.field);
+    if field == FieldPath::Unknown || field.is_phone() {
+        return None;
+    }
+    let numeric = is_numeric(&field);
+    let operand = CompiledCondition::compile(condition, &CompileContext::default()).operand;
+
+    // The literals an EQUALS or IN value can match on this field
+    let literals = match &operand {
+        Operand::Number(n) if numeric => Some(vec![Literal::Number(*n)]),
+        Operand::Text(s) if !numeric => Some(vec![Literal::Text(s.clone())]),
+        Operand::Set(set) if numeric => Some(set.numbers().iter().map(|n| Literal::Number(*n)).collect()),
+        Operand::Set(set) => Some(set.texts().iter().map(|s| Literal::Text(s.clone())).collect()),
+        _ => None,
+    };
+
+    let constraint = match (condition.operator, &operand) {
+        (ComparisonOperator::GreaterThan, Operand::Number(n)) if numeric => {
+            Constraint { lo: Some((*n, false)), ..Default::default() }
+        }
+        (ComparisonOperator::GreaterThanOrEqual, Operand::Number(n)) if numeric => {
+            Constraint { lo: Some((*n, true)), ..Default::default() }
+        }
+        (ComparisonOperator::LessThan, Operand::Number(n)) if numeric => {
+            Constraint { hi: Some((*n, false)), ..Default::default() }
+        }
+        (ComparisonOperator::LessThanOrEqual, Operand::Number(n)) if numeric => {
+            Constraint { hi: Some((*n, true)), ..Default::default() }
+        }
+        (ComparisonOperator::Between, Operand::Range(range)) if numeric => Constraint {
+            lo: range.min.map(|min| (min, range.min_inclusive)),
+            hi: range.max.map(|max| (max, range.max_inclusive)),
+            ..Default::default()
+        },
+        (ComparisonOperator::Equals | ComparisonOperator::In, _) => {
+            Constraint { allowed: Some(literals?), ..Default::default() }
+        }
+        (ComparisonOperator::NotEquals | ComparisonOperator::NotIn, _) => {
+            Constraint { excluded: literals?, ..Default::default() }
+        }
+        _ => return None,
+    };
+
+    let normalize = (!numeric).then(|| condition.normalize.clone());
+    Some(Atom { key: Key { field, normalize }, constraint })
+}
+
+/// The understood conditions among `criteria`, with their indexes.
+fn atoms(criteria: &[Criterion]) -> Vec<(usize, Atom)> {
+    criteria
+        .iter()
+        .enumerate()
+        .filter_map(|(i, criterion)| match criterion {
+            Criterion::Condition(condition) => atom(condition).map(|atom| (i, atom)),
+            Criterion::Group(_) => None,
+        })
+        .collect()
+}
+
+/// The atoms grouped by the key they constrain.
+fn by_key(atoms: Vec<(usize, Atom)>) -> Vec<(Key, Vec<(usize, Constraint)>)> {
+    let mut groups: Vec<(Key, Vec<(usize, Constraint)>)> = Vec::new();
+    for (i, atom) in atoms {
+        match groups.iter_mut().find(|(key, _)| *key == atom.key) {
+            Some((_, members)) => members.push((i, atom.constraint)),
+            None => groups.push((atom.key, vec![(i, atom.constraint)])),
+        }
+    }
+    groups
+}
+
+/// A minimal set of criteria that can't all be true, if there is one. If
+/// `definite`, only criteria that are false (not unknown) for every cart
+/// count.
+fn conflict(criteria: &[Criterion], definite: bool) -> Option<Conflict> {
+    let dead = if definite { criterion_always_false } else { criterion_never_true };
+    if let Some(i) = criteria.iter().position(dead) {
+        return Some(vec![i]);
+    }
+
+    for (key, members) in by_key(atoms(criteria)) {
+        if definite && !always_present(&key.field) {
+            continue;
+        }
+        let satisfiable = |members: &[(usize, Constraint)]| {
+            members
+                .iter()
+                .fold(domain(&key.field), |acc, (_, c)| acc.and(c))
+                .is_satisfiable()
+        };
+        if satisfiable(&members) {
+            continue;
+        }
+        // Drop every member the conflict doesn't need
+        let mut needed = members;
+        let mut i = 0;
+        while i < needed.len() {
+            let mut without = needed.clone();
+            without.remove(i);
+            if satisfiable(&without) {
+                i += 1;
+            } else {
+                needed = without;
+            }
+        }
+        return Some(needed.into_iter().map(|(i, _)| i).collect());
+    }
+    None
+}
+
+/// True if, for some always-present field, every value satisfies at least
+/// one of the criteria (`total < 100 OR total >= 100`).
+fn covers(criteria: &[Criterion]) -> bool {
+    by_key(atoms(criteria)).into_iter().any(|(key, members)| {
+        if !always_present(&key.field) {
+            return false;
+        }
+        // Values no member allows so far
+        let mut uncovered = vec![domain(&key.field)];
+        for (_, constraint) in &members {
+            uncovered = uncovered
+                .iter()
+                .flat_map(|rest| constraint.negate().into_iter().map(move |not_c| rest.and(&not_c)))
+                .filter(Constraint::is_satisfiable)
+                .collect();
+            if uncovered.len() > MAX_TERMS {
+                return false;
+            }
+        }
+        uncovered.is_empty()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn group(operator: &str, criteria: serde_json::Value) -> ConditionGroup {
+        serde_json::from_value(json!({"operator": operator, "criteria": criteria})).unwrap()
+    }
+
+    fn cond(field: &str, operator: &str, value: serde_json::Value) -> serde_json::Value {
+        json!({"field": field, "operator": operator, "value": value})
+    }
+
+    #[test]
+    fn test_intervals() {
+        let contradiction = group("AND", json!([
+            cond("cart.total", "GREATER_THAN", json!(100)),
+            cond("shipping_address.country_code", "EQUALS", json!("US")),
+            cond("cart.total", "GREATER_THAN", json!(20)),
+            cond("cart.total", "LESS_THAN", json!(50)),
+        ]));
+        assert_eq!(never_true(&contradiction), Some(vec![0, 3]));
+
+        // Touching bounds only meet when both are inclusive
+        let open = group("AND", json!([
+            cond("cart.total", "GREATER_THAN_OR_EQUAL", json!(50)),
+            cond("cart.total", "LESS_THAN", json!(50)),
+        ]));
+        assert_eq!(never_true(&open), Some(vec![0, 1]));
+        let point = group("AND", json!([
+            cond("cart.total", "GREATER_THAN_OR_EQUAL", json!(50)),
+            cond("cart.total", "BETWEEN", json!([0, 50])),
+        ]));
+        assert_eq!(never_true(&point), None);
+        let excluded = group("AND", json!([
+            cond("cart.total", "GREATER_THAN_OR_EQUAL", json!(50)),
+            cond("cart.total", "NOT_EQUALS", json!(50)),
+            cond("cart.total", "LESS_THAN_OR_EQUAL", json!(50)),
+        ]));
+        assert_eq!(never_true(&excluded), Some(vec![0, 1, 2]));
+
+        // Cart totals can't be negative
+        let negative = group("AND", json!([cond("cart.total", "LESS_THAN", json!(0))]));
+        assert_eq!(never_true(&negative), Some(vec![0]));
+        // Attributes can be anything
+        let attribute = group("AND", json!([cond("cart.attributes.n", "LESS_THAN", json!(0))]));
+        assert_eq!(never_true(&attribute), None);
+    }
+
+    #[test]
+    fn test_sets() {
+        let countries = group("AND", json!([
+            cond("shipping_address.country_code", "EQUALS", json!("US")),
+            cond("shipping_address.country_code", "EQUALS", json!("CA")),
+        ]));
+        assert_eq!(never_true(&countries), Some(vec![0, 1]));
+
+        let pairwise = group("AND", json!([
+            cond("shipping_address.country_code", "IN", json!(["US", "CA"])),
+            cond("shipping_address.country_code", "IN", json!(["CA", "MX"])),
+            cond("shipping_address.country_code", "NOT_IN", json!(["ca"])),
+        ]));
+        assert_eq!(never_true(&pairwise), Some(vec![0, 1, 2]));
+
+        // Literals normalized differently aren't compared
+        let mut mixed = countries.clone();
+        if let Criterion::Condition(c) = &mut mixed.criteria[1] {
+            c.normalize.case_sensitive = true;
+        }
+        assert_eq!(never_true(&mixed), None);
+
+        let quantities = group("AND", json!([
+            cond("cart.quantity", "IN", json!([1, 2, 3])),
+            cond("cart.quantity", "GREATER_THAN", json!(3)),
+        ]));
+        assert_eq!(never_true(&quantities), Some(vec![0, 1]));
+    }
+
+    #[test]
+    fn test_groups() {
+        let dead_or = group("OR", json!([
+            cond("cart.total", "LESS_THAN", json!(0)),
+            {"operator": "AND", "criteria": [
+                cond("cart.quantity", "GREATER_THAN", json!(5)),
+                cond("cart.quantity", "LESS_THAN", json!(2)),
+            ]},
+        ]));
+        assert_eq!(never_true(&dead_or), Some(vec![0, 1]));
+
+        let nested = group("AND", json!([
+            cond("cart.total", "GREATER_THAN", json!(10)),
+            {"operator": "OR", "criteria": [cond("cart.total", "LESS_THAN", json!(0))]},
+        ]));
+        assert_eq!(never_true(&nested), Some(vec![1]));
+
+        let not_tautology = group("NOT", json!([cond("cart.quantity", "GREATER_THAN_OR_EQUAL", json!(0))]));
+        assert_eq!(never_true(&not_tautology), Some(vec![0]));
+        assert!(always_false(&not_tautology));
+        assert!(!always_true(&not_tautology));
+    }
+
+    #[test]
+    fn test_always_true() {
+        assert!(always_true(&group("AND", json!([
+            cond("cart.quantity", "GREATER_THAN_OR_EQUAL", json!(0)),
+        ]))));
+        assert!(always_true(&group("OR", json!([
+            cond("cart.total", "LESS_THAN", json!(100)),
+            cond("cart.total", "GREATER_THAN_OR_EQUAL", json!(100)),
+        ]))));
+        assert!(always_true(&group("OR", json!([
+            cond("cart.total", "BETWEEN", json!([0, 100])),
+            cond("cart.total", "NOT_EQUALS", json!(50)),
+        ]))));
+        assert!(always_true(&group("NOT", json!([
+            cond("cart.total", "GREATER_THAN", json!(100)),
+            cond("cart.total", "LESS_THAN", json!(50)),
+        ]))));
+
+        // A gap at exactly 100
+        assert!(!always_true(&group("OR", json!([
+            cond("cart.total", "LESS_THAN", json!(100)),
+            cond("cart.total", "GREATER_THAN", json!(100)),
+        ]))));
+        // Missing fields are unknown, not true or false
+        let missing = group("AND", json!([
+            cond("shipping_address.country_code", "EQUALS", json!("US")),
+            cond("shipping_address.country_code", "EQUALS", json!("CA")),
+        ]));
+        assert_eq!(never_true(&missing), Some(vec![0, 1]));
+        assert!(!always_false(&missing));
+        assert!(!always_true(&group("NOT", json!([
+            cond("shipping_address.country_code", "EQUALS", json!("US")),
+            cond("shipping_address.country_code", "EQUALS", json!("CA")),
+        ]))));
+        assert!(!always_true(&group("OR", json!([
+            cond("shipping_address.country_code", "EQUALS", json!("US")),
+            cond("shipping_address.country_code", "NOT_EQUALS", json!("US")),
+        ]))));
+        assert!(!always_true(&group("AND", json!([
+            cond("cart.attributes.n", "GREATER_THAN_OR_EQUAL", json!(0)),
+        ]))));
+    }
+
+    #[test]
+    fn test_redundant() {
+        let and = group("AND", json!([
+            cond("cart.total", "GREATER_THAN", json!(50)),
+            cond("cart.total", "GREATER_THAN", json!(100)),
+            cond("shipping_address.country_code", "IN", json!(["US", "CA"])),
+            cond("shipping_address.country_code", "EQUALS", json!("US")),
+            cond("cart.total", "GREATER_THAN", json!(100)),
+        ]));
+        assert_eq!(redundant(&and), vec![(0, 1), (2, 3), (4, 1)]);
+
+        let or = group("OR", json!([
+            cond("cart.total", "GREATER_THAN", json!(50)),
+            cond("cart.total", "GREATER_THAN", json!(100)),
+            cond("cart.quantity", "EQUALS", json!(3)),
+        ]));
+        assert_eq!(redundant(&or), vec![(1, 0)]);
+    }
+}
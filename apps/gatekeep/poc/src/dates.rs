@@ -0,0 +1,160 @@
+//! chrono wrappers around [`logicflow_core::dates`], which does the parsing.
+
+use crate::models::RulesConfig;
+use chrono::{DateTime, FixedOffset, TimeZone};
+use logicflow_core::dates as core_dates;
+
+pub use logicflow_core::dates::{parse_date_parts, parse_weekday, DateOrder, ParsedDate, Weekday};
+
+/// How the shop writes dates, from the config.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DateSettings {
+    /// The shop's timezone. `None` uses the offset of the evaluation time.
+    pub offset: Option<FixedOffset>,
+    pub order: DateOrder,
+}
+
+impl DateSettings {
+    pub fn from_config(config: &RulesConfig) -> Self {
+        Self {
+            offset: config
+                .timezone_offset_minutes
+                .and_then(|minutes| FixedOffset::east_opt(minutes.checked_mul(60)?)),
+            order: config.date_order,
+        }
+    }
+
+    /// `now` in the shop's timezone.
+    pub fn local_now(&self, now: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self.offset {
+            Some(offset) => now.with_timezone(&offset),
+            None => now,
+        }
+    }
+}
+
+/// Resolve a parsed date, placing local dates in `offset`.
+pub fn resolve(date: &ParsedDate, offset: FixedOffset) -> Option<DateTime<FixedOffset>> {
+    from_core(&date.resolve(offset.local_minus_utc()))
+}
+
+/// Parse a date or datetime string.
+///
+/// Strings with an explicit offset (RFC 3339 / ISO-8601, e.g.
+/// "2024-06-15T10:00:00Z") keep it; anything else is placed in
+/// `default_offset`. Date-only strings resolve to midnight.
+pub fn parse_date(input: &str, default_offset: FixedOffset, order: DateOrder) -> Option<DateTime<FixedOffset>> {
+    resolve(&parse_date_parts(input, order)?, default_offset)
+}
+
+/// True if `date` falls between `now` and `now + days`. Negative `days`
+/// look backwards, so `-30` means "within the last 30 days". Spans too long
+/// to represent count as unbounded.
+pub fn within_days(date: &DateTime<FixedOffset>, now: &DateTime<FixedOffset>, days: f64) -> bool {
+    core_dates::within_days(&to_core(date), &to_core(now), days)
+}
+
+/// True if `date`, in its own offset, falls on one of `days`.
+pub fn day_of_week_in(date: &DateTime<FixedOffset>, days: &[Weekday]) -> bool {
+    core_dates::day_of_week_in(&to_core(date), days)
+}
+
+fn to_core(date: &DateTime<FixedOffset>) -> core_dates::DateTime {
+    core_dates::DateTime::new(date.timestamp(), date.timestamp_subsec_nanos(), date.offset().local_minus_utc())
+}
+
+fn from_core(date: &core_dates::DateTime) -> Option<DateTime<FixedOffset>> {
+    let offset = FixedOffset::east_opt(date.offset())?;
+    offset.timestamp_opt(date.timestamp(), date.nanos()).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, NaiveDate};
+
+    fn utc() -> FixedOffset {
+        FixedOffset::east_opt(0).unwrap()
+    }
+
+    fn parse(input: &str, offset: FixedOffset) -> Option<DateTime<FixedOffset>> {
+        parse_date(input, offset, DateOrder::MonthFirst)
+    }
+
+    #[test]
+    fn test_parse_iso_with_offset() {
+        let dt = parse("2024-06-15T10:00:00+02:00", utc()).unwrap();
+        assert_eq!(dt.offset().local_minus_utc(), 7200);
+        assert_eq!(dt.to_rfc3339(), "2024-06-15T10:00:00+02:00");
+
+        let dt = parse("2024-06-15T10:00:00.123Z", utc()).unwrap();
+        assert_eq!(dt.to_rfc3339(), "2024-06-15T10:00:00.123+00:00");
+    }
+
+    #[test]
+    fn test_parse_naive_uses_default_offset() {
+        let est = FixedOffset::west_opt(5 * 3600).unwrap();
+        let dt = parse("2024-06-15", est).unwrap();
+        assert_eq!(dt.to_rfc3339(), "2024-06-15T00:00:00-05:00");
+    }
+
+    #[test]
+    fn test_parse_date_parts_defers_offset() {
+        let local = parse_date_parts("2024-06-15", DateOrder::MonthFirst).unwrap();
+        assert!(matches!(local, ParsedDate::Local { .. }));
+        let est = FixedOffset::west_opt(5 * 3600).unwrap();
+        assert_eq!(resolve(&local, est).unwrap().to_rfc3339(), "2024-06-15T00:00:00-05:00");
+
+        let fixed = parse_date_parts("2024-06-15T10:00:00Z", DateOrder::MonthFirst).unwrap();
+        assert_eq!(resolve(&fixed, est).unwrap().to_rfc3339(), "2024-06-15T10:00:00+00:00");
+    }
+
+    #[test]
+    fn test_parse_storefront_formats() {
+        let expected = NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
+        for input in ["06/15/2024", "15.06.2024", "Saturday, June 15, 2024", "2024-06-15 14:30"] {
+            let dt = parse(input, utc()).unwrap_or_else(|| panic!("failed to parse {}", input));
+            assert_eq!(dt.date_naive(), expected, "{}", input);
+        }
+        assert!(parse("next tuesday", utc()).is_none());
+
+        let day_first = parse_date("01/02/2024", utc(), DateOrder::DayFirst).unwrap();
+        assert_eq!((day_first.day(), day_first.month()), (1, 2));
+    }
+
+    #[test]
+    fn test_within_days() {
+        let now = parse("2024-06-15T12:00:00Z", utc()).unwrap();
+        let tomorrow = parse("2024-06-16T12:00:00Z", utc()).unwrap();
+        let last_week = parse("2024-06-08T12:00:00Z", utc()).unwrap();
+
+        assert!(within_days(&tomorrow, &now, 2.0));
+        assert!(!within_days(&tomorrow, &now, -2.0));
+        assert!(within_days(&last_week, &now, -7.0));
+        assert!(!within_days(&last_week, &now, -6.0));
+
+        // Spans past what a Duration holds are unbounded instead of panicking
+        assert!(within_days(&tomorrow, &now, 1e300));
+        assert!(within_days(&last_week, &now, f64::NEG_INFINITY));
+        let latest = DateTime::<chrono::Utc>::MAX_UTC.fixed_offset();
+        assert!(within_days(&latest, &now, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_day_of_week_respects_offset() {
+        // Saturday 23:30 in New York is already Sunday in UTC
+        let dt = parse("2024-06-15T23:30:00-04:00", utc()).unwrap();
+        assert!(day_of_week_in(&dt, &[Weekday::Sat]));
+        assert!(!day_of_week_in(&dt.with_timezone(&utc()), &[Weekday::Sat]));
+    }
+
+    #[test]
+    fn test_settings_put_now_in_shop_timezone() {
+        let now = parse("2024-06-15T02:00:00Z", utc()).unwrap();
+        let settings = DateSettings { offset: FixedOffset::west_opt(4 * 3600), ..Default::default() };
+        let local = settings.local_now(now);
+        assert_eq!(local, now);
+        assert_eq!(local.to_rfc3339(), "2024-06-14T22:00:00-04:00");
+        assert_eq!(DateSettings::default().local_now(now).to_rfc3339(), "2024-06-15T02:00:00+00:00");
+    }
+}
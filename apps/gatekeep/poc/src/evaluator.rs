@@ -2,13 +2,28 @@
 //!
 //! This module contains the core logic for evaluating rules against cart data.
 //! It is designed for maximum performance within Shopify Functions' 5ms budget.
+//! Rules are evaluated from a [`CompiledRules`] plan rather than the raw config.
 
+use crate::address_quality::{address_looks_invalid, AddressParts};
+use crate::compiled::{
+    CompiledAggregate, CompiledCondition, CompiledCriterion, CompiledGroup, CompiledRule,
+    CompiledRules, CountrySource, DateOperand, Operand,
+};
 use crate::models::{
-    CartInput, ComparisonOperator, Condition, ConditionGroup, Criterion, FieldValue,
-    LogicalOperator, Rule, RulesConfig,
+    AddressField, AggregateFunction, CartInput, ComparisonOperator, FieldPath, FieldSource, FieldValue, LineItem,
+    LogicalOperator, RulesConfig,
 };
-use crate::patterns::get_preset_pattern;
-use regex::Regex;
+use crate::dates::{day_of_week_in, parse_date, resolve, within_days, DateOrder};
+use crate::emails::{email_domain, is_disposable_email, parent_domains};
+use crate::military::is_military_address;
+use crate::normalize::Normalization;
+use crate::phones::{is_valid_phone, phone_forms};
+use crate::postcodes::{canonical_postcode, in_postcode_range, is_valid_postcode};
+use crate::profanity::contains_profanity;
+use crate::regions::zip_matches_province;
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use std::collections::HashSet;
 
 /// Result of evaluating rules against a cart.
 #[derive(Debug, Clone)]
@@ -19,7 +34,7 @@ pub struct EvaluationResult {
 }
 
 /// A validation error to return to checkout.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ValidationError {
     pub rule_id: String,
     pub message: String,
@@ -53,13 +68,28 @@ pub fn evaluate_rules_with_config(
     config: &RulesConfig,
     cart: &CartInput,
     eval_config: &EvaluatorConfig,
+) -> EvaluationResult {
+    evaluate_compiled_with_config(&CompiledRules::compile(config), cart, eval_config)
+}
+
+/// Evaluate a compiled plan against the cart. Compile once with
+/// [`CompiledRules::compile`] when evaluating the same rules repeatedly.
+pub fn evaluate_compiled(rules: &CompiledRules, cart: &CartInput) -> EvaluationResult {
+    evaluate_compiled_with_config(rules, cart, &EvaluatorConfig::default())
+}
+
+/// Evaluate a compiled plan with custom guardrail configuration.
+pub fn evaluate_compiled_with_config(
+    rules: &CompiledRules,
+    cart: &CartInput,
+    eval_config: &EvaluatorConfig,
 ) -> EvaluationResult {
     let start = std::time::Instant::now();
     let mut errors = Vec::new();
     let mut rules_evaluated = 0;
     let mut regex_count = 0;
 
-    for rule in &config.rules {
+    for rule in &rules.rules {
         // Guardrail 1: Max rules
         if rules_evaluated >= eval_config.max_rules {
             #[cfg(debug_assertions)]
@@ -73,7 +103,7 @@ pub fn evaluate_rules_with_config(
         }
 
         // Guardrail 2: Max regex rules
-        if rule_uses_regex(&rule.conditions) {
+        if rule.uses_regex {
             regex_count += 1;
             if regex_count > eval_config.max_regex_rules {
                 #[cfg(debug_assertions)]
@@ -110,173 +140,601 @@ pub fn evaluate_rules_with_config(
     }
 }
 
-/// Check if a condition group uses any regex operators.
-fn rule_uses_regex(group: &ConditionGroup) -> bool {
-    group.criteria.iter().any(|criterion| match criterion {
-        Criterion::Condition(c) => c.operator == ComparisonOperator::RegexMatch,
-        Criterion::Group(g) => rule_uses_regex(g),
-    })
+/// Three-valued (Kleene) truth value.
+///
+/// A condition whose field is missing, or whose field and value have
+/// incompatible types, is `Unknown` rather than `False`. `Unknown` survives
+/// negation (so `NOT_CONTAINS` on a missing field doesn't silently become
+/// true), loses to `False` under AND and to `True` under OR. A rule only
+/// fires when its conditions are `True`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Truth {
+    True,
+    False,
+    Unknown,
+}
+
+impl Truth {
+    pub fn is_true(self) -> bool {
+        self == Truth::True
+    }
+
+    /// `Some(b)` is a definite answer; `None` means "can't tell".
+    pub fn from_option(value: Option<bool>) -> Self {
+        match value {
+            Some(true) => Truth::True,
+            Some(false) => Truth::False,
+            None => Truth::Unknown,
+        }
+    }
+}
+
+impl From<bool> for Truth {
+    fn from(value: bool) -> Self {
+        if value {
+            Truth::True
+        } else {
+            Truth::False
+        }
+    }
+}
+
+impl std::ops::Not for Truth {
+    type Output = Truth;
+
+    fn not(self) -> Truth {
+        match self {
+            Truth::True => Truth::False,
+            Truth::False => Truth::True,
+            Truth::Unknown => Truth::Unknown,
+        }
+    }
 }
 
 /// Evaluate a single rule against the cart.
-fn evaluate_rule(rule: &Rule, cart: &CartInput) -> bool {
-    evaluate_group(&rule.conditions, cart)
+fn evaluate_rule(rule: &CompiledRule, cart: &CartInput) -> bool {
+    evaluate_group(&rule.conditions, cart).is_true()
 }
 
-/// Evaluate a condition group (AND/OR logic).
-fn evaluate_group(group: &ConditionGroup, cart: &CartInput) -> bool {
+/// Evaluate a condition group (AND/OR/NOT logic), short-circuiting on the
+/// first deciding criterion.
+pub(crate) fn evaluate_group<S: FieldSource>(group: &CompiledGroup, source: &S) -> Truth {
     match group.operator {
-        LogicalOperator::And => group
-            .criteria
-            .iter()
-            .all(|criterion| evaluate_criterion(criterion, cart)),
-        LogicalOperator::Or => group
-            .criteria
-            .iter()
-            .any(|criterion| evaluate_criterion(criterion, cart)),
+        LogicalOperator::And => evaluate_all(&group.criteria, source),
+        LogicalOperator::Or => {
+            let mut result = Truth::False;
+            for criterion in &group.criteria {
+                match evaluate_criterion(criterion, source) {
+                    Truth::True => return Truth::True,
+                    Truth::Unknown => result = Truth::Unknown,
+                    Truth::False => {}
+                }
+            }
+            result
+        }
+        LogicalOperator::Not => !evaluate_all(&group.criteria, source),
     }
 }
 
+/// Kleene AND over a list of criteria.
+fn evaluate_all<S: FieldSource>(criteria: &[CompiledCriterion], source: &S) -> Truth {
+    let mut result = Truth::True;
+    for criterion in criteria {
+        match evaluate_criterion(criterion, source) {
+            Truth::False => return Truth::False,
+            Truth::Unknown => result = Truth::Unknown,
+            Truth::True => {}
+        }
+    }
+    result
+}
+
 /// Evaluate a single criterion (either a condition or nested group).
-fn evaluate_criterion(criterion: &Criterion, cart: &CartInput) -> bool {
+fn evaluate_criterion<S: FieldSource>(criterion: &CompiledCriterion, source: &S) -> Truth {
     match criterion {
-        Criterion::Condition(condition) => evaluate_condition(condition, cart),
-        Criterion::Group(group) => evaluate_group(group, cart),
+        CompiledCriterion::Condition(condition) => evaluate_condition(condition, source),
+        CompiledCriterion::Group(group) => evaluate_group(group, source),
     }
 }
 
-/// Evaluate a single condition against the cart.
-fn evaluate_condition(condition: &Condition, cart: &CartInput) -> bool {
-    let field_value = match cart.get_field(&condition.field) {
-        Some(v) => v,
-        None => return false, // Field not found, condition doesn't match
+/// Evaluate a single condition against the cart (or a line item, inside an
+/// aggregate filter).
+pub(crate) fn evaluate_condition<S: FieldSource>(condition: &CompiledCondition, source: &S) -> Truth {
+    let field_value = match &condition.aggregate {
+        Some(aggregate) => evaluate_aggregate(aggregate, &condition.field, source.line_items()),
+        // In the shop's timezone, so day-of-week checks see the shop's day
+        None if condition.field == FieldPath::Now => {
+            Some(FieldValue::DateTime(condition.dates.local_now(source.now())))
+        }
+        None => source.resolve(&condition.field),
     };
 
-    compare(&field_value, &condition.operator, &condition.value, condition.is_preset)
+    // Presence checks are the only operators with a definite answer for
+    // missing data.
+    match condition.operator {
+        ComparisonOperator::Exists => return field_value.is_some().into(),
+        ComparisonOperator::NotExists => return field_value.is_none().into(),
+        ComparisonOperator::IsEmpty => {
+            return field_value.as_ref().is_none_or(|v| is_empty(v, &condition.normalize)).into()
+        }
+        ComparisonOperator::IsNotEmpty => {
+            return field_value.as_ref().is_some_and(|v| !is_empty(v, &condition.normalize)).into()
+        }
+        ComparisonOperator::IsValidPostcode => {
+            return Truth::from_option(check_postcode(field_value.as_ref(), &condition.operand, source))
+        }
+        ComparisonOperator::IsInvalidPostcode => {
+            return !Truth::from_option(check_postcode(field_value.as_ref(), &condition.operand, source))
+        }
+        ComparisonOperator::IsValidPhone => {
+            return Truth::from_option(check_phone(field_value.as_ref(), &condition.operand, source))
+        }
+        ComparisonOperator::IsInvalidPhone => {
+            return !Truth::from_option(check_phone(field_value.as_ref(), &condition.operand, source))
+        }
+        ComparisonOperator::InZipRanges => {
+            return Truth::from_option(check_postcode_ranges(field_value.as_ref(), &condition.operand, source))
+        }
+        ComparisonOperator::ZipMatchesProvince => {
+            return Truth::from_option(check_region(field_value.as_ref(), &condition.operand, source))
+        }
+        ComparisonOperator::NotZipMatchesProvince => {
+            return !Truth::from_option(check_region(field_value.as_ref(), &condition.operand, source))
+        }
+        ComparisonOperator::AddressLooksInvalid => return Truth::from_option(check_address(&condition.operand, source)),
+        ComparisonOperator::IsMilitaryAddress => return Truth::from_option(check_military(&condition.operand, source)),
+        ComparisonOperator::IsNotMilitaryAddress => return !Truth::from_option(check_military(&condition.operand, source)),
+        _ => {}
+    }
+
+    match field_value {
+        Some(v) => Truth::from_option(compare(&v, condition, &condition.dates.local_now(source.now()))),
+        None => Truth::Unknown, // Field not found, so we can't tell
+    }
 }
 
-/// Compare a field value against a condition value using the specified operator.
-fn compare(
-    field_value: &FieldValue,
-    operator: &ComparisonOperator,
-    condition_value: &serde_json::Value,
-    is_preset: bool,
-) -> bool {
-    match operator {
-        ComparisonOperator::Equals => compare_equals(field_value, condition_value),
-        ComparisonOperator::NotEquals => !compare_equals(field_value, condition_value),
-        ComparisonOperator::GreaterThan => compare_numeric(field_value, condition_value, |a, b| a > b),
-        ComparisonOperator::GreaterThanOrEqual => compare_numeric(field_value, condition_value, |a, b| a >= b),
-        ComparisonOperator::LessThan => compare_numeric(field_value, condition_value, |a, b| a < b),
-        ComparisonOperator::LessThanOrEqual => compare_numeric(field_value, condition_value, |a, b| a <= b),
-        ComparisonOperator::Contains => compare_contains(field_value, condition_value),
-        ComparisonOperator::NotContains => !compare_contains(field_value, condition_value),
-        ComparisonOperator::StartsWith => compare_starts_with(field_value, condition_value),
-        ComparisonOperator::EndsWith => compare_ends_with(field_value, condition_value),
-        ComparisonOperator::RegexMatch => compare_regex(field_value, condition_value, is_preset),
-        ComparisonOperator::In => compare_in(field_value, condition_value),
-        ComparisonOperator::NotIn => !compare_in(field_value, condition_value),
-    }
-}
-
-fn compare_equals(field_value: &FieldValue, condition_value: &serde_json::Value) -> bool {
-    match (field_value, condition_value) {
-        (FieldValue::String(s), serde_json::Value::String(cv)) => s == cv,
-        (FieldValue::Number(n), serde_json::Value::Number(cv)) => {
-            cv.as_f64().map_or(false, |cv| (*n - cv).abs() < f64::EPSILON)
-        }
-        (FieldValue::Bool(b), serde_json::Value::Bool(cv)) => b == cv,
+/// True for empty strings (after normalization, so `trim` makes
+/// whitespace-only values empty) and empty arrays.
+fn is_empty(field_value: &FieldValue, norm: &Normalization) -> bool {
+    match field_value {
+        FieldValue::String(s) => norm.apply(s).is_empty(),
+        FieldValue::StringArray(arr) => arr.is_empty(),
         _ => false,
     }
 }
 
-fn compare_numeric<F>(field_value: &FieldValue, condition_value: &serde_json::Value, cmp: F) -> bool
+/// Check a postcode against its country's formats. Unknown when the
+/// postcode or country is missing, or the country has no format on file.
+fn check_postcode<S: FieldSource>(field_value: Option<&FieldValue>, operand: &Operand, source: &S) -> Option<bool> {
+    let Some(FieldValue::String(postcode)) = field_value else {
+        return None;
+    };
+    is_valid_postcode(&country_code(operand, source)?, postcode)
+}
+
+/// Check a phone number's length for its country. Unknown when the number
+/// is missing, or it's national and the country is missing or has no
+/// numbering plan on file.
+fn check_phone<S: FieldSource>(field_value: Option<&FieldValue>, operand: &Operand, source: &S) -> Option<bool> {
+    let Some(FieldValue::String(phone)) = field_value else {
+        return None;
+    };
+    // Numbers already in E.164 don't need a country
+    let country = country_code(operand, source).unwrap_or_default();
+    is_valid_phone(&country, phone)
+}
+
+/// The country a postcode or phone check reads its value in.
+fn country_code<S: FieldSource>(operand: &Operand, source: &S) -> Option<String> {
+    match operand {
+        Operand::Country(CountrySource::Code(code)) => Some(code.clone()),
+        Operand::Country(CountrySource::Field(field)) => match source.resolve(field)? {
+            FieldValue::String(code) => Some(code),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Check a postcode against `IN_ZIP_RANGES`. The address's country picks
+/// numeric or lexicographic comparison; without one it's lexicographic.
+fn check_postcode_ranges<S: FieldSource>(field_value: Option<&FieldValue>, operand: &Operand, source: &S) -> Option<bool> {
+    let (Some(FieldValue::String(postcode)), Operand::PostcodeRanges { ranges, country }) = (field_value, operand) else {
+        return None;
+    };
+    let country = match country.as_ref().and_then(|field| source.resolve(field)) {
+        Some(FieldValue::String(code)) => Some(code),
+        _ => None,
+    };
+    // Kleene OR: a range we can't compare against leaves a miss unknown
+    let mut result = Some(false);
+    for (low, high) in ranges {
+        match in_postcode_range(country.as_deref(), postcode, low, high) {
+            Some(true) => return Some(true),
+            Some(false) => {}
+            None => result = None,
+        }
+    }
+    result
+}
+
+/// Check that a postcode belongs to its address's province. Unknown when
+/// the postcode, country or province is missing, or the postcode can't be
+/// placed.
+fn check_region<S: FieldSource>(field_value: Option<&FieldValue>, operand: &Operand, source: &S) -> Option<bool> {
+    let (Some(FieldValue::String(postcode)), Operand::Region { country, province }) = (field_value, operand) else {
+        return None;
+    };
+    match (source.resolve(country)?, source.resolve(province)?) {
+        (FieldValue::String(country), FieldValue::String(province)) => zip_matches_province(&country, postcode, &province),
+        _ => None,
+    }
+}
+
+/// Run the selected address quality signals over the shipping address.
+/// Unknown when there's no shipping address.
+fn check_address<S: FieldSource>(operand: &Operand, source: &S) -> Option<bool> {
+    let Operand::AddressSignals(signals) = operand else {
+        return None;
+    };
+    with_shipping_address(source, |parts| address_looks_invalid(signals, parts))
+}
+
+/// Check for a military address. Unknown when there's no shipping address.
+fn check_military<S: FieldSource>(operand: &Operand, source: &S) -> Option<bool> {
+    let Operand::ShippingAddress = operand else {
+        return None;
+    };
+    with_shipping_address(source, is_military_address)
+}
+
+/// Call `f` with the shipping address, or return `None` if there isn't one.
+fn with_shipping_address<S: FieldSource, T>(source: &S, f: impl FnOnce(&AddressParts) -> T) -> Option<T> {
+    let text = |field| match source.resolve(&FieldPath::ShippingAddress(field)) {
+        Some(FieldValue::String(s)) => Some(s),
+        _ => None,
+    };
+    let (address1, address2, city, province_code, country_code) = (
+        text(AddressField::Address1)?,
+        text(AddressField::Address2).unwrap_or_default(),
+        text(AddressField::City).unwrap_or_default(),
+        text(AddressField::ProvinceCode).unwrap_or_default(),
+        text(AddressField::CountryCode).unwrap_or_default(),
+    );
+    Some(f(&AddressParts {
+        address1: &address1,
+        address2: &address2,
+        city: &city,
+        province_code: &province_code,
+        country_code: &country_code,
+    }))
+}
+
+/// Compute an aggregate of `field` over the line items matching the filter.
+///
+/// Returns `None` for `MIN`/`MAX` over no lines, or when `field` is not a
+/// numeric line item field for `SUM`/`MIN`/`MAX`. Lines whose filter
+/// evaluates to `Unknown` don't contribute.
+pub(crate) fn evaluate_aggregate(aggregate: &CompiledAggregate, field: &FieldPath, lines: &[LineItem]) -> Option<FieldValue> {
+    let matching = lines.iter().filter(|line| {
+        aggregate
+            .filter
+            .as_ref()
+            .is_none_or(|filter| evaluate_group(filter, *line).is_true())
+    });
+
+    match aggregate.function {
+        AggregateFunction::Count => Some(FieldValue::Number(matching.count() as f64)),
+        AggregateFunction::CountDistinct => {
+            let mut seen = HashSet::new();
+            for line in matching {
+                match line.resolve(field) {
+                    Some(FieldValue::String(s)) => seen.insert(s.to_lowercase()),
+                    Some(FieldValue::Number(n)) => seen.insert(n.to_string()),
+                    _ => continue,
+                };
+            }
+            Some(FieldValue::Number(seen.len() as f64))
+        }
+        AggregateFunction::Sum | AggregateFunction::Min | AggregateFunction::Max => {
+            let mut values = Vec::new();
+            for line in matching {
+                match line.resolve(field) {
+                    Some(FieldValue::Number(n)) => values.push(n),
+                    _ => return None,
+                }
+            }
+
+            let result = match aggregate.function {
+                AggregateFunction::Sum => values.iter().sum(),
+                AggregateFunction::Min => values.iter().copied().reduce(f64::min)?,
+                _ => values.iter().copied().reduce(f64::max)?,
+            };
+            Some(FieldValue::Number(result))
+        }
+    }
+}
+
+/// Compare a field value against the condition's operand using its operator.
+///
+/// Returns `None` when the two can't be compared (e.g. `GREATER_THAN` on a
+/// string field, or `IN` with a non-array value).
+fn compare(field_value: &FieldValue, condition: &CompiledCondition, now: &DateTime<FixedOffset>) -> Option<bool> {
+    let operand = &condition.operand;
+    let norm = &condition.normalize;
+    let order = condition.dates.order;
+
+    match condition.operator {
+        ComparisonOperator::Equals => compare_equals(field_value, operand, norm),
+        ComparisonOperator::NotEquals => compare_equals(field_value, operand, norm).map(|b| !b),
+        ComparisonOperator::GreaterThan => compare_numeric(field_value, operand, |a, b| a > b),
+        ComparisonOperator::GreaterThanOrEqual => compare_numeric(field_value, operand, |a, b| a >= b),
+        ComparisonOperator::LessThan => compare_numeric(field_value, operand, |a, b| a < b),
+        ComparisonOperator::LessThanOrEqual => compare_numeric(field_value, operand, |a, b| a <= b),
+        ComparisonOperator::Contains => compare_contains(field_value, operand, norm),
+        ComparisonOperator::NotContains => compare_contains(field_value, operand, norm).map(|b| !b),
+        ComparisonOperator::StartsWith => {
+            compare_text(field_value, operand, norm, |s, cv| s.starts_with(cv))
+        }
+        ComparisonOperator::EndsWith => compare_text(field_value, operand, norm, |s, cv| s.ends_with(cv)),
+        ComparisonOperator::RegexMatch => compare_regex(field_value, operand, norm),
+        ComparisonOperator::In => compare_in(field_value, operand, norm),
+        ComparisonOperator::NotIn => compare_in(field_value, operand, norm).map(|b| !b),
+        ComparisonOperator::IsDisposableEmail => match field_value {
+            FieldValue::String(email) => is_disposable_email(email),
+            _ => None,
+        },
+        ComparisonOperator::EmailDomainIn => compare_email_domain(field_value, operand),
+        ComparisonOperator::NotEmailDomainIn => compare_email_domain(field_value, operand).map(|b| !b),
+        ComparisonOperator::Before => compare_dates(field_value, operand, now, order, |a, b| a < b),
+        ComparisonOperator::After => compare_dates(field_value, operand, now, order, |a, b| a > b),
+        ComparisonOperator::WithinDays => compare_within_days(field_value, operand, now, order),
+        ComparisonOperator::DayOfWeekIn => compare_day_of_week(field_value, operand, now, order),
+        ComparisonOperator::Between => compare_between(field_value, operand),
+        ComparisonOperator::InRanges => compare_in_ranges(field_value, operand),
+        // Handled in evaluate_condition, since they apply to missing fields
+        ComparisonOperator::Exists => Some(true),
+        ComparisonOperator::NotExists => Some(false),
+        ComparisonOperator::IsEmpty => Some(is_empty(field_value, norm)),
+        ComparisonOperator::IsNotEmpty => Some(!is_empty(field_value, norm)),
+        // Need the country, so also handled in evaluate_condition
+        ComparisonOperator::IsValidPostcode
+        | ComparisonOperator::IsInvalidPostcode
+        | ComparisonOperator::IsValidPhone
+        | ComparisonOperator::IsInvalidPhone
+        | ComparisonOperator::InZipRanges
+        | ComparisonOperator::ZipMatchesProvince
+        | ComparisonOperator::NotZipMatchesProvince
+        | ComparisonOperator::AddressLooksInvalid
+        | ComparisonOperator::IsMilitaryAddress
+        | ComparisonOperator::IsNotMilitaryAddress => None,
+        ComparisonOperator::ContainsProfanity => compare_profanity(field_value, operand),
+        ComparisonOperator::NotContainsProfanity => compare_profanity(field_value, operand).map(|b| !b),
+        ComparisonOperator::ContainsAnyOf => compare_keywords(field_value, operand, norm),
+        ComparisonOperator::ContainsNoneOf => compare_keywords(field_value, operand, norm).map(|b| !b),
+        ComparisonOperator::MatchesPrefixAny => compare_prefixes(field_value, operand),
+    }
+}
+
+fn compare_equals(field_value: &FieldValue, operand: &Operand, norm: &Normalization) -> Option<bool> {
+    match (field_value, operand) {
+        (FieldValue::String(s), Operand::Text(cv)) => Some(norm.apply(s) == cv.as_str()),
+        (FieldValue::Number(n), Operand::Number(cv)) => Some((*n - cv).abs() < f64::EPSILON),
+        (FieldValue::Bool(b), Operand::Bool(cv)) => Some(b == cv),
+        (FieldValue::String(s), Operand::Phone(key)) => Some(phone_forms(s).contains(key)),
+        _ => None,
+    }
+}
+
+fn compare_numeric<F>(field_value: &FieldValue, operand: &Operand, cmp: F) -> Option<bool>
 where
     F: Fn(f64, f64) -> bool,
 {
+    match (field_as_number(field_value), operand) {
+        (Some(n), Operand::Number(cv)) => Some(cmp(n, *cv)),
+        _ => None,
+    }
+}
+
+/// Numeric view of a field value. Money and weight types should resolve
+/// here so range operators work on them unchanged.
+fn field_as_number(field_value: &FieldValue) -> Option<f64> {
     match field_value {
-        FieldValue::Number(n) => condition_value
-            .as_f64()
-            .map_or(false, |cv| cmp(*n, cv)),
-        _ => false,
+        FieldValue::Number(n) => Some(*n),
+        _ => None,
     }
 }
 
-fn compare_contains(field_value: &FieldValue, condition_value: &serde_json::Value) -> bool {
-    match (field_value, condition_value) {
-        (FieldValue::String(s), serde_json::Value::String(cv)) => {
-            s.to_lowercase().contains(&cv.to_lowercase())
-        }
-        (FieldValue::StringArray(arr), serde_json::Value::String(cv)) => {
-            arr.iter().any(|s| s.to_lowercase() == cv.to_lowercase())
+fn compare_between(field_value: &FieldValue, operand: &Operand) -> Option<bool> {
+    match (field_as_number(field_value), operand) {
+        (Some(n), Operand::Range(range)) => Some(range.contains(n)),
+        _ => None,
+    }
+}
+
+fn compare_in_ranges(field_value: &FieldValue, operand: &Operand) -> Option<bool> {
+    match (field_as_number(field_value), operand) {
+        (Some(n), Operand::Ranges(ranges)) => Some(ranges.iter().any(|range| range.contains(n))),
+        _ => None,
+    }
+}
+
+fn compare_contains(field_value: &FieldValue, operand: &Operand, norm: &Normalization) -> Option<bool> {
+    match (field_value, operand) {
+        (FieldValue::String(s), Operand::Text(cv)) => Some(norm.apply(s).contains(cv.as_str())),
+        (FieldValue::StringArray(arr), Operand::Text(cv)) => {
+            Some(arr.iter().any(|s| norm.apply(s) == cv.as_str()))
         }
-        _ => false,
+        _ => None,
     }
 }
 
-fn compare_starts_with(field_value: &FieldValue, condition_value: &serde_json::Value) -> bool {
-    match (field_value, condition_value) {
-        (FieldValue::String(s), serde_json::Value::String(cv)) => {
-            s.to_lowercase().starts_with(&cv.to_lowercase())
+/// `CONTAINS` against a keyword list: a substring of text, or a member of a
+/// list field.
+fn compare_keywords(field_value: &FieldValue, operand: &Operand, norm: &Normalization) -> Option<bool> {
+    match (field_value, operand) {
+        (FieldValue::String(s), Operand::Keywords(keywords)) => Some(keywords.is_match(&norm.apply(s))),
+        (FieldValue::StringArray(arr), Operand::Keywords(keywords)) => {
+            Some(arr.iter().any(|s| keywords.contains(&norm.apply(s))))
         }
-        _ => false,
+        _ => None,
     }
 }
 
-fn compare_ends_with(field_value: &FieldValue, condition_value: &serde_json::Value) -> bool {
-    match (field_value, condition_value) {
-        (FieldValue::String(s), serde_json::Value::String(cv)) => {
-            s.to_lowercase().ends_with(&cv.to_lowercase())
+/// Text, or any member of a list, starts with one of the prefixes after
+/// postcode canonicalization (case, spaces and hyphens).
+fn compare_prefixes(field_value: &FieldValue, operand: &Operand) -> Option<bool> {
+    let Operand::Prefixes(prefixes) = operand else {
+        return None;
+    };
+    let matches = |s: &str| {
+        let s = canonical_postcode(s);
+        prefixes.iter().any(|prefix| s.starts_with(prefix.as_str()))
+    };
+    match field_value {
+        FieldValue::String(s) => Some(matches(s)),
+        FieldValue::StringArray(arr) => Some(arr.iter().any(|s| matches(s))),
+        _ => None,
+    }
+}
+
+/// Check text, or any member of a list, against profanity word lists. The
+/// filter folds case and look-alikes itself, so normalization is skipped.
+fn compare_profanity(field_value: &FieldValue, operand: &Operand) -> Option<bool> {
+    match (field_value, operand) {
+        (FieldValue::String(s), Operand::WordLists(lists)) => Some(contains_profanity(s, lists)),
+        (FieldValue::StringArray(arr), Operand::WordLists(lists)) => {
+            Some(arr.iter().any(|s| contains_profanity(s, lists)))
         }
-        _ => false,
+        _ => None,
     }
 }
 
-fn compare_regex(field_value: &FieldValue, condition_value: &serde_json::Value, is_preset: bool) -> bool {
-    let pattern_str = match condition_value.as_str() {
-        Some(s) => s,
-        None => return false,
+/// Compare a normalized string field against a text operand.
+fn compare_text<F>(field_value: &FieldValue, operand: &Operand, norm: &Normalization, cmp: F) -> Option<bool>
+where
+    F: Fn(&str, &str) -> bool,
+{
+    match (field_value, operand) {
+        (FieldValue::String(s), Operand::Text(cv)) => Some(cmp(&norm.apply(s), cv)),
+        _ => None,
+    }
+}
+
+fn compare_regex(field_value: &FieldValue, operand: &Operand, norm: &Normalization) -> Option<bool> {
+    match (field_value, operand) {
+        // The pattern decides case sensitivity; other normalization applies.
+        (FieldValue::String(s), Operand::Pattern(re)) => Some(re.is_match(&norm.apply_keep_case(s))),
+        _ => None,
+    }
+}
+
+fn compare_in(field_value: &FieldValue, operand: &Operand, norm: &Normalization) -> Option<bool> {
+    let set = match (field_value, operand) {
+        (FieldValue::String(s), Operand::Phones(set)) => {
+            return Some(phone_forms(s).iter().any(|form| set.contains_text(form)))
+        }
+        (_, Operand::Set(set)) => set,
+        _ => return None,
     };
 
-    let field_str = match field_value {
-        FieldValue::String(s) => s,
-        _ => return false,
+    match field_value {
+        FieldValue::String(s) => Some(set.contains_text(&norm.apply(s))),
+        FieldValue::Number(n) => Some(set.contains_number(*n)),
+        _ => None,
+    }
+}
+
+/// True if the email's domain, or one it's a subdomain of, is in the set.
+/// `None` for values that aren't email addresses.
+fn compare_email_domain(field_value: &FieldValue, operand: &Operand) -> Option<bool> {
+    let (FieldValue::String(email), Operand::Set(set)) = (field_value, operand) else {
+        return None;
     };
+    let domain = email_domain(email)?;
+    let listed = parent_domains(&domain).any(|d| set.contains_text(d));
+    Some(listed)
+}
 
-    // Use preset pattern if available
-    if is_preset {
-        if let Some(preset) = get_preset_pattern(pattern_str) {
-            return preset.is_match(field_str);
-        }
+/// Coerce a field value to a datetime. String fields (e.g. cart
+/// attributes) are parsed in the shop's timezone, which `now` is in.
+fn field_as_date(
+    field_value: &FieldValue,
+    now: &DateTime<FixedOffset>,
+    order: DateOrder,
+) -> Option<DateTime<FixedOffset>> {
+    match field_value {
+        FieldValue::DateTime(dt) => Some(*dt),
+        FieldValue::String(s) => parse_date(s, *now.offset(), order),
+        _ => None,
     }
+}
 
-    // Fall back to compiling the pattern (slower, but allows custom regex)
-    // In production, custom patterns would be pre-validated at save time
-    match Regex::new(pattern_str) {
-        Ok(re) => re.is_match(field_str),
-        Err(_) => false,
+/// Resolve a date operand; `"now"` is the evaluation time.
+fn operand_as_date(operand: &Operand, now: &DateTime<FixedOffset>) -> Option<DateTime<FixedOffset>> {
+    match operand {
+        Operand::Date(DateOperand::Now) => Some(*now),
+        Operand::Date(DateOperand::Parsed(date)) => resolve(date, *now.offset()),
+        _ => None,
     }
 }
 
-fn compare_in(field_value: &FieldValue, condition_value: &serde_json::Value) -> bool {
-    match condition_value {
-        serde_json::Value::Array(arr) => match field_value {
-            FieldValue::String(s) => arr.iter().any(|v| {
-                v.as_str().map_or(false, |vs| vs.to_lowercase() == s.to_lowercase())
-            }),
-            FieldValue::Number(n) => arr.iter().any(|v| {
-                v.as_f64().map_or(false, |vn| (*n - vn).abs() < f64::EPSILON)
-            }),
-            _ => false,
-        },
-        _ => false,
+fn compare_dates<F>(
+    field_value: &FieldValue,
+    operand: &Operand,
+    now: &DateTime<FixedOffset>,
+    order: DateOrder,
+    cmp: F,
+) -> Option<bool>
+where
+    F: Fn(DateTime<FixedOffset>, DateTime<FixedOffset>) -> bool,
+{
+    match (field_as_date(field_value, now, order), operand_as_date(operand, now)) {
+        (Some(a), Some(b)) => Some(cmp(a, b)),
+        _ => None,
+    }
+}
+
+fn compare_within_days(
+    field_value: &FieldValue,
+    operand: &Operand,
+    now: &DateTime<FixedOffset>,
+    order: DateOrder,
+) -> Option<bool> {
+    match (field_as_date(field_value, now, order), operand) {
+        (Some(date), Operand::Number(days)) => Some(within_days(&date, now, *days)),
+        _ => None,
+    }
+}
+
+fn compare_day_of_week(
+    field_value: &FieldValue,
+    operand: &Operand,
+    now: &DateTime<FixedOffset>,
+    order: DateOrder,
+) -> Option<bool> {
+    match (field_as_date(field_value, now, order), operand) {
+        (Some(date), Operand::Weekdays(days)) => Some(day_of_week_in(&date, days)),
+        _ => None,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::Address;
+    use crate::compiled::CompileContext;
+    use crate::models::{Address, Aggregate, Condition, ConditionGroup, Criterion, Rule};
+    use crate::sets::encode_set;
+
+    // The tests are written against the config models; compile on the way in.
+    fn evaluate_rule(rule: &Rule, cart: &CartInput) -> bool {
+        super::evaluate_rule(&CompiledRule::compile(rule, &rule.conditions, &CompileContext::default()), cart)
+    }
+
+    fn evaluate_group<S: FieldSource>(group: &ConditionGroup, source: &S) -> Truth {
+        super::evaluate_group(&CompiledGroup::compile(group, &CompileContext::default()), source)
+    }
 
     fn make_simple_rule(field: &str, op: ComparisonOperator, value: serde_json::Value) -> Rule {
         Rule {
@@ -291,7 +749,7 @@ mod tests {
                     field: field.to_string(),
                     operator: op,
                     value,
-                    is_preset: false,
+                    ..Default::default()
                 })],
             },
         }
@@ -332,10 +790,10 @@ mod tests {
     #[test]
     fn test_string_contains() {
         let cart = CartInput {
-            shipping_address: Address {
+            shipping_address: Some(Address {
                 address1: "PO Box 123".to_string(),
                 ..Default::default()
-            },
+            }),
             ..Default::default()
         };
 
@@ -368,10 +826,10 @@ mod tests {
     fn test_and_logic() {
         let cart = CartInput {
             total: 150.0,
-            shipping_address: Address {
+            shipping_address: Some(Address {
                 country_code: "US".to_string(),
                 ..Default::default()
-            },
+            }),
             ..Default::default()
         };
 
@@ -388,13 +846,13 @@ mod tests {
                         field: "cart.total".to_string(),
                         operator: ComparisonOperator::GreaterThan,
                         value: serde_json::json!(100.0),
-                        is_preset: false,
+                        ..Default::default()
                     }),
                     Criterion::Condition(Condition {
                         field: "shipping_address.country_code".to_string(),
                         operator: ComparisonOperator::Equals,
                         value: serde_json::json!("US"),
-                        is_preset: false,
+                        ..Default::default()
                     }),
                 ],
             },
@@ -407,10 +865,10 @@ mod tests {
     fn test_and_logic_partial_match() {
         let cart = CartInput {
             total: 50.0, // Doesn't match > 100
-            shipping_address: Address {
+            shipping_address: Some(Address {
                 country_code: "US".to_string(),
                 ..Default::default()
-            },
+            }),
             ..Default::default()
         };
 
@@ -427,13 +885,13 @@ mod tests {
                         field: "cart.total".to_string(),
                         operator: ComparisonOperator::GreaterThan,
                         value: serde_json::json!(100.0),
-                        is_preset: false,
+                        ..Default::default()
                     }),
                     Criterion::Condition(Condition {
                         field: "shipping_address.country_code".to_string(),
                         operator: ComparisonOperator::Equals,
                         value: serde_json::json!("US"),
-                        is_preset: false,
+                        ..Default::default()
                     }),
                 ],
             },
@@ -446,10 +904,10 @@ mod tests {
     fn test_or_logic() {
         let cart = CartInput {
             total: 50.0,
-            shipping_address: Address {
+            shipping_address: Some(Address {
                 country_code: "US".to_string(),
                 ..Default::default()
-            },
+            }),
             ..Default::default()
         };
 
@@ -466,13 +924,13 @@ mod tests {
                         field: "cart.total".to_string(),
                         operator: ComparisonOperator::GreaterThan,
                         value: serde_json::json!(100.0),
-                        is_preset: false,
+                        ..Default::default()
                     }),
                     Criterion::Condition(Condition {
                         field: "shipping_address.country_code".to_string(),
                         operator: ComparisonOperator::Equals,
                         value: serde_json::json!("US"),
-                        is_preset: false,
+                        ..Default::default()
                     }),
                 ],
             },
@@ -484,10 +942,10 @@ mod tests {
     #[test]
     fn test_preset_regex() {
         let cart = CartInput {
-            shipping_address: Address {
+            shipping_address: Some(Address {
                 address1: "PO Box 456".to_string(),
                 ..Default::default()
-            },
+            }),
             ..Default::default()
         };
 
@@ -504,6 +962,7 @@ mod tests {
                     operator: ComparisonOperator::RegexMatch,
                     value: serde_json::json!("po_box"),
                     is_preset: true,
+                    ..Default::default()
                 })],
             },
         };
@@ -533,10 +992,13 @@ mod tests {
                         field: "cart.total".to_string(),
                         operator: ComparisonOperator::GreaterThan,
                         value: serde_json::json!(100.0),
-                        is_preset: false,
+                        ..Default::default()
                     })],
                 },
             }],
+            regex_patterns: Default::default(),
+            timezone_offset_minutes: None,
+            date_order: Default::default(),
         };
 
         let result = evaluate_rules(&config, &cart);
@@ -567,7 +1029,7 @@ mod tests {
                             field: "cart.total".to_string(),
                             operator: ComparisonOperator::GreaterThan,
                             value: serde_json::json!(100.0),
-                            is_preset: false,
+                            ..Default::default()
                         })],
                     },
                 },
@@ -583,15 +1045,746 @@ mod tests {
                             field: "cart.quantity".to_string(),
                             operator: ComparisonOperator::GreaterThan,
                             value: serde_json::json!(5.0),
-                            is_preset: false,
+                            ..Default::default()
                         })],
                     },
                 },
             ],
+            regex_patterns: Default::default(),
+            timezone_offset_minutes: None,
+            date_order: Default::default(),
         };
 
         let result = evaluate_rules(&config, &cart);
         assert_eq!(result.errors.len(), 2);
+
+        // A compiled plan can be reused across carts
+        let compiled = CompiledRules::compile(&config);
+        assert_eq!(evaluate_compiled(&compiled, &cart).errors.len(), 2);
+        let small_cart = CartInput {
+            total: 20.0,
+            quantity: 10,
+            ..Default::default()
+        };
+        let result = evaluate_compiled(&compiled, &small_cart);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].rule_id, "rule2");
+    }
+
+    #[test]
+    fn test_named_patterns_from_config() {
+        let config: RulesConfig = serde_json::from_value(serde_json::json!({
+            "version": "1.1",
+            "rules": [
+                {
+                    "id": "named",
+                    "name": "Named",
+                    "error_message": "Blocked warehouse",
+                    "conditions": {"operator": "AND", "criteria": [{
+                        "field": "shipping_address.address1",
+                        "operator": "REGEX_MATCH",
+                        "value": "warehouse",
+                        "is_preset": true
+                    }]}
+                },
+                {
+                    "id": "override",
+                    "name": "Override",
+                    "error_message": "Only SW postcodes",
+                    "conditions": {"operator": "AND", "criteria": [{
+                        "field": "shipping_address.zip",
+                        "operator": "REGEX_MATCH",
+                        "value": "uk_postcode",
+                        "is_preset": true
+                    }]}
+                },
+                {
+                    "id": "broken",
+                    "name": "Broken",
+                    "error_message": "Never shown",
+                    "conditions": {"operator": "NOT", "criteria": [{
+                        "field": "shipping_address.address1",
+                        "operator": "REGEX_MATCH",
+                        "value": "broken",
+                        "is_preset": true
+                    }]}
+                }
+            ],
+            "regex_patterns": {
+                "warehouse": "(?i)\\bunit\\s+\\d+",
+                "uk_postcode": "^SW",
+                "broken": "(unclosed"
+            }
+        }))
+        .unwrap();
+
+        let cart = CartInput {
+            shipping_address: Some(Address {
+                address1: "Unit 4, Riverside Estate".to_string(),
+                zip: "E1 6AN".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let result = evaluate_rules(&config, &cart);
+        let fired: Vec<&str> = result.errors.iter().map(|e| e.rule_id.as_str()).collect();
+        // "uk_postcode" is the config's pattern, not the built-in one, and an
+        // invalid named pattern leaves its condition unknown
+        assert_eq!(fired, vec!["named"]);
     }
-}
 
+    fn make_line(vendor: &str, sku: &str, quantity: u32, price: f64) -> LineItem {
+        LineItem {
+            vendor: vendor.to_string(),
+            sku: sku.to_string(),
+            quantity,
+            price,
+            ..Default::default()
+        }
+    }
+
+    fn make_aggregate_rule(
+        field: &str,
+        function: AggregateFunction,
+        filter: Option<ConditionGroup>,
+        op: ComparisonOperator,
+        value: serde_json::Value,
+    ) -> Rule {
+        let mut rule = make_simple_rule(field, op, value);
+        if let Criterion::Condition(c) = &mut rule.conditions.criteria[0] {
+            c.aggregate = Some(Aggregate { function, filter });
+        }
+        rule
+    }
+
+    #[test]
+    fn test_aggregate_sum_with_filter() {
+        let cart = CartInput {
+            line_items: vec![
+                make_line("Acme", "A-1", 2, 50.0),
+                make_line("Acme", "A-2", 1, 60.0),
+                make_line("Other", "O-1", 10, 100.0),
+            ],
+            ..Default::default()
+        };
+
+        let acme_only = ConditionGroup {
+            operator: LogicalOperator::And,
+            criteria: vec![Criterion::Condition(Condition {
+                field: "line_item.vendor".to_string(),
+                operator: ComparisonOperator::Equals,
+                value: serde_json::json!("Acme"),
+                ..Default::default()
+            })],
+        };
+
+        // Acme subtotal is $160, below the $200 wholesale minimum
+        let rule = make_aggregate_rule(
+            "line_item.line_total",
+            AggregateFunction::Sum,
+            Some(acme_only),
+            ComparisonOperator::LessThan,
+            serde_json::json!(200.0),
+        );
+
+        assert!(evaluate_rule(&rule, &cart));
+    }
+
+    #[test]
+    fn test_aggregate_count_distinct_and_max() {
+        let cart = CartInput {
+            line_items: vec![
+                make_line("Acme", "A-1", 2, 5.0),
+                make_line("Acme", "A-1", 7, 5.0),
+                make_line("Acme", "A-2", 1, 5.0),
+            ],
+            ..Default::default()
+        };
+
+        let distinct_skus = make_aggregate_rule(
+            "line_item.sku",
+            AggregateFunction::CountDistinct,
+            None,
+            ComparisonOperator::Equals,
+            serde_json::json!(2),
+        );
+        assert!(evaluate_rule(&distinct_skus, &cart));
+
+        let max_quantity = make_aggregate_rule(
+            "line_item.quantity",
+            AggregateFunction::Max,
+            None,
+            ComparisonOperator::GreaterThanOrEqual,
+            serde_json::json!(7),
+        );
+        assert!(evaluate_rule(&max_quantity, &cart));
+    }
+
+    #[test]
+    fn test_aggregate_over_no_lines() {
+        let cart = CartInput::default();
+
+        let sum = make_aggregate_rule(
+            "line_item.quantity",
+            AggregateFunction::Sum,
+            None,
+            ComparisonOperator::Equals,
+            serde_json::json!(0),
+        );
+        assert!(evaluate_rule(&sum, &cart));
+
+        // MIN over no lines has no value, so the condition doesn't match
+        let min = make_aggregate_rule(
+            "line_item.price",
+            AggregateFunction::Min,
+            None,
+            ComparisonOperator::LessThan,
+            serde_json::json!(100),
+        );
+        assert!(!evaluate_rule(&min, &cart));
+    }
+
+    #[test]
+    fn test_date_operators_on_cart_attribute() {
+        let now = parse_date("2024-06-14T09:00:00-04:00", FixedOffset::east_opt(0).unwrap(), DateOrder::MonthFirst);
+        let cart = CartInput {
+            attributes: [("delivery_date".to_string(), "06/15/2024".to_string())].into(),
+            now,
+            ..Default::default()
+        };
+
+        let field = "cart.attributes.delivery_date";
+        let before = make_simple_rule(field, ComparisonOperator::Before, serde_json::json!("2024-06-20"));
+        assert!(evaluate_rule(&before, &cart));
+
+        let after_now = make_simple_rule(field, ComparisonOperator::After, serde_json::json!("now"));
+        assert!(evaluate_rule(&after_now, &cart));
+
+        let within = make_simple_rule(field, ComparisonOperator::WithinDays, serde_json::json!(2));
+        assert!(evaluate_rule(&within, &cart));
+
+        let weekend = make_simple_rule(field, ComparisonOperator::DayOfWeekIn, serde_json::json!(["SAT", "SUN"]));
+        assert!(evaluate_rule(&weekend, &cart));
+    }
+
+    #[test]
+    fn test_customer_created_within_last_days() {
+        let utc = FixedOffset::east_opt(0).unwrap();
+        let cart = CartInput {
+            customer_created_at: parse_date("2024-06-01T00:00:00Z", utc, DateOrder::MonthFirst),
+            now: parse_date("2024-06-14T00:00:00Z", utc, DateOrder::MonthFirst),
+            ..Default::default()
+        };
+
+        let new_customer = make_simple_rule("customer.created_at", ComparisonOperator::WithinDays, serde_json::json!(-30));
+        assert!(evaluate_rule(&new_customer, &cart));
+
+        // Missing dates never match
+        let no_customer = CartInput::default();
+        assert!(!evaluate_rule(&new_customer, &no_customer));
+
+        // Unparseable attribute values never match
+        let garbage = CartInput {
+            attributes: [("delivery_date".to_string(), "asap".to_string())].into(),
+            ..Default::default()
+        };
+        let rule = make_simple_rule("cart.attributes.delivery_date", ComparisonOperator::Before, serde_json::json!("now"));
+        assert!(!evaluate_rule(&rule, &garbage));
+    }
+
+    #[test]
+    fn test_between_and_in_ranges() {
+        let cart = CartInput {
+            quantity: 10,
+            ..Default::default()
+        };
+
+        let inclusive = make_simple_rule("cart.quantity", ComparisonOperator::Between, serde_json::json!([5, 10]));
+        assert!(evaluate_rule(&inclusive, &cart));
+
+        let exclusive = make_simple_rule(
+            "cart.quantity",
+            ComparisonOperator::Between,
+            serde_json::json!({"min": 5, "max": 10, "max_inclusive": false}),
+        );
+        assert!(!evaluate_rule(&exclusive, &cart));
+
+        let ranges = make_simple_rule(
+            "cart.quantity",
+            ComparisonOperator::InRanges,
+            serde_json::json!([[1, 3], {"min": 9, "max": 12}]),
+        );
+        assert!(evaluate_rule(&ranges, &cart));
+
+        let no_match = make_simple_rule("cart.quantity", ComparisonOperator::InRanges, serde_json::json!([[1, 3], [20, 30]]));
+        assert!(!evaluate_rule(&no_match, &cart));
+
+        // Ranges never match string fields
+        let on_string = make_simple_rule("shipping_address.zip", ComparisonOperator::Between, serde_json::json!([0, 99999]));
+        assert!(!evaluate_rule(&on_string, &cart));
+    }
+
+    #[test]
+    fn test_equals_is_case_insensitive_by_default() {
+        let cart = CartInput {
+            shipping_address: Some(Address {
+                country_code: "us".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let rule = make_simple_rule("shipping_address.country_code", ComparisonOperator::Equals, serde_json::json!("US"));
+        assert!(evaluate_rule(&rule, &cart));
+
+        let mut strict = rule.clone();
+        if let Criterion::Condition(c) = &mut strict.conditions.criteria[0] {
+            c.normalize.case_sensitive = true;
+        }
+        assert!(!evaluate_rule(&strict, &cart));
+    }
+
+    #[test]
+    fn test_normalization_applies_to_every_string_operator() {
+        let cart = CartInput {
+            shipping_address: Some(Address {
+                city: "  Montréal ".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let norm = Normalization {
+            trim: true,
+            fold_diacritics: true,
+            ..Default::default()
+        };
+
+        for (op, value) in [
+            (ComparisonOperator::Equals, serde_json::json!("MONTREAL")),
+            (ComparisonOperator::Contains, serde_json::json!("treal")),
+            (ComparisonOperator::StartsWith, serde_json::json!("Mont")),
+            (ComparisonOperator::EndsWith, serde_json::json!("real")),
+            (ComparisonOperator::In, serde_json::json!(["Québec", "Montreal"])),
+            (ComparisonOperator::RegexMatch, serde_json::json!("^Montreal$")),
+        ] {
+            let mut rule = make_simple_rule("shipping_address.city", op, value);
+            assert!(!evaluate_rule(&rule, &cart), "{:?} matched without normalization", op);
+
+            if let Criterion::Condition(c) = &mut rule.conditions.criteria[0] {
+                c.normalize = norm.clone();
+            }
+            assert!(evaluate_rule(&rule, &cart), "{:?} did not match with normalization", op);
+        }
+    }
+
+    #[test]
+    fn test_exists_and_empty_operators() {
+        let no_address = CartInput::default();
+        let empty_line = CartInput {
+            shipping_address: Some(Address::default()),
+            ..Default::default()
+        };
+        let filled = CartInput {
+            shipping_address: Some(Address {
+                address1: "1 Main St".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let field = "shipping_address.address1";
+        let exists = make_simple_rule(field, ComparisonOperator::Exists, serde_json::Value::Null);
+        let not_exists = make_simple_rule(field, ComparisonOperator::NotExists, serde_json::Value::Null);
+        let is_empty = make_simple_rule(field, ComparisonOperator::IsEmpty, serde_json::Value::Null);
+        let is_not_empty = make_simple_rule(field, ComparisonOperator::IsNotEmpty, serde_json::Value::Null);
+
+        assert!(!evaluate_rule(&exists, &no_address));
+        assert!(evaluate_rule(&not_exists, &no_address));
+        assert!(evaluate_rule(&is_empty, &no_address));
+
+        assert!(evaluate_rule(&exists, &empty_line));
+        assert!(evaluate_rule(&is_empty, &empty_line));
+        assert!(!evaluate_rule(&is_not_empty, &empty_line));
+
+        assert!(evaluate_rule(&is_not_empty, &filled));
+        assert!(!evaluate_rule(&is_empty, &filled));
+    }
+
+    #[test]
+    fn test_encoded_set_operators() {
+        let cart = CartInput {
+            quantity: 12,
+            shipping_address: Some(Address {
+                zip: "90211".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let zips: Vec<String> = (0..5000).map(|i| format!("{}", 90000 + i)).collect();
+        let blocklist = serde_json::json!({ "set": encode_set(zips.iter().map(String::as_str)) });
+
+        let blocked = make_simple_rule("shipping_address.zip", ComparisonOperator::In, blocklist.clone());
+        let allowed = make_simple_rule("shipping_address.zip", ComparisonOperator::NotIn, blocklist);
+        assert!(evaluate_rule(&blocked, &cart));
+        assert!(!evaluate_rule(&allowed, &cart));
+
+        // Numeric entries match numeric fields
+        let sizes = serde_json::json!({ "set": encode_set(["6", "12", "24"]) });
+        assert!(evaluate_rule(&make_simple_rule("cart.quantity", ComparisonOperator::In, sizes), &cart));
+
+        // A malformed set is unknown, so neither IN nor NOT_IN triggers
+        let malformed = serde_json::json!({ "set": "0902,9" });
+        assert!(!evaluate_rule(&make_simple_rule("shipping_address.zip", ComparisonOperator::In, malformed.clone()), &cart));
+        assert!(!evaluate_rule(&make_simple_rule("shipping_address.zip", ComparisonOperator::NotIn, malformed), &cart));
+    }
+
+    #[test]
+    fn test_keyword_list_operators() {
+        let cart = CartInput {
+            customer_tags: vec!["Wholesale".to_string()],
+            shipping_address: Some(Address {
+                address1: "Unit 7, 12 HARBOUR ROAD".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let streets = serde_json::json!(["harbour road", "quay street", "dock lane"]);
+
+        let any_of = make_simple_rule("shipping_address.address1", ComparisonOperator::ContainsAnyOf, streets.clone());
+        let none_of = make_simple_rule("shipping_address.address1", ComparisonOperator::ContainsNoneOf, streets);
+        assert!(evaluate_rule(&any_of, &cart));
+        assert!(!evaluate_rule(&none_of, &cart));
+
+        // List fields: a member must equal a keyword, as with CONTAINS
+        let tags = make_simple_rule("customer.tags", ComparisonOperator::ContainsAnyOf, serde_json::json!(["vip", "wholesale"]));
+        let partial = make_simple_rule("customer.tags", ComparisonOperator::ContainsAnyOf, serde_json::json!(["whole"]));
+        assert!(evaluate_rule(&tags, &cart));
+        assert!(!evaluate_rule(&partial, &cart));
+
+        // Missing field or a value that isn't a list: unknown either way
+        let no_address = CartInput::default();
+        let none_of = make_simple_rule("shipping_address.address1", ComparisonOperator::ContainsNoneOf, serde_json::json!(["x"]));
+        assert!(!evaluate_rule(&none_of, &no_address));
+        let mistyped = make_simple_rule("shipping_address.address1", ComparisonOperator::ContainsNoneOf, serde_json::json!("x"));
+        assert!(!evaluate_rule(&mistyped, &cart));
+    }
+
+    #[test]
+    fn test_profanity_operators() {
+        let message = |text: &str| CartInput {
+            attributes: [("gift_message".to_string(), text.to_string())].into(),
+            ..Default::default()
+        };
+        let field = "cart.attributes.gift_message";
+        let any = make_simple_rule(field, ComparisonOperator::ContainsProfanity, serde_json::Value::Null);
+        let french = make_simple_rule(field, ComparisonOperator::ContainsProfanity, serde_json::json!(["fr"]));
+        let clean = make_simple_rule(field, ComparisonOperator::NotContainsProfanity, serde_json::json!("en"));
+
+        assert!(evaluate_rule(&any, &message("Joyeux anniversaire, p.u.t.a.i.n")));
+        assert!(evaluate_rule(&french, &message("Joyeux anniversaire, p.u.t.a.i.n")));
+        assert!(!evaluate_rule(&french, &message("sh1t")));
+        assert!(evaluate_rule(&clean, &message("Love from Scunthorpe")));
+        assert!(!evaluate_rule(&clean, &message("sh1t")));
+
+        // No message: unknown either way
+        assert!(!evaluate_rule(&any, &CartInput::default()));
+        assert!(!evaluate_rule(&clean, &CartInput::default()));
+
+        // An unknown language leaves the condition unknown
+        let klingon = make_simple_rule(field, ComparisonOperator::NotContainsProfanity, serde_json::json!(["en", "tlh"]));
+        assert!(!evaluate_rule(&klingon, &message("Qapla'")));
+    }
+
+    #[test]
+    fn test_postcode_operators_use_address_country() {
+        let address = |country_code: &str, zip: &str| CartInput {
+            shipping_address: Some(Address {
+                country_code: country_code.to_string(),
+                zip: zip.to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let invalid = make_simple_rule("shipping_address.zip", ComparisonOperator::IsInvalidPostcode, serde_json::Value::Null);
+        let valid = make_simple_rule("shipping_address.zip", ComparisonOperator::IsValidPostcode, serde_json::Value::Null);
+
+        assert!(evaluate_rule(&valid, &address("US", "90210")));
+        assert!(evaluate_rule(&invalid, &address("GB", "90210")));
+        assert!(evaluate_rule(&valid, &address("gb", "SW1A 1AA")));
+        assert!(evaluate_rule(&invalid, &address("US", "")));
+
+        // No format on file, no country, or no address: can't tell
+        assert!(!evaluate_rule(&invalid, &address("ZZ", "90210")));
+        assert!(!evaluate_rule(&valid, &address("ZZ", "90210")));
+        assert!(!evaluate_rule(&invalid, &address("", "90210")));
+        assert!(!evaluate_rule(&invalid, &CartInput::default()));
+
+        // A country in the value overrides the address
+        let us_format = make_simple_rule("shipping_address.zip", ComparisonOperator::IsValidPostcode, serde_json::json!("us"));
+        assert!(evaluate_rule(&us_format, &address("GB", "90210")));
+    }
+
+    #[test]
+    fn test_zip_ranges_and_prefixes() {
+        let address = |country_code: &str, zip: &str| CartInput {
+            shipping_address: Some(Address {
+                country_code: country_code.to_string(),
+                zip: zip.to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let hawaii = make_simple_rule(
+            "shipping_address.zip",
+            ComparisonOperator::InZipRanges,
+            serde_json::json!([["96701", "96898"], ["00501", "00544"]]),
+        );
+        assert!(evaluate_rule(&hawaii, &address("US", "96813-2701")));
+        // Too short to compare numerically: unknown, not a miss
+        assert!(!evaluate_rule(&hawaii, &address("US", "501")));
+        assert!(!evaluate_rule(&hawaii, &address("US", "10001")));
+
+        let northern_ireland = make_simple_rule(
+            "shipping_address.zip",
+            ComparisonOperator::MatchesPrefixAny,
+            serde_json::json!(["BT*"]),
+        );
+        assert!(evaluate_rule(&northern_ireland, &address("GB", "bt7 1nn")));
+        assert!(!evaluate_rule(&northern_ireland, &address("GB", "SW1A 1AA")));
+
+        let beverly_hills = make_simple_rule("shipping_address.zip", ComparisonOperator::MatchesPrefixAny, serde_json::json!(["902*"]));
+        assert!(evaluate_rule(&beverly_hills, &address("US", "90210-1234")));
+
+        // Malformed entries make the whole condition unknown
+        let bad_range = make_simple_rule("shipping_address.zip", ComparisonOperator::InZipRanges, serde_json::json!([["1"]]));
+        let bad_prefix = make_simple_rule("shipping_address.zip", ComparisonOperator::MatchesPrefixAny, serde_json::json!(["BT", "9*0"]));
+        let mut not_bad_prefix = bad_prefix.clone();
+        not_bad_prefix.conditions.operator = LogicalOperator::Not;
+        assert!(!evaluate_rule(&bad_range, &address("US", "1")));
+        assert!(!evaluate_rule(&bad_prefix, &address("GB", "BT7 1NN")));
+        assert!(!evaluate_rule(&not_bad_prefix, &address("GB", "BT7 1NN")));
+    }
+
+    #[test]
+    fn test_zip_matches_province() {
+        let address = |country_code: &str, province_code: &str, zip: &str| CartInput {
+            shipping_address: Some(Address {
+                country_code: country_code.to_string(),
+                province_code: province_code.to_string(),
+                zip: zip.to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mismatch = make_simple_rule("shipping_address.zip", ComparisonOperator::NotZipMatchesProvince, serde_json::Value::Null);
+        let matches = make_simple_rule("shipping_address.zip", ComparisonOperator::ZipMatchesProvince, serde_json::Value::Null);
+
+        assert!(evaluate_rule(&mismatch, &address("US", "CA", "10001")));
+        assert!(!evaluate_rule(&mismatch, &address("US", "NY", "10001")));
+        assert!(evaluate_rule(&matches, &address("CA", "ON", "K1A 0B1")));
+        assert!(evaluate_rule(&mismatch, &address("CA", "BC", "K1A 0B1")));
+
+        // Unsupported country, invalid zip, no province: neither operator fires
+        for cart in [address("GB", "LND", "SW1A 1AA"), address("US", "NY", "1000"), address("US", "", "10001")] {
+            assert!(!evaluate_rule(&mismatch, &cart));
+            assert!(!evaluate_rule(&matches, &cart));
+        }
+        assert!(!evaluate_rule(&mismatch, &CartInput::default()));
+
+        // Only address fields carry a province
+        let cart_field = make_simple_rule("cart.attributes.zip", ComparisonOperator::NotZipMatchesProvince, serde_json::Value::Null);
+        let mut cart = address("US", "CA", "10001");
+        cart.attributes.insert("zip".to_string(), "10001".to_string());
+        assert!(!evaluate_rule(&cart_field, &cart));
+    }
+
+    #[test]
+    fn test_address_looks_invalid() {
+        let address = |address1: &str, city: &str| CartInput {
+            shipping_address: Some(Address {
+                address1: address1.to_string(),
+                city: city.to_string(),
+                country_code: "US".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let any_signal = make_simple_rule("shipping_address.address1", ComparisonOperator::AddressLooksInvalid, serde_json::Value::Null);
+        assert!(evaluate_rule(&any_signal, &address("asdf", "Boston")));
+        assert!(evaluate_rule(&any_signal, &address("12 Main St", "test")));
+        assert!(!evaluate_rule(&any_signal, &address("12 Main St", "Boston")));
+        assert!(!evaluate_rule(&any_signal, &CartInput::default()));
+
+        let mash_only = make_simple_rule(
+            "shipping_address.address1",
+            ComparisonOperator::AddressLooksInvalid,
+            serde_json::json!(["keyboard_mash"]),
+        );
+        assert!(evaluate_rule(&mash_only, &address("12 Main St", "sdfgh")));
+        assert!(!evaluate_rule(&mash_only, &address("Main Street", "Boston")));
+
+        // Unknown signal names and non-address fields leave it unknown
+        let mut not_bad_name = make_simple_rule("shipping_address.address1", ComparisonOperator::AddressLooksInvalid, serde_json::json!("typo"));
+        not_bad_name.conditions.operator = LogicalOperator::Not;
+        assert!(!evaluate_rule(&not_bad_name, &address("12 Main St", "Boston")));
+        let cart_field = make_simple_rule("cart.total", ComparisonOperator::AddressLooksInvalid, serde_json::Value::Null);
+        assert!(!evaluate_rule(&cart_field, &address("asdf", "Boston")));
+    }
+
+    #[test]
+    fn test_military_address() {
+        let address = |address1: &str, city: &str, province_code: &str| CartInput {
+            shipping_address: Some(Address {
+                address1: address1.to_string(),
+                city: city.to_string(),
+                province_code: province_code.to_string(),
+                country_code: "US".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let military = make_simple_rule("shipping_address.address1", ComparisonOperator::IsMilitaryAddress, serde_json::Value::Null);
+        let civilian = make_simple_rule("shipping_address.address1", ComparisonOperator::IsNotMilitaryAddress, serde_json::Value::Null);
+
+        assert!(evaluate_rule(&military, &address("Unit 2050 Box 4190", "APO", "AP")));
+        assert!(evaluate_rule(&military, &address("PSC 802 Box 74", "Anytown", "NY")));
+        assert!(evaluate_rule(&military, &address("123 Main St", "Anytown", "AE")));
+        assert!(evaluate_rule(&civilian, &address("123 Main St", "Boston", "MA")));
+
+        // No shipping address: neither fires
+        assert!(!evaluate_rule(&military, &CartInput::default()));
+        assert!(!evaluate_rule(&civilian, &CartInput::default()));
+    }
+
+    #[test]
+    fn test_email_domains() {
+        let buyer = |email: &str| CartInput { customer_email: Some(email.to_string()), ..Default::default() };
+        let disposable = make_simple_rule("customer.email", ComparisonOperator::IsDisposableEmail, serde_json::Value::Null);
+        assert!(evaluate_rule(&disposable, &buyer("bot@Mailinator.com")));
+        assert!(!evaluate_rule(&disposable, &buyer("jane@example.com")));
+        assert!(!evaluate_rule(&disposable, &CartInput::default()));
+
+        let blocked = make_simple_rule(
+            "customer.email",
+            ComparisonOperator::EmailDomainIn,
+            serde_json::json!(["@Competitor.com", "example.org."]),
+        );
+        assert!(evaluate_rule(&blocked, &buyer("spy@competitor.com")));
+        assert!(evaluate_rule(&blocked, &buyer("spy@mail.example.org")));
+        assert!(!evaluate_rule(&blocked, &buyer("jane@notcompetitor.com")));
+
+        let outside_allow_list = make_simple_rule(
+            "customer.email",
+            ComparisonOperator::NotEmailDomainIn,
+            serde_json::json!({ "set": encode_set(["acme.com", "acme.co.uk"]) }),
+        );
+        assert!(!evaluate_rule(&outside_allow_list, &buyer("jane@acme.co.uk")));
+        assert!(evaluate_rule(&outside_allow_list, &buyer("jane@gmail.com")));
+        // Not an email address: unknown
+        assert!(!evaluate_rule(&outside_allow_list, &buyer("jane")));
+    }
+
+    #[test]
+    fn test_phone_fields() {
+        let address = |country_code: &str, phone: &str| CartInput {
+            shipping_address: Some(Address {
+                country_code: country_code.to_string(),
+                phone: phone.to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let valid = make_simple_rule("shipping_address.phone", ComparisonOperator::IsValidPhone, serde_json::Value::Null);
+        let invalid = make_simple_rule("shipping_address.phone", ComparisonOperator::IsInvalidPhone, serde_json::Value::Null);
+        assert!(evaluate_rule(&valid, &address("US", "(555) 123-4567")));
+        assert!(evaluate_rule(&invalid, &address("US", "123-4567")));
+        assert!(evaluate_rule(&valid, &address("US", "+44 20 7946 0000")));
+        assert!(evaluate_rule(&invalid, &address("US", "call me")));
+        // National number, no numbering plan on file: can't tell
+        assert!(!evaluate_rule(&invalid, &address("ZZ", "5551234")));
+        assert!(!evaluate_rule(&invalid, &CartInput::default()));
+
+        // Compared by number, however either side is written
+        let equals = make_simple_rule("shipping_address.phone", ComparisonOperator::Equals, serde_json::json!("5551234567"));
+        assert!(evaluate_rule(&equals, &address("US", "+1 (555) 123-4567")));
+        assert!(evaluate_rule(&equals, &address("CA", "1-555-123-4567")));
+        assert!(!evaluate_rule(&equals, &address("US", "555 123 0000")));
+        // A number without `+` is read in the buyer's country
+        let international = make_simple_rule("shipping_address.phone", ComparisonOperator::Equals, serde_json::json!("+15551234567"));
+        assert!(!evaluate_rule(&international, &address("GB", "555 123 4567")));
+        let blocked = make_simple_rule(
+            "shipping_address.phone",
+            ComparisonOperator::In,
+            serde_json::json!(["+1 555 123 4567", "020 7946 0000"]),
+        );
+        assert!(evaluate_rule(&blocked, &address("US", "555.123.4567")));
+        assert!(evaluate_rule(&blocked, &address("GB", "+442079460000")));
+        assert!(!evaluate_rule(&blocked, &address("US", "555-123-0000")));
+
+        // Fields resolve to E.164; customer.phone uses the address country
+        let uk = make_simple_rule("customer.phone", ComparisonOperator::StartsWith, serde_json::json!("+44"));
+        let cart = CartInput { customer_phone: Some("07911 123456".to_string()), ..address("GB", "") };
+        assert!(evaluate_rule(&uk, &cart));
+    }
+
+    #[test]
+    fn test_negated_operators_on_missing_or_mistyped_data_do_not_fire() {
+        let cart = CartInput {
+            total: 150.0,
+            ..Default::default()
+        };
+
+        // Missing field
+        let rule = make_simple_rule("shipping_address.address1", ComparisonOperator::NotContains, serde_json::json!("box"));
+        assert!(!evaluate_rule(&rule, &cart));
+
+        // Type mismatch: a number can't "not contain" a string
+        let rule = make_simple_rule("cart.total", ComparisonOperator::NotContains, serde_json::json!("box"));
+        assert!(!evaluate_rule(&rule, &cart));
+
+        // NOT_IN with a non-array value
+        let rule = make_simple_rule("cart.total", ComparisonOperator::NotIn, serde_json::json!(5));
+        assert!(!evaluate_rule(&rule, &cart));
+    }
+
+    #[test]
+    fn test_three_valued_group_logic() {
+        let cart = CartInput {
+            total: 150.0,
+            ..Default::default()
+        };
+
+        let known_true = Criterion::Condition(Condition {
+            field: "cart.total".to_string(),
+            operator: ComparisonOperator::GreaterThan,
+            value: serde_json::json!(100.0),
+            ..Default::default()
+        });
+        let known_false = Criterion::Condition(Condition {
+            field: "cart.total".to_string(),
+            operator: ComparisonOperator::LessThan,
+            value: serde_json::json!(100.0),
+            ..Default::default()
+        });
+        let unknown = Criterion::Condition(Condition {
+            field: "shipping_address.zip".to_string(),
+            operator: ComparisonOperator::Equals,
+            value: serde_json::json!("90210"),
+            ..Default::default()
+        });
+
+        let group = |operator, criteria: Vec<Criterion>| ConditionGroup { operator, criteria };
+
+        assert_eq!(evaluate_group(&group(LogicalOperator::And, vec![known_true.clone(), unknown.clone()]), &cart), Truth::Unknown);
+        assert_eq!(evaluate_group(&group(LogicalOperator::And, vec![known_false.clone(), unknown.clone()]), &cart), Truth::False);
+        assert_eq!(evaluate_group(&group(LogicalOperator::Or, vec![known_true.clone(), unknown.clone()]), &cart), Truth::True);
+        assert_eq!(evaluate_group(&group(LogicalOperator::Or, vec![known_false.clone(), unknown.clone()]), &cart), Truth::Unknown);
+        assert_eq!(evaluate_group(&group(LogicalOperator::Not, vec![unknown.clone()]), &cart), Truth::Unknown);
+        assert_eq!(evaluate_group(&group(LogicalOperator::Not, vec![known_false.clone()]), &cart), Truth::True);
+        assert_eq!(evaluate_group(&group(LogicalOperator::Not, vec![known_true, known_false]), &cart), Truth::True);
+    }
+}
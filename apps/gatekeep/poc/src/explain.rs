@@ -0,0 +1,305 @@
+//! Explain mode: evaluation with a trace, for the Test Simulator.
+//!
+//! [`evaluate_rules_explained`] evaluates every rule and returns, next to
+//! the usual errors, a tree per rule mirroring its `ConditionGroup` as
+//! written: each condition records the value its field resolved to, the
+//! operator, the literal and the result. Criteria are evaluated in the
+//! author's order (not the optimized one), so a criterion marked
+//! `skipped` is one the group was already decided without.
+
+use crate::compiled::{CompileContext, CompiledCondition, CompiledCriterion, CompiledGroup, CompiledRule};
+use crate::evaluator::{evaluate_aggregate, evaluate_condition, Truth, ValidationError};
+use crate::models::{
+    AggregateFunction, CartInput, ComparisonOperator, Condition, ConditionGroup, Criterion, FieldSource,
+    FieldValue, LogicalOperator, RulesConfig,
+};
+use serde::Serialize;
+
+/// Result of evaluating rules in explain mode.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExplainedResult {
+    pub errors: Vec<ValidationError>,
+    pub rules: Vec<RuleTrace>,
+}
+
+/// How one rule was evaluated.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleTrace {
+    pub rule_id: String,
+    pub enabled: bool,
+    /// True if the rule's conditions were true, so checkout is blocked.
+    pub fired: bool,
+    /// Disabled rules aren't evaluated; every node is skipped.
+    pub conditions: GroupTrace,
+}
+
+/// A criterion's trace.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TraceNode {
+    Condition(ConditionTrace),
+    Group(GroupTrace),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupTrace {
+    pub operator: LogicalOperator,
+    /// `None` when skipped.
+    pub result: Option<Truth>,
+    pub skipped: bool,
+    pub criteria: Vec<TraceNode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConditionTrace {
+    pub field: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aggregate: Option<AggregateFunction>,
+    /// What the field (or aggregate) resolved to; `None` when it's missing
+    /// or the condition was skipped. Phone numbers are shown normalized.
+    pub field_value: Option<serde_json::Value>,
+    pub operator: ComparisonOperator,
+    /// The condition's value as written in the config.
+    pub value: serde_json::Value,
+    /// `None` when skipped.
+    pub result: Option<Truth>,
+    pub skipped: bool,
+}
+
+/// Evaluate every rule against the cart, recording how each was decided.
+/// Unlike [`crate::evaluate_rules`], no guardrails apply: the simulator
+/// shows every enabled rule.
+pub fn evaluate_rules_explained(config: &RulesConfig, cart: &CartInput) -> ExplainedResult {
+    let context = CompileContext::new(config);
+    let mut errors = Vec::new();
+    let mut rules = Vec::new();
+
+    for rule in &config.rules {
+        let compiled = CompiledRule::compile(rule, &rule.conditions, &context);
+        let conditions = trace_group(&rule.conditions, &compiled.conditions, cart, !rule.enabled);
+        let fired = conditions.result.is_some_and(Truth::is_true);
+        if fired {
+            errors.push(ValidationError {
+                rule_id: rule.id.clone(),
+                message: rule.error_message.clone(),
+            });
+        }
+        rules.push(RuleTrace {
+            rule_id: rule.id.clone(),
+            enabled: rule.enabled,
+            fired,
+            conditions,
+        });
+    }
+
+    ExplainedResult { errors, rules }
+}
+
+/// Trace a group the way `evaluate_group` evaluates it. `group` and
+/// `compiled` have the same shape.
+fn trace_group(group: &ConditionGroup, compiled: &CompiledGroup, cart: &CartInput, skipped: bool) -> GroupTrace {
+    // A false criterion decides an AND (and so a NOT); a true one an OR
+    let (deciding, mut result) = match group.operator {
+        LogicalOperator::Or => (Truth::True, Truth::False),
+        _ => (Truth::False, Truth::True),
+    };
+    let mut decided = false;
+
+    let criteria = group
+        .criteria
+        .iter()
+        .zip(&compiled.criteria)
+        .map(|(criterion, compiled)| {
+            let node = match (criterion, compiled) {
+                (Criterion::Condition(c), CompiledCriterion::Condition(cc)) => {
+                    TraceNode::Condition(trace_condition(c, cc, cart, skipped || decided))
+                }
+                (Criterion::Group(g), CompiledCriterion::Group(cg)) => {
+                    TraceNode::Group(trace_group(g, cg, cart, skipped || decided))
+                }
+                _ => unreachable!("compiled group has the same shape"),
+            };
+            let value = match &node {
+                TraceNode::Condition(trace) => trace.result,
+                TraceNode::Group(trace) => trace.result,
+            };
+            match value {
+                Some(value) if value == deciding => {
+                    result = deciding;
+                    decided = true;
+                }
+                Some(Truth::Unknown) => result = Truth::Unknown,
+                _ => {}
+            }
+            node
+        })
+        .collect();
+
+    if group.operator == LogicalOperator::Not {
+        result = !result;
+    }
+    GroupTrace {
+        operator: group.operator,
+        result: (!skipped).then_some(result),
+        skipped,
+        criteria,
+    }
+}
+
+fn trace_condition(
+    condition: &Condition,
+    compiled: &CompiledCondition,
+    cart: &CartInput,
+    skipped: bool,
+) -> ConditionTrace {
+    let (field_value, result) = if skipped {
+        (None, None)
+    } else {
+        let field_value = match &compiled.aggregate {
+            Some(aggregate) => evaluate_aggregate(aggregate, &compiled.field, cart.line_items()),
+            None => cart.resolve(&compiled.field),
+        };
+        (field_value.map(|v| field_json(&v)), Some(evaluate_condition(compiled, cart)))
+    };
+    ConditionTrace {
+        field: condition.field.clone(),
+        aggregate: condition.aggregate.as_ref().map(|a| a.function),
+        field_value,
+        operator: condition.operator,
+        value: condition.value.clone(),
+        result,
+        skipped,
+    }
+}
+
+fn field_json(value: &FieldValue) -> serde_json::Value {
+    match value {
+        FieldValue::String(s) => s.as_str().into(),
+        FieldValue::Number(n) => (*n).into(),
+        FieldValue::Bool(b) => (*b).into(),
+        FieldValue::StringArray(items) => items.as_slice().into(),
+        FieldValue::DateTime(dt) => dt.to_rfc3339().into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::evaluate_rules;
+    use crate::models::Address;
+    use serde_json::json;
+
+    fn config() -> RulesConfig {
+        serde_json::from_value(json!({
+            "version": "1.0",
+            "rules": [
+                {
+                    "id": "po_box",
+                    "name": "PO boxes",
+                    "error_message": "No PO boxes",
+                    "conditions": {"operator": "AND", "criteria": [
+                        {"field": "cart.total", "operator": "GREATER_THAN", "value": 100},
+                        {"operator": "OR", "criteria": [
+                            {"field": "shipping_address.address1", "operator": "CONTAINS", "value": "po box"},
+                            {"field": "shipping_address.address2", "operator": "CONTAINS", "value": "po box"}
+                        ]},
+                        {"field": "cart.attributes.gift", "operator": "EQUALS", "value": "yes"}
+                    ]}
+                },
+                {
+                    "id": "heavy",
+                    "name": "Heavy",
+                    "error_message": "Too heavy",
+                    "conditions": {"operator": "AND", "criteria": [
+                        {"field": "cart.total_weight", "operator": "GREATER_THAN", "value": 5000},
+                        {"field": "line_item.line_total", "operator": "GREATER_THAN", "value": 10,
+                         "aggregate": {"function": "SUM"}}
+                    ]}
+                },
+                {
+                    "id": "off",
+                    "name": "Off",
+                    "enabled": false,
+                    "error_message": "Off",
+                    "conditions": {"operator": "AND", "criteria": [
+                        {"field": "cart.total", "operator": "GREATER_THAN", "value": 0}
+                    ]}
+                }
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn cart() -> CartInput {
+        CartInput {
+            total: 150.0,
+            total_weight: 100.0,
+            shipping_address: Some(Address {
+                address1: "PO Box 12".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let explained = evaluate_rules_explained(&config(), &cart());
+        assert!(explained.errors.is_empty());
+
+        // The gift attribute is missing, so the rule is unknown and doesn't fire
+        let po_box = &explained.rules[0];
+        assert!(!po_box.fired);
+        assert_eq!(po_box.conditions.result, Some(Truth::Unknown));
+        let TraceNode::Condition(total) = &po_box.conditions.criteria[0] else { panic!() };
+        assert_eq!((total.field_value.clone(), total.result), (Some(json!(150.0)), Some(Truth::True)));
+        let TraceNode::Group(or) = &po_box.conditions.criteria[1] else { panic!() };
+        assert_eq!(or.result, Some(Truth::True));
+        let TraceNode::Condition(address2) = &or.criteria[1] else { panic!() };
+        assert!(address2.skipped && address2.result.is_none());
+        let TraceNode::Condition(gift) = &po_box.conditions.criteria[2] else { panic!() };
+        assert_eq!((gift.field_value.clone(), gift.result), (None, Some(Truth::Unknown)));
+
+        // Decided by the weight; the aggregate is skipped
+        let heavy = &explained.rules[1];
+        assert_eq!(heavy.conditions.result, Some(Truth::False));
+        let TraceNode::Condition(sum) = &heavy.conditions.criteria[1] else { panic!() };
+        assert!(sum.skipped);
+        assert_eq!(sum.aggregate, Some(AggregateFunction::Sum));
+
+        let off = &explained.rules[2];
+        assert!(!off.enabled && !off.fired && off.conditions.skipped);
+    }
+
+    #[test]
+    fn test_matches_evaluate_rules() {
+        let config = config();
+        let mut cart = cart();
+        cart.attributes.insert("gift".to_string(), "yes".to_string());
+        let explained = evaluate_rules_explained(&config, &cart);
+        let expected: Vec<_> = evaluate_rules(&config, &cart).errors.into_iter().map(|e| e.rule_id).collect();
+        let errors: Vec<_> = explained.errors.iter().map(|e| e.rule_id.clone()).collect();
+        assert_eq!(errors, expected);
+        assert_eq!(errors, vec!["po_box"]);
+    }
+
+    #[test]
+    fn test_json() {
+        let explained = evaluate_rules_explained(&config(), &cart());
+        let json = serde_json::to_value(&explained.rules[0]).unwrap();
+        assert_eq!(json["rule_id"], "po_box");
+        assert_eq!(json["conditions"]["operator"], "AND");
+        assert_eq!(json["conditions"]["result"], "UNKNOWN");
+        let total = &json["conditions"]["criteria"][0];
+        assert_eq!(total["type"], "condition");
+        assert_eq!(total["operator"], "GREATER_THAN");
+        assert_eq!(total["value"], 100);
+        assert_eq!(total["field_value"], 150.0);
+        assert_eq!(total["result"], "TRUE");
+        assert!(total.get("aggregate").is_none());
+        let or = &json["conditions"]["criteria"][1];
+        assert_eq!(or["type"], "group");
+        assert_eq!(or["criteria"][1]["skipped"], true);
+        assert_eq!(or["criteria"][1]["result"], serde_json::Value::Null);
+    }
+}
@@ -3,13 +3,36 @@
 //! This crate implements a high-performance rule evaluation engine
 //! designed to run within Shopify Functions' strict execution limits.
 
+pub mod compiled;
+pub mod complexity;
+pub mod contradictions;
+pub mod dates;
 pub mod evaluator;
+pub mod explain;
+pub mod lint;
 pub mod models;
+pub mod normalize;
+pub mod optimizer;
 pub mod patterns;
+pub mod regex_safety;
 
-pub use evaluator::evaluate_rules;
-pub use models::{CartInput, ComparisonOperator, Condition, ConditionGroup, LogicalOperator, Rule, RulesConfig};
+// Matchers shared with the checkout function
+pub use logicflow_core::{
+    address_quality, attributes, emails, keywords, military, phones, postcodes, profanity, regions, sets,
+};
+
+pub use compiled::CompiledRules;
+pub use complexity::{complexity_report, ComplexityReport, Plan};
+pub use evaluator::{evaluate_compiled, evaluate_rules};
+pub use explain::{evaluate_rules_explained, ExplainedResult};
+pub use lint::lint;
+pub use models::{
+    Aggregate, AggregateFunction, CartInput, ComparisonOperator, Condition, ConditionGroup,
+    FieldPath, FieldSource, LineItem, LogicalOperator, NumericRange, Rule, RulesConfig,
+};
+pub use normalize::Normalization;
 pub use patterns::get_preset_pattern;
+pub use regex_safety::analyze_pattern;
 
 #[cfg(test)]
 mod tests {
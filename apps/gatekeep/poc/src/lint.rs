@@ -0,0 +1,809 @@
+//! Config linter: finds rules that can't do what the merchant meant.
+//!
+//! The evaluator is forgiving by design: an unknown field is missing, a
+//! value that doesn't fit its operator makes the condition unknown, and an
+//! empty `AND` is true. That keeps checkout running, but it also means a
+//! typo produces a rule that silently never fires (or always does). The
+//! admin runs [`lint`] before saving a config and shows each
+//! [`Diagnostic`] next to the offending part of the rule, located by its
+//! JSON pointer. Contradictions, tautologies and redundant conditions
+//! come from [`crate::contradictions`].
+
+use crate::attributes::is_checkout_attribute;
+use crate::compiled::{CompileContext, CompiledCondition, Operand};
+use crate::contradictions::{
+    always_true, criterion_always_true, criterion_never_true, never_true, redundant,
+};
+use crate::models::{
+    AddressField, AggregateFunction, ComparisonOperator, Condition, ConditionGroup, Criterion, FieldPath,
+    LineItemField, LogicalOperator, RulesConfig,
+};
+use crate::patterns::get_preset_pattern;
+use crate::regex_safety::analyze_pattern;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// A single finding about a config.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: LintCode,
+    pub message: String,
+    /// JSON pointer (RFC 6901) to the offending value in the config, e.g.
+    /// `/rules/0/conditions/criteria/1/field`.
+    pub pointer: String,
+    /// Pointers to other parts of the config involved, e.g. the conditions
+    /// that contradict each other.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub related: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Severity {
+    /// The rule can't work as written: it never fires, always fires, or
+    /// can't be told apart from another rule.
+    Error,
+    /// The rule works but is probably not what the merchant meant.
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum LintCode {
+    /// No source knows the field, so it's always missing.
+    UnknownField,
+    /// `delivery_address.*`, which is spelled `shipping_address.*`.
+    DeliveryAddress,
+    /// A `line_item.*` field outside an aggregate, where there are no lines.
+    LineItemOutsideAggregate,
+    /// A cart field inside an aggregate or its filter, which only see lines.
+    CartFieldInAggregate,
+    /// The operator can't compare values of the field's type, e.g.
+    /// `GREATER_THAN` on a text field.
+    TypeMismatch,
+    /// `IN`-style operator whose value is neither a list nor an encoded set.
+    InNotList,
+    /// The value doesn't fit the operator, so the condition is always
+    /// unknown.
+    InvalidValue,
+    /// `is_preset` names a pattern that isn't a named or built-in pattern,
+    /// so the condition is always unknown.
+    UnknownPreset,
+    /// A custom or named pattern the checkout function would reject, or a
+    /// named pattern that doesn't compile at all.
+    InvalidPattern,
+    /// A field or `normalize` option the checkout function can't evaluate,
+    /// so it turns the rule off.
+    UnsupportedAtCheckout,
+    /// A group with no criteria.
+    EmptyGroup,
+    /// Two rules share an id.
+    DuplicateRuleId,
+    /// A group or condition no cart can satisfy; `related` holds the
+    /// smallest set of criteria that contradict each other.
+    NeverTrue,
+    /// A group or condition every cart satisfies.
+    AlwaysTrue,
+    /// A condition implied by another in its group (or, in an `OR`, one
+    /// that implies another); `related` holds the other.
+    RedundantCondition,
+}
+
+/// Lint a config. Named patterns that don't compile come first, by name;
+/// then rule by rule, in config order.
+pub fn lint(config: &RulesConfig) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        config,
+        context: CompileContext::new(config),
+        diagnostics: Vec::new(),
+    };
+
+    let mut errors: Vec<_> = linter
+        .context
+        .patterns
+        .errors()
+        .map(|(name, e)| (name.to_string(), e.to_string()))
+        .collect();
+    errors.sort();
+    for (name, error) in errors {
+        // regex::Error puts the pattern and a caret above the reason
+        let reason = error.lines().last().unwrap_or_default().trim_start_matches("error: ");
+        linter.push(
+            Severity::Error,
+            LintCode::InvalidPattern,
+            format!("pattern \"{}\" doesn't compile: {}", name, reason),
+            format!("/regex_patterns/{}", name.replace('~', "~0").replace('/', "~1")),
+        );
+    }
+
+    let mut first_use: HashMap<&str, usize> = HashMap::new();
+    for (i, rule) in config.rules.iter().enumerate() {
+        let pointer = format!("/rules/{}", i);
+        if let Some(first) = first_use.get(rule.id.as_str()) {
+            linter.push(
+                Severity::Error,
+                LintCode::DuplicateRuleId,
+                format!("rule id \"{}\" is already used by /rules/{}", rule.id, first),
+                format!("{}/id", pointer),
+            );
+        } else {
+            first_use.insert(&rule.id, i);
+        }
+        linter.group(&rule.conditions, &format!("{}/conditions", pointer), Scope::Rule);
+        linter.logic(&rule.conditions, &format!("{}/conditions", pointer), true);
+    }
+
+    linter.diagnostics
+}
+
+/// What a group or condition is evaluated against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scope {
+    /// A rule's top-level group: the cart.
+    Rule,
+    /// A nested group: the cart.
+    Cart,
+    /// An aggregate filter: one line item.
+    Line,
+}
+
+/// The type of value a field resolves to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Number,
+    Text,
+    List,
+    Date,
+}
+
+struct Linter<'a> {
+    config: &'a RulesConfig,
+    context: CompileContext,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn push(&mut self, severity: Severity, code: LintCode, message: String, pointer: String) {
+        self.push_related(severity, code, message, pointer, Vec::new());
+    }
+
+    fn push_related(
+        &mut self,
+        severity: Severity,
+        code: LintCode,
+        message: String,
+        pointer: String,
+        related: Vec<String>,
+    ) {
+        self.diagnostics.push(Diagnostic { severity, code, message, pointer, related });
+    }
+
+    /// Report groups that are never or always true, and conditions that
+    /// have no effect. A group reported as a whole isn't looked into.
+    fn logic(&mut self, group: &ConditionGroup, pointer: &str, top: bool) {
+        let criterion_pointer = |i: usize| format!("{}/criteria/{}", pointer, i);
+
+        if let Some(conflict) = never_true(group) {
+            let (severity, message) = match top {
+                true => (Severity::Error, "rule can never fire: these criteria can't all be true"),
+                false => (Severity::Warning, "group is never true"),
+            };
+            let related = conflict.into_iter().map(criterion_pointer).collect();
+            self.push_related(severity, LintCode::NeverTrue, message.to_string(), pointer.to_string(), related);
+            return;
+        }
+        if always_true(group) {
+            let (severity, message) = match top {
+                true => (Severity::Error, "rule's conditions are always true, so it blocks every checkout"),
+                false => (Severity::Warning, "group is always true"),
+            };
+            self.push(severity, LintCode::AlwaysTrue, message.to_string(), pointer.to_string());
+            return;
+        }
+
+        for (i, because) in redundant(group) {
+            let message = match group.operator {
+                LogicalOperator::Or => "condition is covered by another in this OR group",
+                _ => "condition is implied by another in this group",
+            };
+            self.push_related(
+                Severity::Warning,
+                LintCode::RedundantCondition,
+                message.to_string(),
+                criterion_pointer(i),
+                vec![criterion_pointer(because)],
+            );
+        }
+
+        for (i, criterion) in group.criteria.iter().enumerate() {
+            match criterion {
+                Criterion::Group(child) => self.logic(child, &criterion_pointer(i), false),
+                Criterion::Condition(condition) => {
+                    // In an OR a dead condition does nothing; elsewhere a
+                    // tautology does nothing
+                    if group.operator == LogicalOperator::Or && criterion_never_true(criterion) {
+                        let message = "condition is never true".to_string();
+                        self.push(Severity::Warning, LintCode::NeverTrue, message, criterion_pointer(i));
+                    } else if group.operator != LogicalOperator::Or && criterion_always_true(criterion) {
+                        let message = "condition is always true".to_string();
+                        self.push(Severity::Warning, LintCode::AlwaysTrue, message, criterion_pointer(i));
+                    }
+                    if let Some(filter) = condition.aggregate.as_ref().and_then(|a| a.filter.as_ref()) {
+                        self.logic(filter, &format!("{}/aggregate/filter", criterion_pointer(i)), false);
+                    }
+                }
+            }
+        }
+    }
+
+    fn group(&mut self, group: &ConditionGroup, pointer: &str, scope: Scope) {
+        if group.criteria.is_empty() {
+            // An empty AND is true and an empty OR is false
+            let (severity, message) = match (scope, group.operator) {
+                (Scope::Rule, LogicalOperator::And) => {
+                    (Severity::Error, "rule has no conditions, so it blocks every checkout")
+                }
+                (Scope::Rule, _) => (Severity::Warning, "rule has no conditions, so it never fires"),
+                (_, LogicalOperator::And) => (Severity::Warning, "empty AND group is always true"),
+                (_, _) => (Severity::Warning, "empty group is always false"),
+            };
+            self.push(severity, LintCode::EmptyGroup, message.to_string(), format!("{}/criteria", pointer));
+        }
+
+        let scope = if scope == Scope::Rule { Scope::Cart } else { scope };
+        for (i, criterion) in group.criteria.iter().enumerate() {
+            let pointer = format!("{}/criteria/{}", pointer, i);
+            match criterion {
+                Criterion::Condition(condition) => self.condition(condition, &pointer, scope),
+                Criterion::Group(group) => self.group(group, &pointer, scope),
+            }
+        }
+    }
+
+    fn condition(&mut self, condition: &Condition, pointer: &str, scope: Scope) {
+        let field = FieldPath::parse(&condition.field);
+        let operator = condition.operator;
+        let aggregate = condition.aggregate.as_ref();
+        self.checkout_support(condition, &field, pointer);
+
+        // COUNT ignores its field
+        let reads_field = aggregate.is_none_or(|a| a.function != AggregateFunction::Count);
+        let field_ok = !reads_field || self.field(condition, &field, pointer, scope);
+
+        // The compared value: the aggregate's result, or the field itself
+        let kind = match aggregate {
+            Some(_) => Some(Kind::Number),
+            None if field_ok => field_kind(&field),
+            None => None,
+        };
+        if let Some(aggregate) = aggregate {
+            let numeric = matches!(
+                aggregate.function,
+                AggregateFunction::Sum | AggregateFunction::Min | AggregateFunction::Max
+            );
+            if numeric && field_ok && field_kind(&field) != Some(Kind::Number) {
+                self.push(
+                    Severity::Error,
+                    LintCode::TypeMismatch,
+                    format!("{} needs a numeric field; {} isn't one", config_name(aggregate.function), condition.field),
+                    format!("{}/aggregate/function", pointer),
+                );
+            }
+            if let Some(filter) = &aggregate.filter {
+                self.group(filter, &format!("{}/aggregate/filter", pointer), Scope::Line);
+            }
+        }
+
+        if let Some(kind) = kind {
+            if !accepts(operator, kind, &field) {
+                self.push(
+                    Severity::Error,
+                    LintCode::TypeMismatch,
+                    mismatch_message(operator, kind, &condition.field, aggregate.is_some()),
+                    format!("{}/operator", pointer),
+                );
+            }
+        }
+
+        self.value(condition, pointer);
+    }
+
+    /// Report what the checkout function turns the rule off for.
+    fn checkout_support(&mut self, condition: &Condition, field: &FieldPath, pointer: &str) {
+        // Not in the function input: collection membership, attributes
+        // other than the queried keys, and the customer's creation date
+        let unavailable = match field {
+            FieldPath::CartAttribute(key) => !is_checkout_attribute(key),
+            _ => matches!(field, FieldPath::LineItem(LineItemField::Collections) | FieldPath::CustomerCreatedAt),
+        };
+        if unavailable {
+            self.push(
+                Severity::Error,
+                LintCode::UnsupportedAtCheckout,
+                format!("{} isn't available at checkout, so the rule is turned off there", condition.field),
+                format!("{}/field", pointer),
+            );
+        }
+        // The function has no room for the Unicode tables these need
+        let normalize = &condition.normalize;
+        for (option, set) in [("nfkc", normalize.nfkc), ("fold_diacritics", normalize.fold_diacritics)] {
+            if set {
+                self.push(
+                    Severity::Error,
+                    LintCode::UnsupportedAtCheckout,
+                    format!("normalize.{} isn't supported at checkout, so the rule is turned off there", option),
+                    format!("{}/normalize/{}", pointer, option),
+                );
+            }
+        }
+    }
+
+    /// Check the field can be resolved where the condition is evaluated.
+    /// False if it can't, so type checks are skipped.
+    fn field(&mut self, condition: &Condition, field: &FieldPath, pointer: &str, scope: Scope) -> bool {
+        let pointer = format!("{}/field", pointer);
+        let in_aggregate = condition.aggregate.is_some() || scope == Scope::Line;
+
+        if *field == FieldPath::Unknown {
+            let shipping = condition
+                .field
+                .strip_prefix("delivery_address.")
+                .map(|rest| format!("shipping_address.{}", rest))
+                .filter(|path| FieldPath::parse(path) != FieldPath::Unknown);
+            match shipping {
+                Some(path) => self.push(
+                    Severity::Error,
+                    LintCode::DeliveryAddress,
+                    format!("unknown field {}; did you mean {}?", condition.field, path),
+                    pointer,
+                ),
+                None => self.push(
+                    Severity::Error,
+                    LintCode::UnknownField,
+                    format!("unknown field {}, so it's always missing", condition.field),
+                    pointer,
+                ),
+            }
+            return false;
+        }
+
+        match (matches!(field, FieldPath::LineItem(_)), in_aggregate) {
+            (true, false) => {
+                self.push(
+                    Severity::Error,
+                    LintCode::LineItemOutsideAggregate,
+                    format!("{} is only available inside an aggregate", condition.field),
+                    pointer,
+                );
+                false
+            }
+            (false, true) => {
+                self.push(
+                    Severity::Error,
+                    LintCode::CartFieldInAggregate,
+                    format!("aggregates read line_item.* fields; {} is always missing here", condition.field),
+                    pointer,
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Check the value converts to the operand the operator expects.
+    fn value(&mut self, condition: &Condition, pointer: &str) {
+        let pointer = format!("{}/value", pointer);
+        let operator = condition.operator;
+        let value = &condition.value;
+
+        if operator == ComparisonOperator::RegexMatch {
+            if let Some(pattern) = value.as_str() {
+                self.pattern(pattern, condition.is_preset, pointer);
+                return;
+            }
+        }
+
+        let ignores_value = matches!(
+            operator,
+            ComparisonOperator::Exists
+                | ComparisonOperator::NotExists
+                | ComparisonOperator::IsEmpty
+                | ComparisonOperator::IsNotEmpty
+                | ComparisonOperator::IsDisposableEmail
+        );
+        if ignores_value {
+            return;
+        }
+        let compiled = CompiledCondition::compile(condition, &self.context);
+        if !matches!(compiled.operand, Operand::None) {
+            return;
+        }
+
+        let set_operator = matches!(
+            operator,
+            ComparisonOperator::In
+                | ComparisonOperator::NotIn
+                | ComparisonOperator::EmailDomainIn
+                | ComparisonOperator::NotEmailDomainIn
+        );
+        if set_operator && !value.is_array() && !value.is_object() {
+            self.push(
+                Severity::Error,
+                LintCode::InNotList,
+                format!("{} needs a list of values, got {}", config_name(operator), value),
+                pointer,
+            );
+        } else {
+            self.push(
+                Severity::Error,
+                LintCode::InvalidValue,
+                format!("{} can't use {} here, so the condition is always unknown", config_name(operator), value),
+                pointer,
+            );
+        }
+    }
+
+    fn pattern(&mut self, pattern: &str, is_preset: bool, pointer: String) {
+        let named = self.config.regex_patterns.get(pattern);
+        if is_preset && named.is_none() && get_preset_pattern(pattern).is_none() {
+            self.push(
+                Severity::Error,
+                LintCode::UnknownPreset,
+                format!("no pattern named \"{}\", so the condition is always unknown", pattern),
+                pointer.clone(),
+            );
+        }
+
+        // Built-in presets are known to be fine
+        let source = match named {
+            Some(source) if is_preset => source.as_str(),
+            _ if is_preset && get_preset_pattern(pattern).is_some() => return,
+            _ => pattern,
+        };
+        if !analyze_pattern(source).is_ok() {
+            self.push(
+                Severity::Error,
+                LintCode::InvalidPattern,
+                format!("pattern {:?} would be rejected by checkout; see the pattern checker", source),
+                pointer,
+            );
+        }
+    }
+}
+
+fn field_kind(field: &FieldPath) -> Option<Kind> {
+    let kind = match field {
+        FieldPath::CartTotal | FieldPath::CartSubtotal | FieldPath::CartQuantity | FieldPath::CartTotalWeight => {
+            Kind::Number
+        }
+        FieldPath::CustomerTags => Kind::List,
+        FieldPath::CustomerCreatedAt | FieldPath::Now => Kind::Date,
+        FieldPath::CartAttribute(_)
+        | FieldPath::CustomerEmail
+        | FieldPath::CustomerPhone
+        | FieldPath::ShippingAddress(
+            AddressField::Address1
+            | AddressField::Address2
+            | AddressField::City
+            | AddressField::Province
+            | AddressField::ProvinceCode
+            | AddressField::Country
+            | AddressField::CountryCode
+            | AddressField::Zip
+            | AddressField::Phone,
+        ) => Kind::Text,
+        FieldPath::LineItem(LineItemField::Quantity | LineItemField::Price | LineItemField::LineTotal) => Kind::Number,
+        FieldPath::LineItem(LineItemField::Collections) => Kind::List,
+        FieldPath::LineItem(
+            LineItemField::ProductId | LineItemField::VariantId | LineItemField::Sku | LineItemField::Vendor,
+        ) => Kind::Text,
+        FieldPath::Unknown => return None,
+    };
+    Some(kind)
+}
+
+/// True if the operator can compare a value of `kind`. Text dates (cart
+/// attributes) are parsed, so date operators accept text.
+fn accepts(operator: ComparisonOperator, kind: Kind, field: &FieldPath) -> bool {
+    use ComparisonOperator::*;
+    match operator {
+        GreaterThan | GreaterThanOrEqual | LessThan | LessThanOrEqual | Between | InRanges => kind == Kind::Number,
+        Before | After | WithinDays | DayOfWeekIn => matches!(kind, Kind::Date | Kind::Text),
+        Equals | NotEquals | In | NotIn => matches!(kind, Kind::Number | Kind::Text),
+        Contains | NotContains | ContainsAnyOf | ContainsNoneOf | ContainsProfanity | NotContainsProfanity => {
+            matches!(kind, Kind::Text | Kind::List)
+        }
+        // These read the whole shipping address
+        ZipMatchesProvince | NotZipMatchesProvince | AddressLooksInvalid | IsMilitaryAddress
+        | IsNotMilitaryAddress => matches!(field, FieldPath::ShippingAddress(_)),
+        Exists | NotExists | IsEmpty | IsNotEmpty => true,
+        StartsWith | EndsWith | RegexMatch | IsValidPostcode | IsInvalidPostcode | IsValidPhone | IsInvalidPhone
+        | InZipRanges | MatchesPrefixAny | IsDisposableEmail | EmailDomainIn | NotEmailDomainIn => kind == Kind::Text,
+    }
+}
+
+fn mismatch_message(operator: ComparisonOperator, kind: Kind, field: &str, aggregated: bool) -> String {
+    let name = config_name(operator);
+    let subject = if aggregated { format!("the aggregate of {}", field) } else { field.to_string() };
+    let hint = match (operator, kind) {
+        (ComparisonOperator::Equals | ComparisonOperator::NotEquals | ComparisonOperator::In, Kind::List) => {
+            "; use CONTAINS or CONTAINS_ANY_OF on lists"
+        }
+        _ => "",
+    };
+    match operator {
+        ComparisonOperator::ZipMatchesProvince
+        | ComparisonOperator::NotZipMatchesProvince
+        | ComparisonOperator::AddressLooksInvalid
+        | ComparisonOperator::IsMilitaryAddress
+        | ComparisonOperator::IsNotMilitaryAddress => {
+            format!("{} reads the shipping address; use a shipping_address.* field", name)
+        }
+        _ => format!("{} can't compare {} ({}), so it's always unknown{}", name, subject, kind_name(kind), hint),
+    }
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Number => "a number",
+        Kind::Text => "text",
+        Kind::List => "a list",
+        Kind::Date => "a date",
+    }
+}
+
+/// An operator or function as written in configs, e.g. `GREATER_THAN`.
+fn config_name<T: Serialize>(name: T) -> String {
+    serde_json::to_value(name)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(value: serde_json::Value) -> RulesConfig {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn rule(id: &str, criteria: serde_json::Value) -> serde_json::Value {
+        json!({
+            "id": id,
+            "name": id,
+            "error_message": "Blocked",
+            "conditions": {"operator": "AND", "criteria": criteria}
+        })
+    }
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<(LintCode, &str)> {
+        diagnostics.iter().map(|d| (d.code, d.pointer.as_str())).collect()
+    }
+
+    #[test]
+    fn test_clean_config() {
+        let config = config(json!({
+            "version": "1.0",
+            "regex_patterns": {"sku": "^[A-Z]{3}-\\d+$"},
+            "rules": [
+                rule("a", json!([
+                    {"field": "cart.total", "operator": "GREATER_THAN", "value": 100},
+                    {"field": "customer.tags", "operator": "CONTAINS", "value": "VIP"},
+                    {"field": "shipping_address.country_code", "operator": "IN", "value": ["US", "CA"]},
+                    {"field": "cart.attributes.delivery_date", "operator": "BEFORE", "value": "now"},
+                    {"field": "shipping_address.zip", "operator": "REGEX_MATCH", "value": "us_zip", "is_preset": true},
+                    {"field": "shipping_address.zip", "operator": "EXISTS", "value": null},
+                    {"field": "line_item.quantity", "operator": "GREATER_THAN", "value": 5,
+                     "aggregate": {"function": "SUM", "filter": {"operator": "AND", "criteria": [
+                        {"field": "line_item.sku", "operator": "REGEX_MATCH", "value": "sku", "is_preset": true}
+                     ]}}}
+                ])),
+            ]
+        }));
+        assert_eq!(lint(&config), vec![]);
+    }
+
+    #[test]
+    fn test_fields() {
+        let config = config(json!({
+            "version": "1.0",
+            "rules": [rule("a", json!([
+                {"field": "cart.totl", "operator": "GREATER_THAN", "value": 100},
+                {"field": "delivery_address.address1", "operator": "CONTAINS", "value": "PO Box"},
+                {"field": "line_item.sku", "operator": "EQUALS", "value": "X"},
+                {"field": "cart.total", "operator": "GREATER_THAN", "value": 1,
+                 "aggregate": {"function": "SUM"}},
+                {"field": "line_item.sku", "operator": "GREATER_THAN", "value": 1,
+                 "aggregate": {"function": "COUNT", "filter": {"operator": "AND", "criteria": [
+                    {"field": "customer.email", "operator": "EXISTS", "value": null}
+                 ]}}}
+            ]))]
+        }));
+        let diagnostics = lint(&config);
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                (LintCode::UnknownField, "/rules/0/conditions/criteria/0/field"),
+                (LintCode::DeliveryAddress, "/rules/0/conditions/criteria/1/field"),
+                (LintCode::LineItemOutsideAggregate, "/rules/0/conditions/criteria/2/field"),
+                (LintCode::CartFieldInAggregate, "/rules/0/conditions/criteria/3/field"),
+                (LintCode::CartFieldInAggregate, "/rules/0/conditions/criteria/4/aggregate/filter/criteria/0/field"),
+            ]
+        );
+        assert!(diagnostics[1].message.contains("shipping_address.address1"));
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Error));
+    }
+
+    #[test]
+    fn test_checkout_support() {
+        let config = config(json!({
+            "version": "1.0",
+            "rules": [rule("a", json!([
+                {"field": "shipping_address.city", "operator": "EQUALS", "value": "Montreal",
+                 "normalize": {"fold_diacritics": true}},
+                {"field": "shipping_address.city", "operator": "EQUALS", "value": "ABC",
+                 "normalize": {"nfkc": true, "fold_diacritics": true, "trim": true}},
+                {"field": "line_item.collections", "operator": "GREATER_THAN", "value": 1,
+                 "aggregate": {"function": "COUNT", "filter": {"operator": "AND", "criteria": [
+                    {"field": "line_item.collections", "operator": "CONTAINS", "value": "sale"}
+                 ]}}},
+                {"field": "shipping_address.city", "operator": "EQUALS", "value": "x",
+                 "normalize": {"nfkc": false, "trim": true}},
+                {"field": "customer.created_at", "operator": "WITHIN_DAYS", "value": -30},
+                {"field": "cart.attributes.gift_wrap", "operator": "EQUALS", "value": "yes"},
+                {"field": "cart.attributes.Delivery Date", "operator": "BEFORE", "value": "now"}
+            ]))]
+        }));
+        let diagnostics = lint(&config);
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                (LintCode::UnsupportedAtCheckout, "/rules/0/conditions/criteria/0/normalize/fold_diacritics"),
+                (LintCode::UnsupportedAtCheckout, "/rules/0/conditions/criteria/1/normalize/nfkc"),
+                (LintCode::UnsupportedAtCheckout, "/rules/0/conditions/criteria/1/normalize/fold_diacritics"),
+                (LintCode::UnsupportedAtCheckout, "/rules/0/conditions/criteria/2/field"),
+                (LintCode::UnsupportedAtCheckout, "/rules/0/conditions/criteria/2/aggregate/filter/criteria/0/field"),
+                (LintCode::UnsupportedAtCheckout, "/rules/0/conditions/criteria/4/field"),
+                (LintCode::UnsupportedAtCheckout, "/rules/0/conditions/criteria/5/field"),
+            ]
+        );
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Error));
+    }
+
+    #[test]
+    fn test_types_and_values() {
+        let config = config(json!({
+            "version": "1.0",
+            "rules": [rule("a", json!([
+                {"field": "cart.attributes.gift_message", "operator": "GREATER_THAN", "value": 1},
+                {"field": "customer.tags", "operator": "EQUALS", "value": "VIP"},
+                {"field": "cart.total", "operator": "STARTS_WITH", "value": "1"},
+                {"field": "cart.total", "operator": "ZIP_MATCHES_PROVINCE", "value": null},
+                {"field": "line_item.vendor", "operator": "GREATER_THAN", "value": 1,
+                 "aggregate": {"function": "SUM"}},
+                {"field": "shipping_address.country_code", "operator": "IN", "value": "US"},
+                {"field": "cart.total", "operator": "GREATER_THAN", "value": "100"},
+                {"field": "cart.total", "operator": "BETWEEN", "value": [1]}
+            ]))]
+        }));
+        assert_eq!(
+            codes(&lint(&config)),
+            vec![
+                (LintCode::TypeMismatch, "/rules/0/conditions/criteria/0/operator"),
+                (LintCode::TypeMismatch, "/rules/0/conditions/criteria/1/operator"),
+                (LintCode::TypeMismatch, "/rules/0/conditions/criteria/2/operator"),
+                (LintCode::TypeMismatch, "/rules/0/conditions/criteria/3/operator"),
+                (LintCode::InvalidValue, "/rules/0/conditions/criteria/3/value"),
+                (LintCode::TypeMismatch, "/rules/0/conditions/criteria/4/aggregate/function"),
+                (LintCode::InNotList, "/rules/0/conditions/criteria/5/value"),
+                (LintCode::InvalidValue, "/rules/0/conditions/criteria/6/value"),
+                (LintCode::InvalidValue, "/rules/0/conditions/criteria/7/value"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_patterns() {
+        let config = config(json!({
+            "version": "1.0",
+            "regex_patterns": {"bad": "(a", "a/b": "[z-a]", "unused": "^x$"},
+            "rules": [rule("a", json!([
+                {"field": "shipping_address.zip", "operator": "REGEX_MATCH", "value": "no_such", "is_preset": true},
+                {"field": "shipping_address.zip", "operator": "REGEX_MATCH", "value": "bad", "is_preset": true},
+                {"field": "shipping_address.zip", "operator": "REGEX_MATCH", "value": "(?=x)"},
+                {"field": "shipping_address.zip", "operator": "REGEX_MATCH", "value": "(?:){99999}x"}
+            ]))]
+        }));
+        assert_eq!(
+            codes(&lint(&config)),
+            vec![
+                (LintCode::InvalidPattern, "/regex_patterns/a~1b"),
+                (LintCode::InvalidPattern, "/regex_patterns/bad"),
+                (LintCode::UnknownPreset, "/rules/0/conditions/criteria/0/value"),
+                (LintCode::InvalidPattern, "/rules/0/conditions/criteria/1/value"),
+                (LintCode::InvalidPattern, "/rules/0/conditions/criteria/2/value"),
+                (LintCode::InvalidPattern, "/rules/0/conditions/criteria/3/value"),
+            ]
+        );
+        assert_eq!(lint(&config)[1].message, "pattern \"bad\" doesn't compile: unclosed group");
+    }
+
+    #[test]
+    fn test_rules() {
+        let config = config(json!({
+            "version": "1.0",
+            "rules": [
+                rule("a", json!([])),
+                rule("b", json!([{"operator": "OR", "criteria": []}])),
+                rule("a", json!([{"field": "cart.total", "operator": "GREATER_THAN", "value": 1}])),
+            ]
+        }));
+        let diagnostics = lint(&config);
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                (LintCode::EmptyGroup, "/rules/0/conditions/criteria"),
+                (LintCode::EmptyGroup, "/rules/1/conditions/criteria/0/criteria"),
+                (LintCode::DuplicateRuleId, "/rules/2/id"),
+            ]
+        );
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+
+        let json = serde_json::to_value(&diagnostics[2]).unwrap();
+        assert_eq!(json["code"], "DUPLICATE_RULE_ID");
+        assert_eq!(json["severity"], "ERROR");
+        assert_eq!(json["pointer"], "/rules/2/id");
+        assert!(json.get("related").is_none());
+    }
+
+    #[test]
+    fn test_logic() {
+        let config = config(json!({
+            "version": "1.0",
+            "rules": [
+                rule("never", json!([
+                    {"field": "cart.total", "operator": "GREATER_THAN", "value": 100},
+                    {"field": "shipping_address.country_code", "operator": "EQUALS", "value": "US"},
+                    {"field": "cart.total", "operator": "LESS_THAN", "value": 50}
+                ])),
+                rule("always", json!([
+                    {"field": "cart.quantity", "operator": "GREATER_THAN_OR_EQUAL", "value": 0}
+                ])),
+                rule("noise", json!([
+                    {"field": "cart.total", "operator": "GREATER_THAN", "value": 50},
+                    {"field": "cart.total", "operator": "GREATER_THAN", "value": 100},
+                    {"field": "cart.weight_ok", "operator": "EXISTS", "value": null},
+                    {"field": "cart.quantity", "operator": "GREATER_THAN_OR_EQUAL", "value": 0},
+                    {"operator": "OR", "criteria": [
+                        {"field": "shipping_address.country_code", "operator": "EQUALS", "value": "US"},
+                        {"field": "cart.total", "operator": "LESS_THAN", "value": 0}
+                    ]}
+                ]))
+            ]
+        }));
+        let diagnostics = lint(&config);
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                (LintCode::NeverTrue, "/rules/0/conditions"),
+                (LintCode::AlwaysTrue, "/rules/1/conditions"),
+                (LintCode::UnknownField, "/rules/2/conditions/criteria/2/field"),
+                (LintCode::RedundantCondition, "/rules/2/conditions/criteria/0"),
+                (LintCode::AlwaysTrue, "/rules/2/conditions/criteria/3"),
+                (LintCode::NeverTrue, "/rules/2/conditions/criteria/4/criteria/1"),
+            ]
+        );
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(
+            diagnostics[0].related,
+            vec!["/rules/0/conditions/criteria/0", "/rules/0/conditions/criteria/2"]
+        );
+        assert_eq!(diagnostics[1].severity, Severity::Error);
+        assert_eq!(diagnostics[3].related, vec!["/rules/2/conditions/criteria/1"]);
+        assert!(diagnostics[3..].iter().all(|d| d.severity == Severity::Warning));
+    }
+}
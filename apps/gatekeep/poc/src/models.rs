@@ -1,6 +1,11 @@
 //! Data models for LogicFlow rule configuration and cart input.
 
+use chrono::{DateTime, FixedOffset, Utc};
+use crate::dates::DateOrder;
+use crate::normalize::Normalization;
+use crate::phones::normalize_phone;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// Top-level configuration stored in Shopify metafield.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,6 +14,20 @@ pub struct RulesConfig {
     #[serde(default)]
     pub total_complexity: u32,
     pub rules: Vec<Rule>,
+    /// Named custom patterns, referenced from `REGEX_MATCH` conditions with
+    /// `is_preset: true`. A name here takes precedence over a built-in
+    /// preset of the same name.
+    #[serde(default)]
+    pub regex_patterns: HashMap<String, String>,
+    /// The shop's UTC offset in minutes, used for dates written without
+    /// one. The app writes the shop's current offset on every save, so it
+    /// lags a daylight saving change until the next save. Defaults to the
+    /// offset of the evaluation time (`CartInput::now`).
+    #[serde(default)]
+    pub timezone_offset_minutes: Option<i32>,
+    /// How all-numeric slash dates (`01/02/2024`) are read.
+    #[serde(default)]
+    pub date_order: DateOrder,
 }
 
 /// A single validation rule.
@@ -28,7 +47,7 @@ fn default_true() -> bool {
     true
 }
 
-/// A group of conditions combined with AND/OR logic.
+/// A group of conditions combined with AND/OR/NOT logic.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConditionGroup {
     pub operator: LogicalOperator,
@@ -49,10 +68,12 @@ pub enum Criterion {
 pub enum LogicalOperator {
     And,
     Or,
+    /// Negation of the AND of the group's criteria.
+    Not,
 }
 
 /// A single condition comparing a field to a value.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Condition {
     pub field: String,
     pub operator: ComparisonOperator,
@@ -60,12 +81,47 @@ pub struct Condition {
     /// If true, `value` is a key into the preset patterns map.
     #[serde(default)]
     pub is_preset: bool,
+    /// If set, `field` names a `line_item.*` field that is aggregated
+    /// across the cart's line items before comparison.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregate: Option<Aggregate>,
+    /// How strings are normalized before comparison.
+    #[serde(default, skip_serializing_if = "Normalization::is_default")]
+    pub normalize: Normalization,
 }
 
-/// Comparison operators for conditions.
+/// An aggregate over line items, e.g. "sum of `line_item.line_total`
+/// where `line_item.vendor` = Acme".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    /// Only line items matching this group contribute to the aggregate.
+    /// Conditions inside the filter use `line_item.*` fields.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<ConditionGroup>,
+}
+
+/// Aggregate functions over line items.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AggregateFunction {
+    /// Sum of a numeric field (0 when no lines match).
+    Sum,
+    /// Number of matching lines.
+    Count,
+    /// Number of distinct values of the field among matching lines.
+    CountDistinct,
+    /// Smallest value of a numeric field (no value when no lines match).
+    Min,
+    /// Largest value of a numeric field (no value when no lines match).
+    Max,
+}
+
+/// Comparison operators for conditions.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum ComparisonOperator {
+    #[default]
     Equals,
     NotEquals,
     GreaterThan,
@@ -79,6 +135,122 @@ pub enum ComparisonOperator {
     RegexMatch,
     In,
     NotIn,
+    /// Date is strictly before the value (a date string or "now").
+    Before,
+    /// Date is strictly after the value (a date string or "now").
+    After,
+    /// Date is within N days of now; negative N looks backwards.
+    WithinDays,
+    /// Date falls on one of the listed weekdays (e.g. `["SAT", "SUN"]`).
+    DayOfWeekIn,
+    /// Number lies within a range: `[5, 10]` (inclusive) or
+    /// `{"min": 5, "max": 10, "max_inclusive": false}`.
+    Between,
+    /// Number lies within any of a list of ranges (same shapes as `BETWEEN`).
+    /// A malformed range leaves the condition unknown.
+    InRanges,
+    /// Field is present (e.g. the cart has a shipping address). Ignores `value`.
+    Exists,
+    /// Field is absent. Ignores `value`.
+    NotExists,
+    /// Field is absent, an empty string (after normalization) or an empty
+    /// list. Ignores `value`.
+    IsEmpty,
+    /// Field is present and not empty. Ignores `value`.
+    IsNotEmpty,
+    /// Postal code fits a format for its country (see `postcodes`). The
+    /// country is the address's `country_code`, or `value` if it names one.
+    /// Unknown for countries with no format on file.
+    IsValidPostcode,
+    /// Postal code doesn't fit any format for its country.
+    IsInvalidPostcode,
+    /// Phone number has a valid length for its country (see `phones`).
+    /// National numbers are read in the address's `country_code`, or in
+    /// `value` if it names a country. Unknown for countries with no
+    /// numbering plan on file.
+    IsValidPhone,
+    IsInvalidPhone,
+    /// Text contains a word from the profanity lists (see `profanity`),
+    /// allowing for obfuscation. `value` is a language code or a list of
+    /// them; omitted means every language.
+    ContainsProfanity,
+    NotContainsProfanity,
+    /// Text contains any of the strings in `value` (a list), matched in a
+    /// single pass. On list fields, any member equals one of them.
+    ContainsAnyOf,
+    ContainsNoneOf,
+    /// Postal code lies within any of a list of `[low, high]` ranges (see
+    /// `postcodes::in_postcode_range`): numeric for all-digit countries
+    /// such as the US, lexicographic otherwise.
+    InZipRanges,
+    /// Text starts with any of a list of prefixes, ignoring case, spaces
+    /// and hyphens. A trailing `*` is allowed (`"902*"`).
+    MatchesPrefixAny,
+    /// The address's zip belongs to its `province_code` (see `regions`):
+    /// US ZIP3 to state, Canadian FSA to province. Ignores `value`. Unknown
+    /// for other countries, invalid postcodes and missing provinces.
+    ZipMatchesProvince,
+    NotZipMatchesProvince,
+    /// The shipping address shows signs of junk input (see
+    /// `address_quality`). `value` is a signal name or a list of them;
+    /// omitted means every signal. Unknown without a shipping address.
+    AddressLooksInvalid,
+    /// The shipping address is a US military (APO/FPO/DPO) address (see
+    /// `military`). Ignores `value`. Unknown without a shipping address.
+    IsMilitaryAddress,
+    IsNotMilitaryAddress,
+    /// Email address is at a disposable mail domain or a subdomain of one
+    /// (see `emails`). Ignores `value`.
+    IsDisposableEmail,
+    /// Email address's domain, or a domain it's a subdomain of, is in
+    /// `value`: a list of domains or an encoded set, as with `IN`.
+    EmailDomainIn,
+    NotEmailDomainIn,
+}
+
+/// A numeric range used by `BETWEEN` and `IN_RANGES`. Either bound may be
+/// omitted for an open-ended range; bounds are inclusive unless stated.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NumericRange {
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+    #[serde(default = "default_true")]
+    pub min_inclusive: bool,
+    #[serde(default = "default_true")]
+    pub max_inclusive: bool,
+}
+
+impl NumericRange {
+    /// Parse a range from a condition value: either a `[min, max]` pair
+    /// (inclusive) or an object with `min`/`max`/`*_inclusive` keys.
+    pub fn from_value(value: &serde_json::Value) -> Option<Self> {
+        match value {
+            serde_json::Value::Array(pair) if pair.len() == 2 => Some(Self {
+                min: Some(pair[0].as_f64()?),
+                max: Some(pair[1].as_f64()?),
+                min_inclusive: true,
+                max_inclusive: true,
+            }),
+            serde_json::Value::Object(_) => {
+                let range: Self = serde_json::from_value(value.clone()).ok()?;
+                (range.min.is_some() || range.max.is_some()).then_some(range)
+            }
+            _ => None,
+        }
+    }
+
+    /// True if `n` lies within the range.
+    pub fn contains(&self, n: f64) -> bool {
+        let above_min = self.min.is_none_or(|min| {
+            if self.min_inclusive { n >= min } else { n > min }
+        });
+        let below_max = self.max.is_none_or(|max| {
+            if self.max_inclusive { n <= max } else { n < max }
+        });
+        above_min && below_max
+    }
 }
 
 // ============================================================================
@@ -94,8 +266,19 @@ pub struct CartInput {
     pub quantity: u32,
     pub total_weight: f64,
     pub customer_tags: Vec<String>,
-    pub shipping_address: Address,
+    /// `None` when checkout has no shipping address yet, in which case every
+    /// `shipping_address.*` field is missing.
+    pub shipping_address: Option<Address>,
     pub line_items: Vec<LineItem>,
+    /// Cart attributes, e.g. a delivery date written by a date picker.
+    pub attributes: HashMap<String, String>,
+    pub customer_created_at: Option<DateTime<FixedOffset>>,
+    /// The buyer's email, once checkout has it (guests included).
+    pub customer_email: Option<String>,
+    /// The buyer's phone, as entered.
+    pub customer_phone: Option<String>,
+    /// Evaluation time in the shop's timezone. Defaults to the system clock.
+    pub now: Option<DateTime<FixedOffset>>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -108,6 +291,7 @@ pub struct Address {
     pub country: String,
     pub country_code: String,
     pub zip: String,
+    pub phone: String,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -118,31 +302,205 @@ pub struct LineItem {
     pub vendor: String,
     pub quantity: u32,
     pub price: f64,
-    pub properties: std::collections::HashMap<String, String>,
+    pub collections: Vec<String>,
+    pub properties: HashMap<String, String>,
 }
 
-impl CartInput {
-    /// Get a field value by path (e.g., "cart.total", "shipping_address.zip").
-    pub fn get_field(&self, path: &str) -> Option<FieldValue> {
+/// A field path resolved once, when rules are compiled, so evaluation
+/// doesn't re-split "shipping_address.zip" for every condition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldPath {
+    CartTotal,
+    CartSubtotal,
+    CartQuantity,
+    CartTotalWeight,
+    /// `cart.attributes.<key>`
+    CartAttribute(String),
+    CustomerTags,
+    CustomerCreatedAt,
+    CustomerEmail,
+    /// `customer.phone`, normalized like `shipping_address.phone`.
+    CustomerPhone,
+    Now,
+    /// `shipping_address.<field>`
+    ShippingAddress(AddressField),
+    /// `line_item.<field>`, only available inside aggregates.
+    LineItem(LineItemField),
+    /// A path no source knows about; always missing.
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressField {
+    Address1,
+    Address2,
+    City,
+    Province,
+    ProvinceCode,
+    Country,
+    CountryCode,
+    Zip,
+    /// Normalized to E.164 using the address's country, or as entered if
+    /// it can't be.
+    Phone,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineItemField {
+    ProductId,
+    VariantId,
+    Sku,
+    Vendor,
+    Quantity,
+    Price,
+    LineTotal,
+    Collections,
+}
+
+impl FieldPath {
+    /// Parse a dotted path (e.g. "cart.total", "shipping_address.zip").
+    pub fn parse(path: &str) -> FieldPath {
+        if let Some(key) = path.strip_prefix("cart.attributes.") {
+            return FieldPath::CartAttribute(key.to_string());
+        }
+
         let parts: Vec<&str> = path.split('.').collect();
-        
+
         match parts.as_slice() {
-            ["cart", "total"] => Some(FieldValue::Number(self.total)),
-            ["cart", "subtotal"] => Some(FieldValue::Number(self.subtotal)),
-            ["cart", "quantity"] => Some(FieldValue::Number(self.quantity as f64)),
-            ["cart", "total_weight"] => Some(FieldValue::Number(self.total_weight)),
-            ["customer", "tags"] => Some(FieldValue::StringArray(self.customer_tags.clone())),
-            ["shipping_address", "address1"] => Some(FieldValue::String(self.shipping_address.address1.clone())),
-            ["shipping_address", "address2"] => Some(FieldValue::String(self.shipping_address.address2.clone())),
-            ["shipping_address", "city"] => Some(FieldValue::String(self.shipping_address.city.clone())),
-            ["shipping_address", "province"] => Some(FieldValue::String(self.shipping_address.province.clone())),
-            ["shipping_address", "province_code"] => Some(FieldValue::String(self.shipping_address.province_code.clone())),
-            ["shipping_address", "country"] => Some(FieldValue::String(self.shipping_address.country.clone())),
-            ["shipping_address", "country_code"] => Some(FieldValue::String(self.shipping_address.country_code.clone())),
-            ["shipping_address", "zip"] => Some(FieldValue::String(self.shipping_address.zip.clone())),
-            _ => None,
+            ["cart", "total"] => FieldPath::CartTotal,
+            ["cart", "subtotal"] => FieldPath::CartSubtotal,
+            ["cart", "quantity"] => FieldPath::CartQuantity,
+            ["cart", "total_weight"] => FieldPath::CartTotalWeight,
+            ["customer", "tags"] => FieldPath::CustomerTags,
+            ["customer", "created_at"] => FieldPath::CustomerCreatedAt,
+            ["customer", "email"] => FieldPath::CustomerEmail,
+            ["customer", "phone"] => FieldPath::CustomerPhone,
+            ["now"] => FieldPath::Now,
+            ["shipping_address", field] => match *field {
+                "address1" => FieldPath::ShippingAddress(AddressField::Address1),
+                "address2" => FieldPath::ShippingAddress(AddressField::Address2),
+                "city" => FieldPath::ShippingAddress(AddressField::City),
+                "province" => FieldPath::ShippingAddress(AddressField::Province),
+                "province_code" => FieldPath::ShippingAddress(AddressField::ProvinceCode),
+                "country" => FieldPath::ShippingAddress(AddressField::Country),
+                "country_code" => FieldPath::ShippingAddress(AddressField::CountryCode),
+                "zip" => FieldPath::ShippingAddress(AddressField::Zip),
+                "phone" => FieldPath::ShippingAddress(AddressField::Phone),
+                _ => FieldPath::Unknown,
+            },
+            ["line_item", field] => match *field {
+                "product_id" => FieldPath::LineItem(LineItemField::ProductId),
+                "variant_id" => FieldPath::LineItem(LineItemField::VariantId),
+                "sku" => FieldPath::LineItem(LineItemField::Sku),
+                "vendor" => FieldPath::LineItem(LineItemField::Vendor),
+                "quantity" => FieldPath::LineItem(LineItemField::Quantity),
+                "price" => FieldPath::LineItem(LineItemField::Price),
+                "line_total" => FieldPath::LineItem(LineItemField::LineTotal),
+                "collections" => FieldPath::LineItem(LineItemField::Collections),
+                _ => FieldPath::Unknown,
+            },
+            _ => FieldPath::Unknown,
         }
     }
+
+    /// True for phone fields, which resolve to E.164 and compare by number.
+    pub fn is_phone(&self) -> bool {
+        matches!(self, FieldPath::CustomerPhone | FieldPath::ShippingAddress(AddressField::Phone))
+    }
+}
+
+/// Something conditions can be evaluated against.
+pub trait FieldSource {
+    /// Get the value of a resolved field, or `None` if it's missing here.
+    fn resolve(&self, field: &FieldPath) -> Option<FieldValue>;
+
+    /// Get a field value by path (e.g., "cart.total", "shipping_address.zip").
+    fn get_field(&self, path: &str) -> Option<FieldValue> {
+        self.resolve(&FieldPath::parse(path))
+    }
+
+    /// Line items visible to aggregate conditions.
+    fn line_items(&self) -> &[LineItem] {
+        &[]
+    }
+
+    /// The reference time for relative date operators.
+    fn now(&self) -> DateTime<FixedOffset> {
+        Utc::now().fixed_offset()
+    }
+}
+
+impl FieldSource for CartInput {
+    fn resolve(&self, field: &FieldPath) -> Option<FieldValue> {
+        match field {
+            FieldPath::CartTotal => Some(FieldValue::Number(self.total)),
+            FieldPath::CartSubtotal => Some(FieldValue::Number(self.subtotal)),
+            FieldPath::CartQuantity => Some(FieldValue::Number(self.quantity as f64)),
+            FieldPath::CartTotalWeight => Some(FieldValue::Number(self.total_weight)),
+            FieldPath::CartAttribute(key) => self.attributes.get(key).map(|v| FieldValue::String(v.clone())),
+            FieldPath::CustomerTags => Some(FieldValue::StringArray(self.customer_tags.clone())),
+            FieldPath::CustomerCreatedAt => self.customer_created_at.map(FieldValue::DateTime),
+            FieldPath::CustomerEmail => self.customer_email.clone().map(FieldValue::String),
+            FieldPath::CustomerPhone => {
+                let country = self.shipping_address.as_ref().map_or("", |a| a.country_code.as_str());
+                self.customer_phone.as_ref().map(|phone| FieldValue::String(phone_field(country, phone)))
+            }
+            FieldPath::Now => Some(FieldValue::DateTime(self.now())),
+            FieldPath::ShippingAddress(field) => Some(self.shipping_address.as_ref()?.get_field(*field)),
+            FieldPath::LineItem(_) | FieldPath::Unknown => None,
+        }
+    }
+
+    fn line_items(&self) -> &[LineItem] {
+        &self.line_items
+    }
+
+    fn now(&self) -> DateTime<FixedOffset> {
+        self.now.unwrap_or_else(|| Utc::now().fixed_offset())
+    }
+}
+
+impl Address {
+    /// Get an address field.
+    fn get_field(&self, field: AddressField) -> FieldValue {
+        let value = match field {
+            AddressField::Address1 => &self.address1,
+            AddressField::Address2 => &self.address2,
+            AddressField::City => &self.city,
+            AddressField::Province => &self.province,
+            AddressField::ProvinceCode => &self.province_code,
+            AddressField::Country => &self.country,
+            AddressField::CountryCode => &self.country_code,
+            AddressField::Zip => &self.zip,
+            AddressField::Phone => return FieldValue::String(phone_field(&self.country_code, &self.phone)),
+        };
+        FieldValue::String(value.clone())
+    }
+}
+
+/// A phone field's value: E.164 if the number can be normalized, otherwise
+/// as entered.
+fn phone_field(country_code: &str, phone: &str) -> String {
+    normalize_phone(country_code, phone).unwrap_or_else(|| phone.to_string())
+}
+
+impl FieldSource for LineItem {
+    fn resolve(&self, field: &FieldPath) -> Option<FieldValue> {
+        let FieldPath::LineItem(field) = field else {
+            return None;
+        };
+
+        Some(match field {
+            LineItemField::ProductId => FieldValue::String(self.product_id.clone()),
+            LineItemField::VariantId => FieldValue::String(self.variant_id.clone()),
+            LineItemField::Sku => FieldValue::String(self.sku.clone()),
+            LineItemField::Vendor => FieldValue::String(self.vendor.clone()),
+            LineItemField::Quantity => FieldValue::Number(self.quantity as f64),
+            LineItemField::Price => FieldValue::Number(self.price),
+            LineItemField::LineTotal => FieldValue::Number(self.price * self.quantity as f64),
+            LineItemField::Collections => FieldValue::StringArray(self.collections.clone()),
+        })
+    }
 }
 
 /// Represents a field value that can be compared.
@@ -152,6 +510,7 @@ pub enum FieldValue {
     Number(f64),
     Bool(bool),
     StringArray(Vec<String>),
+    DateTime(DateTime<FixedOffset>),
 }
 
 #[cfg(test)]
@@ -172,20 +531,94 @@ mod tests {
         assert_eq!(op, ComparisonOperator::GreaterThan);
     }
 
+    #[test]
+    fn test_numeric_range_from_value() {
+        let pair = NumericRange::from_value(&serde_json::json!([5, 10])).unwrap();
+        assert!(pair.contains(5.0) && pair.contains(10.0));
+        assert!(!pair.contains(10.5));
+
+        let half_open = NumericRange::from_value(&serde_json::json!({"min": 5, "max": 10, "max_inclusive": false})).unwrap();
+        assert!(half_open.contains(5.0));
+        assert!(!half_open.contains(10.0));
+
+        let open_ended = NumericRange::from_value(&serde_json::json!({"min": 100, "min_inclusive": false})).unwrap();
+        assert!(open_ended.contains(1e9));
+        assert!(!open_ended.contains(100.0));
+
+        assert!(NumericRange::from_value(&serde_json::json!({})).is_none());
+        assert!(NumericRange::from_value(&serde_json::json!([1, 2, 3])).is_none());
+        assert!(NumericRange::from_value(&serde_json::json!(["a", 2])).is_none());
+    }
+
     #[test]
     fn test_cart_get_field() {
         let cart = CartInput {
             total: 150.0,
-            shipping_address: Address {
+            shipping_address: Some(Address {
                 zip: "90210".to_string(),
                 ..Default::default()
-            },
+            }),
             ..Default::default()
         };
 
         assert!(matches!(cart.get_field("cart.total"), Some(FieldValue::Number(150.0))));
         assert!(matches!(cart.get_field("shipping_address.zip"), Some(FieldValue::String(s)) if s == "90210"));
         assert!(cart.get_field("invalid.field").is_none());
+
+        // No address at all is distinct from an empty address line
+        let no_address = CartInput::default();
+        assert!(no_address.get_field("shipping_address.zip").is_none());
+        assert!(matches!(cart.get_field("shipping_address.address1"), Some(FieldValue::String(s)) if s.is_empty()));
+    }
+
+    #[test]
+    fn test_line_item_get_field() {
+        let line = LineItem {
+            vendor: "Acme".to_string(),
+            quantity: 3,
+            price: 12.5,
+            ..Default::default()
+        };
+
+        assert!(matches!(line.get_field("line_item.vendor"), Some(FieldValue::String(s)) if s == "Acme"));
+        assert!(matches!(line.get_field("line_item.line_total"), Some(FieldValue::Number(n)) if n == 37.5));
+        assert!(line.get_field("cart.total").is_none());
+    }
+
+    #[test]
+    fn test_field_path_parse() {
+        assert_eq!(FieldPath::parse("cart.total"), FieldPath::CartTotal);
+        assert_eq!(FieldPath::parse("shipping_address.zip"), FieldPath::ShippingAddress(AddressField::Zip));
+        assert_eq!(FieldPath::parse("line_item.sku"), FieldPath::LineItem(LineItemField::Sku));
+        assert_eq!(
+            FieldPath::parse("cart.attributes.delivery.date"),
+            FieldPath::CartAttribute("delivery.date".to_string())
+        );
+        assert_eq!(FieldPath::parse("shipping_address.planet"), FieldPath::Unknown);
+        assert_eq!(FieldPath::parse("cart.total.extra"), FieldPath::Unknown);
+    }
+
+    #[test]
+    fn test_deserialize_aggregate_condition() {
+        let json = r#"{
+            "field": "line_item.line_total",
+            "operator": "LESS_THAN",
+            "value": 200,
+            "aggregate": {
+                "function": "SUM",
+                "filter": {
+                    "operator": "AND",
+                    "criteria": [
+                        { "field": "line_item.vendor", "operator": "EQUALS", "value": "Acme" }
+                    ]
+                }
+            }
+        }"#;
+
+        let condition: Condition = serde_json::from_str(json).unwrap();
+        let aggregate = condition.aggregate.unwrap();
+        assert_eq!(aggregate.function, AggregateFunction::Sum);
+        assert_eq!(aggregate.filter.unwrap().criteria.len(), 1);
     }
 }
 
@@ -0,0 +1,226 @@
+//! Per-condition string normalization.
+//!
+//! Every string comparison runs both the field value and the condition
+//! value through the same [`Normalization`] before comparing, so
+//! `EQUALS`, `CONTAINS`, `IN` etc. agree on what "the same string" means.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// String normalization options for a condition.
+///
+/// The default is case-insensitive matching with no other changes, as the
+/// checkout function has always compared strings. (`EQUALS` used to be
+/// case-sensitive here; `case_sensitive` restores that.)
+/// Steps are applied in a fixed order: NFKC, whitespace trimming and
+/// collapsing, case folding, then diacritic folding.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Normalization {
+    /// Compare case-sensitively.
+    pub case_sensitive: bool,
+    /// Strip leading and trailing whitespace.
+    pub trim: bool,
+    /// Replace runs of whitespace with a single space.
+    pub collapse_whitespace: bool,
+    /// Apply Unicode NFKC (e.g. full-width "ＡＢＣ" becomes "ABC").
+    pub nfkc: bool,
+    /// Remove accents, so "Montréal" matches "Montreal".
+    pub fold_diacritics: bool,
+    /// BCP 47 language tag for locale-specific case folding. Only Turkish
+    /// and Azeri ("tr", "az") differ: "I" folds to "ı" and "İ" to "i".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+}
+
+impl Normalization {
+    /// True if this is the default (case-insensitive only) mode.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Normalize `input`, borrowing when no step changes it.
+    pub fn apply<'a>(&self, input: &'a str) -> Cow<'a, str> {
+        self.apply_steps(input, !self.case_sensitive)
+    }
+
+    /// Normalize `input` but leave its case alone. Used for regex matching,
+    /// where the pattern controls case sensitivity (e.g. `(?i)`).
+    pub fn apply_keep_case<'a>(&self, input: &'a str) -> Cow<'a, str> {
+        self.apply_steps(input, false)
+    }
+
+    fn apply_steps<'a>(&self, input: &'a str, fold_case: bool) -> Cow<'a, str> {
+        let mut out = Cow::Borrowed(input);
+
+        if self.nfkc {
+            out = Cow::Owned(out.nfkc().collect());
+        }
+        if self.trim {
+            out = match out {
+                Cow::Borrowed(s) => Cow::Borrowed(s.trim()),
+                Cow::Owned(s) => Cow::Owned(s.trim().to_string()),
+            };
+        }
+        if self.collapse_whitespace && out.contains(char::is_whitespace) {
+            out = Cow::Owned(collapse_whitespace(&out));
+        }
+        if fold_case {
+            out = Cow::Owned(self.fold_case(&out));
+        }
+        if self.fold_diacritics && !out.is_ascii() {
+            out = Cow::Owned(fold_diacritics(&out));
+        }
+
+        out
+    }
+
+    fn is_turkic(&self) -> bool {
+        self.locale.as_deref().is_some_and(|locale| {
+            let lang = locale.split(['-', '_']).next().unwrap_or("");
+            lang.eq_ignore_ascii_case("tr") || lang.eq_ignore_ascii_case("az")
+        })
+    }
+
+    fn fold_case(&self, s: &str) -> String {
+        if self.is_turkic() {
+            s.chars()
+                .map(|c| match c {
+                    'I' => 'ı',
+                    'İ' => 'i',
+                    _ => c,
+                })
+                .flat_map(char::to_lowercase)
+                .collect()
+        } else {
+            s.to_lowercase()
+        }
+    }
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_space = false;
+    for c in s.chars() {
+        if c.is_whitespace() {
+            if !in_space {
+                out.push(' ');
+            }
+            in_space = true;
+        } else {
+            out.push(c);
+            in_space = false;
+        }
+    }
+    out
+}
+
+/// Strip combining marks after canonical decomposition, and fold the
+/// handful of Latin letters that carry no decomposition (ø, ł, đ, ß, ...).
+fn fold_diacritics(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.nfd().filter(|c| !is_combining_mark(*c)) {
+        match c {
+            'ø' => out.push('o'),
+            'Ø' => out.push('O'),
+            'ł' => out.push('l'),
+            'Ł' => out.push('L'),
+            'đ' | 'ð' => out.push('d'),
+            'Đ' | 'Ð' => out.push('D'),
+            'ħ' => out.push('h'),
+            'Ħ' => out.push('H'),
+            'ı' => out.push('i'),
+            'ß' => out.push_str("ss"),
+            'æ' => out.push_str("ae"),
+            'Æ' => out.push_str("AE"),
+            'œ' => out.push_str("oe"),
+            'Œ' => out.push_str("OE"),
+            'þ' => out.push_str("th"),
+            'Þ' => out.push_str("TH"),
+            _ => out.push(c),
+        }
+    }
+    out.nfc().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_case_insensitive_only() {
+        let n = Normalization::default();
+        assert_eq!(n.apply("  Hello  World "), "  hello  world ");
+        assert!(n.is_default());
+    }
+
+    #[test]
+    fn test_case_sensitive_borrows_unchanged_input() {
+        let n = Normalization {
+            case_sensitive: true,
+            ..Default::default()
+        };
+        assert!(matches!(n.apply("Hello"), Cow::Borrowed("Hello")));
+    }
+
+    #[test]
+    fn test_trim_and_collapse() {
+        let n = Normalization {
+            trim: true,
+            collapse_whitespace: true,
+            ..Default::default()
+        };
+        assert_eq!(n.apply(" \t123   Main\n St  "), "123 main st");
+    }
+
+    #[test]
+    fn test_nfkc() {
+        let n = Normalization {
+            nfkc: true,
+            ..Default::default()
+        };
+        assert_eq!(n.apply("ＡＢＣ１２３"), "abc123");
+        assert_eq!(n.apply("ﬁle"), "file");
+    }
+
+    #[test]
+    fn test_fold_diacritics() {
+        let n = Normalization {
+            fold_diacritics: true,
+            ..Default::default()
+        };
+        assert_eq!(n.apply("Montréal"), n.apply("Montreal"));
+        assert_eq!(n.apply("Straße"), "strasse");
+        assert_eq!(n.apply("Øresund Łódź"), "oresund lodz");
+    }
+
+    #[test]
+    fn test_turkish_case_folding() {
+        let turkish = Normalization {
+            locale: Some("tr-TR".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(turkish.apply("İSTANBUL"), "istanbul");
+        assert_eq!(turkish.apply("ISPARTA"), "ısparta");
+
+        // Outside Turkic locales, dotted İ keeps its dot as a combining mark
+        // unless diacritics are folded.
+        let root = Normalization::default();
+        assert_ne!(root.apply("İSTANBUL"), "istanbul");
+        let folded = Normalization {
+            fold_diacritics: true,
+            ..Default::default()
+        };
+        assert_eq!(folded.apply("İSTANBUL"), "istanbul");
+    }
+
+    #[test]
+    fn test_deserialize_partial_options() {
+        let n: Normalization = serde_json::from_str(r#"{"trim": true, "locale": "tr"}"#).unwrap();
+        assert!(n.trim);
+        assert!(!n.case_sensitive);
+        assert_eq!(n.locale.as_deref(), Some("tr"));
+    }
+}
@@ -0,0 +1,433 @@
+//! Rule tree optimizer, run when rules are compiled.
+//!
+//! `evaluate_group` stops at the first criterion that decides a group, so
+//! the order of criteria sets the cost of a rule: a cheap `cart.total >
+//! 500` written after a regex still pays for the regex. [`optimize`]
+//! rewrites a group into an equivalent one that's cheaper to evaluate:
+//!
+//! - nested groups with the same operator are flattened (`a AND (b AND
+//!   c)` is `a AND b AND c`), as are groups of one criterion and double
+//!   negations;
+//! - constants are folded: criteria that are true (or false) for every
+//!   cart (see `contradictions`) are dropped, or decide their group;
+//! - criteria are reordered so the ones most likely to decide the group
+//!   cheaply come first, by complexity points and an estimate of how often
+//!   each operator matches.
+//!
+//! Kleene AND and OR don't depend on the order of their operands, so a
+//! rule's result (true, false or unknown) is unchanged. A constant is an
+//! empty group: an empty `AND` is true and an empty `OR` is false.
+
+use crate::complexity::condition_points;
+use crate::contradictions::{always_false, always_true, criterion_always_false, criterion_always_true};
+use crate::models::{ComparisonOperator, Condition, ConditionGroup, Criterion, LogicalOperator};
+use std::collections::HashMap;
+
+/// Optimize a rule's conditions. `patterns` are the config's named
+/// patterns, which cost as custom regexes.
+pub fn optimize(group: &ConditionGroup, patterns: &HashMap<String, String>) -> ConditionGroup {
+    optimize_group(group, patterns, true)
+}
+
+/// Aggregate filters are evaluated against line items, where cart fields
+/// are missing, so they aren't folded (`cart.total >= 0` is unknown there).
+fn optimize_group(group: &ConditionGroup, patterns: &HashMap<String, String>, fold: bool) -> ConditionGroup {
+    // A NOT group negates the AND of its criteria
+    let splices = |child: &ConditionGroup| match (group.operator, child.operator) {
+        (_, _) if child.criteria.len() == 1 && child.operator != LogicalOperator::Not => true,
+        (LogicalOperator::And | LogicalOperator::Not, LogicalOperator::And) => true,
+        (LogicalOperator::Or, LogicalOperator::Or) => true,
+        _ => false,
+    };
+
+    let mut criteria = Vec::new();
+    for criterion in &group.criteria {
+        match optimize_criterion(criterion, patterns, fold) {
+            Criterion::Group(child) if splices(&child) => criteria.extend(child.criteria),
+            criterion => criteria.push(criterion),
+        }
+    }
+
+    // A true criterion decides an OR and does nothing in an AND; a false
+    // one the reverse. Siblings can also decide together (`total > 100
+    // AND total < 50`).
+    let or = group.operator == LogicalOperator::Or;
+    let mut decided = criteria.iter().any(|c| if or { is_true(c, fold) } else { is_false(c, fold) });
+    criteria.retain(|c| if or { !is_false(c, fold) } else { !is_true(c, fold) });
+    if fold && !decided && !criteria.is_empty() {
+        let operator = if or { LogicalOperator::Or } else { LogicalOperator::And };
+        let siblings = ConditionGroup { operator, criteria };
+        decided = if or { always_true(&siblings) } else { always_false(&siblings) };
+        criteria = siblings.criteria;
+    }
+
+    let inner = if decided {
+        // The AND (or OR) is false (or true) whatever the rest say
+        match group.operator {
+            LogicalOperator::Or => constant(true),
+            _ => constant(false),
+        }
+    } else {
+        order(&mut criteria, group.operator, patterns);
+        let operator = match group.operator {
+            LogicalOperator::Or => LogicalOperator::Or,
+            _ => LogicalOperator::And,
+        };
+        ConditionGroup { operator, criteria }
+    };
+
+    if group.operator != LogicalOperator::Not {
+        return inner;
+    }
+    match inner.criteria.as_slice() {
+        // NOT of a constant
+        [] => constant(inner.operator == LogicalOperator::Or),
+        // NOT (NOT x) is x
+        [Criterion::Group(child)] if child.operator == LogicalOperator::Not => ConditionGroup {
+            operator: LogicalOperator::And,
+            criteria: child.criteria.clone(),
+        },
+        _ => ConditionGroup { operator: LogicalOperator::Not, criteria: inner.criteria },
+    }
+}
+
+fn optimize_criterion(criterion: &Criterion, patterns: &HashMap<String, String>, fold: bool) -> Criterion {
+    match criterion {
+        Criterion::Group(group) => Criterion::Group(optimize_group(group, patterns, fold)),
+        Criterion::Condition(condition) => {
+            let mut condition = condition.clone();
+            if let Some(filter) = condition.aggregate.as_mut().and_then(|a| a.filter.as_mut()) {
+                *filter = optimize_group(filter, patterns, false);
+            }
+            Criterion::Condition(condition)
+        }
+    }
+}
+
+/// The group that's always `value`.
+fn constant(value: bool) -> ConditionGroup {
+    let operator = if value { LogicalOperator::And } else { LogicalOperator::Or };
+    ConditionGroup { operator, criteria: Vec::new() }
+}
+
+fn is_true(criterion: &Criterion, fold: bool) -> bool {
+    match criterion {
+        Criterion::Group(group) if group.criteria.is_empty() => group.operator == LogicalOperator::And,
+        _ => fold && criterion_always_true(criterion),
+    }
+}
+
+fn is_false(criterion: &Criterion, fold: bool) -> bool {
+    match criterion {
+        Criterion::Group(group) if group.criteria.is_empty() => group.operator != LogicalOperator::And,
+        _ => fold && criterion_always_false(criterion),
+    }
+}
+
+/// Sort criteria so the cheapest way to decide the group comes first: in
+/// an AND, by cost per chance of being false; in an OR, per chance of
+/// being true. Ties keep the author's order.
+fn order(criteria: &mut [Criterion], operator: LogicalOperator, patterns: &HashMap<String, String>) {
+    let mut keyed: Vec<(f64, Criterion)> = criteria
+        .iter()
+        .map(|criterion| {
+            let (cost, p) = estimate(criterion, patterns);
+            let decides = if operator == LogicalOperator::Or { p } else { 1.0 - p };
+            (cost / decides.max(f64::EPSILON), criterion.clone())
+        })
+        .collect();
+    keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+    for (slot, (_, criterion)) in criteria.iter_mut().zip(keyed) {
+        *slot = criterion;
+    }
+}
+
+/// Expected cost of evaluating a criterion, in complexity points, and the
+/// chance that it's true. Groups are costed in their current order.
+fn estimate(criterion: &Criterion, patterns: &HashMap<String, String>) -> (f64, f64) {
+    let group = match criterion {
+        Criterion::Condition(condition) => {
+            return (condition_points(condition, patterns) as f64, selectivity(condition));
+        }
+        Criterion::Group(group) => group,
+    };
+
+    // Each criterion is only reached if the ones before didn't decide
+    let or = group.operator == LogicalOperator::Or;
+    let (mut cost, mut reached) = (0.0, 1.0);
+    for criterion in &group.criteria {
+        let (c, p) = estimate(criterion, patterns);
+        cost += reached * c;
+        reached *= if or { 1.0 - p } else { p };
+    }
+    let p = match group.operator {
+        LogicalOperator::And => reached,
+        LogicalOperator::Or => 1.0 - reached,
+        LogicalOperator::Not => 1.0 - reached,
+    };
+    (cost, p)
+}
+
+/// Rough chance that a condition is true on a typical cart. Only the
+/// relative order matters.
+fn selectivity(condition: &Condition) -> f64 {
+    use ComparisonOperator::*;
+    match condition.operator {
+        IsMilitaryAddress => 0.02,
+        ContainsProfanity | AddressLooksInvalid | IsDisposableEmail => 0.05,
+        Equals | RegexMatch | NotExists | IsEmpty | IsInvalidPostcode | IsInvalidPhone | NotZipMatchesProvince
+        | EmailDomainIn => 0.1,
+        Contains | StartsWith | EndsWith | ContainsAnyOf | MatchesPrefixAny => 0.2,
+        In | Between | InRanges | WithinDays | DayOfWeekIn | InZipRanges => 0.3,
+        GreaterThan | GreaterThanOrEqual | LessThan | LessThanOrEqual | Before | After => 0.5,
+        NotIn => 0.7,
+        NotContains | ContainsNoneOf => 0.8,
+        NotEquals | Exists | IsNotEmpty | IsValidPostcode | IsValidPhone | ZipMatchesProvince | NotEmailDomainIn => 0.9,
+        NotContainsProfanity => 0.95,
+        IsNotMilitaryAddress => 0.98,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiled::{CompileContext, CompiledGroup};
+    use crate::evaluator::evaluate_group;
+    use crate::models::{Address, CartInput, LineItem};
+    use serde_json::json;
+
+    fn group(value: serde_json::Value) -> ConditionGroup {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn cond(field: &str, operator: &str, value: serde_json::Value) -> serde_json::Value {
+        json!({"field": field, "operator": operator, "value": value})
+    }
+
+    fn fields(group: &ConditionGroup) -> Vec<String> {
+        group
+            .criteria
+            .iter()
+            .map(|criterion| match criterion {
+                Criterion::Condition(c) => c.field.clone(),
+                Criterion::Group(g) => format!("{:?}{:?}", g.operator, fields(g)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_flatten() {
+        let nested = group(json!({"operator": "AND", "criteria": [
+            cond("cart.total", "GREATER_THAN", json!(10)),
+            {"operator": "AND", "criteria": [
+                cond("cart.quantity", "GREATER_THAN", json!(1)),
+                {"operator": "OR", "criteria": [cond("cart.subtotal", "GREATER_THAN", json!(5))]}
+            ]},
+            {"operator": "NOT", "criteria": [{"operator": "NOT", "criteria": [
+                cond("cart.total_weight", "GREATER_THAN", json!(5))
+            ]}]}
+        ]}));
+        let optimized = optimize(&nested, &HashMap::new());
+        assert_eq!(optimized.operator, LogicalOperator::And);
+        assert_eq!(fields(&optimized), vec!["cart.total", "cart.quantity", "cart.subtotal", "cart.total_weight"]);
+
+        // An OR inside an AND stays a group
+        let mixed = group(json!({"operator": "AND", "criteria": [
+            cond("cart.total", "GREATER_THAN", json!(10)),
+            {"operator": "OR", "criteria": [
+                cond("cart.quantity", "GREATER_THAN", json!(1)),
+                cond("cart.subtotal", "GREATER_THAN", json!(5))
+            ]}
+        ]}));
+        assert_eq!(optimize(&mixed, &HashMap::new()).criteria.len(), 2);
+    }
+
+    #[test]
+    fn test_fold() {
+        let patterns = HashMap::new();
+        let tautology = cond("cart.quantity", "GREATER_THAN_OR_EQUAL", json!(0));
+        let contradiction = cond("cart.total", "LESS_THAN", json!(0));
+        let country = cond("shipping_address.country_code", "EQUALS", json!("US"));
+
+        let and = group(json!({"operator": "AND", "criteria": [tautology.clone(), country.clone()]}));
+        assert_eq!(fields(&optimize(&and, &patterns)), vec!["shipping_address.country_code"]);
+
+        let dead = group(json!({"operator": "AND", "criteria": [country.clone(), contradiction.clone()]}));
+        let folded = optimize(&dead, &patterns);
+        assert_eq!((folded.operator, folded.criteria.len()), (LogicalOperator::Or, 0));
+
+        let or = group(json!({"operator": "OR", "criteria": [country.clone(), tautology.clone()]}));
+        let folded = optimize(&or, &patterns);
+        assert_eq!((folded.operator, folded.criteria.len()), (LogicalOperator::And, 0));
+
+        let not = group(json!({"operator": "NOT", "criteria": [contradiction]}));
+        let folded = optimize(&not, &patterns);
+        assert_eq!((folded.operator, folded.criteria.len()), (LogicalOperator::And, 0));
+
+        // Siblings that contradict each other
+        let not = group(json!({"operator": "NOT", "criteria": [
+            cond("cart.total", "GREATER_THAN", json!(100)),
+            cond("cart.total", "LESS_THAN", json!(50))
+        ]}));
+        let folded = optimize(&not, &patterns);
+        assert_eq!((folded.operator, folded.criteria.len()), (LogicalOperator::And, 0));
+
+        // A missing address is unknown, so this isn't false
+        let missing = group(json!({"operator": "AND", "criteria": [
+            cond("shipping_address.country_code", "EQUALS", json!("US")),
+            cond("shipping_address.country_code", "EQUALS", json!("CA"))
+        ]}));
+        assert_eq!(optimize(&missing, &patterns).criteria.len(), 2);
+
+        // Aggregate filters see line items, where cart fields are missing
+        let filtered = group(json!({"operator": "AND", "criteria": [{
+            "field": "line_item.quantity", "operator": "GREATER_THAN", "value": 1,
+            "aggregate": {"function": "COUNT", "filter": {"operator": "AND", "criteria": [tautology, country]}}
+        }]}));
+        let Criterion::Condition(condition) = &optimize(&filtered, &patterns).criteria[0] else {
+            panic!("expected a condition");
+        };
+        assert_eq!(condition.aggregate.as_ref().unwrap().filter.as_ref().unwrap().criteria.len(), 2);
+    }
+
+    #[test]
+    fn test_order() {
+        let patterns = HashMap::from([("sku".to_string(), "^[A-Z]{3}-\\d{4,8}$".to_string())]);
+        let and = group(json!({"operator": "AND", "criteria": [
+            {"field": "shipping_address.address1", "operator": "REGEX_MATCH", "value": "sku", "is_preset": true},
+            cond("shipping_address.address1", "CONTAINS", json!("box")),
+            cond("cart.total", "GREATER_THAN", json!(500)),
+            cond("shipping_address.zip", "EXISTS", json!(null))
+        ]}));
+        assert_eq!(
+            fields(&optimize(&and, &patterns)),
+            vec!["cart.total", "shipping_address.address1", "shipping_address.address1", "shipping_address.zip"]
+        );
+        assert!(matches!(
+            &optimize(&and, &patterns).criteria[2],
+            Criterion::Condition(c) if c.operator == ComparisonOperator::RegexMatch
+        ));
+
+        // In an OR, likely-true criteria come first
+        let or = group(json!({"operator": "OR", "criteria": [
+            cond("shipping_address.zip", "NOT_EXISTS", json!(null)),
+            cond("shipping_address.zip", "EXISTS", json!(null))
+        ]}));
+        assert_eq!(
+            optimize(&or, &patterns).criteria.iter().map(|c| match c {
+                Criterion::Condition(c) => c.operator,
+                _ => unreachable!(),
+            }).collect::<Vec<_>>(),
+            vec![ComparisonOperator::Exists, ComparisonOperator::NotExists]
+        );
+    }
+
+    /// A small xorshift generator, so the differential test is repeatable.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    /// Conditions that are true, false, unknown or constant depending on
+    /// the cart.
+    fn condition_pool() -> Vec<serde_json::Value> {
+        vec![
+            cond("cart.total", "GREATER_THAN", json!(100)),
+            cond("cart.total", "LESS_THAN", json!(50)),
+            cond("cart.total", "LESS_THAN", json!(0)),
+            cond("cart.total", "BETWEEN", json!([20, 80])),
+            cond("cart.quantity", "GREATER_THAN_OR_EQUAL", json!(0)),
+            cond("cart.quantity", "IN", json!([1, 2, 3])),
+            cond("cart.quantity", "NOT_EQUALS", json!(2)),
+            cond("shipping_address.country_code", "EQUALS", json!("US")),
+            cond("shipping_address.country_code", "IN", json!(["US", "CA"])),
+            cond("shipping_address.country_code", "NOT_IN", json!(["CA"])),
+            cond("shipping_address.zip", "REGEX_MATCH", json!("^9")),
+            cond("shipping_address.address1", "CONTAINS", json!("box")),
+            cond("cart.attributes.gift", "EQUALS", json!("yes")),
+            cond("customer.tags", "CONTAINS", json!("vip")),
+            cond("no.such.field", "EXISTS", json!(null)),
+            json!({"field": "line_item.line_total", "operator": "GREATER_THAN", "value": 50,
+                   "aggregate": {"function": "SUM", "filter": {"operator": "AND", "criteria": [
+                       cond("cart.total", "GREATER_THAN_OR_EQUAL", json!(0)),
+                       cond("line_item.vendor", "EQUALS", json!("acme"))
+                   ]}}}),
+        ]
+    }
+
+    fn random_group(rng: &mut Rng, pool: &[serde_json::Value], depth: usize) -> serde_json::Value {
+        let operator = ["AND", "OR", "NOT"][rng.below(3)];
+        let criteria: Vec<_> = (0..rng.below(4))
+            .map(|_| {
+                if depth > 0 && rng.below(3) == 0 {
+                    random_group(rng, pool, depth - 1)
+                } else {
+                    pool[rng.below(pool.len())].clone()
+                }
+            })
+            .collect();
+        json!({"operator": operator, "criteria": criteria})
+    }
+
+    fn random_cart(rng: &mut Rng) -> CartInput {
+        let mut cart = CartInput {
+            total: [0.0, 30.0, 50.0, 100.0, 150.0][rng.below(5)],
+            quantity: rng.below(5) as u32,
+            customer_tags: if rng.below(2) == 0 { vec!["VIP".to_string()] } else { vec![] },
+            line_items: vec![LineItem {
+                vendor: ["Acme", "Other"][rng.below(2)].to_string(),
+                quantity: 1 + rng.below(3) as u32,
+                price: 40.0,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        if rng.below(3) > 0 {
+            cart.shipping_address = Some(Address {
+                country_code: ["US", "CA", "GB"][rng.below(3)].to_string(),
+                zip: ["90210", "10001"][rng.below(2)].to_string(),
+                address1: ["1 Main St", "PO Box 12"][rng.below(2)].to_string(),
+                ..Default::default()
+            });
+        }
+        if rng.below(2) == 0 {
+            cart.attributes.insert("gift".to_string(), "yes".to_string());
+        }
+        cart
+    }
+
+    #[test]
+    fn test_matches_unoptimized() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let pool = condition_pool();
+        let patterns = HashMap::new();
+        let carts: Vec<_> = (0..24).map(|_| random_cart(&mut rng)).collect();
+
+        for _ in 0..500 {
+            let original = group(random_group(&mut rng, &pool, 3));
+            let optimized = optimize(&original, &patterns);
+            let before = CompiledGroup::compile(&original, &CompileContext::default());
+            let after = CompiledGroup::compile(&optimized, &CompileContext::default());
+            for cart in &carts {
+                assert_eq!(
+                    evaluate_group(&before, cart),
+                    evaluate_group(&after, cart),
+                    "{:#?}\nbecame\n{:#?}\nfor {:#?}",
+                    original,
+                    optimized,
+                    cart
+                );
+            }
+        }
+    }
+}
@@ -54,8 +54,9 @@ lazy_static! {
         r"^(\+1[-.\s]?)?(\(?\d{3}\)?[-.\s]?)?\d{3}[-.\s]?\d{4}$"
     ).expect("Invalid US phone regex");
 
-    /// Detects common profanity (basic word list).
-    /// In production, this would be a more comprehensive list.
+    /// Detects common profanity (placeholder word list).
+    /// Kept for existing configs; the `CONTAINS_PROFANITY` operator does
+    /// real filtering (see `profanity`).
     pub static ref PROFANITY: Regex = Regex::new(
         r"(?i)\b(badword1|badword2|offensive)\b"
     ).expect("Invalid profanity regex");
@@ -191,6 +192,32 @@ mod tests {
         assert!(!US_PHONE.is_match("abcdefghij"));
     }
 
+    /// Test vectors shared with the checkout function's regex-free
+    /// matchers, which check them against these regexes too.
+    const SHARED_VECTORS: &str = include_str!("../../testdata/preset_patterns.json");
+
+    #[test]
+    fn test_shared_vectors() {
+        let vectors: serde_json::Value = serde_json::from_str(SHARED_VECTORS).unwrap();
+        let vectors = vectors.as_object().unwrap();
+
+        let mut names: Vec<&str> = vectors.keys().map(String::as_str).collect();
+        let mut presets = list_preset_patterns();
+        names.sort();
+        presets.sort();
+        assert_eq!(names, presets, "every preset needs shared vectors");
+
+        for (name, cases) in vectors {
+            let re = get_preset_pattern(name).unwrap();
+            for (key, expected) in [("match", true), ("no_match", false)] {
+                for input in cases[key].as_array().unwrap() {
+                    let input = input.as_str().unwrap();
+                    assert_eq!(re.is_match(input), expected, "{} on {:?}", name, input);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_get_preset_pattern() {
         assert!(get_preset_pattern("po_box").is_some());
@@ -208,6 +235,7 @@ mod tests {
 
     // Performance sanity check
     #[test]
+    #[cfg_attr(debug_assertions, ignore = "timing needs an optimized build; run with --release")]
     fn test_regex_performance() {
         let test_strings: Vec<&str> = vec![
             "123 Main Street",
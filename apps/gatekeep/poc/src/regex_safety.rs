@@ -0,0 +1,524 @@
+//! Static safety analysis for custom regex patterns (TRD 4.2, Layer 2).
+//!
+//! Custom patterns are checked before they are saved, so a pattern that
+//! the checkout function would reject, or that would eat into its time
+//! budget, never reaches the metafield. The analysis mirrors the bounded
+//! matcher in `gatekeep-validator` (`src/regex.rs`): the same syntax
+//! subset, the same length limit and the same program-size accounting.
+//! Keep the two in sync.
+//!
+//! `\d \w \s` and `\b` need no diagnostic: the matcher uses the same
+//! Unicode definitions as the `regex` crate and is differentially tested
+//! against it.
+
+use regex_syntax::ast::{
+    self, Ast, AssertionKind, ClassSet, ClassSetItem, Flag, FlagsItemKind, GroupKind, LiteralKind,
+    RepetitionKind, RepetitionRange, SpecialLiteralKind,
+};
+use serde::Serialize;
+
+/// Longest pattern accepted, in bytes.
+pub const MAX_PATTERN_LEN: usize = 256;
+/// Largest compiled program accepted, in matcher instructions (including
+/// the final match instruction). Counted repetitions are expanded.
+pub const MAX_PROGRAM_LEN: usize = 2048;
+/// Largest count accepted in a counted repetition, whatever it repeats.
+pub const MAX_REPETITION: u32 = 1000;
+/// Complexity points for a custom regex condition (TRD complexity table).
+pub const CUSTOM_REGEX_POINTS: u32 = 5;
+/// Program instructions per extra complexity point. The matcher's cost per
+/// input character grows with program size.
+const INSTRUCTIONS_PER_POINT: usize = 256;
+
+/// Result of analyzing one pattern.
+#[derive(Debug, Clone, Serialize)]
+pub struct PatternReport {
+    /// Estimated size of the compiled program, in instructions. Zero when
+    /// the pattern doesn't parse.
+    pub program_size: usize,
+    /// Estimated complexity points for a condition using this pattern.
+    pub complexity_points: u32,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl PatternReport {
+    /// True if the pattern can be saved (it may still have warnings).
+    pub fn is_ok(&self) -> bool {
+        !self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+}
+
+/// A single finding about a pattern.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: DiagnosticCode,
+    pub message: String,
+    /// Byte range in the pattern the finding refers to, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Severity {
+    /// The pattern must not be saved.
+    Error,
+    /// The pattern works but is probably not what the merchant meant.
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DiagnosticCode {
+    /// Longer than [`MAX_PATTERN_LEN`].
+    TooLong,
+    /// Compiles to more than [`MAX_PROGRAM_LEN`] instructions.
+    TooLarge,
+    /// A counted repetition over [`MAX_REPETITION`]. Checked apart from the
+    /// program size, since `(){4294967295}` compiles to nothing but still
+    /// costs the checkout matcher a loop per count.
+    TooManyRepetitions,
+    /// Not a valid pattern.
+    Syntax,
+    /// Valid syntax the checkout matcher doesn't implement.
+    Unsupported,
+    /// Matches the empty string, so it matches every value.
+    MatchesEmpty,
+    /// An unbounded quantifier inside another one, e.g. `(a+)*`. Linear
+    /// time in the checkout matcher, but catastrophic on backtracking
+    /// engines, so it's flagged in case the pattern is reused elsewhere.
+    NestedQuantifier,
+    /// A `[...]` class with non-ASCII characters under `(?i)`. The checkout
+    /// matcher only folds ASCII letters in classes, so `(?i)[é]` won't
+    /// match "É" there.
+    CaseInsensitiveClass,
+}
+
+/// Analyze a custom pattern.
+pub fn analyze_pattern(pattern: &str) -> PatternReport {
+    let mut diagnostics = Vec::new();
+
+    if pattern.len() > MAX_PATTERN_LEN {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            code: DiagnosticCode::TooLong,
+            message: format!("pattern is {} bytes; the limit is {}", pattern.len(), MAX_PATTERN_LEN),
+            span: None,
+        });
+    }
+
+    let ast = match ast::parse::Parser::new().parse(pattern) {
+        Ok(ast) => ast,
+        Err(err) => {
+            let code = match err.kind() {
+                ast::ErrorKind::UnsupportedBackreference | ast::ErrorKind::UnsupportedLookAround => {
+                    DiagnosticCode::Unsupported
+                }
+                _ => DiagnosticCode::Syntax,
+            };
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                code,
+                message: err.kind().to_string(),
+                span: Some(span_of(err.span())),
+            });
+            return PatternReport {
+                program_size: 0,
+                complexity_points: CUSTOM_REGEX_POINTS,
+                diagnostics,
+            };
+        }
+    };
+
+    let mut analyzer = Analyzer {
+        diagnostics: &mut diagnostics,
+        has_assertion: false,
+        case_insensitive: false,
+    };
+    analyzer.check(&ast, 0);
+    let has_assertion = analyzer.has_assertion;
+
+    // +1 for the final match instruction
+    let program_size = program_size(&ast).saturating_add(1);
+    if program_size > MAX_PROGRAM_LEN {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            code: DiagnosticCode::TooLarge,
+            message: format!(
+                "pattern compiles to about {} instructions; the limit is {}. Large counted repetitions like {{500}} are the usual cause",
+                program_size, MAX_PROGRAM_LEN
+            ),
+            span: None,
+        });
+    }
+
+    if min_len(&ast) == 0 && !has_assertion {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            code: DiagnosticCode::MatchesEmpty,
+            message: "pattern matches the empty string, so it matches every value".to_string(),
+            span: None,
+        });
+    }
+
+    PatternReport {
+        program_size,
+        complexity_points: CUSTOM_REGEX_POINTS + (program_size / INSTRUCTIONS_PER_POINT) as u32,
+        diagnostics,
+    }
+}
+
+fn span_of(span: &ast::Span) -> (usize, usize) {
+    (span.start.offset, span.end.offset)
+}
+
+struct Analyzer<'a> {
+    diagnostics: &'a mut Vec<Diagnostic>,
+    has_assertion: bool,
+    /// Set by a leading `(?i)`.
+    case_insensitive: bool,
+}
+
+impl Analyzer<'_> {
+    fn unsupported(&mut self, what: &str, span: &ast::Span) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            code: DiagnosticCode::Unsupported,
+            message: format!("{} are not supported at checkout", what),
+            span: Some(span_of(span)),
+        });
+    }
+
+    /// Walk the AST. `unbounded_depth` counts enclosing `*`/`+`/`{n,}`.
+    fn check(&mut self, node: &Ast, unbounded_depth: usize) {
+        match node {
+            Ast::Empty(_) | Ast::Dot(_) | Ast::ClassPerl(_) => {}
+            Ast::Flags(set) => {
+                // Only a leading `(?i)` is supported
+                let leading_i = set.span.start.offset == 0
+                    && set.flags.items.len() == 1
+                    && set.flags.items[0].kind == FlagsItemKind::Flag(Flag::CaseInsensitive);
+                if leading_i {
+                    self.case_insensitive = true;
+                } else {
+                    self.unsupported("inline flags other than a leading (?i)", &set.span);
+                }
+            }
+            Ast::Literal(literal) => self.check_literal(literal),
+            Ast::Assertion(assertion) => {
+                self.has_assertion = true;
+                match assertion.kind {
+                    AssertionKind::StartLine
+                    | AssertionKind::EndLine
+                    | AssertionKind::WordBoundary
+                    | AssertionKind::NotWordBoundary => {}
+                    _ => self.unsupported("assertions other than ^, $, \\b and \\B", &assertion.span),
+                }
+            }
+            Ast::ClassUnicode(class) => self.unsupported("Unicode classes", &class.span),
+            Ast::ClassBracketed(class) => {
+                if self.case_insensitive && !class_set_is_ascii(&class.kind) {
+                    self.diagnostics.push(Diagnostic {
+                        severity: Severity::Warning,
+                        code: DiagnosticCode::CaseInsensitiveClass,
+                        message: "(?i) only folds ASCII letters inside [...]; list other cases explicitly".to_string(),
+                        span: Some(span_of(&class.span)),
+                    });
+                }
+                self.check_class_set(&class.kind, true)
+            }
+            Ast::Repetition(rep) => {
+                if !rep.greedy {
+                    self.unsupported("lazy quantifiers", &rep.op.span);
+                }
+                let (min, max) = repetition_bounds(&rep.op.kind);
+                if max.unwrap_or(min) > MAX_REPETITION {
+                    self.diagnostics.push(Diagnostic {
+                        severity: Severity::Error,
+                        code: DiagnosticCode::TooManyRepetitions,
+                        message: format!("counted repetition over {}", MAX_REPETITION),
+                        span: Some(span_of(&rep.op.span)),
+                    });
+                }
+                let unbounded = is_unbounded(&rep.op.kind);
+                // Report once, at the innermost offending quantifier
+                if unbounded && unbounded_depth > 0 && !contains_unbounded_repetition(&rep.ast) {
+                    self.diagnostics.push(Diagnostic {
+                        severity: Severity::Warning,
+                        code: DiagnosticCode::NestedQuantifier,
+                        message: "unbounded quantifier nested inside another one".to_string(),
+                        span: Some(span_of(&rep.span)),
+                    });
+                }
+                self.check(&rep.ast, unbounded_depth + unbounded as usize);
+            }
+            Ast::Group(group) => {
+                match &group.kind {
+                    GroupKind::CaptureIndex(_) => {}
+                    GroupKind::CaptureName { .. } => self.unsupported("named groups", &group.span),
+                    GroupKind::NonCapturing(flags) if flags.items.is_empty() => {}
+                    GroupKind::NonCapturing(_) => self.unsupported("inline flags other than a leading (?i)", &group.span),
+                }
+                self.check(&group.ast, unbounded_depth);
+            }
+            Ast::Alternation(alt) => alt.asts.iter().for_each(|a| self.check(a, unbounded_depth)),
+            Ast::Concat(concat) => concat.asts.iter().for_each(|a| self.check(a, unbounded_depth)),
+        }
+    }
+
+    fn check_literal(&mut self, literal: &ast::Literal) {
+        match &literal.kind {
+            LiteralKind::Verbatim | LiteralKind::Meta | LiteralKind::Superfluous => {}
+            LiteralKind::Special(
+                SpecialLiteralKind::Tab | SpecialLiteralKind::LineFeed | SpecialLiteralKind::CarriageReturn,
+            ) => {}
+            LiteralKind::Special(_) => self.unsupported("escapes other than \\t, \\n and \\r", &literal.span),
+            LiteralKind::Octal | LiteralKind::HexFixed(_) | LiteralKind::HexBrace(_) => {
+                self.unsupported("hex and octal escapes", &literal.span)
+            }
+        }
+    }
+
+    fn check_class_set(&mut self, set: &ClassSet, top_level: bool) {
+        match set {
+            ClassSet::BinaryOp(op) => self.unsupported("class set operations (&&, --, ~~)", &op.span),
+            ClassSet::Item(item) => self.check_class_item(item, top_level),
+        }
+    }
+
+    fn check_class_item(&mut self, item: &ClassSetItem, top_level: bool) {
+        match item {
+            ClassSetItem::Empty(_) => {}
+            ClassSetItem::Literal(literal) => self.check_literal(literal),
+            ClassSetItem::Range(range) => {
+                self.check_literal(&range.start);
+                self.check_literal(&range.end);
+            }
+            ClassSetItem::Ascii(class) => self.unsupported("POSIX classes", &class.span),
+            ClassSetItem::Unicode(class) => self.unsupported("Unicode classes", &class.span),
+            ClassSetItem::Perl(class) if class.negated => {
+                self.unsupported("negated classes inside [...]", &class.span)
+            }
+            ClassSetItem::Perl(_) => {}
+            ClassSetItem::Bracketed(class) if top_level => {
+                // `[[a]]` style nesting
+                self.unsupported("nested classes", &class.span)
+            }
+            ClassSetItem::Bracketed(_) => {}
+            ClassSetItem::Union(union) => {
+                union.items.iter().for_each(|i| self.check_class_item(i, top_level))
+            }
+        }
+    }
+}
+
+/// True if every literal in the class is ASCII.
+fn class_set_is_ascii(set: &ClassSet) -> bool {
+    fn item_is_ascii(item: &ClassSetItem) -> bool {
+        match item {
+            ClassSetItem::Literal(literal) => literal.c.is_ascii(),
+            ClassSetItem::Range(range) => range.start.c.is_ascii() && range.end.c.is_ascii(),
+            ClassSetItem::Bracketed(class) => class_set_is_ascii(&class.kind),
+            ClassSetItem::Union(union) => union.items.iter().all(item_is_ascii),
+            _ => true,
+        }
+    }
+    match set {
+        ClassSet::BinaryOp(op) => class_set_is_ascii(&op.lhs) && class_set_is_ascii(&op.rhs),
+        ClassSet::Item(item) => item_is_ascii(item),
+    }
+}
+
+fn is_unbounded(kind: &RepetitionKind) -> bool {
+    repetition_bounds(kind).1.is_none()
+}
+
+fn contains_unbounded_repetition(node: &Ast) -> bool {
+    match node {
+        Ast::Repetition(rep) => is_unbounded(&rep.op.kind) || contains_unbounded_repetition(&rep.ast),
+        Ast::Group(group) => contains_unbounded_repetition(&group.ast),
+        Ast::Alternation(alt) => alt.asts.iter().any(contains_unbounded_repetition),
+        Ast::Concat(concat) => concat.asts.iter().any(contains_unbounded_repetition),
+        _ => false,
+    }
+}
+
+/// (min, max) repetition counts, `None` max meaning unbounded.
+fn repetition_bounds(kind: &RepetitionKind) -> (u32, Option<u32>) {
+    match kind {
+        RepetitionKind::ZeroOrOne => (0, Some(1)),
+        RepetitionKind::ZeroOrMore => (0, None),
+        RepetitionKind::OneOrMore => (1, None),
+        RepetitionKind::Range(RepetitionRange::Exactly(n)) => (*n, Some(*n)),
+        RepetitionKind::Range(RepetitionRange::AtLeast(n)) => (*n, None),
+        RepetitionKind::Range(RepetitionRange::Bounded(min, max)) => (*min, Some(*max)),
+    }
+}
+
+/// Instructions the checkout matcher emits for `node`: one per atom, two
+/// per extra alternation branch, and repetitions expanded (`x{2,4}` is
+/// `x x (split x) (split x)`; `x*` is `split x jmp`).
+fn program_size(node: &Ast) -> usize {
+    match node {
+        Ast::Empty(_) | Ast::Flags(_) => 0,
+        Ast::Literal(_)
+        | Ast::Dot(_)
+        | Ast::Assertion(_)
+        | Ast::ClassUnicode(_)
+        | Ast::ClassPerl(_)
+        | Ast::ClassBracketed(_) => 1,
+        Ast::Repetition(rep) => {
+            let body = program_size(&rep.ast);
+            let (min, max) = repetition_bounds(&rep.op.kind);
+            let required = body.saturating_mul(min as usize);
+            let optional = match max {
+                None => body.saturating_add(2),
+                Some(max) => (body + 1).saturating_mul(max.saturating_sub(min) as usize),
+            };
+            required.saturating_add(optional)
+        }
+        Ast::Group(group) => program_size(&group.ast),
+        Ast::Alternation(alt) => alt
+            .asts
+            .iter()
+            .map(program_size)
+            .fold(2 * (alt.asts.len() - 1), usize::saturating_add),
+        Ast::Concat(concat) => concat.asts.iter().map(program_size).fold(0, usize::saturating_add),
+    }
+}
+
+/// Shortest input `node` can match, in characters.
+fn min_len(node: &Ast) -> usize {
+    match node {
+        Ast::Empty(_) | Ast::Flags(_) | Ast::Assertion(_) => 0,
+        Ast::Literal(_) | Ast::Dot(_) | Ast::ClassUnicode(_) | Ast::ClassPerl(_) | Ast::ClassBracketed(_) => 1,
+        Ast::Repetition(rep) => {
+            let (min, _) = repetition_bounds(&rep.op.kind);
+            min_len(&rep.ast).saturating_mul(min as usize)
+        }
+        Ast::Group(group) => min_len(&group.ast),
+        Ast::Alternation(alt) => alt.asts.iter().map(min_len).min().unwrap_or(0),
+        Ast::Concat(concat) => concat.asts.iter().map(min_len).fold(0, usize::saturating_add),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patterns::{get_preset_pattern, list_preset_patterns};
+
+    fn codes(pattern: &str) -> Vec<DiagnosticCode> {
+        analyze_pattern(pattern).diagnostics.iter().map(|d| d.code).collect()
+    }
+
+    #[test]
+    fn test_presets_pass() {
+        for name in list_preset_patterns() {
+            let pattern = get_preset_pattern(name).unwrap().as_str();
+            let report = analyze_pattern(pattern);
+            assert!(report.diagnostics.is_empty(), "{}: {:?}", name, report.diagnostics);
+            assert_eq!(report.complexity_points, CUSTOM_REGEX_POINTS);
+        }
+    }
+
+    #[test]
+    fn test_program_size_matches_matcher_layout() {
+        // a, b, c + match
+        assert_eq!(analyze_pattern("abc").program_size, 4);
+        // split a jmp + match
+        assert_eq!(analyze_pattern("a*").program_size, 4);
+        // split a jmp b + match
+        assert_eq!(analyze_pattern("a|b").program_size, 5);
+        // x x (split x) (split x) + match
+        assert_eq!(analyze_pattern("x{2,4}").program_size, 7);
+    }
+
+    #[test]
+    fn test_limits() {
+        let long = "a".repeat(MAX_PATTERN_LEN + 1);
+        assert!(codes(&long).contains(&DiagnosticCode::TooLong));
+
+        let report = analyze_pattern(r"(\d{100}){100}");
+        assert!(!report.is_ok());
+        assert!(codes(r"(\d{100}){100}").contains(&DiagnosticCode::TooLarge));
+        assert!(report.complexity_points > CUSTOM_REGEX_POINTS);
+
+        // Huge counts don't overflow the estimate
+        assert!(codes(r"a{4294967295}{4294967295}").contains(&DiagnosticCode::TooLarge));
+
+        // Empty bodies compile to nothing, but the count is still checked
+        for pattern in ["(){4294967295}", "(?:){99999}x", "a{2,1001}"] {
+            let report = analyze_pattern(pattern);
+            assert!(!report.is_ok(), "{}", pattern);
+            assert!(codes(pattern).contains(&DiagnosticCode::TooManyRepetitions), "{}", pattern);
+        }
+        assert!(analyze_pattern("a{1000}").is_ok());
+    }
+
+    #[test]
+    fn test_rejects_unsupported_constructs() {
+        for pattern in [
+            r"a(?=b)",
+            r"(a)\1",
+            r"a+?",
+            r"\p{L}",
+            r"[\D]",
+            r"[[:alpha:]]",
+            r"a(?i)b",
+            r"(?i:a)",
+            r"(?P<zip>\d{5})",
+            r"\Aabc\z",
+            r"\x41",
+            r"[a-z&&[^aeiou]]",
+        ] {
+            let report = analyze_pattern(pattern);
+            assert!(!report.is_ok(), "{} should be rejected", pattern);
+            assert_eq!(report.diagnostics[0].code, DiagnosticCode::Unsupported, "{}", pattern);
+            assert!(report.diagnostics[0].span.is_some(), "{}", pattern);
+        }
+
+        assert!(analyze_pattern(r"(?i)^po\s*box\b").is_ok());
+        assert!(analyze_pattern(r"^[\w.-]+\t\n$").is_ok());
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert_eq!(codes("(abc"), vec![DiagnosticCode::Syntax]);
+        assert_eq!(codes("[a"), vec![DiagnosticCode::Syntax]);
+        assert_eq!(codes("*a"), vec![DiagnosticCode::Syntax]);
+    }
+
+    #[test]
+    fn test_warnings() {
+        let report = analyze_pattern("a*");
+        assert!(report.is_ok());
+        assert_eq!(codes("a*"), vec![DiagnosticCode::MatchesEmpty]);
+        assert!(codes("^$").is_empty());
+
+        assert_eq!(codes("(a+)+b"), vec![DiagnosticCode::NestedQuantifier]);
+        assert_eq!(codes("((a+)*)+b"), vec![DiagnosticCode::NestedQuantifier]);
+        assert!(codes("(ab?)+c").is_empty());
+
+        assert_eq!(codes("(?i)[àé]"), vec![DiagnosticCode::CaseInsensitiveClass]);
+        assert_eq!(codes("(?i)[a-ü]"), vec![DiagnosticCode::CaseInsensitiveClass]);
+        assert!(codes("[àé]").is_empty());
+        assert!(codes("(?i)[a-z]é").is_empty());
+    }
+
+    #[test]
+    fn test_perl_classes_and_boundaries_pass() {
+        // The checkout matcher implements these with Unicode semantics
+        for pattern in [r"\d", r"\D", r"\w", r"\W", r"\s", r"\S", r"[\d\w\s]", r"x", r"\Bx\B"] {
+            assert!(codes(pattern).is_empty(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_report_serializes() {
+        let json = serde_json::to_value(analyze_pattern("a+?")).unwrap();
+        assert_eq!(json["diagnostics"][0]["severity"], "ERROR");
+        assert_eq!(json["diagnostics"][0]["code"], "UNSUPPORTED");
+        assert_eq!(json["diagnostics"][0]["span"], serde_json::json!([1, 3]));
+    }
+}